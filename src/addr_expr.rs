@@ -0,0 +1,290 @@
+//! Address expressions accepted wherever the CLI takes a guest address
+//!
+//! An expression is a literal (`0x80000010`, `2147483664`), a symbol
+//! (`main`) or a symbol with an offset (`lifegame_step+0x14`, `buf - 4`).
+//! Parsing needs no ELF; symbols are resolved later against the loaded
+//! program's symbol table, and unknown names are reported together with the
+//! closest names that do exist.
+
+use std::fmt;
+
+/// Most near-miss names listed when a symbol is not found
+const MAX_SUGGESTIONS: usize = 5;
+
+/// A parsed, not yet resolved, address expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddrExpr {
+    /// A numeric address
+    Literal(u32),
+    /// A symbol's address plus a signed byte offset
+    Symbol { name: String, offset: i64 },
+}
+
+/// Why an address expression could not be resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+    /// No symbol has this name; `near` lists similar names that do exist
+    UnknownSymbol { name: String, near: Vec<String> },
+    /// The symbol plus offset falls outside the 32-bit address space
+    OutOfRange(AddrExpr),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResolveError::UnknownSymbol { name, near } => {
+                write!(f, "symbol `{name}` not found")?;
+                if !near.is_empty() {
+                    write!(f, " (did you mean {}?)", near.join(", "))?;
+                }
+                Ok(())
+            }
+            ResolveError::OutOfRange(expr) => {
+                write!(f, "address expression `{expr}` is out of range")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+/// Parse a hex (0x-prefixed) or decimal number
+pub fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Whether `name` can be a symbol name in an expression
+fn is_symbol_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '_' | '.' | '$'))
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'))
+}
+
+impl AddrExpr {
+    /// Parse `0x80000010`, `123`, `main` or `main+0x14` / `main - 4`
+    pub fn parse(s: &str) -> std::result::Result<Self, String> {
+        let s = s.trim();
+        let invalid = || format!("invalid address expression '{s}'");
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(s)
+                .and_then(|value| u32::try_from(value).ok())
+                .map(AddrExpr::Literal)
+                .ok_or_else(invalid);
+        }
+        let (name, offset) = match s.find(['+', '-']) {
+            Some(at) => {
+                let magnitude = parse_number(s[at + 1..].trim())
+                    .and_then(|value| i64::try_from(value).ok())
+                    .ok_or_else(invalid)?;
+                let sign = if s.as_bytes()[at] == b'-' { -1 } else { 1 };
+                (s[..at].trim(), sign * magnitude)
+            }
+            None => (s, 0),
+        };
+        if !is_symbol_name(name) {
+            return Err(invalid());
+        }
+        Ok(AddrExpr::Symbol {
+            name: name.to_string(),
+            offset,
+        })
+    }
+
+    /// The address this expression names, looking symbols up in `symbols`
+    ///
+    /// `symbols` holds `(address, name)` pairs such as `ElfLoader::symbols` returns.
+    pub fn resolve(&self, symbols: &[(u32, String)]) -> std::result::Result<u32, ResolveError> {
+        let (name, offset) = match self {
+            AddrExpr::Literal(address) => return Ok(*address),
+            AddrExpr::Symbol { name, offset } => (name, *offset),
+        };
+        let Some(&(address, _)) = symbols.iter().find(|(_, symbol)| symbol == name) else {
+            return Err(ResolveError::UnknownSymbol {
+                name: name.clone(),
+                near: near_misses(name, symbols),
+            });
+        };
+        u32::try_from(i64::from(address) + offset)
+            .map_err(|_| ResolveError::OutOfRange(self.clone()))
+    }
+}
+
+impl fmt::Display for AddrExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddrExpr::Literal(address) => write!(f, "0x{address:08x}"),
+            AddrExpr::Symbol { name, offset: 0 } => write!(f, "{name}"),
+            AddrExpr::Symbol { name, offset } if *offset < 0 => {
+                write!(f, "{name}-0x{:x}", offset.unsigned_abs())
+            }
+            AddrExpr::Symbol { name, offset } => write!(f, "{name}+0x{offset:x}"),
+        }
+    }
+}
+
+/// Names in `symbols` close to `name`: case-insensitive containment or a
+/// small edit distance, closest first
+fn near_misses(name: &str, symbols: &[(u32, String)]) -> Vec<String> {
+    let wanted = name.to_ascii_lowercase();
+    let threshold = (wanted.len() / 3).max(2);
+    let mut near: Vec<(usize, &str)> = symbols
+        .iter()
+        .filter_map(|(_, symbol)| {
+            let candidate = symbol.to_ascii_lowercase();
+            let distance = edit_distance(&wanted, &candidate);
+            let related = candidate.contains(&wanted) || wanted.contains(&candidate);
+            (distance <= threshold || related).then_some((distance, symbol.as_str()))
+        })
+        .collect();
+    near.sort_unstable();
+    near.dedup_by_key(|&mut (_, symbol)| symbol);
+    near.into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, symbol)| symbol.to_string())
+        .collect()
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols() -> Vec<(u32, String)> {
+        [
+            (0x8000_0000, "_start"),
+            (0x8000_0100, "main"),
+            (0x8000_0200, "lifegame_step"),
+            (0x8000_0300, "lifegame_init"),
+            (0x8000_1000, "buffer"),
+        ]
+        .into_iter()
+        .map(|(address, name)| (address, name.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_parse_address_expressions() {
+        let symbol = |name: &str, offset| AddrExpr::Symbol {
+            name: name.to_string(),
+            offset,
+        };
+        assert_eq!(
+            AddrExpr::parse("0x80000010"),
+            Ok(AddrExpr::Literal(0x8000_0010))
+        );
+        assert_eq!(AddrExpr::parse("0XFF"), Ok(AddrExpr::Literal(0xFF)));
+        assert_eq!(AddrExpr::parse("4096"), Ok(AddrExpr::Literal(4096)));
+        assert_eq!(AddrExpr::parse(" main "), Ok(symbol("main", 0)));
+        assert_eq!(
+            AddrExpr::parse("lifegame_step+0x14"),
+            Ok(symbol("lifegame_step", 0x14))
+        );
+        assert_eq!(AddrExpr::parse("buffer - 4"), Ok(symbol("buffer", -4)));
+        assert_eq!(AddrExpr::parse(".L_tmp$1+8"), Ok(symbol(".L_tmp$1", 8)));
+
+        for bad in [
+            "",
+            "0x",
+            "0x1_0000_0000",
+            "4294967296",
+            "12ab",
+            "main+",
+            "main+x",
+            "main+-4",
+            "+4",
+            "ma in",
+            "main*2",
+        ] {
+            assert_eq!(
+                AddrExpr::parse(bad),
+                Err(format!("invalid address expression '{}'", bad.trim())),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_against_symbols() {
+        let symbols = symbols();
+        let resolve = |s: &str| AddrExpr::parse(s).unwrap().resolve(&symbols);
+        assert_eq!(resolve("0x1234"), Ok(0x1234));
+        assert_eq!(resolve("1234"), Ok(1234));
+        assert_eq!(resolve("main"), Ok(0x8000_0100));
+        assert_eq!(resolve("lifegame_step+0x14"), Ok(0x8000_0214));
+        assert_eq!(resolve("buffer-16"), Ok(0x8000_0FF0));
+        // Literals never need symbols
+        assert_eq!(AddrExpr::Literal(8).resolve(&[]), Ok(8));
+
+        let out_of_range = AddrExpr::parse("_start-0x80000001").unwrap();
+        assert_eq!(
+            out_of_range.resolve(&symbols),
+            Err(ResolveError::OutOfRange(out_of_range.clone()))
+        );
+        assert_eq!(
+            resolve("buffer+0x80000000").unwrap_err().to_string(),
+            "address expression `buffer+0x80000000` is out of range"
+        );
+    }
+
+    #[test]
+    fn test_unknown_symbols_list_near_misses() {
+        let symbols = symbols();
+        let near = |s: &str| match AddrExpr::parse(s).unwrap().resolve(&symbols) {
+            Err(ResolveError::UnknownSymbol { near, .. }) => near,
+            other => panic!("{s} resolved to {other:?}"),
+        };
+        assert_eq!(near("mian"), ["main"]);
+        assert_eq!(near("Main"), ["main"]);
+        assert_eq!(near("lifegame_stp"), ["lifegame_step", "lifegame_init"]);
+        assert_eq!(near("lifegame"), ["lifegame_init", "lifegame_step"]);
+        assert_eq!(near("start"), ["_start"]);
+        assert!(near("completely_unrelated").is_empty());
+
+        assert_eq!(
+            AddrExpr::parse("mian+4")
+                .unwrap()
+                .resolve(&symbols)
+                .unwrap_err()
+                .to_string(),
+            "symbol `mian` not found (did you mean main?)"
+        );
+        assert_eq!(
+            AddrExpr::parse("nothing")
+                .unwrap()
+                .resolve(&[])
+                .unwrap_err()
+                .to_string(),
+            "symbol `nothing` not found"
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for text in ["0x80000010", "main", "lifegame_step+0x14", "buffer-0x4"] {
+            let expr = AddrExpr::parse(text).unwrap();
+            assert_eq!(expr.to_string(), text);
+            assert_eq!(AddrExpr::parse(&expr.to_string()), Ok(expr));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Instruction encoders for control transfers with numeric offsets
+//!
+//! Offsets are relative to the instruction's own PC. Branch immediates cover
+//! ±4 KiB and JAL immediates ±1 MiB, both in multiples of 2 bytes; offsets
+//! outside that are rejected instead of being silently truncated.
+
+use crate::reg::Reg;
+
+/// Why an instruction could not be encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmError {
+    /// The offset does not fit the signed immediate of `bits` bits
+    OffsetOutOfRange { offset: i32, bits: u32 },
+    /// The offset is not a multiple of 2 bytes
+    Misaligned { offset: i32 },
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AsmError::OffsetOutOfRange { offset, bits } => {
+                let limit = 1i64 << (bits - 1);
+                write!(
+                    f,
+                    "offset {offset} out of range (must be within {}..={})",
+                    -limit,
+                    limit - 2
+                )
+            }
+            AsmError::Misaligned { offset } => {
+                write!(f, "offset {offset} is not a multiple of 2")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Conditional branch mnemonics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+}
+
+impl Branch {
+    fn funct3(self) -> u32 {
+        match self {
+            Branch::Beq => 0,
+            Branch::Bne => 1,
+            Branch::Blt => 4,
+            Branch::Bge => 5,
+            Branch::Bltu => 6,
+            Branch::Bgeu => 7,
+        }
+    }
+}
+
+/// Check that `offset` is 2-byte aligned and fits a signed `bits`-bit immediate
+fn check_offset(offset: i32, bits: u32) -> Result<u32, AsmError> {
+    if offset % 2 != 0 {
+        return Err(AsmError::Misaligned { offset });
+    }
+    let limit = 1i64 << (bits - 1);
+    if !(-limit..limit).contains(&i64::from(offset)) {
+        return Err(AsmError::OffsetOutOfRange { offset, bits });
+    }
+    Ok(offset as u32)
+}
+
+/// Encode `op rs1, rs2, offset`
+pub fn encode_branch(op: Branch, rs1: Reg, rs2: Reg, offset: i32) -> Result<u32, AsmError> {
+    let imm = check_offset(offset, 13)?;
+    Ok((imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3F) << 25
+        | (rs2.index() as u32) << 20
+        | (rs1.index() as u32) << 15
+        | op.funct3() << 12
+        | (imm >> 1 & 0xF) << 8
+        | (imm >> 11 & 1) << 7
+        | 0x63)
+}
+
+/// Encode `jal rd, offset`
+pub fn encode_jal(rd: Reg, offset: i32) -> Result<u32, AsmError> {
+    let imm = check_offset(offset, 21)?;
+    Ok((imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3FF) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xFF) << 12
+        | (rd.index() as u32) << 7
+        | 0x6F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_branch_and_jump_offsets() {
+        // Encodings checked against llvm-mc
+        assert_eq!(
+            encode_branch(Branch::Beq, Reg::Ra, Reg::Sp, -4),
+            Ok(0xFE20_8EE3)
+        );
+        assert_eq!(
+            encode_branch(Branch::Bgeu, Reg::A0, Reg::A1, 4094),
+            Ok(0x7EB5_7FE3)
+        );
+        assert_eq!(
+            encode_branch(Branch::Blt, Reg::T0, Reg::Zero, -4096),
+            Ok(0x8002_C063)
+        );
+        assert_eq!(encode_jal(Reg::Ra, 2048), Ok(0x0010_00EF));
+        assert_eq!(encode_jal(Reg::Zero, -1_048_576), Ok(0x8000_006F));
+        assert_eq!(encode_jal(Reg::Zero, 1_048_574), Ok(0x7FFF_F06F));
+
+        assert_eq!(
+            encode_branch(Branch::Beq, Reg::Ra, Reg::Sp, 5),
+            Err(AsmError::Misaligned { offset: 5 })
+        );
+        assert_eq!(
+            encode_branch(Branch::Bne, Reg::Ra, Reg::Sp, 4096),
+            Err(AsmError::OffsetOutOfRange {
+                offset: 4096,
+                bits: 13
+            })
+        );
+        assert_eq!(
+            encode_jal(Reg::Ra, -1_048_578),
+            Err(AsmError::OffsetOutOfRange {
+                offset: -1_048_578,
+                bits: 21
+            })
+        );
+        assert_eq!(
+            AsmError::OffsetOutOfRange {
+                offset: 4096,
+                bits: 13
+            }
+            .to_string(),
+            "offset 4096 out of range (must be within -4096..=4094)"
+        );
+    }
+}
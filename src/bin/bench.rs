@@ -0,0 +1,282 @@
+use nekov::{
+    asm::{encode_branch, encode_jal, Branch},
+    cpu::Cpu,
+    memory::Memory,
+    peripheral::{ConsolePeriph, PeripheralManager},
+    reg::Reg,
+};
+use serde::Serialize;
+use std::env;
+use std::process::exit;
+use std::time::Instant;
+
+const USAGE: &str = "Usage: bench [--instructions N] [--min-mips MIPS] [--json]";
+
+/// Default instruction budget per workload and path
+const DEFAULT_INSTRUCTIONS: u32 = 5_000_000;
+
+// Registers used by the workloads
+const ZERO: Reg = Reg::Zero;
+const RA: Reg = Reg::Ra;
+const SP: Reg = Reg::Sp;
+const T0: Reg = Reg::T0;
+const T1: Reg = Reg::T1;
+const T2: Reg = Reg::T2;
+const S0: Reg = Reg::S0;
+const S1: Reg = Reg::S1;
+const A0: Reg = Reg::A0;
+
+/// Register number for an instruction field
+fn x(reg: Reg) -> u32 {
+    reg.index() as u32
+}
+
+fn r_type(funct7: u32, rs2: Reg, rs1: Reg, funct3: u32, rd: Reg) -> u32 {
+    funct7 << 25 | x(rs2) << 20 | x(rs1) << 15 | funct3 << 12 | x(rd) << 7 | 0x33
+}
+
+fn i_type(imm: i32, rs1: Reg, funct3: u32, rd: Reg, opcode: u32) -> u32 {
+    ((imm as u32) & 0xFFF) << 20 | x(rs1) << 15 | funct3 << 12 | x(rd) << 7 | opcode
+}
+
+fn addi(rd: Reg, rs1: Reg, imm: i32) -> u32 {
+    i_type(imm, rs1, 0, rd, 0x13)
+}
+
+fn lw(rd: Reg, rs1: Reg, imm: i32) -> u32 {
+    i_type(imm, rs1, 2, rd, 0x03)
+}
+
+fn sw(rs2: Reg, rs1: Reg, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7F) << 25 | x(rs2) << 20 | x(rs1) << 15 | 2 << 12 | (imm & 0x1F) << 7 | 0x23
+}
+
+fn branch(op: Branch, rs1: Reg, rs2: Reg, offset: i32) -> u32 {
+    encode_branch(op, rs1, rs2, offset).expect("branch offset out of range")
+}
+
+fn jal(rd: Reg, offset: i32) -> u32 {
+    encode_jal(rd, offset).expect("jump offset out of range")
+}
+
+fn lui(rd: Reg, upper: u32) -> u32 {
+    upper << 12 | x(rd) << 7 | 0x37
+}
+
+fn ret() -> u32 {
+    i_type(0, RA, 0, ZERO, 0x67)
+}
+
+/// A synthesized program that loops forever
+struct Workload {
+    name: &'static str,
+    program: Vec<u32>,
+    /// Extra data placed in memory before the run
+    data: Option<(u32, Vec<u8>)>,
+}
+
+/// Arithmetic-only loop (ALU and multiplier dispatch)
+fn alu_loop() -> Workload {
+    Workload {
+        name: "alu",
+        program: vec![
+            addi(T0, ZERO, 0),
+            addi(T1, ZERO, 1),
+            addi(T0, T0, 1),             // loop:
+            r_type(0, T0, T1, 4, T1),    // xor t1, t1, t0
+            i_type(3, T1, 1, T2, 0x13),  // slli t2, t1, 3
+            r_type(0, T2, T1, 0, T1),    // add t1, t1, t2
+            r_type(1, T0, T1, 0, T2),    // mul t2, t1, t0
+            r_type(0x20, T1, T2, 0, T1), // sub t1, t2, t1
+            jal(ZERO, -24),              // j loop
+        ],
+        data: None,
+    }
+}
+
+/// Word-by-word copy of 1 KiB, restarted forever
+fn memcpy_loop() -> Workload {
+    Workload {
+        name: "memcpy",
+        program: vec![
+            lui(S0, 0x80010),    // src
+            lui(S1, 0x80020),    // dst
+            addi(T0, ZERO, 256), // words
+            lw(T1, S0, 0),       // loop:
+            sw(T1, S1, 0),
+            addi(S0, S0, 4),
+            addi(S1, S1, 4),
+            addi(T0, T0, -1),
+            branch(Branch::Bne, T0, ZERO, -20), // bnez t0, loop
+            jal(ZERO, -36),                     // restart
+        ],
+        data: Some((0x8001_0000, (0..1024).map(|i| i as u8).collect())),
+    }
+}
+
+/// Recursive fib(15), called forever (calls, returns and stack traffic)
+fn fib_recursion() -> Workload {
+    Workload {
+        name: "fib",
+        program: vec![
+            lui(SP, 0x80100),
+            addi(A0, ZERO, 15), // again:
+            jal(RA, 12),        // call fib
+            jal(ZERO, -8),      // j again
+            addi(ZERO, ZERO, 0),
+            addi(T0, ZERO, 2),               // fib:
+            branch(Branch::Blt, A0, T0, 60), // blt a0, t0, small
+            addi(SP, SP, -16),
+            sw(RA, SP, 12),
+            sw(A0, SP, 8),
+            addi(A0, A0, -1),
+            jal(RA, -24), // fib(n - 1)
+            sw(A0, SP, 4),
+            lw(A0, SP, 8),
+            addi(A0, A0, -2),
+            jal(RA, -40), // fib(n - 2)
+            lw(T1, SP, 4),
+            r_type(0, T1, A0, 0, A0), // add a0, a0, t1
+            lw(RA, SP, 12),
+            addi(SP, SP, 16),
+            ret(),
+            ret(), // small:
+        ],
+        data: None,
+    }
+}
+
+#[derive(Serialize)]
+struct BenchResult {
+    workload: &'static str,
+    path: &'static str,
+    executed: u32,
+    seconds: f64,
+    mips: f64,
+}
+
+/// Run `workload` for `budget` instructions on plain memory or with peripherals
+fn run_workload(workload: &Workload, budget: u32, with_peripherals: bool) -> BenchResult {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    let base = memory.base_address();
+    for (i, &word) in workload.program.iter().enumerate() {
+        memory.write_u32_le(base + i as u32 * 4, word).unwrap();
+    }
+    if let Some((address, bytes)) = &workload.data {
+        memory.load_data(*address, bytes).unwrap();
+    }
+    cpu.pc = base;
+
+    let start = Instant::now();
+    let executed = if with_peripherals {
+        let mut peripherals = PeripheralManager::new();
+        peripherals.add_peripheral(Box::new(ConsolePeriph::new(0x1000_0000)));
+        cpu.run_with_peripherals(&mut memory, &mut peripherals, Some(budget))
+    } else {
+        cpu.run(&mut memory, Some(budget))
+    };
+    let seconds = start.elapsed().as_secs_f64();
+    let executed = executed.unwrap_or_else(|e| {
+        eprintln!("Workload {} failed: {e}", workload.name);
+        exit(1);
+    });
+    BenchResult {
+        workload: workload.name,
+        path: if with_peripherals {
+            "peripherals"
+        } else {
+            "memory"
+        },
+        executed,
+        seconds,
+        mips: executed as f64 / seconds / 1e6,
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> T {
+    match value.and_then(|v| v.parse().ok()) {
+        Some(value) => value,
+        None => {
+            eprintln!("Invalid or missing value for {flag}");
+            eprintln!("{USAGE}");
+            exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut budget = DEFAULT_INSTRUCTIONS;
+    let mut min_mips: Option<f64> = None;
+    let mut json_output = false;
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--instructions" => budget = parse_value(arg, rest.next()),
+            "--min-mips" => min_mips = Some(parse_value(arg, rest.next())),
+            "--json" => json_output = true,
+            _ => {
+                eprintln!("Unknown argument: {arg}");
+                eprintln!("{USAGE}");
+                exit(1);
+            }
+        }
+    }
+
+    let workloads = [alu_loop(), memcpy_loop(), fib_recursion()];
+    let mut results = Vec::new();
+    for workload in &workloads {
+        for with_peripherals in [false, true] {
+            results.push(run_workload(workload, budget, with_peripherals));
+        }
+    }
+    let executed: u64 = results.iter().map(|r| u64::from(r.executed)).sum();
+    let seconds: f64 = results.iter().map(|r| r.seconds).sum();
+    let total_mips = executed as f64 / seconds / 1e6;
+    let slow: Vec<&BenchResult> = results
+        .iter()
+        .filter(|r| min_mips.is_some_and(|floor| r.mips < floor))
+        .collect();
+
+    if json_output {
+        let report = serde_json::json!({
+            "instructions": budget,
+            "results": results,
+            "total_mips": total_mips,
+            "min_mips": min_mips,
+            "passed": slow.is_empty(),
+        });
+        println!("{report}");
+    } else {
+        println!("🐈 Nekov micro-benchmark ({budget} instructions per run)");
+        println!();
+        println!(
+            "{:<10} {:<12} {:>12} {:>10} {:>10}",
+            "workload", "path", "executed", "seconds", "MIPS"
+        );
+        for r in &results {
+            println!(
+                "{:<10} {:<12} {:>12} {:>10.3} {:>10.2}",
+                r.workload, r.path, r.executed, r.seconds, r.mips
+            );
+        }
+        println!();
+        println!("Total: {executed} instructions in {seconds:.3}s ({total_mips:.2} MIPS)");
+        for r in &slow {
+            println!(
+                "❌ {} ({}) below floor: {:.2} < {:.2} MIPS",
+                r.workload,
+                r.path,
+                r.mips,
+                min_mips.unwrap_or_default()
+            );
+        }
+    }
+
+    if !slow.is_empty() {
+        exit(1);
+    }
+}
@@ -1,38 +1,60 @@
+use nekov::riscv_tests::{binary_hash, default_cache_path, ResultsCache, TestResult};
 use std::env;
 use std::fs;
+use std::path::Path;
 use std::process::{exit, Command};
 
+const USAGE: &str =
+    "Usage: test_runner <emulator_path> <tests_dir> [--json] [--rerun-failed] [--no-cache] [-v|-vv|-vvv]";
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() < 3 {
-        eprintln!("Usage: test_runner <emulator_path> <tests_dir> [--json] [-v|-vv|-vvv]");
+        eprintln!("{USAGE}");
         exit(1);
     }
 
+    eprintln!("Note: test_runner is deprecated; use `nekov test <tests_dir>` instead");
+
     let emulator_path = &args[1];
     let tests_dir = &args[2];
 
     let mut json_output = false;
     let mut verbose_flag = None;
+    let mut rerun_failed = false;
+    let mut use_cache = true;
 
     // Parse remaining arguments
     for arg in &args[3..] {
         match arg.as_str() {
             "--json" => json_output = true,
+            "--rerun-failed" => rerun_failed = true,
+            "--no-cache" => use_cache = false,
             "-v" => verbose_flag = Some("-v"),
             "-vv" => verbose_flag = Some("-vv"),
             "-vvv" => verbose_flag = Some("-vvv"),
             _ => {
                 eprintln!("Unknown argument: {arg}");
-                eprintln!("Usage: test_runner <emulator_path> <tests_dir> [--json] [-v|-vv|-vvv]");
+                eprintln!("{USAGE}");
                 exit(1);
             }
         }
     }
 
+    if rerun_failed && !use_cache {
+        eprintln!("--rerun-failed needs the results cache; drop --no-cache");
+        exit(1);
+    }
+    let cache_path = use_cache.then(|| default_cache_path(Path::new(tests_dir)));
+    let mut cache = cache_path
+        .as_deref()
+        .map(ResultsCache::load)
+        .unwrap_or_default();
+
     let mut test_results = Vec::new();
     let mut total_tests = 0;
     let mut passed_tests = 0;
+    let mut cached_tests = 0;
 
     if !json_output {
         println!("🐈 Nekov RISC-V Test Runner");
@@ -72,6 +94,15 @@ fn main() {
 
         total_tests += 1;
 
+        // Passing tests whose binary is unchanged since the cached run are not rerun
+        let hash = binary_hash(&path).unwrap_or(0);
+        if rerun_failed && cache.reusable(&filename, hash).is_some() {
+            passed_tests += 1;
+            cached_tests += 1;
+            test_results.push((filename.to_string(), "PASS", String::new()));
+            continue;
+        }
+
         // Run the emulator on this test with riscv-tests mode
         let mut cmd = Command::new(emulator_path);
         cmd.arg("--riscv-tests").arg(&path);
@@ -103,15 +134,27 @@ fn main() {
             Err(e) => ("FAIL", format!("Failed to run: {e}")),
         };
 
+        let result = match status {
+            "PASS" => TestResult::Pass,
+            _ => TestResult::Error(result_msg.clone()),
+        };
+        cache.record(&filename, hash, result, None);
         test_results.push((filename.to_string(), status, result_msg));
     }
 
+    if let Some(path) = &cache_path {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Failed to write results cache {}: {e}", path.display());
+        }
+    }
+
     if json_output {
         // Output JSON format for machine processing
         println!("{{");
         println!("  \"total_tests\": {total_tests},");
         println!("  \"passed_tests\": {passed_tests},");
         println!("  \"failed_tests\": {},", total_tests - passed_tests);
+        println!("  \"cached_tests\": {cached_tests},");
         println!(
             "  \"pass_rate\": {:.2},",
             if total_tests > 0 {
@@ -158,6 +201,9 @@ fn main() {
                 0.0
             }
         );
+        if cached_tests > 0 {
+            println!("({cached_tests} passing results reused from the cache)");
+        }
 
         if passed_tests == total_tests {
             println!("🎉 All tests passed!");
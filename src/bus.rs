@@ -0,0 +1,155 @@
+/// Memory bus abstraction used by the CPU for loads, stores and atomics
+use crate::{memory::Memory, peripheral::PeripheralManager, EmulatorError, Result};
+
+/// Address space seen by the CPU when executing memory instructions
+///
+/// Reads take `&mut self` because memory-mapped devices may have read side effects.
+pub trait Bus {
+    /// Read a byte
+    fn read_byte(&mut self, address: u32) -> Result<u8>;
+
+    /// Read a little-endian halfword
+    fn read_halfword(&mut self, address: u32) -> Result<u16>;
+
+    /// Read a little-endian word
+    fn read_word(&mut self, address: u32) -> Result<u32>;
+
+    /// Write a byte
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<()>;
+
+    /// Write a little-endian halfword
+    fn write_halfword(&mut self, address: u32, value: u16) -> Result<()>;
+
+    /// Write a little-endian word
+    fn write_word(&mut self, address: u32, value: u32) -> Result<()>;
+
+    /// Whether atomic read-modify-write operations are allowed at `address`
+    fn supports_atomics(&self, _address: u32) -> bool {
+        true
+    }
+
+    /// Poison `len` bytes of RAM as a heap redzone (ignored without a RAM shadow map)
+    fn poison(&mut self, _address: u32, _len: u32) {}
+
+    /// Unpoison `len` bytes of RAM and record them as a heap allocation
+    fn add_allocation(&mut self, _address: u32, _len: u32) {}
+}
+
+impl Bus for Memory {
+    fn read_byte(&mut self, address: u32) -> Result<u8> {
+        Memory::read_byte(self, address)
+    }
+
+    fn read_halfword(&mut self, address: u32) -> Result<u16> {
+        Memory::read_u16_le(self, address)
+    }
+
+    fn read_word(&mut self, address: u32) -> Result<u32> {
+        Memory::read_u32_le(self, address)
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<()> {
+        Memory::write_byte(self, address, value)
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) -> Result<()> {
+        Memory::write_u16_le(self, address, value)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) -> Result<()> {
+        Memory::write_u32_le(self, address, value)
+    }
+
+    fn poison(&mut self, address: u32, len: u32) {
+        Memory::poison(self, address, len)
+    }
+
+    fn add_allocation(&mut self, address: u32, len: u32) {
+        Memory::add_allocation(self, address, len)
+    }
+}
+
+/// RAM plus memory-mapped peripherals
+///
+/// Peripherals support word accesses, byte accesses where the device has
+/// byte registers, and no atomics; other accesses to a peripheral address
+/// fail as unsupported.
+pub struct SystemBus<'a> {
+    pub memory: &'a mut Memory,
+    pub peripherals: &'a mut PeripheralManager,
+}
+
+impl<'a> SystemBus<'a> {
+    /// Combine memory and peripherals into a single bus
+    pub fn new(memory: &'a mut Memory, peripherals: &'a mut PeripheralManager) -> Self {
+        Self {
+            memory,
+            peripherals,
+        }
+    }
+
+    /// Reject a halfword access to a peripheral address
+    fn check_narrow(&self, address: u32) -> Result<()> {
+        if self.peripherals.is_peripheral_address(address) {
+            Err(EmulatorError::UnsupportedInstruction)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Bus for SystemBus<'_> {
+    fn read_byte(&mut self, address: u32) -> Result<u8> {
+        if self.peripherals.is_peripheral_address(address) {
+            self.peripherals.read_byte(address)
+        } else {
+            self.memory.read_byte(address)
+        }
+    }
+
+    fn read_halfword(&mut self, address: u32) -> Result<u16> {
+        self.check_narrow(address)?;
+        self.memory.read_u16_le(address)
+    }
+
+    fn read_word(&mut self, address: u32) -> Result<u32> {
+        if self.peripherals.is_peripheral_address(address) {
+            self.peripherals.read(address)
+        } else {
+            self.memory.read_u32_le(address)
+        }
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<()> {
+        if self.peripherals.is_peripheral_address(address) {
+            self.peripherals.write_byte(address, value)
+        } else {
+            self.memory.write_byte(address, value)
+        }
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) -> Result<()> {
+        self.check_narrow(address)?;
+        self.memory.write_u16_le(address, value)
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) -> Result<()> {
+        if self.peripherals.is_peripheral_address(address) {
+            self.peripherals.write(address, value)
+        } else {
+            self.memory.write_u32_le(address, value)
+        }
+    }
+
+    fn supports_atomics(&self, address: u32) -> bool {
+        !self.peripherals.is_peripheral_address(address)
+    }
+
+    fn poison(&mut self, address: u32, len: u32) {
+        self.memory.poison(address, len)
+    }
+
+    fn add_allocation(&mut self, address: u32, len: u32) {
+        self.memory.add_allocation(address, len)
+    }
+}
@@ -1,5 +1,15 @@
 /// RISC-V CPU implementation
-use crate::{memory::Memory, EmulatorError, Result};
+use crate::{
+    bus::{Bus, SystemBus},
+    htif::Htif,
+    memory::{AccessKind, Memory},
+    reg::Reg,
+    syscall::{EcallBehavior, SyscallAction},
+    trace_compare::{Divergence, TraceComparator, TraceRecord},
+    watch::Watch,
+    EmulatorError, ExitReason, Result,
+};
+use std::io::Write;
 
 /// Macro for verbose logging at different levels
 macro_rules! verbose_log {
@@ -32,69 +42,1757 @@ macro_rules! basic_log {
 }
 
 /// RISC-V register count (x0-x31)
-const NUM_REGISTERS: usize = 32;
+pub const NUM_REGISTERS: usize = 32;
+
+/// Output format of the per-instruction trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// Human-readable text, one line per instruction
+    #[default]
+    Text,
+    /// One JSON object per line with register old/new values and memory accesses
+    Jsonl,
+}
+
+impl TraceFormat {
+    /// Every trace format, in the order the CLI lists them
+    pub const ALL: [TraceFormat; 2] = [TraceFormat::Text, TraceFormat::Jsonl];
+
+    /// Name used by `--trace-format`
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceFormat::Text => "text",
+            TraceFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Look up a `--trace-format` name; `json` is accepted as an alias of `jsonl`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(TraceFormat::Jsonl),
+            _ => Self::ALL.into_iter().find(|format| format.name() == name),
+        }
+    }
+}
+
+/// ANSI escape wrapped around changed values in colored verbose output
+const DELTA_COLOR: (&str, &str) = ("\x1b[1;33m", "\x1b[0m");
+
+/// `-vv` lines for one step: `x{n}(abi): old -> new` per changed register,
+/// then `mem[address] <- value` for a store (`width` bytes)
+pub fn format_step_delta(
+    changes: &[(Reg, u32, u32)],
+    store: Option<(u32, u8, u32)>,
+    color: bool,
+) -> Vec<String> {
+    let (on, off) = if color { DELTA_COLOR } else { ("", "") };
+    let mut lines: Vec<String> = changes
+        .iter()
+        .map(|(reg, old, new)| {
+            format!(
+                "  x{}({reg}): 0x{old:08x} -> {on}0x{new:08x}{off}",
+                reg.index()
+            )
+        })
+        .collect();
+    if let Some((address, width, value)) = store {
+        let digits = usize::from(width) * 2;
+        lines.push(format!(
+            "  mem[0x{address:08x}] <- {on}0x{value:0digits$x}{off}"
+        ));
+    }
+    lines
+}
+
+/// Machine state captured before a traced instruction executes
+struct TracePoint {
+    pc: u32,
+    instruction: u32,
+    /// Destination register and its value before the instruction
+    rd: Option<(usize, u32)>,
+    /// Load/store address and access width in bytes
+    mem: Option<(u32, u8)>,
+    /// Value written by a store
+    store_value: Option<u32>,
+}
+
+/// What a single step did, as returned by `Cpu::step_info`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StepInfo {
+    /// Address of the executed instruction
+    pub pc: u32,
+    /// Raw instruction word
+    pub word: u32,
+    /// Disassembly of `word`
+    pub asm: String,
+    /// Register changed by the instruction, if any
+    pub write: Option<RegisterWrite>,
+    /// PC after the step
+    pub next_pc: u32,
+    /// Name of the exit reason if the step stopped execution
+    pub exit_reason: Option<&'static str>,
+}
+
+/// A register write performed by one instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct RegisterWrite {
+    pub reg: Reg,
+    pub old: u32,
+    pub new: u32,
+}
+
+/// A `jsonl` trace record; absent fields are omitted
+#[derive(serde::Serialize)]
+struct JsonlRecord {
+    i: u32,
+    pc: String,
+    insn: String,
+    asm: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rd: Option<JsonlRegister>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mem: Option<JsonlMemory>,
+}
+
+#[derive(serde::Serialize)]
+struct JsonlRegister {
+    x: usize,
+    old: String,
+    new: String,
+}
+
+#[derive(serde::Serialize)]
+struct JsonlMemory {
+    addr: String,
+    w: u8,
+    val: String,
+}
+
+/// Format a value as a 0x-prefixed, zero-padded hex string
+fn hex(value: u32) -> String {
+    format!("0x{value:08x}")
+}
+
+/// Mask a value to the low `width` bytes
+fn mask_width(value: u32, width: u8) -> u32 {
+    match width {
+        1 => value & 0xFF,
+        2 => value & 0xFFFF,
+        _ => value,
+    }
+}
+
+/// ECALL instruction word
+pub(crate) const ECALL: u32 = 0x0000_0073;
+
+/// Machine-mode CSR addresses used by trap handling
+pub const CSR_MSTATUS: u16 = 0x300;
+pub const CSR_MTVEC: u16 = 0x305;
+pub const CSR_MEPC: u16 = 0x341;
+pub const CSR_MCAUSE: u16 = 0x342;
+pub const CSR_MTVAL: u16 = 0x343;
+pub const CSR_MIE: u16 = 0x304;
+pub const CSR_MCOUNTEREN: u16 = 0x306;
+pub const CSR_SCOUNTEREN: u16 = 0x106;
+pub const CSR_MIP: u16 = 0x344;
+pub const CSR_MHARTID: u16 = 0xF14;
+pub const CSR_MISA: u16 = 0x301;
+pub const CSR_MCYCLE: u16 = 0xB00;
+pub const CSR_MINSTRET: u16 = 0xB02;
+pub const CSR_MCYCLEH: u16 = 0xB80;
+pub const CSR_MINSTRETH: u16 = 0xB82;
+pub const CSR_CYCLE: u16 = 0xC00;
+pub const CSR_TIME: u16 = 0xC01;
+pub const CSR_INSTRET: u16 = 0xC02;
+pub const CSR_CYCLEH: u16 = 0xC80;
+pub const CSR_TIMEH: u16 = 0xC81;
+pub const CSR_INSTRETH: u16 = 0xC82;
+
+/// Named CSRs, for code that would otherwise pass raw addresses to `read_csr`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Csr {
+    Mstatus,
+    Misa,
+    Mie,
+    Mtvec,
+    Mcounteren,
+    Mscratch,
+    Mepc,
+    Mcause,
+    Mtval,
+    Mip,
+    Mhartid,
+    Scounteren,
+    Mcycle,
+    Minstret,
+    Mcycleh,
+    Minstreth,
+    Cycle,
+    Time,
+    Instret,
+    Cycleh,
+    Timeh,
+    Instreth,
+}
+
+impl Csr {
+    /// Every named CSR, in address order within each privilege group
+    pub const ALL: [Csr; 22] = [
+        Csr::Mstatus,
+        Csr::Misa,
+        Csr::Mie,
+        Csr::Mtvec,
+        Csr::Mcounteren,
+        Csr::Mscratch,
+        Csr::Mepc,
+        Csr::Mcause,
+        Csr::Mtval,
+        Csr::Mip,
+        Csr::Mhartid,
+        Csr::Scounteren,
+        Csr::Mcycle,
+        Csr::Minstret,
+        Csr::Mcycleh,
+        Csr::Minstreth,
+        Csr::Cycle,
+        Csr::Time,
+        Csr::Instret,
+        Csr::Cycleh,
+        Csr::Timeh,
+        Csr::Instreth,
+    ];
+
+    /// The CSR's 12-bit address
+    pub fn address(self) -> u16 {
+        match self {
+            Csr::Mstatus => CSR_MSTATUS,
+            Csr::Misa => CSR_MISA,
+            Csr::Mie => CSR_MIE,
+            Csr::Mtvec => CSR_MTVEC,
+            Csr::Mcounteren => CSR_MCOUNTEREN,
+            Csr::Mscratch => 0x340,
+            Csr::Mepc => CSR_MEPC,
+            Csr::Mcause => CSR_MCAUSE,
+            Csr::Mtval => CSR_MTVAL,
+            Csr::Mip => CSR_MIP,
+            Csr::Mhartid => CSR_MHARTID,
+            Csr::Scounteren => CSR_SCOUNTEREN,
+            Csr::Mcycle => CSR_MCYCLE,
+            Csr::Minstret => CSR_MINSTRET,
+            Csr::Mcycleh => CSR_MCYCLEH,
+            Csr::Minstreth => CSR_MINSTRETH,
+            Csr::Cycle => CSR_CYCLE,
+            Csr::Time => CSR_TIME,
+            Csr::Instret => CSR_INSTRET,
+            Csr::Cycleh => CSR_CYCLEH,
+            Csr::Timeh => CSR_TIMEH,
+            Csr::Instreth => CSR_INSTRETH,
+        }
+    }
+
+    /// Assembler name, e.g. "mstatus"
+    pub fn name(self) -> &'static str {
+        match self {
+            Csr::Mstatus => "mstatus",
+            Csr::Misa => "misa",
+            Csr::Mie => "mie",
+            Csr::Mtvec => "mtvec",
+            Csr::Mcounteren => "mcounteren",
+            Csr::Mscratch => "mscratch",
+            Csr::Mepc => "mepc",
+            Csr::Mcause => "mcause",
+            Csr::Mtval => "mtval",
+            Csr::Mip => "mip",
+            Csr::Mhartid => "mhartid",
+            Csr::Scounteren => "scounteren",
+            Csr::Mcycle => "mcycle",
+            Csr::Minstret => "minstret",
+            Csr::Mcycleh => "mcycleh",
+            Csr::Minstreth => "minstreth",
+            Csr::Cycle => "cycle",
+            Csr::Time => "time",
+            Csr::Instret => "instret",
+            Csr::Cycleh => "cycleh",
+            Csr::Timeh => "timeh",
+            Csr::Instreth => "instreth",
+        }
+    }
+
+    /// The named CSR at `address`, if any
+    pub fn from_address(address: u16) -> Option<Csr> {
+        Csr::ALL.into_iter().find(|csr| csr.address() == address)
+    }
+
+    /// The CSR with assembler name `name`, if any
+    pub fn from_name(name: &str) -> Option<Csr> {
+        Csr::ALL.into_iter().find(|csr| csr.name() == name)
+    }
+}
+
+impl std::fmt::Display for Csr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// misa letter bits of the single-letter extensions
+pub const MISA_A: u32 = 1 << 0;
+pub const MISA_I: u32 = 1 << 8;
+pub const MISA_M: u32 = 1 << 12;
+
+/// misa letter bit of C; not implemented, but it sets the `mepc` alignment if enabled
+const MISA_C: u32 = 1 << 2;
+
+/// misa MXL field for XLEN = 32
+const MISA_MXL_32: u32 = 1 << 30;
+
+/// An ISA extension implemented by the decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extension {
+    /// Canonical name, e.g. `"M"` or `"Zicsr"`
+    pub name: &'static str,
+    /// misa letter bit; 0 for extensions misa cannot describe (always enabled)
+    pub misa_bit: u32,
+    /// Mnemonics the extension adds, as `disasm` prints them
+    pub mnemonics: &'static [&'static str],
+}
+
+/// Every extension the decoder implements, in canonical ISA string order
+///
+/// misa, `Cpu::extensions`, `Cpu::instructions` and `nekov::capabilities`
+/// are all derived from this table, so adding an extension here is what
+/// makes it visible.
+pub const EXTENSIONS: [Extension; 6] = [
+    Extension {
+        name: "I",
+        misa_bit: MISA_I,
+        mnemonics: &[
+            "lui",
+            "auipc",
+            "jal",
+            "jalr",
+            "beq",
+            "bne",
+            "blt",
+            "bge",
+            "bltu",
+            "bgeu",
+            "lb",
+            "lh",
+            "lw",
+            "lbu",
+            "lhu",
+            "sb",
+            "sh",
+            "sw",
+            "addi",
+            "slti",
+            "sltiu",
+            "xori",
+            "ori",
+            "andi",
+            "slli",
+            "srli",
+            "srai",
+            "add",
+            "sub",
+            "sll",
+            "slt",
+            "sltu",
+            "xor",
+            "srl",
+            "sra",
+            "or",
+            "and",
+            "fence",
+            "fence.tso",
+            "ecall",
+            "ebreak",
+            "c.ebreak",
+            // Machine-mode privileged instructions, always present
+            "mret",
+            "wfi",
+            "sfence.vma",
+        ],
+    },
+    Extension {
+        name: "M",
+        misa_bit: MISA_M,
+        mnemonics: &[
+            "mul", "mulh", "mulhsu", "mulhu", "div", "divu", "rem", "remu",
+        ],
+    },
+    Extension {
+        name: "A",
+        misa_bit: MISA_A,
+        mnemonics: &[
+            "lr.w",
+            "sc.w",
+            "amoswap.w",
+            "amoadd.w",
+            "amoxor.w",
+            "amoand.w",
+            "amoor.w",
+            "amomin.w",
+            "amomax.w",
+            "amominu.w",
+            "amomaxu.w",
+        ],
+    },
+    Extension {
+        name: "Zicsr",
+        misa_bit: 0,
+        mnemonics: &["csrrw", "csrrs", "csrrc", "csrrwi", "csrrsi", "csrrci"],
+    },
+    Extension {
+        name: "Zifencei",
+        misa_bit: 0,
+        mnemonics: &["fence.i"],
+    },
+    Extension {
+        name: "Zihintpause",
+        misa_bit: 0,
+        mnemonics: &["pause"],
+    },
+];
+
+/// mstatus fields
+const MSTATUS_MIE: u32 = 1 << 3;
+const MSTATUS_MPIE: u32 = 1 << 7;
+const MSTATUS_MPP: u32 = 0b11 << 11;
+const MSTATUS_MPRV: u32 = 1 << 17;
+
+/// Machine software, timer and external interrupt pending bits of `mip`
+pub const MIP_MSIP: u32 = 1 << 3;
+pub const MIP_MTIP: u32 = 1 << 7;
+pub const MIP_MEIP: u32 = 1 << 11;
+
+/// `mip` bits driven by peripheral interrupt lines; read-only to guest CSR writes
+const MIP_LINES: u32 = MIP_MSIP | MIP_MTIP | MIP_MEIP;
+
+/// Machine-level interrupt lines the host can raise with `Cpu::raise_interrupt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    MachineSoftware,
+    MachineTimer,
+    MachineExternal,
+}
+
+impl Interrupt {
+    /// The interrupt's pending bit in `mip`
+    pub fn mip_bit(self) -> u32 {
+        match self {
+            Interrupt::MachineSoftware => MIP_MSIP,
+            Interrupt::MachineTimer => MIP_MTIP,
+            Interrupt::MachineExternal => MIP_MEIP,
+        }
+    }
+}
+
+/// Interrupt causes in priority order (external, software, timer)
+const INTERRUPT_PRIORITY: [u32; 3] = [11, 3, 7];
+
+/// mcause bit set for interrupts
+pub const CAUSE_INTERRUPT: u32 = 1 << 31;
+
+/// mcause for an illegal instruction
+pub const CAUSE_ILLEGAL_INSTRUCTION: u32 = 2;
+
+/// mcause for a load access fault (loads of never-written memory under `UninitPolicy::Trap`)
+pub const CAUSE_LOAD_ACCESS_FAULT: u32 = 5;
+
+/// mcause for an environment call from U-mode
+pub const CAUSE_ECALL_FROM_U: u32 = 8;
+
+/// mcause for an environment call from S-mode
+pub const CAUSE_ECALL_FROM_S: u32 = 9;
+
+/// mcause for an environment call from M-mode
+pub const CAUSE_ECALL_FROM_M: u32 = 11;
+
+/// Nested traps allowed before a run stops with `ExitReason::TrapLoop`
+pub const DEFAULT_MAX_TRAP_DEPTH: u32 = 8;
+
+/// Retired instructions between checks of the time budget, unless set with `Cpu::set_time_budget_with_interval`
+pub const TIME_CHECK_INTERVAL: u32 = 4096;
+
+/// First multiple of `interval` above `executed` (saturating at `u32::MAX`)
+fn next_boundary(executed: u32, interval: u32) -> u32 {
+    (executed / interval)
+        .saturating_add(1)
+        .saturating_mul(interval)
+}
+
+/// `unimp` as emitted by assemblers: CSRRW x0, cycle, x0
+const UNIMP: u32 = 0xC000_1073;
+
+/// Canonical NOP (`addi x0, x0, 0`), the only computational instruction writing x0 that is not a hint
+const NOP: u32 = 0x0000_0013;
+pub(crate) const EBREAK: u32 = 0x0010_0073;
+/// Compressed breakpoint, the 2-byte form debuggers plant in compressed code
+pub(crate) const C_EBREAK: u32 = 0x9002;
+pub(crate) const MRET: u32 = 0x3020_0073;
+pub(crate) const WFI: u32 = 0x1050_0073;
+/// FENCE.TSO (fm=1000, pred=succ=RW)
+pub(crate) const FENCE_TSO: u32 = 0x8330_000F;
+/// PAUSE hint (Zihintpause): FENCE with pred=W, succ=0
+pub(crate) const PAUSE: u32 = 0x0100_000F;
+
+/// Whether `instruction` is SFENCE.VMA (any rs1/rs2)
+pub(crate) fn is_sfence_vma(instruction: u32) -> bool {
+    instruction & 0xFE00_7FFF == 0x1200_0073
+}
+
+/// Why a 32-bit encoding is a hint or uses reserved fields, or `None` if it is exactly architected
+///
+/// Only encodings a lenient decode would otherwise accept need to be caught here.
+pub fn strict_decode_violation(instruction: u32) -> Option<&'static str> {
+    if instruction & 0b11 != 0b11 {
+        return None;
+    }
+    let opcode = instruction & 0x7F;
+    let rd = (instruction >> 7) & 0x1F;
+    let funct3 = (instruction >> 12) & 0x7;
+    let rs1 = (instruction >> 15) & 0x1F;
+    let rs2 = (instruction >> 20) & 0x1F;
+    match opcode {
+        0x13 | 0x33 | 0x37 | 0x17 if rd == 0 && instruction != NOP => {
+            Some("hint: computational instruction writing x0")
+        }
+        0x0F if funct3 == 0 => {
+            let fm = instruction >> 28;
+            let pred = (instruction >> 24) & 0xF;
+            let succ = (instruction >> 20) & 0xF;
+            if instruction == PAUSE {
+                None
+            } else if rd != 0 || rs1 != 0 {
+                Some("FENCE with nonzero rd/rs1")
+            } else if fm == 0b1000 && pred == 0b0011 && succ == 0b0011 {
+                None // FENCE.TSO
+            } else if fm != 0 {
+                Some("FENCE with reserved fm")
+            } else if pred == 0 || succ == 0 {
+                Some("hint: FENCE with an empty predecessor or successor set")
+            } else {
+                None
+            }
+        }
+        0x0F if funct3 == 1 && instruction & !0x707F != 0 => {
+            Some("FENCE.I with nonzero imm/rs1/rd")
+        }
+        0x73 if funct3 == 0
+            && !matches!(instruction, ECALL | EBREAK | MRET | WFI)
+            && !is_sfence_vma(instruction) =>
+        {
+            Some("reserved SYSTEM encoding")
+        }
+        0x2F => {
+            let funct5 = instruction >> 27;
+            if !matches!(
+                funct5,
+                0x00 | 0x01 | 0x02 | 0x03 | 0x04 | 0x08 | 0x0C | 0x10 | 0x14 | 0x18 | 0x1C
+            ) {
+                Some("reserved AMO funct5")
+            } else if funct5 == 0x02 && rs2 != 0 {
+                Some("LR.W with nonzero rs2")
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Check for the canonical `unimp` encodings (32-bit form, or compressed all-zero halfword)
+pub(crate) fn is_unimp(instruction: u32) -> bool {
+    instruction == UNIMP || instruction & 0xFFFF == 0
+}
+
+/// JAL/JALR linking to ra (a function call)
+fn is_call(instruction: u32) -> bool {
+    let opcode = instruction & 0x7F;
+    (opcode == 0x6F || opcode == 0x67) && (instruction >> 7) & 0x1F == 1
+}
+
+/// `jalr x0, 0(ra)` and other JALR through ra that discard the link (a function return)
+fn is_return(instruction: u32) -> bool {
+    instruction & 0x7F == 0x67 && (instruction >> 7) & 0x1F == 0 && (instruction >> 15) & 0x1F == 1
+}
+
+/// Stop condition installed by the `run_until_*` helpers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RunTarget {
+    /// Stop when the PC reaches this address
+    Pc(u32),
+    /// Stop after the return leaving the current function; counts calls made since
+    Return { open_calls: u32 },
+}
+
+/// Hook observing or virtualizing CSR accesses
+///
+/// Returning `Some` from either method overrides the architectural behavior.
+pub trait CsrHook {
+    /// Called on every CSR read with the stored value; `Some(v)` makes the read return `v`
+    fn on_read(&mut self, _csr: u16, _value: u32) -> Option<u32> {
+        None
+    }
+
+    /// Called on every CSR write; `Some(v)` stores `v` instead of `new`
+    fn on_write(&mut self, _csr: u16, _old: u32, _new: u32) -> Option<u32> {
+        None
+    }
+}
+
+/// Progress of a run, passed to the progress callback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Instructions retired so far in this run
+    pub retired: u32,
+    /// PC of the next instruction
+    pub pc: u32,
+    /// Wall-clock time since the run started
+    pub elapsed: std::time::Duration,
+}
+
+impl Progress {
+    /// Average speed of the run so far in millions of instructions per second
+    pub fn mips(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.retired as f64 / seconds / 1e6
+        } else {
+            0.0
+        }
+    }
+}
+
+/// What the run loop does after a progress report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressAction {
+    Continue,
+    /// Stop the run with `ExitReason::Cancelled`
+    Cancel,
+}
+
+/// Callback invoked periodically during a run; it can cancel the run
+pub type ProgressCallback = Box<dyn FnMut(&Progress) -> ProgressAction>;
+
+/// Host-side hooks attached to a CPU
+///
+/// Hooks belong to the host session rather than the architectural state, so
+/// they are not carried over when the CPU is cloned.
+#[derive(Default)]
+struct CpuHooks {
+    /// Trace sink and the format written to it
+    trace: Option<(TraceFormat, Box<dyn std::io::Write>)>,
+    /// CSR access hook (interior mutability so reads through `&self` can call it)
+    csr: Option<std::cell::RefCell<Box<dyn CsrHook>>>,
+    /// Reference trace every retired instruction is checked against
+    compare: Option<TraceComparator>,
+    /// Callback invoked every `interval` retired instructions, the start (ms) of the current run
+    /// and the retired count at which it is next due
+    progress: Option<(u32, ProgressCallback, f64, u32)>,
+    /// Watch conditions with the value each saw after the previous step
+    watches: Vec<(Watch, u32)>,
+    /// How ECALL is handled
+    ecall: EcallBehavior,
+    /// Stop condition of the running `run_until_*` helper
+    target: Option<RunTarget>,
+    /// HTIF console served on stores to `tohost`
+    htif: Option<Htif>,
+    /// Wall-clock budget of each run, the deadline (ms) of the current one, the check interval
+    /// and the retired count at which the clock is next read
+    time_budget: Option<(std::time::Duration, f64, u32, u32)>,
+}
+
+impl Clone for CpuHooks {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl std::fmt::Debug for CpuHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CpuHooks")
+            .field("trace", &self.trace.as_ref().map(|(format, _)| format))
+            .field("csr", &self.csr.is_some())
+            .field("compare", &self.compare.is_some())
+            .field(
+                "progress",
+                &self.progress.as_ref().map(|(interval, _, _, _)| interval),
+            )
+            .field("watches", &self.watches.len())
+            .field("ecall", &self.ecall)
+            .field("target", &self.target)
+            .field("htif", &self.htif)
+            .field(
+                "time_budget",
+                &self.time_budget.map(|(budget, _, _, _)| budget),
+            )
+            .finish()
+    }
+}
+
+/// Report a failed read of the instruction word as a fetch rather than a load
+fn as_fetch(error: EmulatorError) -> EmulatorError {
+    match error {
+        EmulatorError::MemoryAccessError { addr, reason, .. } => EmulatorError::MemoryAccessError {
+            addr,
+            kind: AccessKind::Fetch,
+            reason,
+        },
+        error => error,
+    }
+}
+
+/// RISC-V privilege level
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PrivMode {
+    User = 0,
+    Supervisor = 1,
+    #[default]
+    Machine = 3,
+}
+
+impl PrivMode {
+    /// The mode encoded in a 2-bit MPP field; the reserved value 2 reads as Machine
+    fn from_mpp(bits: u32) -> Self {
+        match bits & 0b11 {
+            0 => PrivMode::User,
+            1 => PrivMode::Supervisor,
+            _ => PrivMode::Machine,
+        }
+    }
+
+    /// mcause of an ECALL executed in this mode
+    pub fn ecall_cause(self) -> u32 {
+        match self {
+            PrivMode::User => CAUSE_ECALL_FROM_U,
+            PrivMode::Supervisor => CAUSE_ECALL_FROM_S,
+            PrivMode::Machine => CAUSE_ECALL_FROM_M,
+        }
+    }
+}
+
+/// What strict decode does with a reserved or hint encoding
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StrictDecodeAction {
+    /// Stop with `UnsupportedInstruction`
+    #[default]
+    Fault,
+    /// Report the encoding (to the trace sink, or stderr) and execute it leniently
+    Warn,
+}
+
+/// What `detect_smc` does when modified code is fetched without a FENCE.I since the store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SmcAction {
+    /// Report the fetch (to the trace sink, or stderr) and keep running
+    #[default]
+    Warn,
+    /// Stop with `EmulatorError::UnfencedCode`
+    Fault,
+}
+
+/// A store that overwrote an already-executed instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeModification {
+    /// Address of the store instruction
+    pub store_pc: u32,
+    /// Address of the overwritten instruction
+    pub address: u32,
+    /// Instruction word before the store
+    pub old: u32,
+}
+
+impl std::fmt::Display for CodeModification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "instruction at 0x{:08x} (was 0x{:08x}) modified by the store at pc 0x{:08x} and fetched without FENCE.I",
+            self.address, self.old, self.store_pc
+        )
+    }
+}
+
+/// Executed pages/instructions are tracked in 4 KiB pages so stores elsewhere cost one lookup
+const SMC_PAGE_SHIFT: u32 = 12;
+
+/// Executed-code tracking for `detect_smc`
+#[derive(Debug, Clone, Default)]
+struct SmcDetector {
+    action: SmcAction,
+    /// Pages (address >> `SMC_PAGE_SHIFT`) holding executed instructions
+    pages: std::collections::HashSet<u32>,
+    /// Addresses of executed instructions
+    executed: std::collections::HashSet<u32>,
+    /// Overwritten executed instructions not followed by a FENCE.I yet
+    modified: std::collections::HashMap<u32, CodeModification>,
+}
+
+/// Polling-loop tracking for `set_idle_skip`
+#[derive(Debug, Clone, Default)]
+struct IdleDetector {
+    /// Most cycles fast-forwarded at once
+    max_skip: u64,
+    /// Taken backward branch that closed the last loop iteration
+    branch_pc: u32,
+    /// Registers when that branch was taken
+    registers: [u32; NUM_REGISTERS],
+    /// Bit `i` set: xi holds a value read from a peripheral, or computed from one
+    volatile: u32,
+    /// `instret` when that branch was taken
+    instret: u64,
+    /// No store, AMO or SYSTEM instruction has executed since that branch
+    clean: bool,
+    /// Instructions credited by fast-forwarding so far
+    skipped: u64,
+}
+
+impl IdleDetector {
+    /// Update `volatile` for the destination register of `instruction`
+    fn track_volatile(&mut self, instruction: u32, device_load: bool) {
+        if instruction & 0b11 != 0b11 {
+            // Compressed: destination not tracked, compare every register
+            self.volatile = 0;
+            return;
+        }
+        let rd = (instruction >> 7) & 0x1F;
+        let rs1 = self.volatile >> ((instruction >> 15) & 0x1F) & 1;
+        let rs2 = self.volatile >> ((instruction >> 20) & 0x1F) & 1;
+        let tainted = match instruction & 0x7F {
+            0x03 => device_load,
+            0x13 => rs1 != 0,
+            0x33 => rs1 | rs2 != 0,
+            _ => false,
+        };
+        if tainted && rd != 0 {
+            self.volatile |= 1 << rd;
+        } else {
+            self.volatile &= !(1 << rd);
+        }
+    }
+}
+
+/// When taken jumps and branches are checked for landing in unwritten memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JumpCheck {
+    /// Check when stepping at verbosity 1 or higher
+    #[default]
+    Verbose,
+    Always,
+    Never,
+}
+
+/// Number of PCs kept for the `WildJump` diagnostic
+const JUMP_HISTORY_LEN: usize = 8;
+
+/// Most control transfers `branch_trace` keeps; older ones are dropped
+pub const BRANCH_TRACE_LEN: usize = 65536;
+
+/// A JAL, JALR or taken branch to an address that was never written or loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WildJump {
+    /// Address jumped to
+    pub target: u32,
+    /// Address of the jump instruction
+    pub pc: u32,
+    /// PCs executed up to and including the jump, oldest first
+    pub history: Vec<u32>,
+}
+
+impl std::fmt::Display for WildJump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "jump to unmapped address 0x{:08x} from PC 0x{:08x}",
+            self.target, self.pc
+        )?;
+        if !self.history.is_empty() {
+            write!(f, " (recent PCs:")?;
+            for pc in &self.history {
+                write!(f, " 0x{pc:08x}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// An sp-relative store below the configured stack region (see `Cpu::set_stack_region`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackOverflow {
+    /// Address of the store instruction
+    pub pc: u32,
+    /// Stack pointer at the store
+    pub sp: u32,
+    /// Lowest address of the stack region
+    pub guard: u32,
+    /// Address the store would have written
+    pub address: u32,
+}
+
+impl std::fmt::Display for StackOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stack overflow (sp=0x{:08x}, guard=0x{:08x}): store to 0x{:08x} at pc 0x{:08x}",
+            self.sp, self.guard, self.address, self.pc
+        )
+    }
+}
+
+/// Construction-time CPU configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuConfig {
+    /// PC after construction and `reset`
+    pub reset_vector: u32,
+    /// Only accept the exact architected RV32IMA encodings (no hints or reserved fields)
+    pub strict_decode: bool,
+    /// What to do with encodings rejected by `strict_decode`
+    pub strict_decode_action: StrictDecodeAction,
+    /// Stop with `WildJump` when control transfers to unwritten memory
+    pub jump_check: JumpCheck,
+    /// misa bits of extensions to leave out (e.g. `MISA_M`); the base ISA stays enabled
+    pub disabled_extensions: u32,
+    /// Nested traps allowed without an MRET (None: `DEFAULT_MAX_TRAP_DEPTH`)
+    pub max_trap_depth: Option<u32>,
+}
+
+/// RISC-V CPU state
+#[derive(Debug, Clone)]
+pub struct Cpu {
+    /// General-purpose registers (x0-x31)
+    pub registers: [u32; NUM_REGISTERS],
+    /// Program counter
+    pub pc: u32,
+    /// Control and Status Registers (CSRs)
+    /// For simplicity, we'll store only the most common ones
+    pub csrs: std::collections::HashMap<u16, u32>,
+    /// Stop the run loop at EBREAK instead of treating it as unsupported
+    pub breakpoint_mode: bool,
+    /// Why the most recent run loop stopped (None while running or after an error)
+    pub exit_reason: Option<ExitReason>,
+    /// Decode cache of fetched instruction words keyed by PC (None when disabled)
+    icache: Option<std::collections::HashMap<u32, u32>>,
+    /// Executed-code tracking for `detect_smc` (None when disabled)
+    smc: Option<SmcDetector>,
+    /// Fast-forwarding of interrupt-bound polling loops (`set_idle_skip`)
+    idle: Option<IdleDetector>,
+    /// PC after construction and `reset`
+    reset_vector: u32,
+    /// Strict decode action, if strict decode is enabled
+    strict_decode: Option<StrictDecodeAction>,
+    /// When jump targets are checked against written memory
+    jump_check: JumpCheck,
+    /// Lowest address sp-relative stores may write (`set_stack_region`)
+    stack_guard: Option<u32>,
+    /// Recent PCs while jump checking or history recording is active, oldest first
+    jump_history: std::collections::VecDeque<u32>,
+    /// Record recent PCs even when jump checks are off (`record_pc_history`)
+    record_history: bool,
+    /// Highlight changed values in the verbose step output with ANSI colors
+    color: bool,
+    /// `(pc, csr, old, new)` of every CSR write while `enable_csr_trace` is on
+    csr_trace: Option<Vec<(u32, u16, u32, u32)>>,
+    /// `(src, dst, taken)` of recent branches and jumps while `enable_branch_trace` is on
+    branch_trace: Option<std::collections::VecDeque<(u32, u32, bool)>>,
+    /// Whether the last conditional branch executed was taken
+    branch_taken: bool,
+    /// Interrupt lines held high by the host (`raise_interrupt`), as `mip` bits
+    raised_interrupts: u32,
+    /// `(mcause, mepc)` of each trap taken and not yet returned from with MRET
+    trap_chain: Vec<(u32, u32)>,
+    /// Word address reserved by the last LR.W, until SC.W, a trap or a snooped store
+    reservation: Option<u32>,
+    /// Nesting beyond which a trap stops the run with `TrapLoop`
+    max_trap_depth: u32,
+    /// Current privilege level (Machine after reset and traps, lowered by MRET)
+    privilege: PrivMode,
+    /// misa letter bits of the enabled extensions
+    misa_extensions: u32,
+    /// 64-bit cycle counter behind `mcycle`/`cycle` and `time` (one cycle per instruction)
+    cycle: u64,
+    /// 64-bit retired-instruction counter behind `minstret`/`instret`
+    instret: u64,
+    /// Host-side hooks (trace sink, ...)
+    hooks: CpuHooks,
+}
+
+impl Cpu {
+    /// Create a new CPU instance
+    pub fn new() -> Self {
+        Self::with_config(CpuConfig::default())
+    }
+
+    /// Create a CPU with the given configuration
+    pub fn with_config(config: CpuConfig) -> Self {
+        let misa_extensions = EXTENSIONS
+            .iter()
+            .fold(0, |bits, extension| bits | extension.misa_bit)
+            & (!config.disabled_extensions | MISA_I);
+        Self {
+            registers: [0; NUM_REGISTERS],
+            pc: config.reset_vector,
+            csrs: Self::initial_csrs(MISA_MXL_32 | misa_extensions),
+            breakpoint_mode: false,
+            exit_reason: None,
+            icache: None,
+            smc: None,
+            idle: None,
+            reset_vector: config.reset_vector,
+            strict_decode: config.strict_decode.then_some(config.strict_decode_action),
+            jump_check: config.jump_check,
+            stack_guard: None,
+            jump_history: std::collections::VecDeque::with_capacity(JUMP_HISTORY_LEN),
+            record_history: false,
+            color: false,
+            csr_trace: None,
+            branch_trace: None,
+            branch_taken: false,
+            raised_interrupts: 0,
+            trap_chain: Vec::new(),
+            reservation: None,
+            max_trap_depth: config.max_trap_depth.unwrap_or(DEFAULT_MAX_TRAP_DEPTH),
+            privilege: PrivMode::Machine,
+            misa_extensions,
+            cycle: 0,
+            instret: 0,
+            hooks: CpuHooks::default(),
+        }
+    }
+
+    /// Builder: start execution (and restart after `reset`) at `address`
+    pub fn with_reset_vector(mut self, address: u32) -> Self {
+        self.set_reset_vector(address);
+        self.pc = address;
+        self
+    }
+
+    /// Address `reset` puts the PC back to
+    pub fn reset_vector(&self) -> u32 {
+        self.reset_vector
+    }
+
+    /// Change the address `reset` puts the PC back to; the current PC is unchanged
+    pub fn set_reset_vector(&mut self, address: u32) {
+        self.reset_vector = address;
+    }
+
+    /// Names of the enabled extensions, in canonical ISA string order
+    pub fn extensions(&self) -> Vec<&'static str> {
+        EXTENSIONS
+            .iter()
+            .filter(|extension| self.has_extension(extension.misa_bit))
+            .map(|extension| extension.name)
+            .collect()
+    }
+
+    /// Mnemonics of the enabled extensions, in `EXTENSIONS` order
+    pub fn instructions(&self) -> Vec<&'static str> {
+        EXTENSIONS
+            .iter()
+            .filter(|extension| self.has_extension(extension.misa_bit))
+            .flat_map(|extension| extension.mnemonics.iter().copied())
+            .collect()
+    }
+
+    /// Reset value of misa: MXL = 32 plus the enabled extension letters
+    pub fn misa(&self) -> u32 {
+        MISA_MXL_32 | self.misa_extensions
+    }
+
+    /// Whether the extension with misa letter bit `misa_bit` is enabled
+    pub fn has_extension(&self, misa_bit: u32) -> bool {
+        self.misa_extensions & misa_bit == misa_bit
+    }
+
+    /// CSRs present after reset: misa as given, the rest zero
+    fn initial_csrs(misa: u32) -> std::collections::HashMap<u16, u32> {
+        let mut csrs = std::collections::HashMap::new();
+        // Initialize commonly used CSRs
+        csrs.insert(CSR_MISA, misa); // misa - ISA and extensions
+        csrs.insert(0xF14, 0); // mhartid - hardware thread ID
+        csrs.insert(0x300, 0); // mstatus - machine status
+        csrs.insert(0x341, 0); // mepc - machine exception program counter
+        csrs.insert(0x342, 0); // mcause - machine trap cause
+        csrs.insert(0x343, 0); // mtval - machine trap value
+        csrs.insert(0x344, 0); // mip - machine interrupt pending
+        csrs.insert(0x304, 0); // mie - machine interrupt enable
+        csrs.insert(0x305, 0); // mtvec - machine trap-handler base address
+        csrs.insert(0x340, 0); // mscratch - machine scratch register
+        csrs.insert(0x306, 0); // mcounteren - counter access below M-mode
+        csrs.insert(0x106, 0); // scounteren - counter access in U-mode
+        csrs.insert(0xF11, 0); // mvendorid - vendor ID
+        csrs.insert(0xF12, 0); // marchid - architecture ID
+        csrs.insert(0xF13, 0); // mimpid - implementation ID
+
+        // cycle/time/instret and their high halves are backed by `cycle` and `instret`
+        csrs
+    }
+
+    /// Reset the CPU to initial state, with the PC at the reset vector
+    pub fn reset(&mut self) {
+        self.registers = [0; NUM_REGISTERS];
+        self.pc = self.reset_vector;
+        self.csrs = Self::initial_csrs(self.misa());
+        self.privilege = PrivMode::Machine;
+        self.cycle = 0;
+        self.instret = 0;
+        self.exit_reason = None;
+        self.jump_history.clear();
+        self.trap_chain.clear();
+        self.reservation = None;
+        self.flush_icache();
+    }
+
+    /// Enable or disable the instruction decode cache
+    pub fn set_icache_enabled(&mut self, enabled: bool) {
+        self.icache = if enabled {
+            Some(std::collections::HashMap::new())
+        } else {
+            None
+        };
+    }
+
+    /// Check whether the instruction decode cache is enabled
+    pub fn icache_enabled(&self) -> bool {
+        self.icache.is_some()
+    }
+
+    /// Stop with `StackOverflow` when an sp-relative store falls below `top - size`
+    ///
+    /// Only stores addressed through sp are checked, so data and heap stores
+    /// below the stack are unaffected. The check is one compare per store.
+    pub fn set_stack_region(&mut self, top: u32, size: u32) {
+        self.stack_guard = Some(top.wrapping_sub(size));
+    }
+
+    /// Keep the PCs of recently executed instructions even when jump checks are off
+    pub fn record_pc_history(&mut self, enabled: bool) {
+        self.record_history = enabled;
+    }
+
+    /// Record every CSR write as `(pc, csr, old, new)`; turning it off drops the trace
+    ///
+    /// Writes by CSR instructions, trap entry, MRET, interrupt lines latched
+    /// into `mip` and host calls to `write_csr` are all recorded, in order.
+    pub fn enable_csr_trace(&mut self, enabled: bool) {
+        if !enabled {
+            self.csr_trace = None;
+        } else if self.csr_trace.is_none() {
+            self.csr_trace = Some(Vec::new());
+        }
+    }
+
+    /// CSR writes recorded since `enable_csr_trace(true)`, oldest first
+    pub fn csr_trace(&self) -> &[(u32, u16, u32, u32)] {
+        self.csr_trace.as_deref().unwrap_or_default()
+    }
+
+    /// Highlight new values in the `-vv` register and memory deltas with ANSI colors
+    pub fn set_color(&mut self, enabled: bool) {
+        self.color = enabled;
+    }
+
+    /// Register file and pending store captured before a step, for `log_step_delta`
+    fn delta_prepare(&self, memory: &Memory) -> ([u32; NUM_REGISTERS], Option<(u32, u8, u32)>) {
+        let store = match self.memory_operand(memory.peek_word(self.pc)) {
+            (Some((address, width)), Some(value)) => Some((address, width, value)),
+            _ => None,
+        };
+        (self.registers_snapshot(), store)
+    }
+
+    /// Print the registers a step changed and the store it performed, one line each
+    fn log_step_delta(&self, (before, store): ([u32; NUM_REGISTERS], Option<(u32, u8, u32)>)) {
+        for line in format_step_delta(&self.diff_registers(&before), store, self.color) {
+            println!("{line}");
+        }
+    }
+
+    /// Record `(src_pc, dst_pc, taken)` for every branch, JAL and JALR executed
+    ///
+    /// The last `BRANCH_TRACE_LEN` transfers are kept; jumps are always taken.
+    /// Turning it off drops the trace.
+    pub fn enable_branch_trace(&mut self, enabled: bool) {
+        if !enabled {
+            self.branch_trace = None;
+        } else if self.branch_trace.is_none() {
+            self.branch_trace = Some(std::collections::VecDeque::new());
+        }
+    }
+
+    /// Branches and jumps recorded since `enable_branch_trace(true)`, oldest first
+    pub fn branch_trace(&self) -> Vec<(u32, u32, bool)> {
+        self.branch_trace
+            .iter()
+            .flat_map(|trace| trace.iter().copied())
+            .collect()
+    }
+
+    /// Append the control transfer `instruction` at `pc` just made to the branch trace
+    fn record_branch(&mut self, pc: u32, instruction: u32) {
+        let Some(trace) = &mut self.branch_trace else {
+            return;
+        };
+        let taken = match instruction & 0x7F {
+            0x63 => self.branch_taken,
+            0x67 | 0x6F => true,
+            _ => return,
+        };
+        if trace.len() == BRANCH_TRACE_LEN {
+            trace.pop_front();
+        }
+        trace.push_back((pc, self.pc, taken));
+    }
+
+    /// PCs of the most recently executed instructions, oldest first
+    ///
+    /// Only recorded while jump checks are active or `record_pc_history` is on.
+    pub fn pc_history(&self) -> Vec<u32> {
+        self.jump_history.iter().copied().collect()
+    }
+
+    /// Report stores that overwrite executed instructions, such as ones held in the decode cache
+    ///
+    /// Without a following FENCE.I a cached (stale) instruction keeps
+    /// executing, so such stores usually mean buggy self-modifying code.
+    /// Shorthand for `detect_smc(Some(SmcAction::Warn))`; `false` disables it.
+    pub fn warn_on_smc(&mut self, enabled: bool) {
+        self.detect_smc(enabled.then_some(SmcAction::Warn));
+    }
+
+    /// Track executed instructions and report stores that overwrite them
+    ///
+    /// Each such store is logged with its PC and the overwritten instruction
+    /// (to the trace sink, or stderr). Fetching a modified instruction before a
+    /// FENCE.I is then handled per `action`. Works with or without the decode
+    /// cache; `None` disables tracking.
+    pub fn detect_smc(&mut self, action: Option<SmcAction>) {
+        self.smc = action.map(|action| SmcDetector {
+            action,
+            ..SmcDetector::default()
+        });
+    }
+
+    /// Fast-forward polling loops that only an interrupt can end, by at most `max_skip` cycles
+    ///
+    /// A loop qualifies when two consecutive iterations close at the same
+    /// taken backward branch with identical registers and no store, AMO or
+    /// SYSTEM instruction in between: every further iteration then repeats
+    /// exactly. Registers loaded from a peripheral, such as `mtime` in a loop
+    /// polling for a deadline, and registers computed from them are left out
+    /// of the comparison. With interrupts enabled, peripherals, `cycle` and
+    /// `instret` jump ahead by whole iterations up to the next enabled
+    /// interrupt, so the state on wakeup matches a full emulation except for
+    /// those registers, which keep the last value actually read. Only the
+    /// peripheral run loop does this; peripherals whose state changes from
+    /// outside the emulator (console input) are seen up to `max_skip` cycles
+    /// late, and a polled deadline other than the next interrupt may be
+    /// overshot by as much. `None` disables it.
+    pub fn set_idle_skip(&mut self, max_skip: Option<u64>) {
+        self.idle = max_skip.map(|max_skip| IdleDetector {
+            max_skip,
+            ..IdleDetector::default()
+        });
+    }
+
+    /// Instructions credited by idle-loop fast-forwarding instead of being executed
+    pub fn idle_skipped(&self) -> u64 {
+        self.idle.as_ref().map_or(0, |idle| idle.skipped)
+    }
+
+    /// Whether the instruction at the PC is a load from a peripheral
+    fn loads_from_device(
+        &self,
+        memory: &Memory,
+        peripherals: &crate::peripheral::PeripheralManager,
+    ) -> bool {
+        let instruction = memory.peek_word(self.pc);
+        if instruction & 0x7F != 0x03 {
+            return false;
+        }
+        let offset = (instruction as i32 >> 20) as u32;
+        let address = self.read_register(((instruction >> 15) & 0x1F) as usize);
+        peripherals.is_peripheral_address(address.wrapping_add(offset))
+    }
+
+    /// Track the instruction just executed at `pc`; at the end of a repeating
+    /// polling loop, skip iterations up to the next interrupt
+    ///
+    /// `device_load` tells whether the instruction loaded from a peripheral.
+    /// Returns the number of instructions credited, at most `budget`.
+    fn idle_skip(
+        &mut self,
+        peripherals: &mut crate::peripheral::PeripheralManager,
+        pc: u32,
+        instruction: u32,
+        device_load: bool,
+        budget: u32,
+    ) -> u32 {
+        if let Some(idle) = &mut self.idle {
+            idle.track_volatile(instruction, device_load);
+        }
+        match instruction & 0x7F {
+            0x23 | 0x2F | 0x73 => {
+                if let Some(idle) = &mut self.idle {
+                    idle.clean = false;
+                }
+                return 0;
+            }
+            0x63 | 0x6F if self.pc <= pc => {}
+            _ => return 0,
+        }
+        let registers = self.registers_snapshot();
+        let (mstatus, mie) = (self.mstatus(), self.mie());
+        let Some(idle) = &mut self.idle else {
+            return 0;
+        };
+        let stable = (0..NUM_REGISTERS)
+            .filter(|&i| idle.volatile & (1 << i) == 0)
+            .all(|i| idle.registers[i] == registers[i]);
+        let repeated = idle.clean && idle.branch_pc == pc && stable;
+        let length = self.instret.wrapping_sub(idle.instret);
+        idle.branch_pc = pc;
+        idle.registers = registers;
+        idle.instret = self.instret;
+        idle.clean = true;
+        if !repeated || length == 0 || mstatus & MSTATUS_MIE == 0 {
+            return 0;
+        }
+        let Some(cycles) = peripherals.cycles_until_interrupt(mie) else {
+            return 0;
+        };
+        let skip = cycles.min(idle.max_skip).min(u64::from(budget)) / length * length;
+        if skip == 0 {
+            return 0;
+        }
+        idle.skipped += skip;
+        peripherals.tick_all(skip);
+        self.cycle = self.cycle.wrapping_add(skip);
+        self.instret = self.instret.wrapping_add(skip);
+        if let Some(idle) = &mut self.idle {
+            idle.instret = self.instret;
+        }
+        skip as u32
+    }
+
+    /// Drop the cached instruction for an address (no-op when the cache is disabled)
+    pub fn invalidate_icache(&mut self, address: u32) {
+        if let Some(cache) = &mut self.icache {
+            cache.remove(&address);
+        }
+    }
+
+    /// Drop all cached instructions (no-op when the cache is disabled)
+    pub fn flush_icache(&mut self) {
+        if let Some(cache) = &mut self.icache {
+            cache.clear();
+        }
+    }
+
+    /// Install a sink receiving one trace record per executed instruction
+    pub fn set_trace_sink(&mut self, format: TraceFormat, sink: Box<dyn std::io::Write>) {
+        self.hooks.trace = Some((format, sink));
+    }
+
+    /// Remove the trace sink, returning it
+    pub fn take_trace_sink(&mut self) -> Option<Box<dyn std::io::Write>> {
+        self.hooks.trace.take().map(|(_, sink)| sink)
+    }
+
+    /// Capture the state needed for a trace record before a step when tracing is enabled
+    fn trace_prepare(&self, memory: &Memory) -> Option<TracePoint> {
+        if self.hooks.trace.is_none() && self.hooks.compare.is_none() {
+            return None;
+        }
+        let pc = self.pc;
+        let instruction = memory.peek_word(pc);
+        let rd =
+            crate::disasm::destination_register(instruction).map(|rd| (rd, self.read_register(rd)));
+        let (mem, store_value) = self.memory_operand(instruction);
+        Some(TracePoint {
+            pc,
+            instruction,
+            rd,
+            mem,
+            store_value,
+        })
+    }
+
+    /// Address and width of a load/store `instruction` about to execute, plus the value a store writes
+    pub(crate) fn memory_operand(&self, instruction: u32) -> (Option<(u32, u8)>, Option<u32>) {
+        let rs1 = self.read_register(((instruction >> 15) & 0x1F) as usize);
+        let width = match (instruction >> 12) & 0x3 {
+            0 => 1,
+            1 => 2,
+            _ => 4,
+        };
+        match instruction & 0x7F {
+            0x03 => {
+                let imm = (instruction as i32) >> 20;
+                (Some((rs1.wrapping_add(imm as u32), width)), None)
+            }
+            0x23 => {
+                let imm = (((instruction & 0xFE00_0000) as i32) >> 20)
+                    | ((instruction >> 7) & 0x1F) as i32;
+                let value = self.read_register(((instruction >> 20) & 0x1F) as usize);
+                (
+                    Some((rs1.wrapping_add(imm as u32), width)),
+                    Some(mask_width(value, width)),
+                )
+            }
+            _ => (None, None),
+        }
+    }
+
+    /// Write a trace record for an executed instruction and check it against the reference
+    ///
+    /// Returns false when the instruction diverges from the reference trace.
+    fn trace_step(&mut self, cycle: u32, point: TracePoint) -> bool {
+        let TracePoint {
+            pc,
+            instruction,
+            rd,
+            mem,
+            store_value,
+        } = point;
+        let write = rd.map(|(rd, old)| (rd, old, self.read_register(rd)));
+        let matches = match &mut self.hooks.compare {
+            Some(comparator) => comparator.check(
+                instruction,
+                TraceRecord {
+                    pc,
+                    write: write.map(|(rd, _, new)| (rd, new)),
+                },
+            ),
+            None => true,
+        };
+        let Some((format, sink)) = &mut self.hooks.trace else {
+            return matches;
+        };
+        let mnemonic = crate::disasm::disassemble_at(instruction, pc);
+        // Tracing is best-effort: a failing sink must not stop the guest
+        let _ = match format {
+            TraceFormat::Text => match write {
+                Some((rd, _, value)) => writeln!(
+                    sink,
+                    "{cycle:>8} 0x{pc:08x} (0x{instruction:08x}) {mnemonic:<28} x{rd}=0x{value:08x}"
+                ),
+                None => writeln!(
+                    sink,
+                    "{cycle:>8} 0x{pc:08x} (0x{instruction:08x}) {mnemonic}"
+                ),
+            },
+            TraceFormat::Jsonl => {
+                let record = JsonlRecord {
+                    i: cycle,
+                    pc: hex(pc),
+                    insn: hex(instruction),
+                    asm: mnemonic,
+                    rd: write.map(|(x, old, new)| JsonlRegister {
+                        x,
+                        old: hex(old),
+                        new: hex(new),
+                    }),
+                    // Loads report the loaded value as seen in the destination register
+                    mem: mem.map(|(addr, w)| JsonlMemory {
+                        addr: hex(addr),
+                        w,
+                        val: hex(store_value
+                            .unwrap_or_else(|| mask_width(write.map_or(0, |(_, _, new)| new), w))),
+                    }),
+                };
+                serde_json::to_writer(&mut *sink, &record)
+                    .map_err(std::io::Error::from)
+                    .and_then(|()| writeln!(sink))
+            }
+        };
+        matches
+    }
+
+    /// Invoke `callback` every `interval` retired instructions during a run
+    ///
+    /// Returning `ProgressAction::Cancel` stops the run with `ExitReason::Cancelled`.
+    pub fn set_progress_callback(&mut self, interval: u32, callback: ProgressCallback) {
+        self.hooks.progress = (interval > 0).then_some((interval, callback, 0.0, interval));
+    }
+
+    /// Remove the progress callback
+    pub fn clear_progress_callback(&mut self) {
+        self.hooks.progress = None;
+    }
+
+    /// Stop each run with `ExitReason::TimeBudgetExceeded` once it has taken longer than `budget`
+    ///
+    /// The clock is checked every few thousand instructions, so a run may
+    /// overshoot the budget slightly.
+    pub fn set_time_budget(&mut self, budget: std::time::Duration) {
+        self.set_time_budget_with_interval(budget, TIME_CHECK_INTERVAL);
+    }
+
+    /// Like `set_time_budget`, checking the clock every `interval` retired instructions
+    ///
+    /// Smaller intervals stop closer to the budget at the cost of more clock reads.
+    pub fn set_time_budget_with_interval(&mut self, budget: std::time::Duration, interval: u32) {
+        self.hooks.time_budget = Some((budget, 0.0, interval.max(1), interval.max(1)));
+    }
+
+    /// Remove the time budget
+    pub fn clear_time_budget(&mut self) {
+        self.hooks.time_budget = None;
+    }
+
+    /// Start the time budget and progress clocks for a new run
+    fn arm_time_budget(&mut self) {
+        if let Some((budget, deadline, interval, next)) = &mut self.hooks.time_budget {
+            *deadline = crate::throttle::now_ms() + budget.as_secs_f64() * 1000.0;
+            *next = *interval;
+        }
+        if let Some((interval, _, start, next)) = &mut self.hooks.progress {
+            *start = crate::throttle::now_ms();
+            *next = *interval;
+        }
+    }
+
+    /// Whether the run has used up its time budget (checked once per interval)
+    ///
+    /// The check is due once `executed` reaches the next interval boundary,
+    /// so counts that jump past a boundary (idle skipping) still trigger it.
+    fn time_budget_exceeded(&mut self, executed: u32) -> bool {
+        match &mut self.hooks.time_budget {
+            Some((_, deadline, interval, next)) if executed >= *next => {
+                *next = next_boundary(executed, *interval);
+                crate::throttle::now_ms() >= *deadline
+            }
+            _ => false,
+        }
+    }
+
+    /// Call the progress callback once `executed` reaches the next interval boundary; true if it cancelled the run
+    fn report_progress(&mut self, executed: u32) -> bool {
+        let pc = self.pc;
+        match &mut self.hooks.progress {
+            Some((interval, callback, start, next)) if executed >= *next => {
+                *next = next_boundary(executed, *interval);
+                let elapsed_ms = (crate::throttle::now_ms() - *start).max(0.0);
+                let progress = Progress {
+                    retired: executed,
+                    pc,
+                    elapsed: std::time::Duration::from_secs_f64(elapsed_ms / 1000.0),
+                };
+                callback(&progress) == ProgressAction::Cancel
+            }
+            _ => false,
+        }
+    }
+
+    /// Stop runs with `ExitReason::WatchHit` when the watch condition becomes true
+    pub fn add_watch(&mut self, watch: Watch) {
+        self.hooks.watches.push((watch, 0));
+    }
+
+    /// The watch with the given index (as reported in `ExitReason::WatchHit`)
+    pub fn watch(&self, index: usize) -> Option<&Watch> {
+        self.hooks.watches.get(index).map(|(watch, _)| watch)
+    }
+
+    /// Remove all watches
+    pub fn clear_watches(&mut self) {
+        self.hooks.watches.clear();
+    }
+
+    /// Record the current value of every watched location at the start of a run
+    fn arm_watches(&mut self, memory: &Memory) {
+        let mut watches = std::mem::take(&mut self.hooks.watches);
+        for (watch, last) in &mut watches {
+            *last = watch.value(self, memory);
+        }
+        self.hooks.watches = watches;
+    }
+
+    /// Evaluate watches after the instruction at `pc`, returning the first that fired
+    fn check_watches(&mut self, memory: &Memory, pc: u32) -> Option<ExitReason> {
+        let mut watches = std::mem::take(&mut self.hooks.watches);
+        let mut hit = None;
+        for (index, (watch, last)) in watches.iter_mut().enumerate() {
+            let value = watch.value(self, memory);
+            let old = std::mem::replace(last, value);
+            if hit.is_none() && watch.fires(old, value) {
+                hit = Some(ExitReason::WatchHit {
+                    watch: index,
+                    pc,
+                    value,
+                });
+            }
+        }
+        self.hooks.watches = watches;
+        hit
+    }
+
+    /// Run with a `run_until_*` stop condition installed for the duration of `run`
+    pub(crate) fn run_to_target(
+        &mut self,
+        target: RunTarget,
+        run: impl FnOnce(&mut Self) -> Result<u32>,
+    ) -> Result<u32> {
+        self.hooks.target = Some(target);
+        let result = run(self);
+        self.hooks.target = None;
+        result
+    }
+
+    /// Size in bytes of the instruction at `pc`: 2 for a compressed encoding, 4 otherwise
+    ///
+    /// Only the length encoding (the low two bits) is inspected; compressed
+    /// instructions are not executed by this CPU.
+    pub fn instruction_length_at(&self, memory: &Memory, pc: u32) -> Result<u32> {
+        let low = memory.read_u16_le(pc)?;
+        Ok(if low & 0b11 == 0b11 { 4 } else { 2 })
+    }
+
+    /// Whether the instruction at PC is a call (JAL/JALR with rd=ra)
+    pub fn at_call(&self, memory: &Memory) -> bool {
+        is_call(memory.peek_word(self.pc))
+    }
+
+    /// Evaluate the `run_until_*` stop condition after the instruction at `pc`
+    fn check_run_target(&mut self, memory: &Memory, pc: u32) -> Option<ExitReason> {
+        match self.hooks.target.as_mut()? {
+            RunTarget::Pc(address) => {
+                (self.pc == *address).then_some(ExitReason::TargetReached(self.pc))
+            }
+            RunTarget::Return { open_calls } => {
+                let instruction = memory.peek_word(pc);
+                if is_call(instruction) {
+                    *open_calls += 1;
+                } else if is_return(instruction) {
+                    if *open_calls == 0 {
+                        return Some(ExitReason::TargetReached(self.pc));
+                    }
+                    *open_calls -= 1;
+                }
+                None
+            }
+        }
+    }
 
-/// RISC-V CPU state
-#[derive(Debug, Clone)]
-pub struct Cpu {
-    /// General-purpose registers (x0-x31)
-    pub registers: [u32; NUM_REGISTERS],
-    /// Program counter
-    pub pc: u32,
-    /// Control and Status Registers (CSRs)
-    /// For simplicity, we'll store only the most common ones
-    pub csrs: std::collections::HashMap<u16, u32>,
-}
+    /// Run until the PC reaches `address` (at least one instruction is executed)
+    pub fn run_until_pc(
+        &mut self,
+        memory: &mut Memory,
+        address: u32,
+        max_instructions: Option<u32>,
+    ) -> Result<u32> {
+        self.run_to_target(RunTarget::Pc(address), |cpu| {
+            cpu.run(memory, max_instructions)
+        })
+    }
 
-impl Cpu {
-    /// Create a new CPU instance
-    pub fn new() -> Self {
-        let mut csrs = std::collections::HashMap::new();
-        // Initialize commonly used CSRs
-        csrs.insert(0xF14, 0); // mhartid - hardware thread ID
-        csrs.insert(0x300, 0); // mstatus - machine status
-        csrs.insert(0x341, 0); // mepc - machine exception program counter
-        csrs.insert(0x342, 0); // mcause - machine trap cause
-        csrs.insert(0x343, 0); // mtval - machine trap value
-        csrs.insert(0x344, 0); // mip - machine interrupt pending
-        csrs.insert(0x304, 0); // mie - machine interrupt enable
-        csrs.insert(0x305, 0); // mtvec - machine trap-handler base address
-        csrs.insert(0x340, 0); // mscratch - machine scratch register
-        csrs.insert(0xF11, 0); // mvendorid - vendor ID
-        csrs.insert(0xF12, 0); // marchid - architecture ID
-        csrs.insert(0xF13, 0); // mimpid - implementation ID
-        csrs.insert(0xC00, 0); // cycle - cycle counter
-        csrs.insert(0xC01, 0); // time - time counter
-        csrs.insert(0xC02, 0); // instret - instructions retired counter
+    /// Run until the current function returns to its caller
+    ///
+    /// Calls made on the way are tracked so that their returns do not stop the run.
+    pub fn run_until_return(
+        &mut self,
+        memory: &mut Memory,
+        max_instructions: Option<u32>,
+    ) -> Result<u32> {
+        self.run_to_target(RunTarget::Return { open_calls: 0 }, |cpu| {
+            cpu.run(memory, max_instructions)
+        })
+    }
 
-        Self {
-            registers: [0; NUM_REGISTERS],
-            pc: 0,
-            csrs,
+    /// Step over a call: run until the called function returns, or single-step otherwise
+    pub fn run_over(&mut self, memory: &mut Memory, max_instructions: Option<u32>) -> Result<u32> {
+        let at_call = self.at_call(memory);
+        self.run_over_with(at_call, max_instructions, |cpu, max| cpu.run(memory, max))
+    }
+
+    /// `run_over` for a hart that `run` executes, such as one with peripherals attached
+    pub(crate) fn run_over_with(
+        &mut self,
+        at_call: bool,
+        max_instructions: Option<u32>,
+        mut run: impl FnMut(&mut Self, Option<u32>) -> Result<u32>,
+    ) -> Result<u32> {
+        let limit = max_instructions.map_or(1, |max| max.min(1));
+        if !at_call {
+            return run(self, Some(limit));
+        }
+        let executed = run(self, Some(limit))?;
+        if self.exit_reason != Some(ExitReason::InstructionLimit) || executed == 0 {
+            return Ok(executed);
         }
+        let remaining = max_instructions.map(|max| max - executed);
+        let returned = self.run_to_target(RunTarget::Return { open_calls: 0 }, |cpu| {
+            run(cpu, remaining)
+        })?;
+        Ok(executed + returned)
     }
 
-    /// Reset the CPU to initial state
-    pub fn reset(&mut self) {
-        self.registers = [0; NUM_REGISTERS];
-        self.pc = 0;
-        // Reset CSRs to default values
-        self.csrs.clear();
-        self.csrs.insert(0xF14, 0); // mhartid
-        self.csrs.insert(0x300, 0); // mstatus
-        self.csrs.insert(0x341, 0); // mepc
-        self.csrs.insert(0x342, 0); // mcause
-        self.csrs.insert(0x343, 0); // mtval
-        self.csrs.insert(0x344, 0); // mip
-        self.csrs.insert(0x304, 0); // mie
-        self.csrs.insert(0x305, 0); // mtvec
-        self.csrs.insert(0x340, 0); // mscratch
-        self.csrs.insert(0xF11, 0); // mvendorid
-        self.csrs.insert(0xF12, 0); // marchid
-        self.csrs.insert(0xF13, 0); // mimpid
-        self.csrs.insert(0xC00, 0); // cycle
-        self.csrs.insert(0xC01, 0); // time
-        self.csrs.insert(0xC02, 0); // instret
+    /// Serve HTIF console requests stored to `tohost`
+    pub fn set_htif(&mut self, htif: Htif) {
+        self.hooks.htif = Some(htif);
+    }
+
+    /// Remove the HTIF endpoint, returning it
+    pub fn take_htif(&mut self) -> Option<Htif> {
+        self.hooks.htif.take()
+    }
+
+    /// Serve an HTIF command if the instruction at `pc` was a store completing one
+    fn service_htif(&mut self, memory: &mut Memory, pc: u32) -> Result<()> {
+        if self.hooks.htif.is_none() {
+            return Ok(());
+        }
+        let instruction = memory.peek_word(pc);
+        if instruction & 0x7F != 0x23 {
+            return Ok(());
+        }
+        let rs1 = ((instruction >> 15) & 0x1F) as usize;
+        let imm = ((instruction & 0xFE00_0000) as i32 >> 20) | ((instruction >> 7) & 0x1F) as i32;
+        let address = self.read_register(rs1).wrapping_add(imm as u32);
+        let size = 1 << ((instruction >> 12) & 0x3);
+        if let Some(htif) = &mut self.hooks.htif {
+            if htif.is_command_store(address, size) {
+                htif.handle(memory)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check every retired instruction against a reference trace, stopping at the first divergence
+    pub fn set_trace_comparator(&mut self, comparator: TraceComparator) {
+        self.hooks.compare = Some(comparator);
+    }
+
+    /// Remove the trace comparator, returning it
+    pub fn take_trace_comparator(&mut self) -> Option<TraceComparator> {
+        self.hooks.compare.take()
+    }
+
+    /// The divergence found by the trace comparator, if any
+    pub fn trace_divergence(&self) -> Option<&Divergence> {
+        self.hooks.compare.as_ref()?.divergence()
+    }
+
+    /// Fetch the instruction word at PC, going through the decode cache when enabled
+    fn fetch(&mut self, memory: &Memory) -> Result<u32> {
+        memory.set_access_pc(Some(self.pc));
+        if self.smc.is_some() {
+            self.smc_fetch()?;
+        }
+        if let Some(cache) = &mut self.icache {
+            if let Some(&instruction) = cache.get(&self.pc) {
+                return Ok(instruction);
+            }
+            let instruction = memory.read_u32_le(self.pc).map_err(as_fetch)?;
+            cache.insert(self.pc, instruction);
+            Ok(instruction)
+        } else {
+            memory.read_u32_le(self.pc).map_err(as_fetch)
+        }
     }
 
     /// Read a register value
@@ -116,14 +1814,206 @@ impl Cpu {
         // x0 cannot be written, invalid registers are ignored
     }
 
+    /// Read a register by name
+    pub fn reg(&self, reg: Reg) -> u32 {
+        self.read_register(reg.index())
+    }
+
+    /// Write a register by name (writes to zero are ignored)
+    pub fn set_reg(&mut self, reg: Reg, value: u32) {
+        self.write_register(reg.index(), value);
+    }
+
+    /// Copy of the whole integer register file
+    pub fn registers_snapshot(&self) -> [u32; NUM_REGISTERS] {
+        std::array::from_fn(|i| self.read_register(i))
+    }
+
+    /// Registers that differ from `before`, as `(register, old, new)` in index order
+    pub fn diff_registers(&self, before: &[u32; NUM_REGISTERS]) -> Vec<(Reg, u32, u32)> {
+        Reg::ALL
+            .iter()
+            .filter_map(|&reg| {
+                let (old, new) = (before[reg.index()], self.reg(reg));
+                (old != new).then_some((reg, old, new))
+            })
+            .collect()
+    }
+
+    /// Cycles counted so far (`mcycle`; also the `time` base)
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Instructions retired so far (`minstret`)
+    pub fn instret(&self) -> u64 {
+        self.instret
+    }
+
+    /// Restore the 64-bit counters, e.g. from a saved state
+    pub fn set_counters(&mut self, cycle: u64, instret: u64) {
+        self.cycle = cycle;
+        self.instret = instret;
+    }
+
+    /// Value of a counter CSR; both halves come from the same 64-bit counter
+    fn read_counter_csr(&self, csr: u16) -> Option<u32> {
+        let counter = match csr {
+            CSR_CYCLE | CSR_TIME | CSR_MCYCLE | CSR_CYCLEH | CSR_TIMEH | CSR_MCYCLEH => self.cycle,
+            CSR_INSTRET | CSR_MINSTRET | CSR_INSTRETH | CSR_MINSTRETH => self.instret,
+            _ => return None,
+        };
+        // High-half CSRs have bit 7 of the address set
+        Some(if csr & 0x80 != 0 {
+            (counter >> 32) as u32
+        } else {
+            counter as u32
+        })
+    }
+
+    /// Write a counter CSR, returning whether `csr` is one
+    ///
+    /// `mcycle`/`minstret` and their high halves replace one half of the
+    /// counter; the unprivileged `cycle`/`time`/`instret` views are read-only.
+    fn write_counter_csr(&mut self, csr: u16, value: u32) -> bool {
+        let counter = match csr {
+            CSR_MCYCLE | CSR_MCYCLEH => &mut self.cycle,
+            CSR_MINSTRET | CSR_MINSTRETH => &mut self.instret,
+            CSR_CYCLE..=CSR_INSTRET | CSR_CYCLEH..=CSR_INSTRETH => return true,
+            _ => return false,
+        };
+        *counter = if csr & 0x80 != 0 {
+            (*counter & 0xFFFF_FFFF) | u64::from(value) << 32
+        } else {
+            (*counter & !0xFFFF_FFFF) | u64::from(value)
+        };
+        true
+    }
+
     /// Read a CSR value
     pub fn read_csr(&self, csr: u16) -> u32 {
-        self.csrs.get(&csr).copied().unwrap_or(0)
+        let value = self
+            .read_counter_csr(csr)
+            .unwrap_or_else(|| self.csrs.get(&csr).copied().unwrap_or(0));
+        match &self.hooks.csr {
+            Some(hook) => hook.borrow_mut().on_read(csr, value).unwrap_or(value),
+            None => value,
+        }
+    }
+
+    /// Stored value of a CSR, without the hook
+    fn stored_csr(&self, csr: u16) -> u32 {
+        self.read_counter_csr(csr)
+            .unwrap_or_else(|| self.csrs.get(&csr).copied().unwrap_or(0))
     }
 
     /// Write a CSR value
     pub fn write_csr(&mut self, csr: u16, value: u32) {
-        self.csrs.insert(csr, value);
+        let old = self.stored_csr(csr);
+        let value = match &self.hooks.csr {
+            Some(hook) => hook.borrow_mut().on_write(csr, old, value).unwrap_or(value),
+            None => value,
+        };
+        let value = self.legalize_csr(csr, value);
+        if !self.write_counter_csr(csr, value) {
+            self.csrs.insert(csr, value);
+        }
+        if self.csr_trace.is_some() {
+            let new = self.stored_csr(csr);
+            if let Some(trace) = &mut self.csr_trace {
+                trace.push((self.pc, csr, old, new));
+            }
+        }
+    }
+
+    /// The legal value a write of `value` to a WARL `csr` leaves behind
+    ///
+    /// `mepc` drops the low bits below instruction alignment (2 bytes with C,
+    /// 4 without); `mtvec` turns the reserved modes 2 and 3 into direct mode.
+    fn legalize_csr(&self, csr: u16, value: u32) -> u32 {
+        match csr {
+            CSR_MEPC if self.has_extension(MISA_C) => value & !1,
+            CSR_MEPC => value & !3,
+            CSR_MTVEC if value & 0x3 > 1 => value & !0x3,
+            _ => value,
+        }
+    }
+
+    /// Write a CSR from a guest CSR instruction
+    ///
+    /// The `mip` bits of the machine interrupt lines are only driven by
+    /// peripherals and `raise_interrupt`, so guest writes leave them unchanged.
+    fn write_csr_from_guest(&mut self, csr: u16, value: u32) {
+        let value = match csr {
+            CSR_MIP => (self.read_csr(CSR_MIP) & MIP_LINES) | (value & !MIP_LINES),
+            _ => value,
+        };
+        self.write_csr(csr, value);
+    }
+
+    /// `mstatus`
+    pub fn mstatus(&self) -> u32 {
+        self.read_csr(CSR_MSTATUS)
+    }
+
+    /// Write `mstatus`
+    pub fn set_mstatus(&mut self, value: u32) {
+        self.write_csr(CSR_MSTATUS, value);
+    }
+
+    /// `mtvec`: trap vector base and mode
+    pub fn mtvec(&self) -> u32 {
+        self.read_csr(CSR_MTVEC)
+    }
+
+    /// Write `mtvec`
+    pub fn set_mtvec(&mut self, value: u32) {
+        self.write_csr(CSR_MTVEC, value);
+    }
+
+    /// `mepc`: PC of the instruction that trapped
+    pub fn mepc(&self) -> u32 {
+        self.read_csr(CSR_MEPC)
+    }
+
+    /// Write `mepc`
+    pub fn set_mepc(&mut self, value: u32) {
+        self.write_csr(CSR_MEPC, value);
+    }
+
+    /// `mcause`: cause of the last trap (`CAUSE_INTERRUPT` set for interrupts)
+    pub fn mcause(&self) -> u32 {
+        self.read_csr(CSR_MCAUSE)
+    }
+
+    /// `mtval`: faulting address or instruction of the last trap
+    pub fn mtval(&self) -> u32 {
+        self.read_csr(CSR_MTVAL)
+    }
+
+    /// `mie`: enabled interrupts
+    pub fn mie(&self) -> u32 {
+        self.read_csr(CSR_MIE)
+    }
+
+    /// Write `mie`
+    pub fn set_mie(&mut self, value: u32) {
+        self.write_csr(CSR_MIE, value);
+    }
+
+    /// `mip`: pending interrupts
+    pub fn mip(&self) -> u32 {
+        self.read_csr(CSR_MIP)
+    }
+
+    /// Install a hook invoked on every CSR read and write
+    pub fn set_csr_hook(&mut self, hook: Box<dyn CsrHook>) {
+        self.hooks.csr = Some(std::cell::RefCell::new(hook));
+    }
+
+    /// Remove the CSR hook, returning it
+    pub fn take_csr_hook(&mut self) -> Option<Box<dyn CsrHook>> {
+        self.hooks.csr.take().map(|hook| hook.into_inner())
     }
 
     /// Execute a single instruction
@@ -142,15 +2032,26 @@ impl Cpu {
 
     /// Execute a single instruction with verbose output
     pub fn step_with_verbosity(&mut self, memory: &mut Memory, verbosity: u8) -> Result<()> {
+        // Without peripherals only host-raised interrupts can be pending
+        if self.raised_interrupts != 0 && self.sample_interrupts(0) {
+            debug_log!(
+                verbosity,
+                "  Interrupt taken, mcause=0x{:08x}",
+                self.read_csr(CSR_MCAUSE)
+            );
+            self.check_trap_depth()?;
+        }
+
         // Fetch instruction from memory
-        let instruction = memory.read_word(self.pc)?;
+        let instruction = self.fetch(memory)?;
 
         debug_log!(verbosity, "  Fetched instruction: 0x{instruction:08x}");
 
         // Decode and execute instruction
+        let pc = self.pc;
         self.decode_and_execute_with_verbosity(instruction, memory, verbosity)?;
-
-        Ok(())
+        self.record_branch(pc, instruction);
+        self.check_jump_target(memory, pc, instruction, verbosity)
     }
 
     /// Execute a single instruction with peripheral and verbose support
@@ -160,131 +2061,307 @@ impl Cpu {
         peripherals: &mut crate::peripheral::PeripheralManager,
         verbosity: u8,
     ) -> Result<()> {
+        if self.sample_interrupts(peripherals.pending_interrupts()) {
+            debug_log!(
+                verbosity,
+                "  Interrupt taken, mcause=0x{:08x}",
+                self.read_csr(CSR_MCAUSE)
+            );
+            self.check_trap_depth()?;
+        }
+
         // Fetch instruction from memory
-        let instruction = memory.read_word(self.pc)?;
+        let instruction = self.fetch(memory)?;
 
         debug_log!(verbosity, "  Fetched instruction: 0x{instruction:08x}");
 
         // Decode and execute instruction
-        self.decode_and_execute_with_peripherals_and_verbosity(
-            instruction,
-            memory,
-            peripherals,
-            verbosity,
-        )?;
-
-        Ok(())
+        let pc = self.pc;
+        let mut bus = SystemBus::new(memory, peripherals);
+        let result = self.decode_and_execute_with_verbosity(instruction, &mut bus, verbosity);
+        if matches!(result, Ok(()) | Err(EmulatorError::WaitForInterrupt)) {
+            peripherals.tick_all(1);
+        }
+        result?;
+        self.record_branch(pc, instruction);
+        // A spinning hart yields at PAUSE: take a pending interrupt without waiting for the next step
+        if instruction == PAUSE && self.sample_interrupts(peripherals.pending_interrupts()) {
+            debug_log!(
+                verbosity,
+                "  Interrupt taken at PAUSE, mcause=0x{:08x}",
+                self.read_csr(CSR_MCAUSE)
+            );
+            self.check_trap_depth()?;
+        }
+        self.check_jump_target(memory, pc, instruction, verbosity)
     }
 
-    /// Decode and execute an instruction with verbose output
-    fn decode_and_execute_with_verbosity(
+    /// Fail with `WildJump` if `instruction` at `pc` transferred control to unwritten memory
+    fn check_jump_target(
         &mut self,
+        memory: &Memory,
+        pc: u32,
         instruction: u32,
-        memory: &mut Memory,
         verbosity: u8,
     ) -> Result<()> {
-        // Extract opcode (bits 0-6)
-        let opcode = instruction & 0x7F;
+        let enabled = match self.jump_check {
+            JumpCheck::Verbose => verbosity >= 1,
+            JumpCheck::Always => true,
+            JumpCheck::Never => false,
+        };
+        if enabled || self.record_history {
+            if self.jump_history.len() == JUMP_HISTORY_LEN {
+                self.jump_history.pop_front();
+            }
+            self.jump_history.push_back(pc);
+        }
+        if !enabled {
+            return Ok(());
+        }
+        let transfer = matches!(instruction & 0x7F, 0x63 | 0x67 | 0x6F);
+        // Reaching a `run_until_pc` target stops the run before anything is fetched there
+        let stop = matches!(self.hooks.target, Some(RunTarget::Pc(target)) if target == self.pc);
+        if !transfer || stop || self.pc == pc.wrapping_add(4) || memory.is_written(self.pc) {
+            return Ok(());
+        }
+        Err(EmulatorError::WildJump(WildJump {
+            target: self.pc,
+            pc,
+            history: self.jump_history.iter().copied().collect(),
+        }))
+    }
 
-        debug_log!(verbosity, "  Opcode: 0x{opcode:02x}");
+    /// Execute a single instruction and describe what it did
+    ///
+    /// Stops that end a run (ECALL exit, breakpoint, `unimp`, unsupported
+    /// instruction) are not errors here: they set `exit_reason` and are
+    /// reported in `StepInfo::exit_reason`.
+    pub fn step_info(
+        &mut self,
+        memory: &mut Memory,
+        peripherals: Option<&mut crate::peripheral::PeripheralManager>,
+    ) -> Result<StepInfo> {
+        let pc = self.pc;
+        let word = memory.peek_word(pc);
+        let before = self.registers_snapshot();
+        self.exit_reason = None;
+        let result = match peripherals {
+            Some(peripherals) => self.step_with_peripherals(memory, peripherals),
+            None => self.step(memory),
+        };
+        if let Err(e) = result {
+            self.exit_reason = Some(self.stop_reason(&e).ok_or(e)?);
+        }
+        Ok(StepInfo {
+            pc,
+            word,
+            asm: crate::disasm::disassemble_at(word, pc),
+            write: self
+                .diff_registers(&before)
+                .first()
+                .map(|&(reg, old, new)| RegisterWrite { reg, old, new }),
+            next_pc: self.pc,
+            exit_reason: self.exit_reason.map(|reason| reason.name()),
+        })
+    }
 
-        match opcode {
-            0x13 => {
-                // I-type instruction (ADDI, SLTI, XORI, etc.)
-                debug_log!(verbosity, "  I-type instruction");
-                self.execute_i_type(instruction)
-            }
-            0x33 => {
-                // R-type instruction (ADD, SUB, XOR, etc.)
-                debug_log!(verbosity, "  R-type instruction");
-                self.execute_r_type(instruction)
-            }
-            0x03 => {
-                // Load instructions (LB, LH, LW, LBU, LHU)
-                debug_log!(verbosity, "  Load instruction");
-                self.execute_load(instruction, memory)
-            }
-            0x23 => {
-                // Store instructions (SB, SH, SW)
-                debug_log!(verbosity, "  Store instruction");
-                self.execute_store(instruction, memory)
-            }
-            0x63 => {
-                // Branch instructions (BEQ, BNE, BLT, BGE, BLTU, BGEU)
-                debug_log!(verbosity, "  Branch instruction");
-                self.execute_branch(instruction)
-            }
-            0x37 => {
-                // LUI instruction
-                debug_log!(verbosity, "  LUI instruction");
-                self.execute_lui(instruction)
+    /// The exit reason a run loop reports when a step fails with `error`, if it is a stop
+    pub fn stop_reason(&self, error: &EmulatorError) -> Option<ExitReason> {
+        match error {
+            EmulatorError::EcallTermination => Some(self.ecall_exit_reason()),
+            EmulatorError::Breakpoint => Some(ExitReason::Breakpoint),
+            EmulatorError::Unimp(pc) => Some(ExitReason::Unimp(*pc)),
+            EmulatorError::UnsupportedInstruction => Some(ExitReason::UnsupportedInstruction),
+            EmulatorError::WaitForInterrupt => Some(ExitReason::Waiting),
+            EmulatorError::TrapLoop(depth) => Some(ExitReason::TrapLoop { depth: *depth }),
+            _ => None,
+        }
+    }
+
+    /// End a run loop on a step that failed with `error`
+    ///
+    /// A stop (see `stop_reason`) becomes the exit reason and yields Ok; ECALL
+    /// and WFI retire their instruction, so they count towards `executed`.
+    /// Any other error is logged and returned.
+    fn end_run(
+        &mut self,
+        memory: &Memory,
+        error: EmulatorError,
+        executed: &mut u32,
+        verbosity: u8,
+    ) -> Result<()> {
+        let Some(reason) = self.stop_reason(&error) else {
+            basic_log!(verbosity, "{}", self.describe_fault(memory, &error));
+            return Err(error);
+        };
+        let pc = self.pc;
+        match error {
+            EmulatorError::EcallTermination => {
+                *executed += 1;
+                info_log!(verbosity, "ECALL termination at PC: 0x{pc:08x}");
             }
-            0x17 => {
-                // AUIPC instruction
-                debug_log!(verbosity, "  AUIPC instruction");
-                self.execute_auipc(instruction)
+            EmulatorError::WaitForInterrupt => {
+                *executed += 1;
+                info_log!(verbosity, "WFI with no pending interrupt at PC: 0x{pc:08x}");
             }
-            0x6F => {
-                // JAL instruction
-                debug_log!(verbosity, "  JAL instruction");
-                self.execute_jal(instruction)
+            EmulatorError::Breakpoint => {
+                info_log!(verbosity, "Breakpoint at PC: 0x{pc:08x}");
             }
-            0x67 => {
-                // JALR instruction
-                debug_log!(verbosity, "  JALR instruction");
-                self.execute_jalr(instruction)
+            EmulatorError::TrapLoop(depth) => {
+                basic_log!(verbosity, "{}", self.describe_trap_loop(depth));
             }
-            0x73 => {
-                // System instructions (ECALL, EBREAK)
-                debug_log!(verbosity, "  System instruction");
-                self.execute_system(instruction)
+            EmulatorError::Unimp(pc) => {
+                basic_log!(
+                    verbosity,
+                    "Reached unimp / unreachable code at PC: 0x{pc:08x}"
+                );
             }
-            0x2F => {
-                // RV32A atomic instructions
-                debug_log!(verbosity, "  Atomic instruction");
-                self.execute_atomic(instruction, memory)
+            _ => {
+                basic_log!(verbosity, "{}", self.describe_fault(memory, &error));
             }
-            0x0F => {
-                // FENCE instruction family (memory ordering)
-                debug_log!(verbosity, "  FENCE instruction");
-                let funct3 = (instruction >> 12) & 0x7;
-                match funct3 {
-                    0x0 => {
-                        // FENCE - memory fence
-                        // For our simple emulator, we'll treat it as a no-op
-                        self.pc = self.pc.wrapping_add(4);
-                        Ok(())
-                    }
-                    0x1 => {
-                        // FENCE.I - instruction fence
-                        // For our simple emulator, we'll treat it as a no-op
-                        self.pc = self.pc.wrapping_add(4);
-                        Ok(())
-                    }
-                    _ => Err(EmulatorError::UnsupportedInstruction),
-                }
+        }
+        self.exit_reason = Some(reason);
+        Ok(())
+    }
+
+    /// Mark the PC as executed code; fail or warn if it was modified without FENCE.I
+    fn smc_fetch(&mut self) -> Result<()> {
+        let pc = self.pc;
+        let Some(smc) = &mut self.smc else {
+            return Ok(());
+        };
+        smc.pages.insert(pc >> SMC_PAGE_SHIFT);
+        smc.executed.insert(pc);
+        let Some(modification) = smc.modified.remove(&pc) else {
+            return Ok(());
+        };
+        if smc.action == SmcAction::Fault {
+            return Err(EmulatorError::UnfencedCode(modification));
+        }
+        self.report_code_modification("smc-unfenced", &modification);
+        Ok(())
+    }
+
+    /// Log and remember a store of `width` bytes at `address` that overwrites executed code
+    fn smc_store(&mut self, address: u32, width: u32, bus: &mut dyn Bus) {
+        let first = address & !3;
+        let last = address.wrapping_add(width - 1) & !3;
+        for word in std::iter::once(first).chain((last != first).then_some(last)) {
+            let Some(smc) = &self.smc else {
+                return;
+            };
+            if !smc.pages.contains(&(word >> SMC_PAGE_SHIFT)) || !smc.executed.contains(&word) {
+                continue;
             }
-            _ => {
-                // Unsupported instruction
-                Err(EmulatorError::UnsupportedInstruction)
+            let Ok(old) = bus.read_word(word) else {
+                continue;
+            };
+            let modification = CodeModification {
+                store_pc: self.pc,
+                address: word,
+                old,
+            };
+            self.report_code_modification("smc-store", &modification);
+            if let Some(smc) = &mut self.smc {
+                smc.modified.insert(word, modification);
             }
         }
     }
 
-    /// Decode and execute an instruction with peripheral and verbose support
-    fn decode_and_execute_with_peripherals_and_verbosity(
+    /// Write a diagnostic line to the trace sink, or `warning` to stderr without one
+    ///
+    /// `text` and `json` are the line for the sink's text and JSON formats.
+    fn report(&mut self, text: String, json: serde_json::Value, warning: std::fmt::Arguments) {
+        let Some((format, sink)) = &mut self.hooks.trace else {
+            eprintln!("Warning: {warning}");
+            return;
+        };
+        let _ = match format {
+            TraceFormat::Text => writeln!(sink, "{text}"),
+            TraceFormat::Jsonl => writeln!(sink, "{json}"),
+        };
+    }
+
+    /// Log a `detect_smc` event to the trace sink, or stderr without one
+    fn report_code_modification(&mut self, kind: &str, modification: &CodeModification) {
+        let CodeModification {
+            store_pc,
+            address,
+            old,
+        } = *modification;
+        let asm = crate::disasm::disassemble_at(old, address);
+        let json = serde_json::json!({
+            kind.replace('-', "_"): { "pc": hex(store_pc), "addr": hex(address), "old": hex(old) }
+        });
+        if kind == "smc-store" {
+            self.report(
+                format!("{kind} 0x{store_pc:08x} to executed 0x{address:08x} (was 0x{old:08x} {asm})"),
+                json,
+                format_args!(
+                    "self-modifying code: store at 0x{store_pc:08x} overwrites executed instruction at 0x{address:08x} (was 0x{old:08x} {asm})"
+                ),
+            );
+        } else {
+            self.report(
+                format!(
+                    "{kind} 0x{address:08x} stored by 0x{store_pc:08x} (was 0x{old:08x} {asm})"
+                ),
+                json,
+                format_args!("self-modifying code: {modification}"),
+            );
+        }
+    }
+
+    /// Log a strict-decode violation at the current PC to the trace sink, or stderr without one
+    fn report_strict_decode(&mut self, instruction: u32, reason: &str) {
+        let pc = self.pc;
+        self.report(
+            format!("strict-decode 0x{pc:08x} (0x{instruction:08x}) {reason}"),
+            serde_json::json!({
+                "strict_decode": { "pc": hex(pc), "insn": hex(instruction), "reason": reason }
+            }),
+            format_args!("strict decode: 0x{pc:08x} (0x{instruction:08x}) {reason}"),
+        );
+    }
+
+    /// Execute a raw instruction word as if it had been fetched from the current PC
+    ///
+    /// The word does not need to be in memory. PC side effects still apply:
+    /// the PC advances by 4, or moves to the target of a taken branch or jump.
+    /// Loads, stores and atomics go through `bus`.
+    pub fn execute_instruction(&mut self, word: u32, bus: &mut dyn Bus) -> Result<()> {
+        self.decode_and_execute_with_verbosity(word, bus, 0)
+    }
+
+    /// Decode and execute an instruction with verbose output
+    fn decode_and_execute_with_verbosity(
         &mut self,
         instruction: u32,
-        memory: &mut Memory,
-        peripherals: &mut crate::peripheral::PeripheralManager,
+        bus: &mut dyn Bus,
         verbosity: u8,
     ) -> Result<()> {
+        if is_unimp(instruction) {
+            return Err(EmulatorError::Unimp(self.pc));
+        }
+        // The only compressed instruction recognized; it behaves exactly like EBREAK
+        if instruction & 0xFFFF == C_EBREAK {
+            return self.execute_system(EBREAK);
+        }
+        if let Some(action) = self.strict_decode {
+            if let Some(reason) = strict_decode_violation(instruction) {
+                match action {
+                    StrictDecodeAction::Fault => return Err(EmulatorError::UnsupportedInstruction),
+                    StrictDecodeAction::Warn => self.report_strict_decode(instruction, reason),
+                }
+            }
+        }
+
         // Extract opcode (bits 0-6)
         let opcode = instruction & 0x7F;
 
         debug_log!(verbosity, "  Opcode: 0x{opcode:02x}");
 
-        match opcode {
+        let result = match opcode {
             0x13 => {
                 // I-type instruction (ADDI, SLTI, XORI, etc.)
                 debug_log!(verbosity, "  I-type instruction");
@@ -298,12 +2375,12 @@ impl Cpu {
             0x03 => {
                 // Load instructions (LB, LH, LW, LBU, LHU)
                 debug_log!(verbosity, "  Load instruction");
-                self.execute_load_with_peripherals(instruction, memory, peripherals)
+                self.execute_load(instruction, bus)
             }
             0x23 => {
                 // Store instructions (SB, SH, SW)
                 debug_log!(verbosity, "  Store instruction");
-                self.execute_store_with_peripherals(instruction, memory, peripherals)
+                self.execute_store(instruction, bus)
             }
             0x63 => {
                 // Branch instructions (BEQ, BNE, BLT, BGE, BLTU, BGEU)
@@ -330,21 +2407,36 @@ impl Cpu {
                 debug_log!(verbosity, "  JALR instruction");
                 self.execute_jalr(instruction)
             }
+            0x73 if instruction == ECALL => {
+                // ECALL - dispatched according to the configured EcallBehavior
+                debug_log!(verbosity, "  ECALL");
+                self.execute_ecall(bus)
+            }
             0x73 => {
-                // System instructions (ECALL, EBREAK)
+                // System instructions (EBREAK, MRET, CSR operations)
                 debug_log!(verbosity, "  System instruction");
                 self.execute_system(instruction)
             }
-            0x2F => {
+            0x2F if self.has_extension(MISA_A) => {
                 // RV32A atomic instructions
                 debug_log!(verbosity, "  Atomic instruction");
-                self.execute_atomic_with_peripherals(instruction, memory, peripherals)
+                self.execute_atomic(instruction, bus)
             }
             0x0F => {
                 // FENCE instruction family (memory ordering)
                 debug_log!(verbosity, "  FENCE instruction");
                 let funct3 = (instruction >> 12) & 0x7;
                 match funct3 {
+                    0x0 if instruction == FENCE_TSO => {
+                        // FENCE.TSO - accesses already complete in program order
+                        self.pc = self.pc.wrapping_add(4);
+                        Ok(())
+                    }
+                    0x0 if instruction == PAUSE => {
+                        // PAUSE - spin-wait hint; the step samples interrupts right after it
+                        self.pc = self.pc.wrapping_add(4);
+                        Ok(())
+                    }
                     0x0 => {
                         // FENCE - memory fence
                         // For our simple emulator, we'll treat it as a no-op
@@ -353,7 +2445,11 @@ impl Cpu {
                     }
                     0x1 => {
                         // FENCE.I - instruction fence
-                        // For our simple emulator, we'll treat it as a no-op
+                        // Only the decode cache needs to be synchronized with memory
+                        self.flush_icache();
+                        if let Some(smc) = &mut self.smc {
+                            smc.modified.clear();
+                        }
                         self.pc = self.pc.wrapping_add(4);
                         Ok(())
                     }
@@ -364,7 +2460,13 @@ impl Cpu {
                 // Unsupported instruction
                 Err(EmulatorError::UnsupportedInstruction)
             }
+        };
+        // Counters advance after the instruction, so its own CSR reads see a single snapshot
+        if result.is_ok() {
+            self.cycle = self.cycle.wrapping_add(1);
+            self.instret = self.instret.wrapping_add(1);
         }
+        result
     }
 
     /// Execute I-type instructions
@@ -476,7 +2578,7 @@ impl Cpu {
                 // AND instruction
                 self.execute_and(rd, rs1, rs2)
             }
-            (0x01, _) => {
+            (0x01, _) if self.has_extension(MISA_M) => {
                 // RV32M extensions (MUL, DIV, etc.)
                 self.execute_m_type(rd, rs1, rs2, funct3)
             }
@@ -755,11 +2857,7 @@ impl Cpu {
             }
             0x5 => {
                 // DIVU
-                if rs2_value == 0 {
-                    u32::MAX // Division by zero result
-                } else {
-                    rs1_value / rs2_value
-                }
+                rs1_value.checked_div(rs2_value).unwrap_or(u32::MAX) // Division by zero yields all ones
             }
             0x6 => {
                 // REM
@@ -788,7 +2886,7 @@ impl Cpu {
     }
 
     /// Execute load instructions (LB, LH, LW, LBU, LHU)
-    fn execute_load(&mut self, instruction: u32, memory: &mut Memory) -> Result<()> {
+    fn execute_load(&mut self, instruction: u32, memory: &mut dyn Bus) -> Result<()> {
         let rd = ((instruction >> 7) & 0x1F) as usize;
         let funct3 = (instruction >> 12) & 0x7;
         let rs1 = ((instruction >> 15) & 0x1F) as usize;
@@ -801,38 +2899,31 @@ impl Cpu {
         let base_addr = self.read_register(rs1);
         let addr = base_addr.wrapping_add(imm as u32);
 
-        match funct3 {
-            0x0 => {
-                // LB - Load byte (sign-extended)
-                let value = memory.read_byte(addr)? as i8 as i32 as u32;
-                self.write_register(rd, value);
-            }
-            0x1 => {
-                // LH - Load halfword (sign-extended, supports misaligned access)
-                let value = memory.read_halfword(addr)? as u32;
-                let sign_extended = if value & 0x8000 != 0 {
-                    value | 0xFFFF0000
-                } else {
-                    value
-                };
-                self.write_register(rd, sign_extended);
-            }
-            0x2 => {
-                // LW - Load word
-                let value = memory.read_word(addr)?;
-                self.write_register(rd, value);
-            }
-            0x4 => {
-                // LBU - Load byte unsigned
-                let value = memory.read_byte(addr)? as u32;
-                self.write_register(rd, value);
-            }
-            0x5 => {
-                // LHU - Load halfword unsigned (supports misaligned access)
-                let value = memory.read_halfword(addr)? as u32;
-                self.write_register(rd, value);
-            }
+        let loaded = match funct3 {
+            // LB - Load byte (sign-extended)
+            0x0 => memory
+                .read_byte(addr)
+                .map(|value| value as i8 as i32 as u32),
+            // LH - Load halfword (sign-extended, supports misaligned access)
+            0x1 => memory
+                .read_halfword(addr)
+                .map(|value| value as i16 as i32 as u32),
+            // LW - Load word
+            0x2 => memory.read_word(addr),
+            // LBU - Load byte unsigned
+            0x4 => memory.read_byte(addr).map(u32::from),
+            // LHU - Load halfword unsigned (supports misaligned access)
+            0x5 => memory.read_halfword(addr).map(u32::from),
             _ => return Err(EmulatorError::UnsupportedInstruction),
+        };
+        match loaded {
+            Ok(value) => self.write_register(rd, value),
+            // Never-written memory under `UninitPolicy::Trap`: a load access fault the guest can handle
+            Err(EmulatorError::UninitializedRead(address)) => {
+                self.take_trap(CAUSE_LOAD_ACCESS_FAULT, address);
+                return self.check_trap_depth();
+            }
+            Err(e) => return Err(e),
         }
 
         self.pc = self.pc.wrapping_add(4);
@@ -840,7 +2931,7 @@ impl Cpu {
     }
 
     /// Execute store instructions (SB, SH, SW)
-    fn execute_store(&mut self, instruction: u32, memory: &mut Memory) -> Result<()> {
+    fn execute_store(&mut self, instruction: u32, memory: &mut dyn Bus) -> Result<()> {
         let imm_4_0 = (instruction >> 7) & 0x1F;
         let funct3 = (instruction >> 12) & 0x7;
         let rs1 = ((instruction >> 15) & 0x1F) as usize;
@@ -862,6 +2953,20 @@ impl Cpu {
         let base_addr = self.read_register(rs1);
         let addr = base_addr.wrapping_add(imm as u32);
         let value = self.read_register(rs2);
+        if self.smc.is_some() && funct3 <= 2 {
+            self.smc_store(addr, 1 << funct3, memory);
+        }
+
+        if let Some(guard) = self.stack_guard {
+            if rs1 == Reg::Sp.index() && addr < guard {
+                return Err(EmulatorError::StackOverflow(StackOverflow {
+                    pc: self.pc,
+                    sp: base_addr,
+                    guard,
+                    address: addr,
+                }));
+            }
+        }
 
         match funct3 {
             0x0 => {
@@ -883,145 +2988,6 @@ impl Cpu {
         Ok(())
     }
 
-    /// Execute load instructions with peripheral support
-    fn execute_load_with_peripherals(
-        &mut self,
-        instruction: u32,
-        memory: &mut Memory,
-        peripherals: &mut crate::peripheral::PeripheralManager,
-    ) -> Result<()> {
-        let rd = ((instruction >> 7) & 0x1F) as usize;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs1 = ((instruction >> 15) & 0x1F) as usize;
-        let imm = (instruction as i32) >> 20; // Sign-extend immediate
-
-        if rd >= NUM_REGISTERS || rs1 >= NUM_REGISTERS {
-            return Err(EmulatorError::UnsupportedInstruction);
-        }
-
-        let base_addr = self.read_register(rs1);
-        let addr = base_addr.wrapping_add(imm as u32);
-
-        // Check if this is a peripheral address
-        if peripherals.is_peripheral_address(addr) {
-            // Only support word loads from peripherals for now
-            match funct3 {
-                0x2 => {
-                    // LW - Load word from peripheral
-                    let value = peripherals.read(addr)?;
-                    self.write_register(rd, value);
-                }
-                _ => {
-                    // For now, only support word access to peripherals
-                    return Err(EmulatorError::UnsupportedInstruction);
-                }
-            }
-        } else {
-            // Normal memory access
-            match funct3 {
-                0x0 => {
-                    // LB - Load byte (sign-extended)
-                    let value = memory.read_byte(addr)? as i8 as i32 as u32;
-                    self.write_register(rd, value);
-                }
-                0x1 => {
-                    // LH - Load halfword (sign-extended, supports misaligned access)
-                    let value = memory.read_halfword(addr)? as u32;
-                    let sign_extended = if value & 0x8000 != 0 {
-                        value | 0xFFFF0000
-                    } else {
-                        value
-                    };
-                    self.write_register(rd, sign_extended);
-                }
-                0x2 => {
-                    // LW - Load word
-                    let value = memory.read_word(addr)?;
-                    self.write_register(rd, value);
-                }
-                0x4 => {
-                    // LBU - Load byte unsigned
-                    let value = memory.read_byte(addr)? as u32;
-                    self.write_register(rd, value);
-                }
-                0x5 => {
-                    // LHU - Load halfword unsigned (supports misaligned access)
-                    let value = memory.read_halfword(addr)? as u32;
-                    self.write_register(rd, value);
-                }
-                _ => return Err(EmulatorError::UnsupportedInstruction),
-            }
-        }
-
-        self.pc = self.pc.wrapping_add(4);
-        Ok(())
-    }
-
-    /// Execute store instructions with peripheral support
-    fn execute_store_with_peripherals(
-        &mut self,
-        instruction: u32,
-        memory: &mut Memory,
-        peripherals: &mut crate::peripheral::PeripheralManager,
-    ) -> Result<()> {
-        let imm_4_0 = (instruction >> 7) & 0x1F;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs1 = ((instruction >> 15) & 0x1F) as usize;
-        let rs2 = ((instruction >> 20) & 0x1F) as usize;
-        let imm_11_5 = (instruction >> 25) & 0x7F;
-
-        if rs1 >= NUM_REGISTERS || rs2 >= NUM_REGISTERS {
-            return Err(EmulatorError::UnsupportedInstruction);
-        }
-
-        // Reconstruct 12-bit signed immediate
-        let imm = ((imm_11_5 << 5) | imm_4_0) as i32;
-        let imm = if imm & 0x800 != 0 {
-            imm | 0xFFFFF000u32 as i32 // Sign extend
-        } else {
-            imm
-        };
-
-        let base_addr = self.read_register(rs1);
-        let addr = base_addr.wrapping_add(imm as u32);
-        let value = self.read_register(rs2);
-
-        // Check if this is a peripheral address
-        if peripherals.is_peripheral_address(addr) {
-            // Only support word stores to peripherals for now
-            match funct3 {
-                0x2 => {
-                    // SW - Store word to peripheral
-                    peripherals.write(addr, value)?;
-                }
-                _ => {
-                    // For now, only support word access to peripherals
-                    return Err(EmulatorError::UnsupportedInstruction);
-                }
-            }
-        } else {
-            // Normal memory access
-            match funct3 {
-                0x0 => {
-                    // SB - Store byte
-                    memory.write_byte(addr, value as u8)?;
-                }
-                0x1 => {
-                    // SH - Store halfword (supports misaligned access)
-                    memory.write_halfword(addr, value as u16)?;
-                }
-                0x2 => {
-                    // SW - Store word
-                    memory.write_word(addr, value)?;
-                }
-                _ => return Err(EmulatorError::UnsupportedInstruction),
-            }
-        }
-
-        self.pc = self.pc.wrapping_add(4);
-        Ok(())
-    }
-
     /// Execute branch instructions (BEQ, BNE, BLT, BGE, BLTU, BGEU)
     fn execute_branch(&mut self, instruction: u32) -> Result<()> {
         let imm_11 = (instruction >> 7) & 0x1;
@@ -1056,6 +3022,7 @@ impl Cpu {
             0x7 => rs1_value >= rs2_value,                   // BGEU
             _ => return Err(EmulatorError::UnsupportedInstruction),
         };
+        self.branch_taken = branch_taken;
 
         if branch_taken {
             self.pc = self.pc.wrapping_add(offset as u32);
@@ -1145,6 +3112,217 @@ impl Cpu {
         Ok(())
     }
 
+    /// Execute ECALL according to the configured `EcallBehavior`
+    fn execute_ecall(&mut self, bus: &mut dyn Bus) -> Result<()> {
+        match &self.hooks.ecall {
+            EcallBehavior::TerminateTests => Err(EmulatorError::EcallTermination),
+            EcallBehavior::Trap => {
+                self.take_trap(self.privilege.ecall_cause(), 0);
+                self.check_trap_depth()
+            }
+            EcallBehavior::Handler(_) => {
+                // Detach the handler so it can borrow the CPU mutably
+                let mut behavior = std::mem::take(&mut self.hooks.ecall);
+                let EcallBehavior::Handler(handler) = &mut behavior else {
+                    unreachable!();
+                };
+                let action = handler.handle(self, bus);
+                self.hooks.ecall = behavior;
+                match action? {
+                    SyscallAction::Continue => {
+                        self.pc = self.pc.wrapping_add(4);
+                        Ok(())
+                    }
+                    SyscallAction::Exit => Err(EmulatorError::EcallTermination),
+                }
+            }
+        }
+    }
+
+    /// Enter the machine-mode trap handler for an exception or interrupt at the current PC
+    ///
+    /// Sets mepc, mcause and mtval, stacks MIE into MPIE and jumps to the mtvec base
+    /// (or its vector entry for interrupts in vectored mode).
+    pub fn take_trap(&mut self, cause: u32, tval: u32) {
+        self.trap_chain.push((cause, self.pc));
+        // An SC.W after the handler returns must not pair with an LR.W before it
+        self.reservation = None;
+        let mtvec = self.mtvec();
+        self.set_mepc(self.pc);
+        self.write_csr(CSR_MCAUSE, cause);
+        self.write_csr(CSR_MTVAL, tval);
+        let mstatus = self.mstatus();
+        let mut stacked =
+            (mstatus & !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP)) | (self.privilege as u32) << 11;
+        if mstatus & MSTATUS_MIE != 0 {
+            stacked |= MSTATUS_MPIE;
+        }
+        self.set_mstatus(stacked);
+        self.privilege = PrivMode::Machine;
+        // Vectored mode sends interrupts to base + 4 * cause
+        self.pc = if mtvec & 0x3 == 1 && cause & CAUSE_INTERRUPT != 0 {
+            (mtvec & !0x3).wrapping_add(4 * (cause & !CAUSE_INTERRUPT))
+        } else {
+            mtvec & !0x3
+        };
+    }
+
+    /// Word address reserved by LR.W, if the reservation is still valid
+    pub fn reservation(&self) -> Option<u32> {
+        self.reservation
+    }
+
+    /// Observe another hart's store or AMO of `len` bytes at `address`
+    ///
+    /// Drops this hart's reservation if the store overlaps the reserved word,
+    /// so its next SC.W fails. A scheduler running several harts over shared
+    /// memory calls this on every other hart after each store.
+    pub fn snoop_store(&mut self, address: u32, len: u32) {
+        if let Some(reserved) = self.reservation {
+            let offset = reserved.wrapping_sub(address);
+            if offset < len || address.wrapping_sub(reserved) < 4 {
+                self.reservation = None;
+            }
+        }
+    }
+
+    /// Current privilege level
+    pub fn privilege(&self) -> PrivMode {
+        self.privilege
+    }
+
+    /// Switch to privilege level `mode`
+    ///
+    /// Only ECALL causes, counter access and MRET look at the mode; memory
+    /// accesses are not checked against it.
+    pub fn set_privilege(&mut self, mode: PrivMode) {
+        self.privilege = mode;
+    }
+
+    /// `(mcause, mepc)` of the traps taken and not yet returned from, outermost first
+    pub fn trap_chain(&self) -> &[(u32, u32)] {
+        &self.trap_chain
+    }
+
+    /// Fail with `TrapLoop` once traps nest deeper than the configured maximum
+    fn check_trap_depth(&self) -> Result<()> {
+        let depth = self.trap_chain.len() as u32;
+        if depth > self.max_trap_depth {
+            return Err(EmulatorError::TrapLoop(depth));
+        }
+        Ok(())
+    }
+
+    /// One line per nested trap of a `TrapLoop`, for the run log
+    fn describe_trap_loop(&self, depth: u32) -> String {
+        let mut text = format!("Trap loop: {depth} nested traps without MRET");
+        for &(cause, epc) in &self.trap_chain {
+            text.push_str(&format!("\n  mcause=0x{cause:08x} mepc=0x{epc:08x}"));
+        }
+        text
+    }
+
+    /// Hold `interrupt` pending until `clear_interrupt`, as a peripheral line would
+    ///
+    /// The bit is visible in `mip` immediately and is taken as a trap at the
+    /// start of the next step once enabled in `mie` and `mstatus.MIE`.
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) {
+        self.raised_interrupts |= interrupt.mip_bit();
+        let mip = self.read_csr(CSR_MIP) | interrupt.mip_bit();
+        self.write_csr(CSR_MIP, mip);
+    }
+
+    /// Release an interrupt raised with `raise_interrupt`
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.raised_interrupts &= !interrupt.mip_bit();
+        let mip = self.read_csr(CSR_MIP) & !interrupt.mip_bit();
+        self.write_csr(CSR_MIP, mip);
+    }
+
+    /// Latch peripheral interrupt `lines` into `mip` and take the highest-priority enabled one
+    ///
+    /// Returns whether an interrupt trap was taken.
+    pub fn sample_interrupts(&mut self, lines: u32) -> bool {
+        let old = self.read_csr(CSR_MIP);
+        let mip = (old & !MIP_LINES) | ((lines | self.raised_interrupts) & MIP_LINES);
+        if mip != old {
+            self.write_csr(CSR_MIP, mip);
+        }
+        if mip == 0 || self.read_csr(CSR_MSTATUS) & MSTATUS_MIE == 0 {
+            return false;
+        }
+        let pending = mip & self.read_csr(CSR_MIE);
+        match INTERRUPT_PRIORITY
+            .into_iter()
+            .find(|&code| pending & (1 << code) != 0)
+        {
+            Some(code) => {
+                self.take_trap(CAUSE_INTERRUPT | code, 0);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sleep through WFI: advance virtual time to the next interrupt enabled in mie
+    ///
+    /// The peripherals and the cycle counter jump ahead together. Returns
+    /// whether an enabled interrupt is now pending. Native builds only; the
+    /// web front end waits for WFI in real time (`waiting_for_interrupt`).
+    fn skip_to_interrupt(
+        &mut self,
+        peripherals: &mut crate::peripheral::PeripheralManager,
+    ) -> bool {
+        if cfg!(target_arch = "wasm32") {
+            return false;
+        }
+        let enabled = self.read_csr(CSR_MIE);
+        if self.raised_interrupts & enabled != 0 {
+            return true;
+        }
+        let Some(cycles) = peripherals.cycles_until_interrupt(enabled) else {
+            return false;
+        };
+        peripherals.tick_all(cycles);
+        self.cycle = self.cycle.wrapping_add(cycles);
+        peripherals.pending_interrupts() & enabled != 0
+    }
+
+    /// Whether the last run stopped at WFI and no enabled interrupt is pending yet
+    ///
+    /// `lines` are the peripheral interrupt lines that will be sampled into mip.
+    /// Front ends can skip calling the run loop while this holds.
+    pub fn waiting_for_interrupt(&self, lines: u32) -> bool {
+        let lines = lines | self.raised_interrupts;
+        let mip = (self.read_csr(CSR_MIP) & !MIP_LINES) | (lines & MIP_LINES);
+        self.exit_reason == Some(ExitReason::Waiting) && mip & self.read_csr(CSR_MIE) == 0
+    }
+
+    /// Select how ECALL is handled
+    pub fn set_ecall_behavior(&mut self, behavior: EcallBehavior) {
+        self.hooks.ecall = behavior;
+    }
+
+    /// Current ECALL handling mode
+    pub fn ecall_behavior(&self) -> &EcallBehavior {
+        &self.hooks.ecall
+    }
+
+    /// Whether the current privilege level may access `csr`
+    ///
+    /// Only the unprivileged counters are gated: below M-mode they need their
+    /// `mcounteren` bit, and in U-mode also their `scounteren` bit. Machine
+    /// mode may always read them.
+    fn csr_accessible(&self, csr: u16) -> bool {
+        if self.privilege == PrivMode::Machine || !matches!(csr, 0xC00..=0xC1F | 0xC80..=0xC9F) {
+            return true;
+        }
+        let bit = 1 << (csr & 0x1F);
+        let enabled = |enable_csr| self.csrs.get(&enable_csr).copied().unwrap_or(0) & bit != 0;
+        enabled(CSR_MCOUNTEREN)
+            && (self.privilege == PrivMode::Supervisor || enabled(CSR_SCOUNTEREN))
+    }
+
     /// Execute system instructions (ECALL, EBREAK, CSR operations)
     fn execute_system(&mut self, instruction: u32) -> Result<()> {
         let funct3 = (instruction >> 12) & 0x7;
@@ -1152,7 +3330,17 @@ impl Cpu {
         let rs1 = ((instruction >> 15) & 0x1F) as usize;
         let csr = ((instruction >> 20) & 0xFFF) as u16;
 
+        if funct3 != 0 && !self.csr_accessible(csr) {
+            self.take_trap(CAUSE_ILLEGAL_INSTRUCTION, instruction);
+            return self.check_trap_depth();
+        }
+
         match funct3 {
+            0x0 if is_sfence_vma(instruction) => {
+                // SFENCE.VMA - Address translation is not modeled, so there is no TLB to flush
+                self.pc = self.pc.wrapping_add(4);
+                Ok(())
+            }
             0x0 => {
                 // ECALL/EBREAK/MRET
                 let funct12 = instruction >> 20;
@@ -1164,27 +3352,53 @@ impl Cpu {
                     }
                     0x001 => {
                         // EBREAK - Environment break
-                        Err(EmulatorError::UnsupportedInstruction)
+                        // In breakpoint mode this stops the run loop with PC left at the EBREAK
+                        if self.breakpoint_mode {
+                            Err(EmulatorError::Breakpoint)
+                        } else {
+                            Err(EmulatorError::UnsupportedInstruction)
+                        }
                     }
                     0x302 => {
-                        // MRET - Machine return
-                        // For our simple emulator, we'll just treat it as a no-op and continue
-                        // In a real implementation, this would restore machine-mode state
-                        self.pc = self.pc.wrapping_add(4);
+                        // MRET - Machine return: resume at mepc in the mode held in MPP
+                        // and restore MIE from MPIE
+                        let mstatus = self.mstatus();
+                        let mpie = (mstatus & MSTATUS_MPIE) != 0;
+                        let previous = PrivMode::from_mpp(mstatus >> 11);
+                        // MPP falls back to the least-privileged mode, U
+                        let mut mstatus = (mstatus & !(MSTATUS_MIE | MSTATUS_MPP)) | MSTATUS_MPIE;
+                        if mpie {
+                            mstatus |= MSTATUS_MIE;
+                        }
+                        if previous != PrivMode::Machine {
+                            mstatus &= !MSTATUS_MPRV;
+                        }
+                        self.set_mstatus(mstatus);
+                        self.privilege = previous;
+                        self.pc = self.mepc();
+                        self.trap_chain.pop();
                         Ok(())
                     }
+                    0x105 => {
+                        // WFI - Wait for interrupt: a no-op if one is pending, otherwise stop the run
+                        self.pc = self.pc.wrapping_add(4);
+                        if self.mip() & self.mie() != 0 {
+                            Ok(())
+                        } else {
+                            Err(EmulatorError::WaitForInterrupt)
+                        }
+                    }
                     _ => Err(EmulatorError::UnsupportedInstruction),
                 }
             }
             0x1 => {
-                // CSRRW - CSR Read/Write
-                let old_value = self.read_csr(csr);
+                // CSRRW - CSR Read/Write; with rd=x0 the CSR is not read (no read side effects)
                 if rd != 0 {
-                    // Only read old value if rd is non-zero
+                    let old_value = self.read_csr(csr);
                     self.write_register(rd, old_value);
                 }
                 let new_value = self.read_register(rs1);
-                self.write_csr(csr, new_value);
+                self.write_csr_from_guest(csr, new_value);
                 self.pc = self.pc.wrapping_add(4);
                 Ok(())
             }
@@ -1195,7 +3409,7 @@ impl Cpu {
                     // Only write if rs1 is non-zero
                     let mask = self.read_register(rs1);
                     let new_value = old_value | mask;
-                    self.write_csr(csr, new_value);
+                    self.write_csr_from_guest(csr, new_value);
                 }
                 self.write_register(rd, old_value);
                 self.pc = self.pc.wrapping_add(4);
@@ -1208,21 +3422,20 @@ impl Cpu {
                     // Only write if rs1 is non-zero
                     let mask = self.read_register(rs1);
                     let new_value = old_value & !mask;
-                    self.write_csr(csr, new_value);
+                    self.write_csr_from_guest(csr, new_value);
                 }
                 self.write_register(rd, old_value);
                 self.pc = self.pc.wrapping_add(4);
                 Ok(())
             }
             0x5 => {
-                // CSRRWI - CSR Read/Write Immediate
-                let old_value = self.read_csr(csr);
+                // CSRRWI - CSR Read/Write Immediate; with rd=x0 the CSR is not read
                 if rd != 0 {
-                    // Only read old value if rd is non-zero
+                    let old_value = self.read_csr(csr);
                     self.write_register(rd, old_value);
                 }
                 let imm = rs1 as u32; // rs1 field contains immediate value (zero-extended)
-                self.write_csr(csr, imm);
+                self.write_csr_from_guest(csr, imm);
                 self.pc = self.pc.wrapping_add(4);
                 Ok(())
             }
@@ -1233,7 +3446,7 @@ impl Cpu {
                 if imm != 0 {
                     // Only write if immediate is non-zero
                     let new_value = old_value | imm;
-                    self.write_csr(csr, new_value);
+                    self.write_csr_from_guest(csr, new_value);
                 }
                 self.write_register(rd, old_value);
                 self.pc = self.pc.wrapping_add(4);
@@ -1246,7 +3459,7 @@ impl Cpu {
                 if imm != 0 {
                     // Only write if immediate is non-zero
                     let new_value = old_value & !imm;
-                    self.write_csr(csr, new_value);
+                    self.write_csr_from_guest(csr, new_value);
                 }
                 self.write_register(rd, old_value);
                 self.pc = self.pc.wrapping_add(4);
@@ -1257,7 +3470,7 @@ impl Cpu {
     }
 
     /// Execute RV32A atomic instructions
-    fn execute_atomic(&mut self, instruction: u32, memory: &mut Memory) -> Result<()> {
+    fn execute_atomic(&mut self, instruction: u32, memory: &mut dyn Bus) -> Result<()> {
         let rd = ((instruction >> 7) & 0x1F) as usize;
         let funct3 = (instruction >> 12) & 0x7;
         let rs1 = ((instruction >> 15) & 0x1F) as usize;
@@ -1271,6 +3484,10 @@ impl Cpu {
         }
 
         let addr = self.read_register(rs1);
+        if !memory.supports_atomics(addr) {
+            // Atomic operations on peripherals are not supported
+            return Err(EmulatorError::UnsupportedInstruction);
+        }
 
         // For this implementation, we'll ignore the aq/rl bits for simplicity
         let _ = (aq, rl);
@@ -1280,14 +3497,17 @@ impl Cpu {
                 // LR.W - Load Reserved Word
                 let value = memory.read_word(addr)?;
                 self.write_register(rd, value);
-                // TODO: Set reservation on address for SC.W
+                self.reservation = Some(addr & !3);
             }
             0x03 => {
-                // SC.W - Store Conditional Word
-                let value = self.read_register(rs2);
-                memory.write_word(addr, value)?;
-                // For simplicity, always succeed (return 0)
-                self.write_register(rd, 0);
+                // SC.W - Store Conditional Word: stores and writes 0 only with a
+                // valid reservation on the word, writes 1 otherwise
+                if self.reservation.take() == Some(addr & !3) {
+                    memory.write_word(addr, self.read_register(rs2))?;
+                    self.write_register(rd, 0);
+                } else {
+                    self.write_register(rd, 1);
+                }
             }
             0x01 => {
                 // AMOSWAP.W
@@ -1379,28 +3599,38 @@ impl Cpu {
         Ok(())
     }
 
-    /// Execute atomic instructions with peripheral support
-    fn execute_atomic_with_peripherals(
-        &mut self,
-        instruction: u32,
-        memory: &mut Memory,
-        peripherals: &mut crate::peripheral::PeripheralManager,
-    ) -> Result<()> {
-        // For peripherals, we'll only allow normal memory atomic operations for now
-        // Peripheral addresses don't support atomic operations in this implementation
-        let rs1 = ((instruction >> 15) & 0x1F) as usize;
-        if rs1 >= NUM_REGISTERS {
-            return Err(EmulatorError::UnsupportedInstruction);
+    /// Describe an error at the current PC, including the faulting instruction and its disassembly
+    pub fn describe_fault(&self, memory: &Memory, error: &EmulatorError) -> String {
+        let pc = self.pc;
+        if let EmulatorError::WildJump(jump) = error {
+            let asm = crate::disasm::disassemble_at(memory.peek_word(jump.pc), jump.pc);
+            return format!("Error at 0x{:08x}: {jump} in `{asm}`", jump.pc);
         }
-
-        let addr = self.read_register(rs1);
-        if peripherals.is_peripheral_address(addr) {
-            // Atomic operations on peripherals not supported
-            return Err(EmulatorError::UnsupportedInstruction);
+        let Ok(word) = memory.read_u32_le(pc) else {
+            return format!("Error at 0x{pc:08x}: {error}");
+        };
+        let asm = crate::disasm::disassemble_at(word, pc);
+        let asm = if asm.starts_with(".word") {
+            ".word"
+        } else {
+            asm.as_str()
+        };
+        match error {
+            EmulatorError::UnsupportedInstruction => {
+                format!("Error at 0x{pc:08x}: illegal instruction `0x{word:08x}` ({asm})")
+            }
+            _ => format!("Error at 0x{pc:08x}: {error} in `0x{word:08x}` ({asm})"),
         }
+    }
 
-        // Use normal atomic implementation for memory addresses
-        self.execute_atomic(instruction, memory)
+    /// Exit reason for an ECALL termination; the guest exit code is taken from a0
+    pub fn ecall_exit_reason(&self) -> ExitReason {
+        ExitReason::EcallExit(self.reg(Reg::A0))
+    }
+
+    /// Guest exit code of the most recent run, if it terminated via ECALL
+    pub fn exit_code(&self) -> Option<u32> {
+        self.exit_reason.and_then(|reason| reason.exit_code())
     }
 
     /// Run the CPU until it encounters an error or reaches a halt condition
@@ -1416,6 +3646,9 @@ impl Cpu {
         verbosity: u8,
     ) -> Result<u32> {
         let mut executed_instructions = 0;
+        self.exit_reason = None;
+        self.arm_watches(memory);
+        self.arm_time_budget();
 
         debug_log!(
             verbosity,
@@ -1431,6 +3664,7 @@ impl Cpu {
             if let Some(max) = max_instructions {
                 if executed_instructions >= max {
                     info_log!(verbosity, "Instruction limit ({max}) reached");
+                    self.exit_reason = Some(ExitReason::InstructionLimit);
                     break;
                 }
             }
@@ -1444,61 +3678,96 @@ impl Cpu {
             );
             if verbosity >= 3 {
                 // Show instruction being executed
-                if let Ok(instruction) = memory.read_word(self.pc) {
+                if let Ok(instruction) = memory.read_u32_le(self.pc) {
                     debug_log!(verbosity, "  Instruction: 0x{instruction:08x}");
-                    // Show some key registers before execution
-                    debug_log!(
-                        verbosity,
-                        "  Before: x1=0x{:08x} x2=0x{:08x} x3=0x{:08x} x10=0x{:08x}",
-                        self.read_register(1),
-                        self.read_register(2),
-                        self.read_register(3),
-                        self.read_register(10)
-                    );
                 }
             }
 
             // Execute one instruction
+            let step_pc = self.pc;
+            let traced = self.trace_prepare(memory);
+            let delta = (verbosity >= 2).then(|| self.delta_prepare(memory));
             match self.step_with_verbosity(memory, verbosity) {
                 Ok(()) => {
                     executed_instructions += 1;
-                    debug_log!(
-                        verbosity,
-                        "  After:  x1=0x{:08x} x2=0x{:08x} x3=0x{:08x} x10=0x{:08x}",
-                        self.read_register(1),
-                        self.read_register(2),
-                        self.read_register(3),
-                        self.read_register(10)
-                    );
+                    if let Some(delta) = delta {
+                        self.log_step_delta(delta);
+                    }
+                    if let Some(point) = traced {
+                        if !self.trace_step(executed_instructions, point) {
+                            info_log!(verbosity, "Trace divergence at PC: 0x{:08x}", self.pc);
+                            self.exit_reason =
+                                Some(ExitReason::TraceDivergence(executed_instructions));
+                            break;
+                        }
+                    }
+                    if !self.hooks.watches.is_empty() {
+                        if let Some(hit) = self.check_watches(memory, step_pc) {
+                            info_log!(verbosity, "{hit}");
+                            self.exit_reason = Some(hit);
+                            break;
+                        }
+                    }
+                    self.service_htif(memory, step_pc)?;
+                    if let Some(reached) = self.check_run_target(memory, step_pc) {
+                        info_log!(verbosity, "{reached}");
+                        self.exit_reason = Some(reached);
+                        break;
+                    }
+                    if self.time_budget_exceeded(executed_instructions) {
+                        info_log!(verbosity, "Time budget exceeded at PC: 0x{:08x}", self.pc);
+                        self.exit_reason = Some(ExitReason::TimeBudgetExceeded);
+                        break;
+                    }
+                    if self.report_progress(executed_instructions) {
+                        info_log!(verbosity, "Run cancelled at PC: 0x{:08x}", self.pc);
+                        self.exit_reason = Some(ExitReason::Cancelled);
+                        break;
+                    }
                     debug_log!(verbosity, "");
                 }
-                Err(EmulatorError::UnsupportedInstruction) => {
-                    basic_log!(
-                        verbosity,
-                        "Unsupported instruction at PC: 0x{:08x}",
-                        self.pc
-                    );
+                Err(e) => {
+                    self.end_run(memory, e, &mut executed_instructions, verbosity)?;
                     break;
                 }
-                Err(EmulatorError::EcallTermination) => {
-                    // Normal termination via ECALL - this is expected in riscv-tests
+            }
+        }
+
+        debug_log!(verbosity, "=== CPU execution completed ===");
+        debug_log!(
+            verbosity,
+            "Total instructions executed: {executed_instructions}"
+        );
+
+        Ok(executed_instructions)
+    }
+
+    /// Run without logging, tracing, watches or progress callbacks
+    ///
+    /// Only HTIF is still serviced, since programs that use it cannot
+    /// terminate otherwise. Stops on the same conditions as `run`.
+    pub fn run_fast(&mut self, memory: &mut Memory, max_instructions: Option<u32>) -> Result<u32> {
+        let mut executed_instructions = 0;
+        self.exit_reason = None;
+        let max = max_instructions.unwrap_or(u32::MAX);
+        while executed_instructions < max {
+            let step_pc = self.pc;
+            match self.step(memory) {
+                Ok(()) => {
                     executed_instructions += 1;
-                    info_log!(verbosity, "ECALL termination at PC: 0x{:08x}", self.pc);
-                    break;
+                    self.service_htif(memory, step_pc)?;
+                    if let Some(reached) = self.check_run_target(memory, step_pc) {
+                        self.exit_reason = Some(reached);
+                        return Ok(executed_instructions);
+                    }
                 }
                 Err(e) => {
-                    basic_log!(verbosity, "Error at PC: 0x{:08x}: {e}", self.pc);
-                    return Err(e);
+                    self.end_run(memory, e, &mut executed_instructions, 0)?;
+                    return Ok(executed_instructions);
                 }
             }
         }
-
-        debug_log!(verbosity, "=== CPU execution completed ===");
-        debug_log!(
-            verbosity,
-            "Total instructions executed: {executed_instructions}"
-        );
-
+        self.exit_reason = Some(ExitReason::InstructionLimit);
         Ok(executed_instructions)
     }
 
@@ -1521,6 +3790,9 @@ impl Cpu {
         verbosity: u8,
     ) -> Result<u32> {
         let mut executed_instructions = 0;
+        self.exit_reason = None;
+        self.arm_watches(memory);
+        self.arm_time_budget();
 
         debug_log!(
             verbosity,
@@ -1536,6 +3808,7 @@ impl Cpu {
             if let Some(max) = max_instructions {
                 if executed_instructions >= max {
                     info_log!(verbosity, "Instruction limit ({max}) reached");
+                    self.exit_reason = Some(ExitReason::InstructionLimit);
                     break;
                 }
             }
@@ -1549,15 +3822,75 @@ impl Cpu {
             );
 
             // Execute one instruction
+            let step_pc = self.pc;
+            let device_load = self.idle.is_some() && self.loads_from_device(memory, peripherals);
+            let traced = self.trace_prepare(memory);
+            let delta = (verbosity >= 2).then(|| self.delta_prepare(memory));
             match self.step_with_peripherals_and_verbosity(memory, peripherals, verbosity) {
-                Ok(()) => {
-                    executed_instructions += 1;
+                Ok(()) => {}
+                // A WFI woken by an interrupt retired like any other instruction
+                Err(EmulatorError::WaitForInterrupt) if self.skip_to_interrupt(peripherals) => {
+                    info_log!(
+                        verbosity,
+                        "WFI at PC: 0x{:08x} slept until the next interrupt",
+                        self.pc
+                    );
                 }
-                Err(EmulatorError::EcallTermination) => {
-                    info_log!(verbosity, "ECALL termination detected");
+                Err(e) => {
+                    self.end_run(memory, e, &mut executed_instructions, verbosity)?;
+                    break;
+                }
+            }
+            executed_instructions += 1;
+            if let Some(delta) = delta {
+                self.log_step_delta(delta);
+            }
+            if self.idle.is_some() {
+                let budget = max_instructions.map_or(u32::MAX, |max| max - executed_instructions);
+                let skipped = self.idle_skip(
+                    peripherals,
+                    step_pc,
+                    memory.peek_word(step_pc),
+                    device_load,
+                    budget,
+                );
+                if skipped > 0 {
+                    executed_instructions += skipped;
+                    info_log!(
+                        verbosity,
+                        "Idle loop at PC: 0x{step_pc:08x}: skipped {skipped} instructions"
+                    );
+                }
+            }
+            if let Some(point) = traced {
+                if !self.trace_step(executed_instructions, point) {
+                    info_log!(verbosity, "Trace divergence at PC: 0x{:08x}", self.pc);
+                    self.exit_reason = Some(ExitReason::TraceDivergence(executed_instructions));
+                    break;
+                }
+            }
+            if !self.hooks.watches.is_empty() {
+                if let Some(hit) = self.check_watches(memory, step_pc) {
+                    info_log!(verbosity, "{hit}");
+                    self.exit_reason = Some(hit);
                     break;
                 }
-                Err(e) => return Err(e),
+            }
+            self.service_htif(memory, step_pc)?;
+            if let Some(reached) = self.check_run_target(memory, step_pc) {
+                info_log!(verbosity, "{reached}");
+                self.exit_reason = Some(reached);
+                break;
+            }
+            if self.time_budget_exceeded(executed_instructions) {
+                info_log!(verbosity, "Time budget exceeded at PC: 0x{:08x}", self.pc);
+                self.exit_reason = Some(ExitReason::TimeBudgetExceeded);
+                break;
+            }
+            if self.report_progress(executed_instructions) {
+                info_log!(verbosity, "Run cancelled at PC: 0x{:08x}", self.pc);
+                self.exit_reason = Some(ExitReason::Cancelled);
+                break;
             }
         }
 
@@ -1571,149 +3904,1132 @@ impl Cpu {
     }
 }
 
-impl Default for Cpu {
-    fn default() -> Self {
-        Self::new()
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Trace sink sharing its buffer with a test
+#[cfg(test)]
+pub(crate) struct SharedSink(pub(crate) std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl std::io::Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Memory, UninitRead};
+
+    #[test]
+    fn test_run_loops_agree_on_stops() {
+        let stops = [
+            (0x00000073, ExitReason::EcallExit(0), 3), // ecall retires
+            (0x10500073, ExitReason::Waiting, 3),      // wfi retires
+            (0x00100073, ExitReason::Breakpoint, 2),   // ebreak
+            (0xc0001073, ExitReason::Unimp(0x8000_0008), 2),
+            (0xffffffff, ExitReason::UnsupportedInstruction, 2),
+        ];
+        for (stop, reason, count) in stops {
+            let run = |mode: u8| {
+                let mut cpu = Cpu::new();
+                cpu.breakpoint_mode = true;
+                let mut memory = Memory::new();
+                let base = memory.base_address();
+                for (i, word) in [0x00000013, 0x00000513, stop].into_iter().enumerate() {
+                    memory.write_word(base + i as u32 * 4, word).unwrap();
+                }
+                cpu.pc = base;
+                let executed = match mode {
+                    0 => cpu.run(&mut memory, Some(10)),
+                    1 => cpu.run_fast(&mut memory, Some(10)),
+                    _ => cpu.run_with_peripherals(
+                        &mut memory,
+                        &mut crate::peripheral::PeripheralManager::new(),
+                        Some(10),
+                    ),
+                };
+                (executed.unwrap(), cpu.exit_reason)
+            };
+            for mode in 0..3 {
+                assert_eq!(run(mode), (count, Some(reason)), "0x{stop:08x} mode {mode}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_cpu_new() {
+        let cpu = Cpu::new();
+        assert_eq!(cpu.pc, 0);
+        for i in 0..NUM_REGISTERS {
+            assert_eq!(cpu.read_register(i), 0);
+        }
+    }
+
+    #[test]
+    fn test_register_read_write() {
+        let mut cpu = Cpu::new();
+
+        // Test writing and reading normal registers
+        cpu.write_register(1, 42);
+        assert_eq!(cpu.read_register(1), 42);
+
+        // Test that x0 always returns 0
+        cpu.write_register(0, 42);
+        assert_eq!(cpu.read_register(0), 0);
+
+        // Test invalid register access
+        assert_eq!(cpu.read_register(100), 0);
+        cpu.write_register(100, 42); // Should not panic
+    }
+
+    #[test]
+    fn test_addi_instruction() {
+        let mut cpu = Cpu::new();
+
+        // Test basic ADDI
+        cpu.write_register(1, 10);
+        cpu.execute_addi(2, 1, 5).unwrap();
+        assert_eq!(cpu.read_register(2), 15);
+        assert_eq!(cpu.pc, 4);
+
+        // Test ADDI with negative immediate
+        cpu.execute_addi(3, 2, -3).unwrap();
+        assert_eq!(cpu.read_register(3), 12);
+        assert_eq!(cpu.pc, 8);
+
+        // Test ADDI to x0 (should not change x0)
+        cpu.execute_addi(0, 1, 100).unwrap();
+        assert_eq!(cpu.read_register(0), 0);
+    }
+
+    #[test]
+    fn test_addi_overflow() {
+        let mut cpu = Cpu::new();
+
+        // Test overflow wrapping
+        cpu.write_register(1, u32::MAX);
+        cpu.execute_addi(2, 1, 1).unwrap();
+        assert_eq!(cpu.read_register(2), 0); // Should wrap around
+    }
+
+    #[test]
+    fn test_addi_invalid_registers() {
+        let mut cpu = Cpu::new();
+
+        // Test invalid destination register
+        let result = cpu.execute_addi(100, 1, 5);
+        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+
+        // Test invalid source register
+        let result = cpu.execute_addi(1, 100, 5);
+        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+    }
+
+    #[test]
+    fn test_decode_addi_instruction() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+
+        // Set up initial state
+        cpu.pc = memory.base_address();
+        cpu.write_register(1, 10);
+
+        // Create ADDI instruction: addi x2, x1, 5
+        // Instruction format: [imm(12) | rs1(5) | funct3(3) | rd(5) | opcode(7)]
+        // imm=5, rs1=1, funct3=0, rd=2, opcode=0x13
+        let instruction: u32 = ((5 << 20) | (1 << 15)) | (2 << 7) | 0x13;
+
+        // Write instruction to memory
+        memory.write_word(cpu.pc, instruction).unwrap();
+
+        // Execute instruction
+        cpu.step(&mut memory).unwrap();
+
+        // Verify results
+        assert_eq!(cpu.read_register(2), 15); // 10 + 5
+        assert_eq!(cpu.pc, memory.base_address() + 4);
+    }
+
+    #[test]
+    fn test_unsupported_instruction() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+
+        cpu.pc = memory.base_address();
+
+        // Create an unsupported instruction (opcode 0x7F)
+        let instruction: u32 = 0x7F;
+        memory.write_word(cpu.pc, instruction).unwrap();
+
+        // Should return UnsupportedInstruction error
+        let result = cpu.step(&mut memory);
+        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+    }
+
+    #[test]
+    fn test_cpu_run_with_limit() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+
+        cpu.pc = memory.base_address();
+
+        // Create multiple ADDI instructions
+        let instruction: u32 = ((1 << 20) | (1 << 15)) | (1 << 7) | 0x13; // addi x1, x1, 1
+
+        // Write instructions to memory
+        memory.fill_words(cpu.pc, instruction, 10).unwrap();
+
+        // Set initial register value
+        cpu.write_register(1, 0);
+
+        // Run with instruction limit
+        let executed = cpu.run(&mut memory, Some(5)).unwrap();
+        assert_eq!(executed, 5);
+        assert_eq!(cpu.read_register(1), 5); // Should have incremented 5 times
+    }
+
+    #[test]
+    fn test_warn_on_smc_reports_store_to_cached_instruction() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let program = [
+            0x00150513, // addi a0, a0, 1
+            0x00000297, // auipc t0, 0
+            0xfe02ae23, // sw zero, -4(t0)  overwrites the cached addi
+            0x00000013, // nop
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.set_icache_enabled(true);
+        cpu.warn_on_smc(true);
+        let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        cpu.set_trace_sink(TraceFormat::Text, Box::new(SharedSink(buffer.clone())));
+        cpu.pc = base;
+        cpu.run(&mut memory, Some(4)).unwrap();
+
+        let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let smc: Vec<&str> = log.lines().filter(|line| line.starts_with("smc")).collect();
+        assert_eq!(
+            smc,
+            vec![format!(
+                "smc-store 0x{:08x} to executed 0x{base:08x} (was 0x00150513 addi x10,x10,1)",
+                base + 8
+            )]
+        );
+    }
+
+    /// Patches the already-executed `addi a0, a0, 1` into `addi a0, a0, 10` and jumps back to it
+    fn run_self_patching(fence: u32, action: SmcAction) -> (Cpu, Result<u32>, String) {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let program = [
+            0x00000513, // li a0, 0
+            0x00150513, // target: addi a0, a0, 1
+            0x00059E63, // bnez a1, done
+            0x00100593, // li a1, 1
+            0x00000297, // auipc t0, 0
+            0x0182A303, // lw t1, 24(t0)
+            0xFE62AA23, // sw t1, -12(t0)   overwrites target
+            fence,      // fence.i or nop
+            0xFE5FF06F, // j target
+            0x00000073, // done: ecall
+            0x00A50513, // replacement: addi a0, a0, 10
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.detect_smc(Some(action));
+        let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        cpu.set_trace_sink(TraceFormat::Text, Box::new(SharedSink(buffer.clone())));
+        cpu.pc = base;
+        let result = cpu.run(&mut memory, Some(11));
+        let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let smc = log
+            .lines()
+            .filter(|line| line.starts_with("smc"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        (cpu, result, smc)
+    }
+
+    #[test]
+    fn test_detect_smc_reports_missing_fence_i() {
+        let base = Memory::new().base_address();
+        let store = format!(
+            "smc-store 0x{:08x} to executed 0x{:08x} (was 0x00150513 addi x10,x10,1)",
+            base + 24,
+            base + 4
+        );
+        let unfenced = format!(
+            "smc-unfenced 0x{:08x} stored by 0x{:08x} (was 0x00150513 addi x10,x10,1)",
+            base + 4,
+            base + 24
+        );
+
+        let (cpu, result, smc) = run_self_patching(0x00000013, SmcAction::Warn);
+        assert_eq!(result.unwrap(), 11);
+        assert_eq!(cpu.read_register(10), 11);
+        assert_eq!(smc, format!("{store}\n{unfenced}"));
+
+        let (cpu, result, smc) = run_self_patching(0x0000100F, SmcAction::Warn);
+        assert_eq!(result.unwrap(), 11);
+        assert_eq!(cpu.read_register(10), 11);
+        assert_eq!(smc, store);
+
+        let (_, result, _) = run_self_patching(0x00000013, SmcAction::Fault);
+        assert!(matches!(
+            result,
+            Err(EmulatorError::UnfencedCode(CodeModification { address, old: 0x00150513, .. }))
+                if address == base + 4
+        ));
+    }
+
+    #[test]
+    fn test_json_is_an_alias_of_jsonl() {
+        assert_eq!(TraceFormat::from_name("text"), Some(TraceFormat::Text));
+        assert_eq!(TraceFormat::from_name("jsonl"), Some(TraceFormat::Jsonl));
+        assert_eq!(TraceFormat::from_name("json"), Some(TraceFormat::Jsonl));
+        assert_eq!(TraceFormat::from_name("spike"), None);
+    }
+
+    #[test]
+    fn test_jsonl_trace_format() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        cpu.pc = base;
+        cpu.write_register(2, base + 0x100);
+
+        memory.write_word(base, 0x00a00093).unwrap(); // addi x1, x0, 10
+        memory.write_word(base + 4, 0x00112223).unwrap(); // sw x1, 4(x2)
+        memory.write_word(base + 8, 0x00108463).unwrap(); // beq x1, x1, 8
+
+        let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        cpu.set_trace_sink(TraceFormat::Jsonl, Box::new(SharedSink(buffer.clone())));
+        cpu.run(&mut memory, Some(3)).unwrap();
+
+        let output = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+
+        assert_eq!(
+            records[0],
+            serde_json::json!({
+                "i": 1,
+                "pc": "0x80000000",
+                "insn": "0x00a00093",
+                "asm": "addi x1,x0,10",
+                "rd": {"x": 1, "old": "0x00000000", "new": "0x0000000a"},
+            })
+        );
+        assert_eq!(
+            records[1],
+            serde_json::json!({
+                "i": 2,
+                "pc": "0x80000004",
+                "insn": "0x00112223",
+                "asm": "sw x1,4(x2)",
+                "mem": {"addr": "0x80000104", "w": 4, "val": "0x0000000a"},
+            })
+        );
+        assert_eq!(
+            records[2],
+            serde_json::json!({
+                "i": 3,
+                "pc": "0x80000008",
+                "insn": "0x00108463",
+                "asm": "beq x1,x1,0x80000010",
+            })
+        );
+        assert_eq!(cpu.pc, base + 16); // branch taken
+    }
+
+    #[test]
+    fn test_jump_to_unwritten_memory() {
+        let program = [
+            0x00100513, // addi a0, x0, 1
+            0x000280e7, // jalr ra, 0(t0) with t0 = 0
+        ];
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+
+        let mut cpu = Cpu::with_config(CpuConfig {
+            reset_vector: base,
+            jump_check: JumpCheck::Always,
+            ..CpuConfig::default()
+        });
+        let Err(EmulatorError::WildJump(jump)) = cpu.run(&mut memory, Some(10)) else {
+            panic!("wild jump not reported");
+        };
+        assert_eq!(jump.target, 0);
+        assert_eq!(jump.pc, base + 4);
+        assert_eq!(jump.history, vec![base, base + 4]);
+        assert!(cpu
+            .describe_fault(&memory, &EmulatorError::WildJump(jump))
+            .contains("jump to unmapped address 0x00000000 from PC 0x80000004"));
+
+        // Unchecked at verbosity 0 by default: the garbage fetch is what fails
+        let mut cpu = Cpu::new().with_reset_vector(base);
+        cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::UnsupportedInstruction));
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.fill_words(base, 0x00108093, 35).unwrap(); // addi x1, x1, 1
+        cpu.pc = base;
+
+        let calls = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let recorded = calls.clone();
+        cpu.set_progress_callback(
+            10,
+            Box::new(move |progress| {
+                recorded.borrow_mut().push((progress.retired, progress.pc));
+                ProgressAction::Continue
+            }),
+        );
+        assert_eq!(cpu.run(&mut memory, Some(35)).unwrap(), 35);
+        assert_eq!(
+            *calls.borrow(),
+            vec![(10, base + 40), (20, base + 80), (30, base + 120)]
+        );
+
+        // Cancelling from the callback stops the run on that boundary
+        cpu.pc = base;
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = count.clone();
+        cpu.set_progress_callback(
+            5,
+            Box::new(move |_| {
+                counter.set(counter.get() + 1);
+                if counter.get() == 2 {
+                    ProgressAction::Cancel
+                } else {
+                    ProgressAction::Continue
+                }
+            }),
+        );
+        assert_eq!(cpu.run(&mut memory, Some(35)).unwrap(), 10);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::Cancelled));
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn test_execute_instruction_without_fetch() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        cpu.pc = 0x1000; // PC does not need to point at mapped instructions
+        cpu.write_register(2, base);
+
+        cpu.execute_instruction(0x00a00093, &mut memory).unwrap(); // addi x1, x0, 10
+        assert_eq!(cpu.read_register(1), 10);
+        assert_eq!(cpu.pc, 0x1004);
+
+        cpu.execute_instruction(0x00112223, &mut memory).unwrap(); // sw x1, 4(x2)
+        assert_eq!(memory.read_word(base + 4).unwrap(), 10);
+        cpu.execute_instruction(0x00412183, &mut memory).unwrap(); // lw x3, 4(x2)
+        assert_eq!(cpu.read_register(3), 10);
+
+        cpu.execute_instruction(0x00108463, &mut memory).unwrap(); // beq x1, x1, 8
+        assert_eq!(cpu.pc, 0x1014);
+
+        cpu.execute_instruction(0x34009073, &mut memory).unwrap(); // csrw mscratch, x1
+        assert_eq!(cpu.read_csr(0x340), 10);
+
+        assert!(matches!(
+            cpu.execute_instruction(0x0000_0073, &mut memory), // ecall
+            Err(EmulatorError::EcallTermination)
+        ));
+    }
+
+    #[test]
+    fn test_execute_instruction_matches_step() {
+        let program = [
+            0x00a00093, // addi x1, x0, 10
+            0x02108133, // mul x2, x1, x1
+            0x002081b3, // add x3, x1, x2
+            0x0030a023, // sw x3, 0(x1)
+            0x0000a203, // lw x4, 0(x1)
+            0x00108463, // beq x1, x1, 8
+            0x123452b7, // lui x5, 0x12345
+            0x008002ef, // jal x5, 8
+        ];
+
+        let mut stepped = Cpu::new();
+        let mut executed = Cpu::new();
+        let mut step_memory = Memory::new();
+        let mut exec_memory = Memory::new();
+        let base = step_memory.base_address();
+        stepped.write_register(1, base + 0x100);
+        executed.write_register(1, base + 0x100);
+
+        for word in program {
+            stepped.pc = base;
+            executed.pc = base;
+            step_memory.write_word(base, word).unwrap();
+            // Re-seed x1 so loads and stores hit the same scratch address
+            stepped.write_register(1, base + 0x100);
+            executed.write_register(1, base + 0x100);
+
+            stepped.step(&mut step_memory).unwrap();
+            executed
+                .execute_instruction(word, &mut exec_memory)
+                .unwrap();
+
+            assert_eq!(stepped.pc, executed.pc, "pc after 0x{word:08x}");
+            for reg in 0..32 {
+                assert_eq!(
+                    stepped.read_register(reg),
+                    executed.read_register(reg),
+                    "x{reg} after 0x{word:08x}"
+                );
+            }
+        }
+        assert_eq!(
+            step_memory.read_word(base + 0x100).unwrap(),
+            exec_memory.read_word(base + 0x100).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_describe_fault_disassembles_instruction() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x00a00093).unwrap(); // addi x1, x0, 10
+        memory.write_word(base + 4, 0xffff_ffff).unwrap(); // illegal
+        cpu.pc = base;
+
+        cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::UnsupportedInstruction));
+        assert_eq!(
+            cpu.describe_fault(&memory, &EmulatorError::UnsupportedInstruction),
+            "Error at 0x80000004: illegal instruction `0xffffffff` (.word)"
+        );
+
+        // Other errors keep their message and show the decoded instruction
+        memory.write_word(base + 4, 0x0000a203).unwrap(); // lw x4, 0(x1)
+        assert_eq!(
+            cpu.describe_fault(
+                &memory,
+                &EmulatorError::MemoryAccessError {
+                    addr: 1,
+                    kind: AccessKind::Load,
+                    reason: crate::memory::AccessFailReason::OutOfRange,
+                }
+            ),
+            "Error at 0x80000004: Memory access error: load at 0x00000001 outside RAM \
+             in `0x0000a203` (lw x4,0(x1))"
+        );
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::memory::Memory;
 
     #[test]
-    fn test_cpu_new() {
-        let cpu = Cpu::new();
-        assert_eq!(cpu.pc, 0);
-        for i in 0..NUM_REGISTERS {
-            assert_eq!(cpu.read_register(i), 0);
-        }
+    fn test_memory_access_errors_carry_address_and_kind() {
+        use crate::memory::AccessFailReason;
+        let setup = |program: &[u32]| {
+            let mut memory = Memory::with_size(0x1000);
+            memory.set_bounds_check(true);
+            let base = memory.base_address();
+            for (i, &word) in program.iter().enumerate() {
+                memory.write_word(base + i as u32 * 4, word).unwrap();
+            }
+            let mut cpu = Cpu::new();
+            cpu.pc = base;
+            (cpu, memory)
+        };
+        let lui_ram_end = 0x8000_10b7; // lui x1, 0x80001
+
+        // Stores just past the end of a 4 KiB RAM fail with the store address
+        let (mut cpu, mut memory) = setup(&[lui_ram_end, 0x0000a023]); // sw x0, 0(x1)
+        let error = cpu.run(&mut memory, Some(10)).unwrap_err();
+        assert!(matches!(
+            error,
+            EmulatorError::MemoryAccessError {
+                addr: 0x8000_1000,
+                kind: AccessKind::Store,
+                reason: AccessFailReason::OutOfRange,
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Memory access error: store at 0x80001000 outside RAM"
+        );
+
+        // The last word of RAM is fine; a load straddling the end is not
+        let (mut cpu, mut memory) = setup(&[
+            lui_ram_end,
+            0xfe00ae23, // sw x0, -4(x1)
+            0xffe0a103, // lw x2, -2(x1)
+        ]);
+        assert!(matches!(
+            cpu.run(&mut memory, Some(10)),
+            Err(EmulatorError::MemoryAccessError {
+                addr: 0x8000_0ffe,
+                kind: AccessKind::Load,
+                ..
+            })
+        ));
+        assert_eq!(cpu.instret(), 2);
+
+        // Jumping out of RAM is reported as a fetch
+        let (mut cpu, mut memory) = setup(&[lui_ram_end, 0x00008067]); // jr x1
+        assert!(matches!(
+            cpu.run(&mut memory, Some(10)),
+            Err(EmulatorError::MemoryAccessError {
+                addr: 0x8000_1000,
+                kind: AccessKind::Fetch,
+                reason: AccessFailReason::OutOfRange,
+            })
+        ));
     }
 
     #[test]
-    fn test_register_read_write() {
+    fn test_register_snapshot_diff() {
         let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x00a00513).unwrap(); // addi a0, zero, 10
+        cpu.pc = base;
+        cpu.set_reg(Reg::Sp, 0x1000);
 
-        // Test writing and reading normal registers
-        cpu.write_register(1, 42);
-        assert_eq!(cpu.read_register(1), 42);
+        let before = cpu.registers_snapshot();
+        assert_eq!(before[2], 0x1000);
+        assert!(cpu.diff_registers(&before).is_empty());
 
-        // Test that x0 always returns 0
-        cpu.write_register(0, 42);
-        assert_eq!(cpu.read_register(0), 0);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.reg(Reg::A0), 10);
+        assert_eq!(cpu.diff_registers(&before), vec![(Reg::A0, 0, 10)]);
 
-        // Test invalid register access
-        assert_eq!(cpu.read_register(100), 0);
-        cpu.write_register(100, 42); // Should not panic
+        cpu.set_reg(Reg::Zero, 5);
+        assert_eq!(cpu.reg(Reg::Zero), 0);
     }
 
     #[test]
-    fn test_addi_instruction() {
-        let mut cpu = Cpu::new();
+    fn test_format_step_delta_colors_new_values() {
+        let changes = [(Reg::Ra, 0x10, 0x8000_0004)];
+        assert_eq!(
+            format_step_delta(&changes, Some((0x8000_1002, 2, 0xBEEF)), true),
+            vec![
+                "  x1(ra): 0x00000010 -> \x1b[1;33m0x80000004\x1b[0m",
+                "  mem[0x80001002] <- \x1b[1;33m0xbeef\x1b[0m",
+            ]
+        );
+        assert!(format_step_delta(&[], None, true).is_empty());
+    }
 
-        // Test basic ADDI
-        cpu.write_register(1, 10);
-        cpu.execute_addi(2, 1, 5).unwrap();
-        assert_eq!(cpu.read_register(2), 15);
-        assert_eq!(cpu.pc, 4);
+    #[test]
+    fn test_ecall_behaviors() {
+        /// Records a7 of every ECALL and resumes the guest
+        struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<u32>>>);
+
+        impl crate::syscall::SyscallHandler for Recorder {
+            fn handle(&mut self, cpu: &mut Cpu, _bus: &mut dyn Bus) -> Result<SyscallAction> {
+                self.0.borrow_mut().push(cpu.reg(Reg::A7));
+                cpu.set_reg(Reg::A0, 0);
+                Ok(SyscallAction::Continue)
+            }
+        }
 
-        // Test ADDI with negative immediate
-        cpu.execute_addi(3, 2, -3).unwrap();
-        assert_eq!(cpu.read_register(3), 12);
-        assert_eq!(cpu.pc, 8);
+        let setup = || {
+            let mut cpu = Cpu::new();
+            let mut memory = Memory::new();
+            let base = memory.base_address();
+            memory.write_word(base, 0x05d00893).unwrap(); // addi a7, zero, 93
+            memory.write_word(base + 4, ECALL).unwrap();
+            memory.write_word(base + 8, 0x00100093).unwrap(); // addi ra, zero, 1
+            memory.write_word(base + 0x100, 0x30200073).unwrap(); // mret
+            cpu.pc = base;
+            cpu.set_reg(Reg::A0, 7);
+            (cpu, memory, base)
+        };
 
-        // Test ADDI to x0 (should not change x0)
-        cpu.execute_addi(0, 1, 100).unwrap();
-        assert_eq!(cpu.read_register(0), 0);
+        // Terminate: the run stops with a0 as the exit code
+        let (mut cpu, mut memory, _) = setup();
+        cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::EcallExit(7)));
+
+        // Trap: vector to mtvec with mepc/mcause describing the ECALL
+        let (mut cpu, mut memory, base) = setup();
+        cpu.set_ecall_behavior(EcallBehavior::Trap);
+        cpu.write_csr(CSR_MTVEC, base + 0x100);
+        cpu.write_csr(CSR_MSTATUS, MSTATUS_MIE);
+        cpu.run(&mut memory, Some(2)).unwrap();
+        assert_eq!(cpu.pc, base + 0x100);
+        assert_eq!(cpu.read_csr(CSR_MEPC), base + 4);
+        assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_ECALL_FROM_M);
+        assert_eq!(
+            cpu.read_csr(CSR_MSTATUS) & (MSTATUS_MIE | MSTATUS_MPIE),
+            MSTATUS_MPIE
+        );
+        // MRET returns to mepc and restores MIE
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, base + 4);
+        assert_eq!(cpu.read_csr(CSR_MSTATUS) & MSTATUS_MIE, MSTATUS_MIE);
+
+        // Trap from U-mode: mcause says so and the handler runs in M-mode
+        let (mut cpu, mut memory, base) = setup();
+        cpu.set_ecall_behavior(EcallBehavior::Trap);
+        cpu.write_csr(CSR_MTVEC, base + 0x100);
+        cpu.set_privilege(PrivMode::User);
+        cpu.run(&mut memory, Some(2)).unwrap();
+        assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_ECALL_FROM_U);
+        assert_eq!(cpu.privilege(), PrivMode::Machine);
+        assert_eq!(cpu.read_csr(CSR_MSTATUS) & MSTATUS_MPP, 0);
+        cpu.reset();
+        assert_eq!(cpu.privilege(), PrivMode::Machine);
+
+        // Handler: the host sees the call and execution continues after it
+        let (mut cpu, mut memory, base) = setup();
+        let calls = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        cpu.set_ecall_behavior(EcallBehavior::Handler(Box::new(Recorder(calls.clone()))));
+        cpu.run(&mut memory, Some(3)).unwrap();
+        assert_eq!(*calls.borrow(), vec![93]);
+        assert_eq!(cpu.pc, base + 12);
+        assert_eq!(cpu.reg(Reg::A0), 0);
+        assert_eq!(cpu.reg(Reg::Ra), 1);
+        assert!(matches!(cpu.ecall_behavior(), EcallBehavior::Handler(_)));
     }
 
     #[test]
-    fn test_addi_overflow() {
-        let mut cpu = Cpu::new();
+    fn test_trap_loop_detection() {
+        let setup = |config: CpuConfig| {
+            let mut memory = Memory::new();
+            let base = memory.base_address();
+            memory.write_word(base, ECALL).unwrap();
+            // The handler faults again before returning
+            memory.write_word(base + 0x100, ECALL).unwrap();
+            let mut cpu = Cpu::with_config(CpuConfig {
+                reset_vector: base,
+                ..config
+            });
+            cpu.set_ecall_behavior(EcallBehavior::Trap);
+            cpu.write_csr(CSR_MTVEC, base + 0x100);
+            (cpu, memory, base)
+        };
 
-        // Test overflow wrapping
-        cpu.write_register(1, u32::MAX);
-        cpu.execute_addi(2, 1, 1).unwrap();
-        assert_eq!(cpu.read_register(2), 0); // Should wrap around
+        let (mut cpu, mut memory, base) = setup(CpuConfig::default());
+        cpu.run(&mut memory, Some(1000)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TrapLoop { depth: 9 }));
+        let chain = cpu.trap_chain();
+        assert_eq!(chain.len(), 9);
+        assert_eq!(chain[0], (CAUSE_ECALL_FROM_M, base));
+        assert!(chain[1..]
+            .iter()
+            .all(|&link| link == (CAUSE_ECALL_FROM_M, base + 0x100)));
+
+        // Handlers that legitimately nest can raise the limit
+        let (mut cpu, mut memory, _) = setup(CpuConfig {
+            max_trap_depth: Some(20),
+            ..CpuConfig::default()
+        });
+        cpu.run(&mut memory, Some(1000)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TrapLoop { depth: 21 }));
+
+        // MRET unwinds the nesting, so a handler that returns never trips it
+        let (mut cpu, mut memory, base) = setup(CpuConfig::default());
+        memory.write_word(base + 0x100, 0x30200073).unwrap(); // mret
+        cpu.run(&mut memory, Some(1000)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::InstructionLimit));
+        assert!(cpu.trap_chain().len() <= 1);
     }
 
     #[test]
-    fn test_addi_invalid_registers() {
+    fn test_run_until_helpers() {
         let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let program = [
+            0x00500513, // addi a0, zero, 5
+            0x00c000ef, // jal ra, helper
+            0x00050593, // addi a1, a0, 0
+            0x00000013, // nop
+            0xff010113, // helper: addi sp, sp, -16
+            0x00112623, // sw ra, 12(sp)
+            0x010000ef, // jal ra, inner
+            0x00c12083, // lw ra, 12(sp)
+            0x01010113, // addi sp, sp, 16
+            0x00008067, // ret
+            0x00a50513, // inner: addi a0, a0, 10
+            0x00008067, // ret
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base;
+        cpu.set_reg(Reg::Sp, base + 0x1000);
+
+        // Not a call: single step
+        assert_eq!(cpu.run_over(&mut memory, None).unwrap(), 1);
+        assert_eq!(cpu.pc, base + 4);
+
+        // Step over the helper, including its nested call
+        assert_eq!(cpu.run_over(&mut memory, None).unwrap(), 9);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TargetReached(base + 8)));
+        assert_eq!(cpu.reg(Reg::A0), 15);
+        assert_eq!(cpu.reg(Reg::Sp), base + 0x1000);
+
+        // Run to the inner function, then finish it
+        cpu.pc = base;
+        cpu.run_until_pc(&mut memory, base + 40, None).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TargetReached(base + 40)));
+        cpu.run_until_return(&mut memory, None).unwrap();
+        assert_eq!(cpu.pc, base + 28);
+        cpu.run_until_return(&mut memory, None).unwrap();
+        assert_eq!(cpu.pc, base + 8);
+
+        // The limit still applies
+        cpu.pc = base + 4;
+        assert_eq!(cpu.run_over(&mut memory, Some(3)).unwrap(), 3);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::InstructionLimit));
+    }
 
-        // Test invalid destination register
-        let result = cpu.execute_addi(100, 1, 5);
-        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+    #[test]
+    fn test_reset_returns_to_reset_vector() {
+        let mut memory = Memory::new();
+        let entry = memory.base_address() + 0x40;
+        memory.write_word(entry, 0x00500513).unwrap(); // addi a0, zero, 5
+        memory.write_word(entry + 4, 0x34051073).unwrap(); // csrw mscratch, a0
+        let mut cpu = Cpu::new().with_reset_vector(entry);
+        assert_eq!(cpu.pc, entry);
+
+        cpu.run(&mut memory, Some(2)).unwrap();
+        assert_eq!(cpu.reg(Reg::A0), 5);
+        assert_eq!(cpu.read_csr(0x340), 5);
+
+        cpu.reset();
+        assert_eq!(cpu.pc, entry);
+        assert_eq!(cpu.registers_snapshot(), [0; NUM_REGISTERS]);
+        assert_eq!(cpu.read_csr(0x340), 0);
+        assert_eq!(cpu.exit_reason, None);
+        assert_eq!(
+            Cpu::with_config(CpuConfig {
+                reset_vector: entry,
+                ..CpuConfig::default()
+            })
+            .pc,
+            entry
+        );
+    }
 
-        // Test invalid source register
-        let result = cpu.execute_addi(1, 100, 5);
-        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+    #[test]
+    fn test_instruction_length_at() {
+        let cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x00500513).unwrap(); // addi a0, zero, 5
+        memory.write_halfword(base + 4, 0x4515).unwrap(); // c.li a0, 5
+        assert_eq!(cpu.instruction_length_at(&memory, base).unwrap(), 4);
+        assert_eq!(cpu.instruction_length_at(&memory, base + 4).unwrap(), 2);
     }
 
     #[test]
-    fn test_decode_addi_instruction() {
+    fn test_uninit_reads_are_attributed_to_the_loading_pc() {
         let mut cpu = Cpu::new();
         let mut memory = Memory::new();
+        let base = memory.base_address();
+        let data = base + 0x100;
+        memory.write_word(base, 0x00052583).unwrap(); // lw a1, 0(a0)
+        memory.write_word(base + 4, 0x00451603).unwrap(); // lh a2, 4(a0)
+        memory.write_byte(data + 5, 0x12).unwrap();
+        cpu.pc = base;
+        cpu.set_reg(Reg::A0, data);
+        cpu.run(&mut memory, Some(2)).unwrap();
+
+        let report = memory.uninit_report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(
+            report[0],
+            UninitRead {
+                start: data,
+                len: 4,
+                pc: Some(base),
+                count: 4
+            }
+        );
+        assert_eq!(
+            report[1],
+            UninitRead {
+                start: data + 4,
+                len: 1,
+                pc: Some(base + 4),
+                count: 1
+            }
+        );
+        assert_eq!(cpu.reg(Reg::A2), 0x12FF);
+    }
 
-        // Set up initial state
-        cpu.pc = memory.base_address();
-        cpu.write_register(1, 10);
+    #[test]
+    fn test_time_budget_stops_long_loop() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x00150513).unwrap(); // loop: addi a0, a0, 1
+        memory.write_word(base + 4, 0xffdff06f).unwrap(); // j loop
+        cpu.pc = base;
+        cpu.set_time_budget(std::time::Duration::from_millis(1));
+
+        let executed = cpu.run(&mut memory, Some(u32::MAX)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TimeBudgetExceeded));
+        assert!(executed < u32::MAX);
+        assert_eq!(executed % TIME_CHECK_INTERVAL, 0);
+
+        // A shorter check interval stops on its own boundaries
+        cpu.set_time_budget_with_interval(std::time::Duration::from_millis(1), 7);
+        let executed = cpu.run(&mut memory, Some(u32::MAX)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TimeBudgetExceeded));
+        assert_eq!(executed % 7, 0);
+
+        // Without a budget the limit applies again
+        cpu.clear_time_budget();
+        cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::InstructionLimit));
+    }
 
-        // Create ADDI instruction: addi x2, x1, 5
-        // Instruction format: [imm(12) | rs1(5) | funct3(3) | rd(5) | opcode(7)]
-        // imm=5, rs1=1, funct3=0, rd=2, opcode=0x13
-        let instruction: u32 = ((5 << 20) | (1 << 15)) | (2 << 7) | 0x13;
+    #[test]
+    fn test_strict_decode_rejects_reserved_encodings() {
+        let reserved = [
+            0x00508013u32, // addi x0, x1, 5 (hint)
+            0x12345037,    // lui x0, 0x12345 (hint)
+            0x0FF0800F,    // fence iorw, iorw with rs1 = x1
+            0x1FF0000F,    // fence with reserved fm = 0b0001
+            0x0010100F,    // fence.i with imm = 1
+            0x1015A52F,    // lr.w a0, (a1) with rs2 = x1
+        ];
+        let run = |config: CpuConfig, word: u32, sink: Option<SharedSink>| {
+            let mut cpu = Cpu::with_config(config);
+            let mut memory = Memory::new();
+            let base = memory.base_address();
+            memory.write_word(base, word).unwrap();
+            memory.write_word(base + 0x100, 0).unwrap();
+            cpu.pc = base;
+            cpu.set_reg(Reg::A1, base + 0x100);
+            if let Some(sink) = sink {
+                cpu.set_trace_sink(TraceFormat::Text, Box::new(sink));
+            }
+            cpu.step(&mut memory).map(|()| cpu.pc - base)
+        };
+        let strict = CpuConfig {
+            strict_decode: true,
+            ..CpuConfig::default()
+        };
+        let warn = CpuConfig {
+            strict_decode_action: StrictDecodeAction::Warn,
+            ..strict
+        };
+        for word in reserved {
+            assert!(strict_decode_violation(word).is_some(), "0x{word:08x}");
+            assert_eq!(run(CpuConfig::default(), word, None).unwrap(), 4);
+            assert!(matches!(
+                run(strict, word, None),
+                Err(EmulatorError::UnsupportedInstruction)
+            ));
+            let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+            assert_eq!(
+                run(warn, word, Some(SharedSink(buffer.clone()))).unwrap(),
+                4
+            );
+            let log = String::from_utf8(buffer.borrow().clone()).unwrap();
+            assert!(
+                log.starts_with(&format!("strict-decode 0x80000000 (0x{word:08x}) ")),
+                "{log}"
+            );
+        }
 
-        // Write instruction to memory
-        memory.write_word(cpu.pc, instruction).unwrap();
+        // Architected encodings are untouched
+        for word in [
+            NOP, 0x0FF0000F, 0x8330000F, 0x0000100F, 0x1005A52F, ECALL, MRET,
+        ] {
+            assert_eq!(strict_decode_violation(word), None, "0x{word:08x}");
+        }
+    }
 
-        // Execute instruction
-        cpu.step(&mut memory).unwrap();
+    #[test]
+    fn test_fence_tso_and_pause_decode() {
+        assert_eq!(crate::disasm::disassemble(FENCE_TSO), "fence.tso");
+        assert_eq!(crate::disasm::disassemble(PAUSE), "pause");
+        assert_eq!(strict_decode_violation(PAUSE), None);
+        assert_eq!(strict_decode_violation(FENCE_TSO), None);
+        // Other FENCEs with an empty successor set stay hints
+        assert!(strict_decode_violation(0x0200_000F).is_some());
+
+        let mut cpu = Cpu::with_config(CpuConfig {
+            strict_decode: true,
+            ..CpuConfig::default()
+        });
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, FENCE_TSO).unwrap();
+        memory.write_word(base + 4, PAUSE).unwrap();
+        cpu.pc = base;
+        assert_eq!(cpu.run(&mut memory, Some(2)).unwrap(), 2);
+        assert_eq!(cpu.pc, base + 8);
+    }
 
-        // Verify results
-        assert_eq!(cpu.read_register(2), 15); // 10 + 5
-        assert_eq!(cpu.pc, memory.base_address() + 4);
+    #[test]
+    fn test_64bit_counter_read_loop_across_carry() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, word) in [
+            0xC80025F3u32, // again: csrr a1, cycleh
+            0xC0002573,    // csrr a0, cycle
+            0xC8002673,    // csrr a2, cycleh
+            0xFEC59AE3,    // bne a1, a2, again
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base;
+        // The low half wraps between the first cycleh and cycle reads
+        cpu.write_csr(CSR_MCYCLE, 0xFFFF_FFFE);
+        cpu.write_csr(CSR_MCYCLEH, 0);
+
+        cpu.run(&mut memory, Some(8)).unwrap();
+        assert_eq!(cpu.pc, base + 16);
+        assert_eq!(cpu.reg(Reg::A1), cpu.reg(Reg::A2));
+        let value = u64::from(cpu.reg(Reg::A2)) << 32 | u64::from(cpu.reg(Reg::A0));
+        assert_eq!(value, 0x1_0000_0003);
+        assert_eq!(cpu.cycles(), 0x1_0000_0006);
+
+        // Both halves of each counter come from the same 64-bit value
+        cpu.set_counters(0x1234_5678_9ABC_DEF0, 0x0000_0002_0000_0001);
+        assert_eq!(cpu.read_csr(CSR_TIMEH), 0x1234_5678);
+        assert_eq!(cpu.read_csr(CSR_TIME), 0x9ABC_DEF0);
+        assert_eq!(cpu.read_csr(CSR_INSTRETH), 2);
+        assert_eq!(cpu.read_csr(CSR_MINSTRET), 1);
+        // The unprivileged views are read-only
+        cpu.write_csr(CSR_CYCLE, 0);
+        assert_eq!(cpu.read_csr(CSR_CYCLE), 0x9ABC_DEF0);
+        cpu.write_csr(CSR_MINSTRETH, 7);
+        assert_eq!(cpu.instret(), 0x0000_0007_0000_0001);
     }
 
     #[test]
-    fn test_unsupported_instruction() {
+    fn test_mret_drops_to_mpp() {
         let mut cpu = Cpu::new();
         let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, MRET).unwrap();
+        cpu.write_csr(CSR_MEPC, base + 0x40);
+        let mret = |cpu: &mut Cpu, memory: &mut Memory, mstatus| {
+            cpu.pc = base;
+            cpu.privilege = PrivMode::Machine;
+            cpu.set_mstatus(mstatus);
+            cpu.step(memory).unwrap();
+        };
 
-        cpu.pc = memory.base_address();
+        // MPP=U: return to user code, clearing MPRV and resetting MPP to U
+        mret(&mut cpu, &mut memory, MSTATUS_MPRV | MSTATUS_MPIE);
+        assert_eq!(cpu.privilege, PrivMode::User);
+        assert_eq!(cpu.pc, base + 0x40);
+        assert_eq!(cpu.mstatus() & (MSTATUS_MPRV | MSTATUS_MPP), 0);
 
-        // Create an unsupported instruction (opcode 0x7F)
-        let instruction: u32 = 0x7F;
-        memory.write_word(cpu.pc, instruction).unwrap();
+        mret(&mut cpu, &mut memory, 1 << 11);
+        assert_eq!(cpu.privilege, PrivMode::Supervisor);
 
-        // Should return UnsupportedInstruction error
-        let result = cpu.step(&mut memory);
-        assert!(matches!(result, Err(EmulatorError::UnsupportedInstruction)));
+        // MPP=M stays in M-mode and keeps MPRV
+        mret(&mut cpu, &mut memory, MSTATUS_MPRV | MSTATUS_MPP);
+        assert_eq!(cpu.privilege, PrivMode::Machine);
+        assert_eq!(cpu.mstatus() & (MSTATUS_MPRV | MSTATUS_MPP), MSTATUS_MPRV);
     }
 
     #[test]
-    fn test_cpu_run_with_limit() {
+    fn test_counter_access_is_gated_by_counteren() {
         let mut cpu = Cpu::new();
         let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0xC0102573).unwrap(); // rdtime a0
+        cpu.write_csr(CSR_MTVEC, base + 0x100);
+        cpu.set_counters(5, 5);
+        let rdtime = |cpu: &mut Cpu, memory: &mut Memory, privilege| {
+            cpu.pc = base;
+            cpu.privilege = privilege;
+            cpu.set_reg(Reg::A0, 0);
+            cpu.step(memory).unwrap();
+        };
 
-        cpu.pc = memory.base_address();
-
-        // Create multiple ADDI instructions
-        let instruction: u32 = ((1 << 20) | (1 << 15)) | (1 << 7) | 0x13; // addi x1, x1, 1
-
-        // Write instructions to memory
-        for i in 0..10 {
-            memory.write_word(cpu.pc + i * 4, instruction).unwrap();
-        }
-
-        // Set initial register value
-        cpu.write_register(1, 0);
+        // M-mode may always read the counters
+        rdtime(&mut cpu, &mut memory, PrivMode::Machine);
+        assert_eq!(cpu.pc, base + 4);
+        assert_eq!(cpu.reg(Reg::A0), 5);
+
+        // U-mode with CY/TM clear traps as an illegal instruction
+        rdtime(&mut cpu, &mut memory, PrivMode::User);
+        assert_eq!(cpu.pc, base + 0x100);
+        assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_ILLEGAL_INSTRUCTION);
+        assert_eq!(cpu.read_csr(CSR_MTVAL), 0xC0102573);
+        assert_eq!(cpu.read_csr(CSR_MSTATUS) & MSTATUS_MPP, 0);
+        assert_eq!(cpu.privilege, PrivMode::Machine);
+        assert_eq!(cpu.reg(Reg::A0), 0);
+
+        // Enabled in mcounteren only: S-mode may read, U-mode still needs scounteren
+        cpu.write_csr(CSR_MCOUNTEREN, 0b011);
+        rdtime(&mut cpu, &mut memory, PrivMode::Supervisor);
+        assert_eq!(cpu.pc, base + 4);
+        rdtime(&mut cpu, &mut memory, PrivMode::User);
+        assert_eq!(cpu.pc, base + 0x100);
+        cpu.write_csr(CSR_SCOUNTEREN, 0b011);
+        rdtime(&mut cpu, &mut memory, PrivMode::User);
+        assert_eq!(cpu.pc, base + 4);
+        assert_ne!(cpu.reg(Reg::A0), 0);
+    }
 
-        // Run with instruction limit
-        let executed = cpu.run(&mut memory, Some(5)).unwrap();
-        assert_eq!(executed, 5);
-        assert_eq!(cpu.read_register(1), 5); // Should have incremented 5 times
+    #[test]
+    fn test_step_info() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x00500513).unwrap(); // addi a0, zero, 5
+        memory.write_word(base + 4, 0x05d00893).unwrap(); // addi a7, zero, 93
+        memory.write_word(base + 8, ECALL).unwrap();
+        cpu.pc = base;
+
+        let info = cpu.step_info(&mut memory, None).unwrap();
+        assert_eq!(info.pc, base);
+        assert_eq!(info.word, 0x00500513);
+        assert_eq!(info.asm, "addi x10,x0,5");
+        assert_eq!(
+            info.write,
+            Some(RegisterWrite {
+                reg: Reg::A0,
+                old: 0,
+                new: 5
+            })
+        );
+        assert_eq!(info.next_pc, base + 4);
+        assert_eq!(info.exit_reason, None);
+
+        cpu.step_info(&mut memory, None).unwrap();
+        let info = cpu.step_info(&mut memory, None).unwrap();
+        assert_eq!(info.write, None);
+        assert_eq!(info.exit_reason, Some("ecall_exit"));
+        assert_eq!(cpu.exit_code(), Some(5));
     }
 
     #[test]
@@ -2100,4 +5416,369 @@ mod tests {
         cpu.step(&mut memory).unwrap();
         assert_eq!(cpu.pc, old_pc + 4); // Should advance PC
     }
+
+    #[test]
+    fn test_sfence_vma_is_a_no_op() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base_addr = memory.base_address();
+        memory.write_word(base_addr, 0x1200_0073).unwrap(); // sfence.vma x0, x0
+        memory.write_word(base_addr + 4, 0x12b5_0073).unwrap(); // sfence.vma a0, a1
+        cpu.pc = base_addr;
+
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, base_addr + 4);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, base_addr + 8);
+        assert_eq!(
+            crate::disasm::disassemble(0x12b5_0073),
+            "sfence.vma x10,x11"
+        );
+    }
+
+    /// `retry: lr.w t0, (a0); addi t0, t0, 1; sc.w t1, t0, (a0); bnez t1, retry`
+    const ATOMIC_INCREMENT: [u32; 4] = [0x1005_22AF, 0x0012_8293, 0x1855_232F, 0xFE03_1AE3];
+
+    #[test]
+    fn test_interrupt_between_lr_and_sc_fails_sc() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, &word) in ATOMIC_INCREMENT.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        memory.write_word(base + 0x100, MRET).unwrap();
+        memory.write_word(base + 0x200, 5).unwrap();
+        let mut cpu = Cpu::new();
+        cpu.pc = base;
+        cpu.set_reg(Reg::A0, base + 0x200);
+        cpu.set_mtvec(base + 0x100);
+        cpu.set_mie(MIP_MTIP);
+        cpu.write_csr(CSR_MSTATUS, MSTATUS_MIE);
+
+        cpu.step(&mut memory).unwrap(); // lr.w
+        assert_eq!(cpu.reservation(), Some(base + 0x200));
+
+        // The timer interrupt is taken and returned from before the SC
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.reservation(), None);
+        cpu.clear_interrupt(Interrupt::MachineTimer);
+        while cpu.pc != base + 4 {
+            cpu.step(&mut memory).unwrap();
+        }
+        cpu.step(&mut memory).unwrap(); // addi
+        cpu.step(&mut memory).unwrap(); // sc.w fails
+        assert_eq!(cpu.reg(Reg::T1), 1);
+        assert_eq!(memory.read_word(base + 0x200).unwrap(), 5);
+
+        // The retry succeeds
+        for _ in 0..5 {
+            cpu.step(&mut memory).unwrap();
+        }
+        assert_eq!(cpu.pc, base + 16);
+        assert_eq!(cpu.reg(Reg::T1), 0);
+        assert_eq!(memory.read_word(base + 0x200).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_other_harts_store_breaks_reservation() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, &word) in ATOMIC_INCREMENT.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        memory.write_word(base + 0x100, 0x00B5_2023).unwrap(); // sw a1, 0(a0)
+        let counter = base + 0x200;
+        memory.write_word(counter, 5).unwrap();
+        let mut harts = [Cpu::new(), Cpu::new()];
+        harts[0].pc = base;
+        harts[1].pc = base + 0x100;
+        for hart in &mut harts {
+            hart.set_reg(Reg::A0, counter);
+        }
+        harts[1].set_reg(Reg::A1, 40);
+
+        // Step one hart, then let the others snoop its store like a scheduler would
+        let step = |harts: &mut [Cpu; 2], memory: &mut Memory, index: usize| {
+            let word = memory.peek_word(harts[index].pc);
+            let (operand, stored) = harts[index].memory_operand(word);
+            harts[index].step(memory).unwrap();
+            if let (Some((address, width)), Some(_)) = (operand, stored) {
+                harts[1 - index].snoop_store(address, width.into());
+            }
+        };
+
+        step(&mut harts, &mut memory, 0); // lr.w
+        step(&mut harts, &mut memory, 1); // hart 1 stores 40
+        assert_eq!(harts[0].reservation(), None);
+        step(&mut harts, &mut memory, 0); // addi
+        step(&mut harts, &mut memory, 0); // sc.w fails
+        assert_eq!(harts[0].reg(Reg::T1), 1);
+        assert_eq!(memory.read_word(counter).unwrap(), 40);
+
+        // Hart 0 retries from its LR and increments hart 1's value
+        for _ in 0..5 {
+            step(&mut harts, &mut memory, 0);
+        }
+        assert_eq!(harts[0].pc, base + 16);
+        assert_eq!(memory.read_word(counter).unwrap(), 41);
+
+        // Stores to other words leave the reservation alone
+        let mut cpu = Cpu::new();
+        cpu.reservation = Some(counter);
+        cpu.snoop_store(counter + 4, 4);
+        cpu.snoop_store(counter - 2, 2);
+        assert_eq!(cpu.reservation(), Some(counter));
+        cpu.snoop_store(counter + 3, 1);
+        assert_eq!(cpu.reservation(), None);
+        cpu.reservation = Some(counter);
+        cpu.snoop_store(counter - 2, 4);
+        assert_eq!(cpu.reservation(), None);
+    }
+
+    #[test]
+    fn test_wfi_stops_until_interrupt_pending() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base_addr = memory.base_address();
+        memory.write_word(base_addr, WFI).unwrap();
+        memory.write_word(base_addr + 4, WFI).unwrap();
+        cpu.pc = base_addr;
+
+        assert_eq!(cpu.run(&mut memory, Some(100)).unwrap(), 1);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::Waiting));
+        assert_eq!(cpu.pc, base_addr + 4);
+        assert!(cpu.waiting_for_interrupt(0));
+        // A pending, enabled line wakes the hart even with mstatus.MIE clear
+        cpu.write_csr(CSR_MIE, MIP_MTIP);
+        assert!(!cpu.waiting_for_interrupt(MIP_MTIP));
+        cpu.write_csr(CSR_MIP, MIP_MTIP);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.pc, base_addr + 8);
+    }
+
+    #[test]
+    fn test_mepc_and_mtvec_are_warl() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let csrw = |csr: u32, rs1: u32| (csr << 20) | (rs1 << 15) | (1 << 12) | 0x73;
+        memory.write_word(base, csrw(0x341, 1)).unwrap(); // csrw mepc, x1
+        memory.write_word(base + 4, csrw(0x305, 2)).unwrap(); // csrw mtvec, x2
+        cpu.pc = base;
+        cpu.write_register(1, 0x8000_0103);
+        cpu.write_register(2, 0x8000_0102);
+        cpu.step(&mut memory).unwrap();
+        cpu.step(&mut memory).unwrap();
+        // Without C, mepc is 4-byte aligned; mode 2 is reserved and reads as direct
+        assert_eq!(cpu.mepc(), 0x8000_0100);
+        assert_eq!(cpu.mtvec(), 0x8000_0100);
+
+        // Host writes are legalized the same way; legal modes are kept
+        cpu.set_mtvec(0x8000_0203);
+        assert_eq!(cpu.mtvec(), 0x8000_0200);
+        cpu.set_mtvec(0x8000_0201);
+        assert_eq!(cpu.mtvec(), 0x8000_0201);
+        cpu.set_mepc(0x8000_0006);
+        assert_eq!(cpu.mepc(), 0x8000_0004);
+
+        // With C only bit 0 is cleared
+        cpu.misa_extensions |= MISA_C;
+        cpu.set_mepc(0x8000_0007);
+        assert_eq!(cpu.mepc(), 0x8000_0006);
+    }
+
+    #[test]
+    fn test_typed_csr_accessors() {
+        let mut cpu = Cpu::new();
+        cpu.set_mtvec(0x8000_0101);
+        assert_eq!(cpu.read_csr(0x305), 0x8000_0101);
+        cpu.write_csr(0x341, 0x8000_0040);
+        assert_eq!(cpu.mepc(), 0x8000_0040);
+        cpu.set_mie(MIP_MTIP);
+        assert_eq!(cpu.read_csr(Csr::Mie.address()), MIP_MTIP);
+
+        for csr in Csr::ALL {
+            assert_eq!(Csr::from_address(csr.address()), Some(csr));
+            assert_eq!(Csr::from_name(csr.name()), Some(csr));
+        }
+        assert_eq!(Csr::from_name("mscratch").map(Csr::address), Some(0x340));
+        assert_eq!(Csr::from_address(0x7FF), None);
+    }
+
+    #[test]
+    fn test_branch_trace_records_edges() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let program = [
+            0x0010_0293, // addi t0, zero, 1
+            0x0002_8463, // beqz t0, +8 (not taken)
+            0x0002_9463, // bnez t0, +8 (taken)
+            0x0000_0013, // nop (skipped)
+            0x0080_00EF, // jal ra, +8
+            0x0000_0013, // nop
+            0x0000_8067, // ret
+            0x0000_0263, // beqz zero, +4 (taken, to the next instruction)
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        let mut cpu = Cpu::new().with_reset_vector(base);
+        cpu.run(&mut memory, Some(2)).unwrap();
+        assert!(cpu.branch_trace().is_empty());
+
+        cpu.pc = base;
+        cpu.enable_branch_trace(true);
+        cpu.run(&mut memory, Some(6)).unwrap();
+        assert_eq!(
+            cpu.branch_trace(),
+            [
+                (base + 4, base + 8, false),
+                (base + 8, base + 16, true),
+                (base + 16, base + 24, true),
+                (base + 24, base + 20, true),
+            ]
+        );
+        cpu.pc = base + 28;
+        cpu.run(&mut memory, Some(1)).unwrap();
+        assert_eq!(
+            cpu.branch_trace().last(),
+            Some(&(base + 28, base + 32, true))
+        );
+
+        cpu.enable_branch_trace(false);
+        assert!(cpu.branch_trace().is_empty());
+    }
+
+    #[test]
+    fn test_csr_trace_records_instruction_and_trap_writes() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x3403_12F3).unwrap(); // csrrw t0, mscratch, t1
+        memory.write_word(base + 4, ECALL).unwrap();
+        cpu.pc = base;
+        cpu.set_mtvec(base + 0x100);
+        cpu.set_mstatus(MSTATUS_MIE);
+        cpu.set_ecall_behavior(EcallBehavior::Trap);
+        cpu.set_reg(Reg::T1, 0x1234);
+
+        cpu.enable_csr_trace(true);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(
+            cpu.csr_trace(),
+            [(base, Csr::Mscratch.address(), 0, 0x1234)]
+        );
+
+        // Trap entry writes mepc, mcause, mtval, then mstatus
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(
+            cpu.csr_trace()[1..],
+            [
+                (base + 4, CSR_MEPC, 0, base + 4),
+                (base + 4, CSR_MCAUSE, 0, CAUSE_ECALL_FROM_M),
+                (base + 4, CSR_MTVAL, 0, 0),
+                (
+                    base + 4,
+                    CSR_MSTATUS,
+                    MSTATUS_MIE,
+                    MSTATUS_MPIE | MSTATUS_MPP
+                ),
+            ]
+        );
+
+        cpu.enable_csr_trace(false);
+        assert!(cpu.csr_trace().is_empty());
+    }
+
+    #[test]
+    fn test_mip_lines_are_host_driven() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x3442_A073).unwrap(); // csrrs x0, mip, t0
+        memory.write_word(base + 4, 0x3443_3073).unwrap(); // csrrc x0, mip, t1
+        cpu.pc = base;
+        cpu.set_reg(Reg::T0, MIP_MTIP | MIP_MSIP | MIP_MEIP | 1 << 1);
+        cpu.step(&mut memory).unwrap();
+        // Only the non-line bit (SSIP) is writable from the guest
+        assert_eq!(cpu.read_csr(CSR_MIP), 1 << 1);
+
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        assert_eq!(cpu.read_csr(CSR_MIP), MIP_MTIP | 1 << 1);
+        cpu.set_reg(Reg::T1, !0);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.read_csr(CSR_MIP), MIP_MTIP);
+
+        // Enabled, the raised line is taken at the start of the next step
+        cpu.write_csr(CSR_MTVEC, base + 0x100);
+        memory.write_word(base + 0x100, 0x0000_0013).unwrap(); // nop
+        cpu.write_csr(CSR_MIE, MIP_MTIP);
+        cpu.write_csr(CSR_MSTATUS, MSTATUS_MIE);
+        cpu.step(&mut memory).unwrap();
+        assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_INTERRUPT | 7);
+        assert_eq!(cpu.read_csr(CSR_MEPC), base + 8);
+        assert_eq!(cpu.pc, base + 0x104);
+
+        cpu.clear_interrupt(Interrupt::MachineTimer);
+        assert_eq!(cpu.read_csr(CSR_MIP), 0);
+    }
+
+    #[test]
+    fn test_unimp_halts_cleanly() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base_addr = memory.base_address();
+
+        let program = [
+            (1 << 20) | (1 << 7) | 0x13,             // addi x1, x0, 1
+            (1 << 20) | (1 << 15) | (1 << 7) | 0x13, // addi x1, x1, 1
+            (1 << 20) | (1 << 15) | (1 << 7) | 0x13, // addi x1, x1, 1
+            UNIMP,
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base_addr + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base_addr;
+
+        let executed = cpu.run(&mut memory, Some(100)).unwrap();
+        assert_eq!(executed, 3);
+        assert_eq!(cpu.read_register(1), 3);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::Unimp(base_addr + 12)));
+        assert_eq!(cpu.pc, base_addr + 12);
+
+        // The compressed form (an all-zero halfword) is reported the same way
+        memory.write_word(base_addr, 0x0000_0000).unwrap();
+        cpu.pc = base_addr;
+        let result = cpu.step(&mut memory);
+        assert!(matches!(result, Err(EmulatorError::Unimp(pc)) if pc == base_addr));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("reached unimp / unreachable code at pc 0x{base_addr:08x}")
+        );
+    }
+
+    #[test]
+    fn test_c_ebreak_is_a_breakpoint() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x0010_0093).unwrap(); // addi x1, x0, 1
+        memory.write_halfword(base + 4, C_EBREAK as u16).unwrap();
+        memory.write_halfword(base + 6, 0x0001).unwrap(); // c.nop
+
+        cpu.breakpoint_mode = true;
+        cpu.pc = base;
+        assert_eq!(cpu.run(&mut memory, Some(10)).unwrap(), 1);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::Breakpoint));
+        assert_eq!(cpu.pc, base + 4);
+
+        // Outside breakpoint mode it is rejected like EBREAK
+        cpu.breakpoint_mode = false;
+        assert!(matches!(
+            cpu.step(&mut memory),
+            Err(EmulatorError::UnsupportedInstruction)
+        ));
+        assert_eq!(crate::disasm::disassemble(0x0001_9002), "c.ebreak");
+    }
 }
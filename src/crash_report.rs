@@ -0,0 +1,176 @@
+//! Crash report bundles written when a run stops on a fatal error
+//!
+//! A bundle is a directory of small text files a user can attach to a bug
+//! report. Every file is written independently from whatever state exists,
+//! so a missing piece (no sections, unreadable memory) never prevents the
+//! others from being written.
+
+use crate::{
+    cpu::Csr, disasm, memory::Memory, memory_map, memory_map::MapEntry, reg::Reg, RunReport,
+};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Bytes shown on each side of an address in the memory dumps
+const DUMP_RADIUS: u32 = 32;
+
+/// CSRs included in the register dump
+const DUMPED_CSRS: [Csr; 6] = [
+    Csr::Mstatus,
+    Csr::Mtvec,
+    Csr::Mepc,
+    Csr::Mcause,
+    Csr::Mtval,
+    Csr::Mip,
+];
+
+/// Write a crash report bundle for `report` into `dir`, creating it if needed
+///
+/// The bundle holds `crash.txt` (nekov version and error), `report.json`,
+/// `registers.txt`, `history.txt`, `memory.txt`, `sections.txt` and
+/// `machine.txt`. The first failure is returned after attempting every file.
+pub fn write_crash_report(
+    dir: &Path,
+    report: &RunReport,
+    error: &str,
+    sections: &[MapEntry],
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let files = [
+        ("crash.txt", summary(report, error)),
+        ("report.json", report.to_json() + "\n"),
+        ("registers.txt", registers(report)),
+        ("history.txt", history(report)),
+        ("memory.txt", memory_dumps(report)),
+        ("sections.txt", sections_table(sections)),
+        ("machine.txt", machine(report)),
+    ];
+    let mut result = Ok(());
+    for (name, contents) in files {
+        if let Err(e) = std::fs::write(dir.join(name), contents) {
+            result = result.and(Err(e));
+        }
+    }
+    result
+}
+
+fn summary(report: &RunReport, error: &str) -> String {
+    let pc = report.cpu.pc;
+    let asm = disasm::disassemble_at(report.memory.peek_word(pc), pc);
+    format!(
+        "nekov {}\nerror: {error}\npc: 0x{pc:08x} ({asm})\ninstructions executed: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        report.instructions_executed
+    )
+}
+
+fn registers(report: &RunReport) -> String {
+    let cpu = &report.cpu;
+    let mut text = format!("pc       0x{:08x}\n", cpu.pc);
+    for reg in Reg::ALL {
+        let _ = writeln!(
+            text,
+            "{:<8} 0x{:08x}",
+            format!("x{}/{reg}", reg.index()),
+            cpu.reg(reg)
+        );
+    }
+    for csr in DUMPED_CSRS {
+        let _ = writeln!(
+            text,
+            "{:<8} 0x{:08x}",
+            csr.name(),
+            cpu.read_csr(csr.address())
+        );
+    }
+    text
+}
+
+fn history(report: &RunReport) -> String {
+    let history = report.cpu.pc_history();
+    if history.is_empty() {
+        return "(no PC history recorded)\n".to_string();
+    }
+    let mut text = String::new();
+    for pc in history {
+        let word = report.memory.peek_word(pc);
+        let _ = writeln!(
+            text,
+            "0x{pc:08x}: {word:08x}  {}",
+            disasm::disassemble_at(word, pc)
+        );
+    }
+    text
+}
+
+fn memory_dumps(report: &RunReport) -> String {
+    let pc = report.cpu.pc;
+    let mut text = format!("around pc 0x{pc:08x}:\n{}", hexdump(&report.memory, pc));
+    let (operand, _) = report
+        .cpu
+        .memory_operand(report.memory.peek_word(report.cpu.pc));
+    if let Some((address, _)) = operand {
+        let _ = write!(
+            text,
+            "\naround data address 0x{address:08x}:\n{}",
+            hexdump(&report.memory, address)
+        );
+    }
+    text
+}
+
+/// 16-byte rows around `address`; bytes never written are shown as `--`
+fn hexdump(memory: &Memory, address: u32) -> String {
+    let start = address.wrapping_sub(DUMP_RADIUS) & !0xF;
+    let mut text = String::new();
+    for row in 0..(2 * DUMP_RADIUS / 16 + 1) {
+        let row_address = start.wrapping_add(row * 16);
+        let _ = write!(text, "0x{row_address:08x}:");
+        for i in 0..16 {
+            let byte_address = row_address.wrapping_add(i);
+            if memory.is_written(byte_address) {
+                let byte =
+                    memory.peek_word(byte_address & !3).to_le_bytes()[(byte_address & 3) as usize];
+                let _ = write!(text, " {byte:02x}");
+            } else {
+                text.push_str(" --");
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn machine(report: &RunReport) -> String {
+    match &report.machine {
+        Some(machine) => machine.to_string(),
+        None => "(no machine description)\n".to_string(),
+    }
+}
+
+fn sections_table(sections: &[MapEntry]) -> String {
+    if sections.is_empty() {
+        return "(no sections loaded)\n".to_string();
+    }
+    memory_map::format_table(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump_marks_unwritten_bytes() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base + 0x20, 0x1234_5678).unwrap();
+        let dump = hexdump(&memory, base + 0x20);
+        let rows: Vec<&str> = dump.lines().collect();
+        assert_eq!(rows.len(), 5);
+        assert!(rows[0].starts_with(&format!("0x{:08x}:", base)));
+        assert_eq!(
+            rows[2],
+            format!("0x{:08x}: 78 56 34 12{}", base + 0x20, " --".repeat(12))
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! RV32IMA disassembler producing objdump-style text
+//!
+//! Operands are separated by commas without spaces, e.g. `addi x2,x1,5`.
+//! Words that do not decode to a supported instruction render as `.word 0x...`.
+//!
+//! Immediates follow their semantics: arithmetic immediates and load/store
+//! offsets are signed decimal, shift amounts unsigned decimal, LUI/AUIPC
+//! immediates and CSR numbers hex. Branch and jump targets are signed offsets,
+//! or `0x...` addresses when the instruction's PC is known (`disassemble_at`).
+
+use crate::cpu::{is_sfence_vma, is_unimp, C_EBREAK, EBREAK, ECALL, FENCE_TSO, MRET, PAUSE, WFI};
+
+/// Extract the rd field
+fn rd(instruction: u32) -> u32 {
+    (instruction >> 7) & 0x1F
+}
+
+/// Extract the rs1 field
+fn rs1(instruction: u32) -> u32 {
+    (instruction >> 15) & 0x1F
+}
+
+/// Extract the rs2 field
+fn rs2(instruction: u32) -> u32 {
+    (instruction >> 20) & 0x1F
+}
+
+/// Extract the funct3 field
+fn funct3(instruction: u32) -> u32 {
+    (instruction >> 12) & 0x7
+}
+
+/// Extract the funct7 field
+fn funct7(instruction: u32) -> u32 {
+    (instruction >> 25) & 0x7F
+}
+
+/// Sign-extended I-type immediate
+fn imm_i(instruction: u32) -> i32 {
+    (instruction as i32) >> 20
+}
+
+/// Sign-extended S-type immediate
+fn imm_s(instruction: u32) -> i32 {
+    (((instruction & 0xFE00_0000) as i32) >> 20) | ((instruction >> 7) & 0x1F) as i32
+}
+
+/// Sign-extended B-type immediate
+fn imm_b(instruction: u32) -> i32 {
+    (((instruction & 0x8000_0000) as i32) >> 19)
+        | (((instruction >> 7) & 0x1) << 11) as i32
+        | (((instruction >> 25) & 0x3F) << 5) as i32
+        | (((instruction >> 8) & 0xF) << 1) as i32
+}
+
+/// Sign-extended J-type immediate
+fn imm_j(instruction: u32) -> i32 {
+    (((instruction & 0x8000_0000) as i32) >> 11)
+        | (instruction & 0x000F_F000) as i32
+        | (((instruction >> 20) & 0x1) << 11) as i32
+        | (((instruction >> 21) & 0x3FF) << 1) as i32
+}
+
+/// Return the destination register written by an instruction, if any (x0 excluded)
+pub fn destination_register(instruction: u32) -> Option<usize> {
+    let rd = rd(instruction) as usize;
+    let writes_rd = match instruction & 0x7F {
+        0x13 | 0x33 | 0x03 | 0x37 | 0x17 | 0x6F | 0x67 | 0x2F => true,
+        0x73 => funct3(instruction) != 0,
+        _ => false,
+    };
+    if writes_rd && rd != 0 {
+        Some(rd)
+    } else {
+        None
+    }
+}
+
+/// Disassemble a single 32-bit instruction word
+pub fn disassemble(instruction: u32) -> String {
+    decode_text(instruction, None).unwrap_or_else(|| format!(".word 0x{instruction:08x}"))
+}
+
+/// Disassemble the instruction at `pc`, showing branch and jump targets as addresses
+pub fn disassemble_at(instruction: u32, pc: u32) -> String {
+    decode_text(instruction, Some(pc)).unwrap_or_else(|| format!(".word 0x{instruction:08x}"))
+}
+
+/// A PC-relative target: the resolved address if `pc` is known, else the signed offset
+fn target(pc: Option<u32>, offset: i32) -> String {
+    match pc {
+        Some(pc) => format!("0x{:x}", pc.wrapping_add(offset as u32)),
+        None => offset.to_string(),
+    }
+}
+
+fn decode_text(instruction: u32, pc: Option<u32>) -> Option<String> {
+    if is_unimp(instruction) {
+        return Some("unimp".to_string());
+    }
+    if instruction & 0xFFFF == C_EBREAK {
+        return Some("c.ebreak".to_string());
+    }
+
+    let rd = rd(instruction);
+    let rs1 = rs1(instruction);
+    let rs2 = rs2(instruction);
+    let funct3 = funct3(instruction);
+    let funct7 = funct7(instruction);
+
+    let text = match instruction & 0x7F {
+        0x13 => {
+            let imm = imm_i(instruction);
+            let shamt = (instruction >> 20) & 0x1F;
+            match funct3 {
+                0x0 => format!("addi x{rd},x{rs1},{imm}"),
+                0x2 => format!("slti x{rd},x{rs1},{imm}"),
+                0x3 => format!("sltiu x{rd},x{rs1},{imm}"),
+                0x4 => format!("xori x{rd},x{rs1},{imm}"),
+                0x6 => format!("ori x{rd},x{rs1},{imm}"),
+                0x7 => format!("andi x{rd},x{rs1},{imm}"),
+                0x1 if funct7 == 0x00 => format!("slli x{rd},x{rs1},{shamt}"),
+                0x5 if funct7 == 0x00 => format!("srli x{rd},x{rs1},{shamt}"),
+                0x5 if funct7 == 0x20 => format!("srai x{rd},x{rs1},{shamt}"),
+                _ => return None,
+            }
+        }
+        0x33 => {
+            let mnemonic = match (funct7, funct3) {
+                (0x00, 0x0) => "add",
+                (0x20, 0x0) => "sub",
+                (0x00, 0x1) => "sll",
+                (0x00, 0x2) => "slt",
+                (0x00, 0x3) => "sltu",
+                (0x00, 0x4) => "xor",
+                (0x00, 0x5) => "srl",
+                (0x20, 0x5) => "sra",
+                (0x00, 0x6) => "or",
+                (0x00, 0x7) => "and",
+                (0x01, 0x0) => "mul",
+                (0x01, 0x1) => "mulh",
+                (0x01, 0x2) => "mulhsu",
+                (0x01, 0x3) => "mulhu",
+                (0x01, 0x4) => "div",
+                (0x01, 0x5) => "divu",
+                (0x01, 0x6) => "rem",
+                (0x01, 0x7) => "remu",
+                _ => return None,
+            };
+            format!("{mnemonic} x{rd},x{rs1},x{rs2}")
+        }
+        0x03 => {
+            let mnemonic = match funct3 {
+                0x0 => "lb",
+                0x1 => "lh",
+                0x2 => "lw",
+                0x4 => "lbu",
+                0x5 => "lhu",
+                _ => return None,
+            };
+            format!("{mnemonic} x{rd},{}(x{rs1})", imm_i(instruction))
+        }
+        0x23 => {
+            let mnemonic = match funct3 {
+                0x0 => "sb",
+                0x1 => "sh",
+                0x2 => "sw",
+                _ => return None,
+            };
+            format!("{mnemonic} x{rs2},{}(x{rs1})", imm_s(instruction))
+        }
+        0x63 => {
+            let mnemonic = match funct3 {
+                0x0 => "beq",
+                0x1 => "bne",
+                0x4 => "blt",
+                0x5 => "bge",
+                0x6 => "bltu",
+                0x7 => "bgeu",
+                _ => return None,
+            };
+            format!(
+                "{mnemonic} x{rs1},x{rs2},{}",
+                target(pc, imm_b(instruction))
+            )
+        }
+        0x37 => format!("lui x{rd},0x{:x}", instruction >> 12),
+        0x17 => format!("auipc x{rd},0x{:x}", instruction >> 12),
+        0x6F => format!("jal x{rd},{}", target(pc, imm_j(instruction))),
+        0x67 if funct3 == 0 => format!("jalr x{rd},{}(x{rs1})", imm_i(instruction)),
+        0x73 => {
+            let csr = instruction >> 20;
+            match funct3 {
+                0x0 => match instruction {
+                    ECALL => "ecall".to_string(),
+                    EBREAK => "ebreak".to_string(),
+                    MRET => "mret".to_string(),
+                    WFI => "wfi".to_string(),
+                    _ if is_sfence_vma(instruction) => {
+                        format!("sfence.vma x{rs1},x{rs2}")
+                    }
+                    _ => return None,
+                },
+                0x1 => format!("csrrw x{rd},0x{csr:x},x{rs1}"),
+                0x2 => format!("csrrs x{rd},0x{csr:x},x{rs1}"),
+                0x3 => format!("csrrc x{rd},0x{csr:x},x{rs1}"),
+                0x5 => format!("csrrwi x{rd},0x{csr:x},{rs1}"),
+                0x6 => format!("csrrsi x{rd},0x{csr:x},{rs1}"),
+                0x7 => format!("csrrci x{rd},0x{csr:x},{rs1}"),
+                _ => return None,
+            }
+        }
+        0x2F if funct3 == 0x2 => {
+            let mnemonic = match instruction >> 27 {
+                0x02 if rs2 == 0 => "lr.w",
+                0x03 => "sc.w",
+                0x01 => "amoswap.w",
+                0x00 => "amoadd.w",
+                0x04 => "amoxor.w",
+                0x0C => "amoand.w",
+                0x08 => "amoor.w",
+                0x10 => "amomin.w",
+                0x14 => "amomax.w",
+                0x18 => "amominu.w",
+                0x1C => "amomaxu.w",
+                _ => return None,
+            };
+            if mnemonic == "lr.w" {
+                format!("{mnemonic} x{rd},(x{rs1})")
+            } else {
+                format!("{mnemonic} x{rd},x{rs2},(x{rs1})")
+            }
+        }
+        0x0F => match funct3 {
+            0x0 if instruction == FENCE_TSO => "fence.tso".to_string(),
+            0x0 if instruction == PAUSE => "pause".to_string(),
+            0x0 => "fence".to_string(),
+            0x1 => "fence.i".to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_common_instructions() {
+        assert_eq!(disassemble(0x0050_8113), "addi x2,x1,5");
+        assert_eq!(disassemble(0xfff0_8093), "addi x1,x1,-1");
+        assert_eq!(disassemble(0x0020_82b3), "add x5,x1,x2");
+        assert_eq!(disassemble(0x0220_8833), "mul x16,x1,x2");
+        assert_eq!(disassemble(0x0004_2503), "lw x10,0(x8)");
+        assert_eq!(disassemble(0xfe94_2e23), "sw x9,-4(x8)");
+        assert_eq!(disassemble(0x0020_8463), "beq x1,x2,8");
+        assert_eq!(disassemble(0x1234_50b7), "lui x1,0x12345");
+        assert_eq!(disassemble(0x0080_00ef), "jal x1,8");
+        assert_eq!(disassemble(0x3000_2573), "csrrs x10,0x300,x0");
+        assert_eq!(disassemble(0x0000_0073), "ecall");
+        assert_eq!(disassemble(0x100525af), "lr.w x11,(x10)");
+        assert_eq!(disassemble(0xdead_beef), "jal x29,-150038");
+        assert_eq!(disassemble(0xc000_1073), "unimp");
+        assert_eq!(disassemble(0xffff_ffff), ".word 0xffffffff");
+    }
+
+    #[test]
+    fn test_immediate_representation() {
+        // Signed arithmetic immediates, never the raw 32-bit pattern
+        assert_eq!(disassemble(0xfff0_8093), "addi x1,x1,-1");
+        assert_eq!(disassemble(0x8000_2093), "slti x1,x0,-2048");
+        // Upper immediates stay unsigned hex even with the top bit set
+        assert_eq!(disassemble(0xffff_f0b7), "lui x1,0xfffff");
+        assert_eq!(disassemble(0x8000_0097), "auipc x1,0x80000");
+        // Shift amounts are unsigned
+        assert_eq!(disassemble(0x01f0_d093), "srli x1,x1,31");
+        // Branch and jump targets resolve to addresses when the PC is known
+        assert_eq!(
+            disassemble_at(0x0020_8463, 0x8000_0000),
+            "beq x1,x2,0x80000008"
+        );
+        assert_eq!(
+            disassemble_at(0xffdf_f06f, 0x8000_0010),
+            "jal x0,0x8000000c"
+        );
+        assert_eq!(disassemble(0xffdf_f06f), "jal x0,-4");
+    }
+
+    #[test]
+    fn test_mnemonics_match_supported_catalog() {
+        let mut seen = std::collections::HashSet::new();
+        let fields = (0..8u32).flat_map(|funct3| (0..128u32).map(move |funct7| (funct3, funct7)));
+        let words = [
+            0x03u32, 0x0F, 0x13, 0x17, 0x23, 0x2F, 0x33, 0x37, 0x63, 0x67, 0x6F, 0x73,
+        ]
+        .into_iter()
+        .flat_map(|opcode| {
+            fields.clone().map(move |(funct3, funct7)| {
+                funct7 << 25 | 2 << 20 | 1 << 15 | funct3 << 12 | 1 << 7 | opcode
+            })
+        })
+        .chain([
+            0x0000_0073,
+            0x0010_0073,
+            0x3020_0073,
+            0x1050_0073,
+            0x12b5_0073,
+            0x8330_000F,
+            0x0100_000F,
+            0x1005_25af,
+            0x9002,
+        ]);
+        for word in words {
+            let text = disassemble(word);
+            let mnemonic = text.split(' ').next().unwrap();
+            if mnemonic == ".word" || mnemonic == "unimp" {
+                continue;
+            }
+            assert!(
+                crate::supported_instructions().contains(&mnemonic),
+                "{text} (0x{word:08x}) is not in the catalog"
+            );
+            seen.insert(mnemonic.to_string());
+        }
+        for mnemonic in crate::supported_instructions() {
+            assert!(seen.contains(mnemonic), "{mnemonic} is never disassembled");
+        }
+    }
+
+    #[test]
+    fn test_destination_register() {
+        assert_eq!(destination_register(0x0050_8113), Some(2)); // addi x2,x1,5
+        assert_eq!(destination_register(0xfe94_2e23), None); // sw
+        assert_eq!(destination_register(0x0020_8463), None); // beq
+        assert_eq!(destination_register(0x0000_0013), None); // nop writes x0
+    }
+}
@@ -0,0 +1,222 @@
+//! Minimal RV32 ELF writer for tests that need an executable without a toolchain
+//!
+//! Each `Segment` becomes one PT_LOAD entry. Segments given a section name
+//! also get a section header (`SHT_NOBITS` when they hold no file bytes), and
+//! relocations go into a `.rela.dyn` section; without either the file has no
+//! section headers at all.
+
+use object::elf::{
+    ET_DYN, ET_EXEC, PF_W, PF_X, PT_LOAD, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS,
+    SHT_PROGBITS, SHT_RELA, SHT_STRTAB,
+};
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const RELA_SIZE: usize = 12;
+
+/// One PT_LOAD segment: file bytes at the LMA, zero fill up to `mem_size` at the VMA
+#[derive(Debug, Clone)]
+pub struct Segment {
+    vaddr: u32,
+    paddr: u32,
+    data: Vec<u8>,
+    mem_size: u32,
+    flags: u32,
+    section: Option<String>,
+}
+
+impl Segment {
+    /// Segment holding `data` at `vaddr` (also its LMA), with `PF_*` `flags`
+    pub fn new(vaddr: u32, data: &[u8], flags: u32) -> Self {
+        Self {
+            vaddr,
+            paddr: vaddr,
+            data: data.to_vec(),
+            mem_size: data.len() as u32,
+            flags,
+            section: None,
+        }
+    }
+
+    /// Builder: store the file bytes at `paddr` instead of the VMA
+    pub fn with_lma(mut self, paddr: u32) -> Self {
+        self.paddr = paddr;
+        self
+    }
+
+    /// Builder: follow the file bytes with `size` zeroed bytes
+    pub fn with_bss(mut self, size: u32) -> Self {
+        self.mem_size += size;
+        self
+    }
+
+    /// Builder: describe the segment with a section header called `name`
+    pub fn with_section(mut self, name: &str) -> Self {
+        self.section = Some(name.to_string());
+        self
+    }
+}
+
+/// An RV32 little-endian executable under construction
+#[derive(Debug, Clone)]
+pub struct ElfBuilder {
+    entry: u32,
+    elf_type: u16,
+    segments: Vec<Segment>,
+    /// `(r_offset, r_type, r_addend)`
+    relocations: Vec<(u32, u32, u32)>,
+}
+
+impl ElfBuilder {
+    /// Static executable entered at `entry`
+    pub fn new(entry: u32) -> Self {
+        Self {
+            entry,
+            elf_type: ET_EXEC,
+            segments: Vec::new(),
+            relocations: Vec::new(),
+        }
+    }
+
+    /// Builder: mark the file `ET_DYN`, as a position-independent executable
+    pub fn with_pie(mut self) -> Self {
+        self.elf_type = ET_DYN;
+        self
+    }
+
+    /// Builder: append a PT_LOAD segment
+    pub fn with_segment(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Builder: append a `.rela.dyn` entry
+    pub fn with_relocation(mut self, offset: u32, r_type: u32, addend: u32) -> Self {
+        self.relocations.push((offset, r_type, addend));
+        self
+    }
+
+    /// Serialize the headers, segment bytes and any sections
+    pub fn build(&self) -> Vec<u8> {
+        let mut elf = vec![0u8; EHDR_SIZE + PHDR_SIZE * self.segments.len()];
+        let mut offsets = Vec::new();
+        for (index, segment) in self.segments.iter().enumerate() {
+            let offset = elf.len().next_multiple_of(4);
+            offsets.push(offset as u32);
+            elf.resize(offset, 0);
+            elf.extend_from_slice(&segment.data);
+            let ph = EHDR_SIZE + PHDR_SIZE * index;
+            put32(&mut elf, ph, PT_LOAD);
+            put32(&mut elf, ph + 4, offset as u32);
+            put32(&mut elf, ph + 8, segment.vaddr);
+            put32(&mut elf, ph + 12, segment.paddr);
+            put32(&mut elf, ph + 16, segment.data.len() as u32);
+            put32(&mut elf, ph + 20, segment.mem_size);
+            put32(&mut elf, ph + 24, segment.flags);
+            put32(&mut elf, ph + 28, 4); // p_align
+        }
+
+        // (sh_name, sh_type, sh_flags, sh_addr, sh_offset, sh_size, sh_entsize)
+        let mut names = b"\0".to_vec();
+        let mut headers = Vec::new();
+        let add_name = |names: &mut Vec<u8>, name: &str| {
+            let at = names.len() as u32;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            at
+        };
+        for (segment, &offset) in self.segments.iter().zip(&offsets) {
+            let Some(name) = &segment.section else {
+                continue;
+            };
+            let mut flags = SHF_ALLOC;
+            if segment.flags & PF_W != 0 {
+                flags |= SHF_WRITE;
+            }
+            if segment.flags & PF_X != 0 {
+                flags |= SHF_EXECINSTR;
+            }
+            let sh_type = if segment.data.is_empty() {
+                SHT_NOBITS
+            } else {
+                SHT_PROGBITS
+            };
+            let name = add_name(&mut names, name);
+            headers.push((
+                name,
+                sh_type,
+                flags,
+                segment.vaddr,
+                offset,
+                segment.mem_size,
+                0,
+            ));
+        }
+        if !self.relocations.is_empty() {
+            let offset = elf.len().next_multiple_of(4);
+            elf.resize(offset, 0);
+            for &(r_offset, r_type, r_addend) in &self.relocations {
+                elf.extend_from_slice(&r_offset.to_le_bytes());
+                elf.extend_from_slice(&r_type.to_le_bytes());
+                elf.extend_from_slice(&r_addend.to_le_bytes());
+            }
+            let name = add_name(&mut names, ".rela.dyn");
+            let size = (RELA_SIZE * self.relocations.len()) as u32;
+            headers.push((name, SHT_RELA, 0, 0, offset as u32, size, RELA_SIZE as u32));
+        }
+
+        let mut shoff = 0;
+        if !headers.is_empty() {
+            let name = add_name(&mut names, ".shstrtab");
+            let size = names.len() as u32;
+            headers.push((name, SHT_STRTAB, 0, 0, elf.len() as u32, size, 0));
+            elf.extend_from_slice(&names);
+
+            shoff = elf.len().next_multiple_of(4);
+            elf.resize(shoff + SHDR_SIZE * (headers.len() + 1), 0);
+            for (index, &(name, sh_type, flags, addr, offset, size, entsize)) in
+                headers.iter().enumerate()
+            {
+                let sh = shoff + SHDR_SIZE * (index + 1);
+                put32(&mut elf, sh, name);
+                put32(&mut elf, sh + 4, sh_type);
+                put32(&mut elf, sh + 8, flags);
+                put32(&mut elf, sh + 12, addr);
+                put32(&mut elf, sh + 16, offset);
+                put32(&mut elf, sh + 20, size);
+                put32(&mut elf, sh + 32, 4); // sh_addralign
+                put32(&mut elf, sh + 36, entsize);
+            }
+        }
+
+        // ELF header
+        elf[0..4].copy_from_slice(b"\x7fELF");
+        elf[4] = 1; // ELFCLASS32
+        elf[5] = 1; // little-endian
+        elf[6] = 1; // EV_CURRENT
+        put16(&mut elf, 16, self.elf_type);
+        put16(&mut elf, 18, object::elf::EM_RISCV);
+        put32(&mut elf, 20, 1); // e_version
+        put32(&mut elf, 24, self.entry);
+        put32(&mut elf, 28, EHDR_SIZE as u32); // e_phoff
+        put32(&mut elf, 32, shoff as u32);
+        put16(&mut elf, 40, EHDR_SIZE as u16);
+        put16(&mut elf, 42, PHDR_SIZE as u16);
+        put16(&mut elf, 44, self.segments.len() as u16);
+        put16(&mut elf, 46, SHDR_SIZE as u16);
+        if !headers.is_empty() {
+            put16(&mut elf, 48, headers.len() as u16 + 1); // e_shnum
+            put16(&mut elf, 50, headers.len() as u16); // e_shstrndx
+        }
+        elf
+    }
+}
+
+fn put16(elf: &mut [u8], at: usize, value: u16) {
+    elf[at..at + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put32(elf: &mut [u8], at: usize, value: u32) {
+    elf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
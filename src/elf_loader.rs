@@ -1,53 +1,517 @@
 /// ELF binary loading functionality
-use crate::{memory::Memory, EmulatorError, Result};
-use object::{Object, ObjectSegment};
+use crate::{
+    memory::Memory,
+    memory_map::{MapEntry, MapKind},
+    EmulatorError, Result,
+};
+use object::{
+    read::elf::ProgramHeader, Object, ObjectSection, ObjectSegment, ObjectSymbol, RelocationFlags,
+    SectionFlags, SegmentFlags, SymbolKind, SymbolSection,
+};
 use std::fs;
 
+/// Options controlling how an ELF binary is placed in memory
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Report loaded segments at verbosity >= 1
+    pub verbosity: u8,
+    /// Write-protect executable segments
+    pub protect_text: bool,
+    /// Offset added to every segment address and the entry point (PIE load base)
+    pub load_bias: u32,
+    /// Start here instead of at `e_entry`, skipping the check that it was loaded
+    pub entry: Option<u32>,
+    /// Ranges already claimed, such as peripherals, that no segment may be written over
+    pub reserved: Vec<MapEntry>,
+    /// Load overlapping segments anyway, later segments overwriting earlier ones
+    pub allow_overlap: bool,
+}
+
+/// Two ranges an ELF would load on top of each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOverlap {
+    /// Segment range being written
+    pub segment: MapEntry,
+    /// Earlier segment range or reserved range it collides with
+    pub other: MapEntry,
+}
+
+impl std::fmt::Display for LoadOverlap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |entry: &MapEntry| {
+            let kind = match entry.kind {
+                MapKind::Section => "section",
+                MapKind::Segment => "segment",
+                MapKind::Peripheral => "peripheral",
+            };
+            format!(
+                "{kind} {} (0x{:08x}-0x{:08x})",
+                entry.name,
+                entry.start,
+                entry.end()
+            )
+        };
+        write!(
+            f,
+            "{} overlaps {}",
+            describe(&self.segment),
+            describe(&self.other)
+        )
+    }
+}
+
+/// An ELF entry point outside every loaded segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryNotLoaded {
+    /// Entry point (after the load bias)
+    pub entry: u32,
+    /// Loaded `[start, end)` ranges, in program header order
+    pub loaded: Vec<(u32, u32)>,
+}
+
+impl std::fmt::Display for EntryNotLoaded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "entry point 0x{:08x} not within any loaded segment; loaded ranges are ",
+            self.entry
+        )?;
+        if self.loaded.is_empty() {
+            return write!(f, "(none)");
+        }
+        for (i, (start, end)) in self.loaded.iter().enumerate() {
+            let separator = if i == 0 { "" } else { ", " };
+            write!(f, "{separator}0x{start:08x}-0x{end:08x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// What a run needs to know about an ELF it loaded, taken from a single parse
+///
+/// Only `entry` includes the load bias; the rest is as linked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadedElf {
+    /// Address execution starts at
+    pub entry: u32,
+    /// Memory map entries for the allocated, non-empty sections
+    pub sections: Vec<MapEntry>,
+    /// Named symbols as `(address, name)` sorted by address, as `ElfLoader::symbols` returns
+    pub symbols: Vec<(u32, String)>,
+    /// End address (exclusive) of the highest loadable segment, including its BSS
+    pub image_end: u32,
+}
+
+impl LoadedElf {
+    /// Address of the symbol `name`, if defined
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol)| symbol == name)
+            .map(|&(address, _)| address)
+    }
+}
+
 /// ELF loader for loading binaries into emulator memory
 pub struct ElfLoader;
 
 impl ElfLoader {
     /// Load an ELF binary into memory
     pub fn load_elf(file_path: &std::path::Path, memory: &mut Memory) -> Result<u32> {
+        Self::load_elf_with_verbosity(file_path, memory, 1)
+    }
+
+    /// Load an ELF binary into memory, reporting loaded segments at verbosity >= 1
+    pub fn load_elf_with_verbosity(
+        file_path: &std::path::Path,
+        memory: &mut Memory,
+        verbosity: u8,
+    ) -> Result<u32> {
+        let options = LoadOptions {
+            verbosity,
+            ..LoadOptions::default()
+        };
+        Self::load_elf_with_options(file_path, memory, &options)
+    }
+
+    /// Load an ELF binary with the given options, applying dynamic relocations
+    ///
+    /// Segment contents are placed at their load address (LMA, `p_paddr`).
+    /// When that differs from the virtual address, as for `.data` kept in
+    /// flash by an `AT>` linker script, the startup code is expected to copy
+    /// it to the VMA like on hardware; only the BSS part is zeroed at the VMA.
+    pub fn load_elf_with_options(
+        file_path: &std::path::Path,
+        memory: &mut Memory,
+        options: &LoadOptions,
+    ) -> Result<u32> {
+        Ok(Self::load_elf_file(file_path, memory, options)?.entry)
+    }
+
+    /// Load an ELF binary as `load_elf_with_options` does, also returning its
+    /// sections, symbols and image end
+    pub fn load_elf_file(
+        file_path: &std::path::Path,
+        memory: &mut Memory,
+        options: &LoadOptions,
+    ) -> Result<LoadedElf> {
         // Read the ELF file
         let data = fs::read(file_path).map_err(|_| EmulatorError::FileNotFound)?;
+        Self::load_elf_image(&data, memory, options)
+    }
+
+    /// Load an ELF image already in memory, as `load_elf_with_options` does for a file
+    pub fn load_elf_data(data: &[u8], memory: &mut Memory, options: &LoadOptions) -> Result<u32> {
+        Ok(Self::load_elf_image(data, memory, options)?.entry)
+    }
+
+    /// Load an ELF image already in memory, as `load_elf_file` does for a file
+    pub fn load_elf_image(
+        data: &[u8],
+        memory: &mut Memory,
+        options: &LoadOptions,
+    ) -> Result<LoadedElf> {
+        let verbosity = options.verbosity;
+        let bias = options.load_bias;
 
         // Parse the ELF file
-        let obj_file = object::File::parse(&*data).map_err(|_| EmulatorError::InvalidElfFormat)?;
+        let obj_file = object::File::parse(data).map_err(|_| EmulatorError::InvalidElfFormat)?;
 
-        let entry_point = obj_file.entry() as u32;
+        let entry_point = (obj_file.entry() as u32).wrapping_add(bias);
+
+        // Check every target range before writing anything
+        if !options.allow_overlap {
+            Self::check_overlaps(&obj_file, bias, &options.reserved)?;
+        }
 
         // Load segments into memory (program headers)
-        for segment in obj_file.segments() {
-            let vaddr = segment.address() as u32;
+        let load_addresses = Self::load_addresses(&obj_file);
+        let mut loaded = Vec::new();
+        for (index, segment) in obj_file.segments().enumerate() {
+            let vaddr = (segment.address() as u32).wrapping_add(bias);
+            let paddr = load_addresses
+                .get(index)
+                .map_or(vaddr, |&paddr| (paddr as u32).wrapping_add(bias));
             let file_range = segment.file_range();
             let file_size = file_range.1;
+            let mem_size = segment.size();
 
-            if file_size == 0 {
+            if file_size == 0 && mem_size == 0 {
                 continue;
             }
+            loaded.push((vaddr, vaddr.wrapping_add(mem_size as u32)));
 
             // Get segment data
             let segment_data = segment
                 .data()
                 .map_err(|_| EmulatorError::InvalidElfFormat)?;
 
-            // Load segment into memory
-            memory
-                .load_data(vaddr, segment_data)
-                .map_err(|_| EmulatorError::MemoryAccessError)?;
+            // Load segment into memory at its load address
+            memory.load_data(paddr, segment_data)?;
+
+            if verbosity >= 1 && file_size > 0 {
+                if paddr == vaddr {
+                    println!("Loaded segment at 0x{vaddr:08x} (size: {file_size} bytes)");
+                } else {
+                    println!(
+                        "Loaded segment at LMA 0x{paddr:08x} for VMA 0x{vaddr:08x} (size: {file_size} bytes)"
+                    );
+                }
+            }
+
+            // The part of the segment past the file data (BSS) reads as zero
+            let bss_size = mem_size.saturating_sub(file_size) as usize;
+            if bss_size > 0 {
+                let bss_start = vaddr.wrapping_add(file_size as u32);
+                memory.load_data(bss_start, &vec![0; bss_size])?;
+                if verbosity >= 1 {
+                    println!("Zeroed BSS at 0x{bss_start:08x} (size: {bss_size} bytes)");
+                }
+            }
+
+            let executable = match segment.flags() {
+                SegmentFlags::Elf { p_flags } => p_flags & object::elf::PF_X != 0,
+                _ => false,
+            };
+            if options.protect_text && executable {
+                memory.write_protect_range(paddr, segment_data.len() as u32, true);
+            }
+        }
+
+        Self::apply_relocations(&obj_file, memory, bias)?;
+
+        let entry = match options.entry {
+            Some(entry) => entry,
+            None if loaded
+                .iter()
+                .any(|&(start, end)| entry_point.wrapping_sub(start) < end.wrapping_sub(start)) =>
+            {
+                entry_point
+            }
+            None => {
+                return Err(EmulatorError::EntryNotLoaded(EntryNotLoaded {
+                    entry: entry_point,
+                    loaded,
+                }))
+            }
+        };
+        Ok(LoadedElf {
+            entry,
+            sections: Self::file_sections(&obj_file),
+            symbols: Self::file_symbols(&obj_file),
+            image_end: Self::file_image_end(&obj_file),
+        })
+    }
+
+    /// Fail with `LoadOverlap` if two written ranges, or one and a reserved range, overlap
+    fn check_overlaps(obj_file: &object::File, bias: u32, reserved: &[MapEntry]) -> Result<()> {
+        let mut claimed = reserved.to_vec();
+        for entry in Self::written_ranges(obj_file, bias) {
+            let overlaps = |other: &&MapEntry| {
+                u64::from(entry.start) < u64::from(other.start) + u64::from(other.size)
+                    && u64::from(other.start) < u64::from(entry.start) + u64::from(entry.size)
+            };
+            if let Some(other) = claimed.iter().find(overlaps) {
+                return Err(EmulatorError::LoadOverlap(Box::new(LoadOverlap {
+                    segment: entry,
+                    other: other.clone(),
+                })));
+            }
+            claimed.push(entry);
+        }
+        Ok(())
+    }
+
+    /// The ranges loading writes: each PT_LOAD segment's file bytes at its
+    /// LMA and its BSS at its VMA
+    ///
+    /// Entries are named `#index`, plus ` bss` for the zeroed part, followed
+    /// by the sections the range holds when the ELF has section headers.
+    /// Thread-local sections are never listed: `.tbss` shares addresses with
+    /// whatever follows `.tdata` by design.
+    fn written_ranges(obj_file: &object::File, bias: u32) -> Vec<MapEntry> {
+        let sections: Vec<(MapEntry, bool)> = obj_file
+            .sections()
+            .filter(|section| {
+                matches!(section.flags(), SectionFlags::Elf { sh_flags }
+                    if sh_flags & u64::from(object::elf::SHF_TLS) == 0)
+            })
+            .filter_map(|section| {
+                let nobits = section.file_range().is_none();
+                Some((Self::section_entry(&section)?, nobits))
+            })
+            .collect();
+        let named = |label: String, start: u32, end: u32, nobits: bool| {
+            let inside: Vec<&str> = sections
+                .iter()
+                .filter(|(entry, bss)| *bss == nobits && (start..end).contains(&entry.start))
+                .map(|(entry, _)| entry.name.as_str())
+                .collect();
+            if inside.is_empty() {
+                label
+            } else {
+                format!("{label} [{}]", inside.join(", "))
+            }
+        };
+
+        let load_addresses = Self::load_addresses(obj_file);
+        let mut ranges = Vec::new();
+        for (index, segment) in obj_file.segments().enumerate() {
+            let vaddr = segment.address() as u32;
+            let paddr = load_addresses
+                .get(index)
+                .map_or(vaddr, |&paddr| paddr as u32);
+            let file_size = segment.file_range().1 as u32;
+            let mem_size = segment.size() as u32;
+            let perms = match segment.flags() {
+                SegmentFlags::Elf { p_flags } => format!(
+                    "r{}{}",
+                    if p_flags & object::elf::PF_W != 0 {
+                        'w'
+                    } else {
+                        '-'
+                    },
+                    if p_flags & object::elf::PF_X != 0 {
+                        'x'
+                    } else {
+                        '-'
+                    }
+                ),
+                _ => "r--".to_string(),
+            };
+            let mut range = |name: String, start: u32, size: u32| {
+                ranges.push(MapEntry {
+                    kind: MapKind::Segment,
+                    name,
+                    start: start.wrapping_add(bias),
+                    size,
+                    perms: perms.clone(),
+                })
+            };
+            if file_size > 0 {
+                let name = named(
+                    format!("#{index}"),
+                    vaddr,
+                    vaddr.wrapping_add(file_size),
+                    false,
+                );
+                range(name, paddr, file_size);
+            }
+            let bss_size = mem_size.saturating_sub(file_size);
+            if bss_size > 0 {
+                let bss_start = vaddr.wrapping_add(file_size);
+                let bss_end = bss_start.wrapping_add(bss_size);
+                let name = named(format!("#{index} bss"), bss_start, bss_end, true);
+                range(name, bss_start, bss_size);
+            }
+        }
+        ranges
+    }
+
+    /// Load (physical) address of each PT_LOAD program header, in `segments()` order
+    fn load_addresses(obj_file: &object::File) -> Vec<u64> {
+        match obj_file {
+            object::File::Elf32(elf) => elf
+                .elf_program_headers()
+                .iter()
+                .filter(|header| header.p_type(elf.endian()) == object::elf::PT_LOAD)
+                .map(|header| u64::from(header.p_paddr(elf.endian())))
+                .collect(),
+            object::File::Elf64(elf) => elf
+                .elf_program_headers()
+                .iter()
+                .filter(|header| header.p_type(elf.endian()) == object::elf::PT_LOAD)
+                .map(|header| header.p_paddr(elf.endian()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// End address (exclusive) of the highest loadable segment, including its BSS
+    pub fn image_end(file_path: &std::path::Path) -> Result<u32> {
+        let data = fs::read(file_path).map_err(|_| EmulatorError::FileNotFound)?;
+        let obj_file = object::File::parse(&*data).map_err(|_| EmulatorError::InvalidElfFormat)?;
+        Ok(Self::file_image_end(&obj_file))
+    }
+
+    fn file_image_end(obj_file: &object::File) -> u32 {
+        obj_file
+            .segments()
+            .map(|segment| segment.address().saturating_add(segment.size()) as u32)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Memory map entries for the allocated, non-empty sections
+    pub fn sections(file_path: &std::path::Path) -> Result<Vec<MapEntry>> {
+        let data = fs::read(file_path).map_err(|_| EmulatorError::FileNotFound)?;
+        let obj_file = object::File::parse(&*data).map_err(|_| EmulatorError::InvalidElfFormat)?;
+        Ok(Self::file_sections(&obj_file))
+    }
+
+    fn file_sections(obj_file: &object::File) -> Vec<MapEntry> {
+        obj_file
+            .sections()
+            .filter_map(|section| Self::section_entry(&section))
+            .collect()
+    }
 
-            println!("Loaded segment at 0x{vaddr:08x} (size: {file_size} bytes)");
+    /// Memory map entry for `section` if it is allocated and non-empty
+    fn section_entry(section: &object::Section) -> Option<MapEntry> {
+        let SectionFlags::Elf { sh_flags } = section.flags() else {
+            return None;
+        };
+        if sh_flags & u64::from(object::elf::SHF_ALLOC) == 0 || section.size() == 0 {
+            return None;
         }
+        let flag = |bit: u32, c: char| {
+            if sh_flags & u64::from(bit) != 0 {
+                c
+            } else {
+                '-'
+            }
+        };
+        Some(MapEntry {
+            kind: MapKind::Section,
+            name: section.name().unwrap_or("?").to_string(),
+            start: section.address() as u32,
+            size: section.size() as u32,
+            perms: format!(
+                "r{}{}",
+                flag(object::elf::SHF_WRITE, 'w'),
+                flag(object::elf::SHF_EXECINSTR, 'x')
+            ),
+        })
+    }
+
+    /// Named symbols defined in a section, as `(address, name)` sorted by address
+    ///
+    /// Assembler-local `.L` labels are left out.
+    pub fn symbols(file_path: &std::path::Path) -> Result<Vec<(u32, String)>> {
+        let data = fs::read(file_path).map_err(|_| EmulatorError::FileNotFound)?;
+        let obj_file = object::File::parse(&*data).map_err(|_| EmulatorError::InvalidElfFormat)?;
+        Ok(Self::file_symbols(&obj_file))
+    }
+
+    fn file_symbols(obj_file: &object::File) -> Vec<(u32, String)> {
+        let mut symbols: Vec<(u32, String)> = obj_file
+            .symbols()
+            .filter(|symbol| {
+                matches!(symbol.section(), SymbolSection::Section(_))
+                    && !matches!(symbol.kind(), SymbolKind::Section | SymbolKind::File)
+            })
+            .filter_map(|symbol| {
+                let name = symbol.name().ok()?;
+                (!name.is_empty() && !name.starts_with(".L"))
+                    .then(|| (symbol.address() as u32, name.to_string()))
+            })
+            .collect();
+        symbols.sort();
+        symbols
+    }
 
-        Ok(entry_point)
+    /// Address of the symbol `name` in the ELF symbol table, if present
+    pub fn find_symbol(file_path: &std::path::Path, name: &str) -> Result<Option<u32>> {
+        let data = fs::read(file_path).map_err(|_| EmulatorError::FileNotFound)?;
+        let obj_file = object::File::parse(&*data).map_err(|_| EmulatorError::InvalidElfFormat)?;
+        Ok(obj_file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address() as u32))
+    }
+
+    /// Apply `.rela.dyn` relocations against the load bias
+    ///
+    /// Only `R_RISCV_RELATIVE` is supported, which covers simple static PIEs.
+    fn apply_relocations(obj_file: &object::File, memory: &mut Memory, bias: u32) -> Result<()> {
+        let Some(relocations) = obj_file.dynamic_relocations() else {
+            return Ok(());
+        };
+        for (offset, relocation) in relocations {
+            let r_type = match relocation.flags() {
+                RelocationFlags::Elf { r_type } => r_type,
+                _ => return Err(EmulatorError::InvalidElfFormat),
+            };
+            match r_type {
+                object::elf::R_RISCV_NONE => {}
+                object::elf::R_RISCV_RELATIVE => {
+                    let target = (offset as u32).wrapping_add(bias);
+                    let value = bias.wrapping_add(relocation.addend() as u32);
+                    memory.write_u32_le(target, value)?;
+                }
+                _ => return Err(EmulatorError::UnsupportedRelocation(r_type)),
+            }
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::elf_builder::{ElfBuilder, Segment};
     use crate::memory::Memory;
+    use object::elf::{PF_R, PF_W, PF_X};
     use std::io::Write;
 
     #[test]
@@ -59,6 +523,283 @@ mod tests {
         assert!(matches!(result, Err(EmulatorError::FileNotFound)));
     }
 
+    /// Build a minimal RV32 PIE: one PT_LOAD segment at 0x80000000 holding a pointer
+    /// slot at +0x100, the pointed-to global at +0x200, and a `.rela.dyn` entry of
+    /// type `r_type` for the slot.
+    fn build_pie(r_type: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 0x300];
+        data[0x200..0x204].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+        ElfBuilder::new(0x8000_0000)
+            .with_pie()
+            .with_segment(Segment::new(0x8000_0000, &data, PF_R | PF_W))
+            .with_relocation(0x8000_0100, r_type, 0x8000_0200)
+            .build()
+    }
+
+    #[test]
+    fn test_load_pie_applies_relative_relocations() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(&build_pie(object::elf::R_RISCV_RELATIVE))
+            .unwrap();
+
+        // Loaded at the link address
+        let mut memory = Memory::new();
+        let entry = ElfLoader::load_elf_with_verbosity(temp_file.path(), &mut memory, 0).unwrap();
+        assert_eq!(entry, 0x8000_0000);
+        let pointer = memory.read_word(0x8000_0100).unwrap();
+        assert_eq!(pointer, 0x8000_0200);
+        assert_eq!(memory.read_word(pointer).unwrap(), 0x1234_5678);
+
+        // Loaded at a shifted base
+        let mut memory = Memory::new();
+        let options = LoadOptions {
+            load_bias: 0x1000,
+            ..LoadOptions::default()
+        };
+        let entry =
+            ElfLoader::load_elf_with_options(temp_file.path(), &mut memory, &options).unwrap();
+        assert_eq!(entry, 0x8000_1000);
+        let pointer = memory.read_word(0x8000_1100).unwrap();
+        assert_eq!(pointer, 0x8000_1200);
+        assert_eq!(memory.read_word(pointer).unwrap(), 0x1234_5678);
+    }
+
+    #[test]
+    fn test_load_pie_rejects_other_relocations() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file
+            .write_all(&build_pie(object::elf::R_RISCV_32))
+            .unwrap();
+
+        let mut memory = Memory::new();
+        let result = ElfLoader::load_elf_with_verbosity(temp_file.path(), &mut memory, 0);
+        assert!(matches!(
+            result,
+            Err(EmulatorError::UnsupportedRelocation(
+                object::elf::R_RISCV_32
+            ))
+        ));
+    }
+
+    /// Build an RV32 executable linked for flash at 0x20000000 and RAM at 0x80000000:
+    /// `.text` runs from flash, `.data` (8 bytes) is stored in flash right after
+    /// it but linked at the start of RAM, followed by 8 bytes of `.bss`.
+    fn build_flash_elf() -> Vec<u8> {
+        let text = [0x0000_0013u32, 0x0000_0073]; // nop; ecall
+        let data = [0xCAFE_F00Du32, 0x1234_5678];
+        ElfBuilder::new(0x2000_0000)
+            .with_segment(Segment::new(0x2000_0000, &words(&text), PF_R | PF_X))
+            .with_segment(
+                Segment::new(0x8000_0000, &words(&data), PF_R | PF_W)
+                    .with_lma(0x2000_0008)
+                    .with_bss(8),
+            )
+            .build()
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_load_places_data_at_lma() {
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&build_flash_elf()).unwrap();
+
+        let mut memory = Memory::new();
+        let entry = ElfLoader::load_elf_with_verbosity(temp_file.path(), &mut memory, 0).unwrap();
+        assert_eq!(entry, 0x2000_0000);
+        assert_eq!(memory.read_word(0x2000_0004).unwrap(), 0x0000_0073);
+
+        // Initialized data sits in flash until the startup code copies it
+        assert_eq!(memory.read_word(0x2000_0008).unwrap(), 0xCAFE_F00D);
+        assert_eq!(memory.read_word(0x2000_000C).unwrap(), 0x1234_5678);
+        assert!(!memory.is_written(0x8000_0000));
+        assert!(!memory.is_written(0x8000_0004));
+
+        // BSS is zeroed at its VMA
+        assert!(memory.is_written(0x8000_0008));
+        assert_eq!(memory.read_word(0x8000_000C).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_load_single_section_elf() {
+        let text = [0x13, 0x05, 0x10, 0x00, 0x73, 0x00, 0x00, 0x00]; // addi a0, x0, 1; ecall
+        let elf = ElfBuilder::new(0x8000_0004)
+            .with_segment(Segment::new(0x8000_0000, &text, PF_R | PF_X).with_section(".text"))
+            .build();
+
+        let mut memory = Memory::new();
+        let entry = ElfLoader::load_elf_data(&elf, &mut memory, &LoadOptions::default()).unwrap();
+        assert_eq!(entry, 0x8000_0004);
+        assert_eq!(memory.read_word(0x8000_0000).unwrap(), 0x0010_0513);
+        assert_eq!(memory.read_word(0x8000_0004).unwrap(), 0x0000_0073);
+        assert!(!memory.is_written(0x8000_0008));
+    }
+
+    #[test]
+    fn test_load_elf_sections_and_bss() {
+        let elf = ElfBuilder::new(0x8000_0000)
+            .with_segment(
+                Segment::new(0x8000_0000, &[0x73, 0, 0, 0], PF_R | PF_X).with_section(".text"),
+            )
+            .with_segment(
+                Segment::new(0x8000_1000, &[1, 2, 3, 4], PF_R | PF_W).with_section(".data"),
+            )
+            .with_segment(
+                Segment::new(0x8000_2000, &[], PF_R | PF_W)
+                    .with_bss(16)
+                    .with_section(".bss"),
+            )
+            .build();
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        temp_file.write_all(&elf).unwrap();
+
+        let mut memory = Memory::new();
+        let entry = ElfLoader::load_elf_with_verbosity(temp_file.path(), &mut memory, 0).unwrap();
+        assert_eq!(entry, 0x8000_0000);
+        assert_eq!(memory.read_word(0x8000_1000).unwrap(), 0x0403_0201);
+        assert!(memory.is_written(0x8000_200C));
+        assert_eq!(memory.read_word(0x8000_200C).unwrap(), 0);
+        assert!(!memory.is_written(0x8000_2010));
+
+        let sections = ElfLoader::sections(temp_file.path()).unwrap();
+        let summary: Vec<_> = sections
+            .iter()
+            .map(|s| (s.name.as_str(), s.start, s.size, s.perms.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (".text", 0x8000_0000, 4, "r-x"),
+                (".data", 0x8000_1000, 4, "rw-"),
+                (".bss", 0x8000_2000, 16, "rw-"),
+            ]
+        );
+
+        // A single load reports the same
+        let loaded =
+            ElfLoader::load_elf_image(&elf, &mut Memory::new(), &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.entry, entry);
+        assert_eq!(loaded.sections, sections);
+        assert_eq!(loaded.image_end, 0x8000_2010);
+        assert_eq!(
+            loaded.image_end,
+            ElfLoader::image_end(temp_file.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_overlapping_segments_are_rejected() {
+        let elf = ElfBuilder::new(0x8000_0000)
+            .with_segment(
+                Segment::new(0x8000_0000, &words(&[0x13, 0x73]), PF_R | PF_X).with_section(".text"),
+            )
+            .with_segment(
+                Segment::new(0x8000_0004, &[1, 2, 3, 4], PF_R | PF_W).with_section(".data"),
+            )
+            .build();
+        let mut memory = Memory::new();
+        let error =
+            ElfLoader::load_elf_data(&elf, &mut memory, &LoadOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "segment #1 [.data] (0x80000004-0x80000008) overlaps \
+             segment #0 [.text, .data] (0x80000000-0x80000008)"
+        );
+        // Nothing was written
+        assert!(!memory.is_written(0x8000_0000));
+
+        // The escape hatch loads both, the later segment winning
+        let options = LoadOptions {
+            allow_overlap: true,
+            ..LoadOptions::default()
+        };
+        ElfLoader::load_elf_data(&elf, &mut memory, &options).unwrap();
+        assert_eq!(memory.read_word(0x8000_0004).unwrap(), 0x0403_0201);
+    }
+
+    #[test]
+    fn test_segment_over_peripheral_is_rejected() {
+        let elf = ElfBuilder::new(0x8000_0000)
+            .with_segment(
+                Segment::new(0x8000_0000, &[0x73, 0, 0, 0], PF_R | PF_X).with_section(".text"),
+            )
+            .with_segment(Segment::new(0x1000_0ff0, &[0x41; 32], PF_R).with_section(".data"))
+            .build();
+        let console = MapEntry {
+            kind: MapKind::Peripheral,
+            name: "console".to_string(),
+            start: 0x1000_0000,
+            size: 0x1000,
+            perms: "rw-".to_string(),
+        };
+        let options = LoadOptions {
+            reserved: vec![console.clone()],
+            ..LoadOptions::default()
+        };
+        let mut memory = Memory::new();
+        let Err(EmulatorError::LoadOverlap(overlap)) =
+            ElfLoader::load_elf_data(&elf, &mut memory, &options)
+        else {
+            panic!("peripheral collision not detected");
+        };
+        assert_eq!(overlap.other, console);
+        assert_eq!(
+            overlap.to_string(),
+            "segment #1 [.data] (0x10000ff0-0x10001010) overlaps peripheral console (0x10000000-0x10001000)"
+        );
+        assert!(!memory.is_written(0x8000_0000));
+    }
+
+    #[test]
+    fn test_overlap_check_uses_written_ranges() {
+        let load = |elf: &[u8]| {
+            let mut memory = Memory::new();
+            ElfLoader::load_elf_data(elf, &mut memory, &LoadOptions::default())
+                .map_err(|error| error.to_string())
+        };
+        let text = words(&[0x13, 0x73]);
+
+        // .data stored in flash on top of .text, without section headers
+        let elf = ElfBuilder::new(0x2000_0000)
+            .with_segment(Segment::new(0x2000_0000, &text, PF_R | PF_X))
+            .with_segment(Segment::new(0x8000_0000, &[1; 8], PF_R | PF_W).with_lma(0x2000_0004))
+            .build();
+        assert_eq!(
+            load(&elf),
+            Err(
+                "segment #1 (0x20000004-0x2000000c) overlaps segment #0 (0x20000000-0x20000008)"
+                    .to_string()
+            )
+        );
+
+        // Sharing a VMA is fine when only one segment is written there
+        let elf = ElfBuilder::new(0x2000_0000)
+            .with_segment(Segment::new(0x2000_0000, &text, PF_R | PF_X))
+            .with_segment(Segment::new(0x8000_0000, &[1; 8], PF_R | PF_W).with_lma(0x2000_0008))
+            .with_segment(Segment::new(0x8000_0000, &[2; 8], PF_R | PF_W))
+            .build();
+        assert_eq!(load(&elf), Ok(0x2000_0000));
+
+        // BSS is zeroed at its VMA
+        let elf = ElfBuilder::new(0x8000_0000)
+            .with_segment(Segment::new(0x8000_0000, &text, PF_R | PF_X).with_section(".text"))
+            .with_segment(
+                Segment::new(0x8000_0004, &[], PF_R | PF_W)
+                    .with_bss(8)
+                    .with_section(".bss"),
+            )
+            .build();
+        assert_eq!(
+            load(&elf),
+            Err("segment #1 bss [.bss] (0x80000004-0x8000000c) overlaps \
+                 segment #0 [.text] (0x80000000-0x80000008)"
+                .to_string())
+        );
+    }
+
     #[test]
     fn test_load_elf_invalid_format() {
         let mut memory = Memory::new();
@@ -0,0 +1,780 @@
+/// High-level emulator combining CPU, memory and peripherals
+use crate::{
+    cpu::{Cpu, CsrHook, RunTarget, CSR_MHARTID, NUM_REGISTERS},
+    elf_loader::{ElfLoader, LoadOptions},
+    fdt::DEFAULT_UART_BASE,
+    heap::{Heap, HeapStats, HeapSyscalls, DEFAULT_HEAP_SIZE},
+    htif::Htif,
+    memory::Memory,
+    memory_map::MapEntry,
+    peripheral::{ConsoleBuffer, ConsolePeriph, Peripheral, PeripheralManager},
+    reg::Reg,
+    syscall::EcallBehavior,
+    throttle::Throttle,
+    EmulatorError, ExitReason, Result,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Most frames a single `run_recording` call collects
+pub const MAX_RECORDED_FRAMES: u32 = 100_000;
+
+/// Return address `call` places in ra; outside RAM, so no guest code lives there
+pub const CALL_RETURN_SENTINEL: u32 = 0xFFFF_FFF0;
+
+/// Most instructions a single `call` or `call_function` may execute before it is abandoned
+pub const MAX_CALL_INSTRUCTIONS: u32 = 10_000_000;
+
+/// Most return addresses `backtrace` follows
+pub const MAX_BACKTRACE_FRAMES: usize = 64;
+
+/// Guest function to enter with `Emulator::call`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallTarget<'a> {
+    /// A symbol of the loaded ELF
+    Symbol(&'a str),
+    Address(u32),
+}
+
+impl<'a> From<&'a str> for CallTarget<'a> {
+    fn from(symbol: &'a str) -> Self {
+        CallTarget::Symbol(symbol)
+    }
+}
+
+impl From<u32> for CallTarget<'_> {
+    fn from(address: u32) -> Self {
+        CallTarget::Address(address)
+    }
+}
+
+/// Machine state after one instruction, as collected by `run_recording`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StateFrame {
+    /// PC after the instruction
+    pub pc: u32,
+    /// Registers after the instruction
+    pub registers: [u32; NUM_REGISTERS],
+    /// Memory written by the instruction: address, width in bytes and value
+    pub store: Option<(u32, u8, u32)>,
+}
+
+/// A complete machine: CPU, memory and memory-mapped peripherals
+pub struct Emulator {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    pub peripherals: PeripheralManager,
+    /// Guest output kept by `capture_output` / `tee_output`
+    console_output: Option<ConsoleBuffer>,
+    /// Captured output is printed live as well (`tee_output`)
+    tee: bool,
+    /// Peripheral index of the console attached by `capture_output` / `tee_output`
+    capture_console: Option<usize>,
+    /// Speed limit applied by `run_for`
+    throttle: Throttle,
+    /// Memory contents right after the last `load_elf`, restored by `restart`
+    loaded_image: Vec<(u32, Vec<u8>)>,
+    /// Maximum heap size requested with `with_heap`
+    heap_size: Option<u32>,
+    /// Place poisoned redzones after heap extensions (`with_heap_poison`)
+    heap_poison: bool,
+    /// Heap served to the guest through `brk`, created by `load_elf`
+    heap: Option<Rc<RefCell<Heap>>>,
+    /// Allocated sections of the last loaded ELF
+    sections: Vec<MapEntry>,
+    /// Symbols of the last loaded ELF as `(address, name)`, sorted by address
+    symbols: Vec<(u32, String)>,
+}
+
+impl Emulator {
+    /// Create a new emulator with empty memory and no peripherals
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(),
+            memory: Memory::new(),
+            peripherals: PeripheralManager::new(),
+            console_output: None,
+            tee: false,
+            capture_console: None,
+            throttle: Throttle::default(),
+            loaded_image: Vec::new(),
+            heap_size: None,
+            heap_poison: false,
+            heap: None,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Builder: treat `size` bytes below `top` as the stack and stop on stores beneath it
+    ///
+    /// See `Cpu::set_stack_region`; without a region no stack checks are made.
+    pub fn with_stack_region(mut self, top: u32, size: u32) -> Self {
+        self.cpu.set_stack_region(top, size);
+        self
+    }
+
+    /// Builder: fast-forward polling loops that only an interrupt can end (see `Cpu::set_idle_skip`)
+    pub fn with_idle_skip(mut self, max_skip: u64) -> Self {
+        self.cpu.set_idle_skip(Some(max_skip));
+        self
+    }
+
+    /// Builder: install a hook observing or virtualizing CSR accesses
+    pub fn with_csr_hook(mut self, hook: Box<dyn CsrHook>) -> Self {
+        self.cpu.set_csr_hook(hook);
+        self
+    }
+
+    /// Builder: choose how ECALL is handled (terminate, trap or host handler)
+    pub fn with_ecall_behavior(mut self, behavior: EcallBehavior) -> Self {
+        self.cpu.set_ecall_behavior(behavior);
+        self
+    }
+
+    /// Builder: attach a console at the default UART address whose output is kept in memory
+    pub fn with_captured_console(self) -> Self {
+        self.capture_output(true)
+    }
+
+    /// Builder: keep everything the guest prints for `take_output`
+    ///
+    /// Attaches a capturing console at the default UART address, and
+    /// `load_elf` serves HTIF console requests (`tohost`) into the same
+    /// buffer, so UART and HTIF output stay in the order they were written.
+    /// `false` leaves output going to stdout, as by default.
+    pub fn capture_output(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.attach_capture_console();
+        }
+        self
+    }
+
+    /// Builder: like `capture_output`, but the output is printed live as well
+    ///
+    /// The two can be called in either order. After `capture_output(true)`,
+    /// `tee_output(false)` keeps capturing without printing.
+    pub fn tee_output(mut self, enabled: bool) -> Self {
+        self.tee = enabled;
+        if enabled || self.capture_console.is_some() {
+            self.attach_capture_console();
+        }
+        self
+    }
+
+    /// Attach the capturing console, or rebuild it to pick up the current tee setting
+    fn attach_capture_console(&mut self) {
+        let buffer = self
+            .console_output
+            .get_or_insert_with(ConsoleBuffer::default);
+        let console = Box::new(
+            ConsolePeriph::with_capture(DEFAULT_UART_BASE, buffer.clone()).with_tee(self.tee),
+        );
+        match self.capture_console {
+            Some(index) => self.peripherals.replace_peripheral(index, console),
+            None => {
+                self.capture_console = Some(self.peripherals.len());
+                self.add_peripheral(console);
+            }
+        }
+    }
+
+    /// Captured output not yet taken, decoded lossily as UTF-8 (empty without capture)
+    pub fn captured_output(&self) -> String {
+        String::from_utf8_lossy(&self.output_bytes()).into_owned()
+    }
+
+    /// Captured output not yet taken, as raw bytes
+    pub fn output_bytes(&self) -> Vec<u8> {
+        self.console_output
+            .as_ref()
+            .map(|buffer| buffer.borrow().clone())
+            .unwrap_or_default()
+    }
+
+    /// Remove and return the captured output, decoded lossily as UTF-8
+    pub fn take_output(&mut self) -> String {
+        let bytes = self
+            .console_output
+            .as_ref()
+            .map(|buffer| std::mem::take(&mut *buffer.borrow_mut()))
+            .unwrap_or_default();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Builder: serve `brk`/`exit` syscalls with a heap of at most `max_size` bytes
+    ///
+    /// The heap is placed after the image by `load_elf` and never grows into the
+    /// top `DEFAULT_STACK_SIZE` bytes of RAM. This replaces the ECALL behavior.
+    pub fn with_heap(mut self, max_size: u32) -> Self {
+        self.heap_size = Some(max_size);
+        self
+    }
+
+    /// Builder: follow every heap extension with a poisoned redzone
+    ///
+    /// Guest loads and stores touching a redzone fail with
+    /// `EmulatorError::PoisonedAccess`. Enables the heap with
+    /// `DEFAULT_HEAP_SIZE` if `with_heap` was not used.
+    pub fn with_heap_poison(mut self) -> Self {
+        self.heap_size.get_or_insert(DEFAULT_HEAP_SIZE);
+        self.heap_poison = true;
+        self
+    }
+
+    /// Current heap state, if a heap is configured and a program is loaded
+    pub fn heap_stats(&self) -> Option<HeapStats> {
+        self.heap.as_ref().map(|heap| heap.borrow().stats())
+    }
+
+    /// Builder: use empty memory whose RAM starts at `base`
+    pub fn with_memory_base(mut self, base: u32) -> Self {
+        self.memory = Memory::new_with_base(base);
+        self
+    }
+
+    /// Attach a memory-mapped peripheral
+    pub fn add_peripheral(&mut self, peripheral: Box<dyn Peripheral>) {
+        self.peripherals.add_peripheral(peripheral);
+    }
+
+    /// Load an ELF binary and point the CPU (and its reset vector) at the entry point
+    pub fn load_elf(&mut self, path: &std::path::Path) -> Result<u32> {
+        let options = LoadOptions {
+            verbosity: 1,
+            reserved: self.peripherals.memory_map(),
+            ..LoadOptions::default()
+        };
+        let elf = ElfLoader::load_elf_file(path, &mut self.memory, &options)?;
+        let entry_point = elf.entry;
+        self.cpu.set_reset_vector(entry_point);
+        self.cpu.pc = entry_point;
+        self.loaded_image = self.memory.contents();
+        if let Some(buffer) = &self.console_output {
+            if let Some(tohost) = elf.symbol("tohost") {
+                let fromhost = elf.symbol("fromhost");
+                self.cpu.set_htif(
+                    Htif::with_capture(tohost, fromhost, buffer.clone()).with_tee(self.tee),
+                );
+            }
+        }
+        if let Some(max_size) = self.heap_size {
+            let mut heap = Heap::for_memory(elf.image_end, max_size, &self.memory);
+            if self.heap_poison {
+                heap = heap.with_redzones();
+            }
+            self.memory.clear_poison();
+            heap.install(&mut self.memory);
+            let heap = Rc::new(RefCell::new(heap));
+            self.cpu
+                .set_ecall_behavior(EcallBehavior::Handler(Box::new(HeapSyscalls::new(
+                    heap.clone(),
+                ))));
+            self.heap = Some(heap);
+        }
+        self.sections = elf.sections;
+        self.symbols = elf.symbols;
+        Ok(entry_point)
+    }
+
+    /// Write instruction words from the RAM base and point the CPU (and its reset vector) there
+    ///
+    /// Like `load_elf`, the written program is what `restart` restores.
+    pub fn load_program(&mut self, program: &[u32]) -> Result<u32> {
+        let base = self.memory.base_address();
+        for (i, &word) in program.iter().enumerate() {
+            self.memory
+                .write_u32_le(base.wrapping_add(i as u32 * 4), word)?;
+        }
+        self.cpu.set_reset_vector(base);
+        self.cpu.pc = base;
+        self.loaded_image = self.memory.contents();
+        Ok(base)
+    }
+
+    /// Loaded ELF sections followed by the attached peripherals
+    pub fn memory_map(&self) -> Vec<MapEntry> {
+        let mut map = self.sections.clone();
+        map.extend(self.peripherals.memory_map());
+        map
+    }
+
+    /// Return addresses of the active calls, innermost first, found by walking frame pointers
+    ///
+    /// Best effort: follows the standard prologue layout (saved ra at fp-4,
+    /// the caller's fp at fp-8) from s0 until a frame is not in written memory,
+    /// the chain stops going up the stack, or `MAX_BACKTRACE_FRAMES` is reached.
+    /// Code built without frame pointers usually gives an empty or short list.
+    pub fn backtrace(&self) -> Vec<u32> {
+        let mut frames = Vec::new();
+        let mut fp = self.cpu.reg(Reg::S0);
+        while frames.len() < MAX_BACKTRACE_FRAMES {
+            let record = fp.wrapping_sub(8);
+            if !fp.is_multiple_of(4) || record > fp || !self.memory.is_written(record) {
+                break;
+            }
+            let ra = self.memory.peek_word(fp.wrapping_sub(4));
+            let caller_fp = self.memory.peek_word(record);
+            if ra == 0 {
+                break;
+            }
+            frames.push(ra);
+            if caller_fp <= fp {
+                break;
+            }
+            fp = caller_fp;
+        }
+        frames
+    }
+
+    /// `address` as `symbol+0xoffset` using the loaded ELF's symbols, or plain hex
+    pub fn symbolize(&self, address: u32) -> String {
+        let index = self
+            .symbols
+            .partition_point(|&(symbol, _)| symbol <= address);
+        match index.checked_sub(1).map(|i| &self.symbols[i]) {
+            Some((symbol, name)) if *symbol == address => name.clone(),
+            Some((symbol, name)) => format!("{name}+0x{:x}", address - symbol),
+            None => format!("0x{address:08x}"),
+        }
+    }
+
+    /// Run the loaded program again from its entry point without reloading it
+    ///
+    /// Registers, CSRs and counters are reset and memory returns to its
+    /// contents right after `load_elf`, so data and BSS written by the previous
+    /// run are undone, and the heap is emptied. Hooks and peripherals are kept.
+    pub fn restart(&mut self) {
+        self.cpu.reset();
+        self.memory.restore_contents(&self.loaded_image);
+        if let Some(heap) = &self.heap {
+            heap.borrow_mut().reset();
+            self.memory.clear_poison();
+            heap.borrow().install(&mut self.memory);
+        }
+    }
+
+    /// Put the hart in its boot state, as hardware and firmware hand it to a bare-metal program
+    ///
+    /// All registers and CSRs are reset, `mhartid` is `hartid`, and only the
+    /// boot protocol registers are set: pc = `entry`, a0 = `hartid`,
+    /// a1 = `dtb` and sp = `sp`. Memory is left untouched. This is the
+    /// documented way to start a loaded program like hardware would.
+    pub fn boot(&mut self, entry: u32, hartid: u32, dtb: u32, sp: u32) {
+        self.cpu.set_reset_vector(entry);
+        self.cpu.reset();
+        self.cpu.write_csr(CSR_MHARTID, hartid);
+        self.cpu.set_reg(Reg::A0, hartid);
+        self.cpu.set_reg(Reg::A1, dtb);
+        self.cpu.set_reg(Reg::Sp, sp);
+    }
+
+    /// Execute a single instruction
+    pub fn step(&mut self) -> Result<()> {
+        self.cpu
+            .step_with_peripherals(&mut self.memory, &mut self.peripherals)
+    }
+
+    /// Run until termination, an error, or the instruction limit
+    pub fn run(&mut self, max_instructions: Option<u32>) -> Result<u32> {
+        self.cpu
+            .run_with_peripherals(&mut self.memory, &mut self.peripherals, max_instructions)
+    }
+
+    /// Run up to `max` instructions (at most `MAX_RECORDED_FRAMES`), recording the state after each
+    ///
+    /// Recording stops after an instruction that ends the run, such as an
+    /// exiting ECALL. Errors that are not a normal stop are returned.
+    pub fn run_recording(&mut self, max: u32) -> Result<Vec<StateFrame>> {
+        let count = max.min(MAX_RECORDED_FRAMES);
+        let mut frames = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let word = self.memory.peek_word(self.cpu.pc);
+            let (mem, store_value) = self.cpu.memory_operand(word);
+            let info = self
+                .cpu
+                .step_info(&mut self.memory, Some(&mut self.peripherals))?;
+            frames.push(StateFrame {
+                pc: self.cpu.pc,
+                registers: self.cpu.registers_snapshot(),
+                store: mem
+                    .zip(store_value)
+                    .map(|((address, width), value)| (address, width, value)),
+            });
+            if info.exit_reason.is_some() {
+                break;
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Limit `run_for` to `instructions_per_second` (0 = unlimited)
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.throttle.set_speed(instructions_per_second);
+    }
+
+    /// Run up to `max_instructions`, fewer if a speed limit is set
+    ///
+    /// With a speed limit, each call retires only the instructions owed for the
+    /// time elapsed since the previous call; it never sleeps.
+    pub fn run_for(&mut self, max_instructions: u32) -> Result<u32> {
+        let budget = self.throttle.budget(max_instructions);
+        self.run(Some(budget))
+    }
+
+    /// Run until the PC reaches `address` (at least one instruction is executed)
+    pub fn run_until_pc(&mut self, address: u32, max_instructions: Option<u32>) -> Result<u32> {
+        self.cpu.run_to_target(RunTarget::Pc(address), |cpu| {
+            cpu.run_with_peripherals(&mut self.memory, &mut self.peripherals, max_instructions)
+        })
+    }
+
+    /// Run until the current function returns to its caller
+    pub fn run_until_return(&mut self, max_instructions: Option<u32>) -> Result<u32> {
+        self.cpu
+            .run_to_target(RunTarget::Return { open_calls: 0 }, |cpu| {
+                cpu.run_with_peripherals(&mut self.memory, &mut self.peripherals, max_instructions)
+            })
+    }
+
+    /// Call the guest function at `address` with `args` in a0..a7 and return its a0
+    ///
+    /// Same as `call` with an address target.
+    pub fn call_function(&mut self, address: u32, args: &[u32]) -> Result<u32> {
+        self.call(address, args)
+    }
+
+    /// Call a guest function without disturbing the hart, e.g. to unit-test it from Rust
+    ///
+    /// ra is set to `CALL_RETURN_SENTINEL`, sp points at a scratch stack at
+    /// the top of RAM, and the call runs until the PC reaches the sentinel.
+    /// Stopping any other way (exit, unsupported instruction,
+    /// `MAX_CALL_INSTRUCTIONS` reached, ...) is a `NoReturn` error, and more
+    /// than 8 arguments are a `TooManyArguments` error. The registers, PC,
+    /// CSRs and counters are restored afterwards whatever the outcome; memory
+    /// the function writes stays written. Symbols are looked up in the last
+    /// ELF loaded with `load_elf`.
+    pub fn call<'a>(&mut self, target: impl Into<CallTarget<'a>>, args: &[u32]) -> Result<u32> {
+        let address = match target.into() {
+            CallTarget::Address(address) => address,
+            CallTarget::Symbol(name) => self
+                .symbols
+                .iter()
+                .find(|(_, symbol)| symbol == name)
+                .map(|&(address, _)| address)
+                .ok_or_else(|| EmulatorError::UnknownSymbol(name.to_string()))?,
+        };
+        let registers = self.cpu.registers_snapshot();
+        let pc = self.cpu.pc;
+        let csrs = self.cpu.csrs.clone();
+        let counters = (self.cpu.cycles(), self.cpu.instret());
+        let exit_reason = self.cpu.exit_reason;
+
+        self.cpu.set_reg(Reg::Sp, self.scratch_stack_top());
+        let result = self.invoke(address, args);
+
+        self.cpu.registers = registers;
+        self.cpu.pc = pc;
+        self.cpu.csrs = csrs;
+        self.cpu.set_counters(counters.0, counters.1);
+        self.cpu.exit_reason = exit_reason;
+        result
+    }
+
+    /// 16-byte aligned top of RAM
+    fn scratch_stack_top(&self) -> u32 {
+        self.memory.base_address().wrapping_add(self.memory.size()) & !0xF
+    }
+
+    /// Enter `address` with `args` and ra = `CALL_RETURN_SENTINEL`, and run until it returns
+    fn invoke(&mut self, address: u32, args: &[u32]) -> Result<u32> {
+        if args.len() > 8 {
+            return Err(EmulatorError::TooManyArguments(args.len()));
+        }
+        for (i, &arg) in args.iter().enumerate() {
+            self.cpu.write_register(Reg::A0.index() + i, arg);
+        }
+        self.cpu.set_reg(Reg::Ra, CALL_RETURN_SENTINEL);
+        self.cpu.pc = address;
+        self.run_until_pc(CALL_RETURN_SENTINEL, Some(MAX_CALL_INSTRUCTIONS))?;
+        match self.cpu.exit_reason {
+            Some(ExitReason::TargetReached(_)) => Ok(self.cpu.reg(Reg::A0)),
+            reason => Err(EmulatorError::NoReturn(reason)),
+        }
+    }
+
+    /// Step over a call: run until the called function returns, or single-step otherwise
+    pub fn run_over(&mut self, max_instructions: Option<u32>) -> Result<u32> {
+        let at_call = self.cpu.at_call(&self.memory);
+        self.cpu
+            .run_over_with(at_call, max_instructions, |cpu, max| {
+                cpu.run_with_peripherals(&mut self.memory, &mut self.peripherals, max)
+            })
+    }
+
+    /// Overwrite the instruction word at `address`, returning the original word
+    ///
+    /// Any cached decode of the address is invalidated so the new word takes
+    /// effect on the next fetch. Used by debuggers to insert and remove EBREAKs.
+    pub fn patch_instruction(&mut self, address: u32, word: u32) -> Result<u32> {
+        let original = self.memory.read_u32_le(address)?;
+        self.memory.write_u32_le(address, word)?;
+        self.cpu.invalidate_icache(address);
+        Ok(original)
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::EBREAK;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Makes the custom CSR 0x800 return an incrementing counter
+    struct CounterCsr {
+        next: u32,
+    }
+
+    impl CsrHook for CounterCsr {
+        fn on_read(&mut self, csr: u16, _value: u32) -> Option<u32> {
+            if csr != 0x800 {
+                return None;
+            }
+            self.next += 1;
+            Some(self.next)
+        }
+    }
+
+    /// Logs every CSR write and blocks writes to mtvec
+    struct MtvecGuard {
+        log: Rc<RefCell<Vec<(u16, u32, u32)>>>,
+    }
+
+    impl CsrHook for MtvecGuard {
+        fn on_write(&mut self, csr: u16, old: u32, new: u32) -> Option<u32> {
+            self.log.borrow_mut().push((csr, old, new));
+            (csr == 0x305).then_some(old)
+        }
+    }
+
+    /// Logs every CSR read ('r') and write ('w')
+    struct AccessLog {
+        log: Rc<RefCell<Vec<(char, u16)>>>,
+    }
+
+    impl CsrHook for AccessLog {
+        fn on_read(&mut self, csr: u16, _value: u32) -> Option<u32> {
+            self.log.borrow_mut().push(('r', csr));
+            None
+        }
+
+        fn on_write(&mut self, csr: u16, _old: u32, _new: u32) -> Option<u32> {
+            self.log.borrow_mut().push(('w', csr));
+            None
+        }
+    }
+
+    #[test]
+    fn test_csr_x0_operands_suppress_side_effects() {
+        const MSCRATCH: u16 = 0x340;
+        let cases = [
+            (0x34029073, "csrw mscratch, t0", "w"),
+            (0x34029573, "csrrw a0, mscratch, t0", "rw"),
+            (0x34002573, "csrr a0, mscratch", "r"),
+            (0x34003073, "csrc mscratch, zero", "r"),
+            (0x3402D073, "csrwi mscratch, 5", "w"),
+            (0x34006573, "csrrsi a0, mscratch, 0", "r"),
+            (0x3400F073, "csrci mscratch, 1", "rw"),
+        ];
+        for (word, asm, expected) in cases {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let mut emulator =
+                Emulator::new().with_csr_hook(Box::new(AccessLog { log: log.clone() }));
+            emulator.load_program(&[word]).unwrap();
+            emulator.step().unwrap();
+            let accesses: String = log
+                .borrow()
+                .iter()
+                .filter(|&&(_, csr)| csr == MSCRATCH)
+                .map(|&(kind, _)| kind)
+                .collect();
+            assert_eq!(accesses, expected, "{asm}");
+        }
+    }
+
+    #[test]
+    fn test_csr_hook_virtual_counter() {
+        let mut emulator = Emulator::new().with_csr_hook(Box::new(CounterCsr { next: 0 }));
+        emulator
+            .load_program(&[
+                0x800020f3, // csrr x1, 0x800
+                0x80002173, // csrr x2, 0x800
+                0x300021f3, // csrr x3, mstatus (not virtualized)
+            ])
+            .unwrap();
+        emulator.run(Some(3)).unwrap();
+        assert_eq!(emulator.cpu.read_register(1), 1);
+        assert_eq!(emulator.cpu.read_register(2), 2);
+        assert_eq!(emulator.cpu.read_register(3), 0);
+    }
+
+    #[test]
+    fn test_csr_hook_blocks_mtvec_writes() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut emulator = Emulator::new().with_csr_hook(Box::new(MtvecGuard { log: log.clone() }));
+        emulator.cpu.write_register(5, 0x8000_0100);
+        emulator
+            .load_program(&[
+                0x30529073, // csrw mtvec, x5
+                0x30502373, // csrr x6, mtvec
+                0x34029073, // csrw mscratch, x5
+                0x340023f3, // csrr x7, mscratch
+            ])
+            .unwrap();
+        emulator.run(Some(4)).unwrap();
+        assert_eq!(emulator.cpu.read_register(6), 0); // write to mtvec was blocked
+        assert_eq!(emulator.cpu.read_register(7), 0x8000_0100);
+        assert_eq!(
+            *log.borrow(),
+            vec![(0x305, 0, 0x8000_0100), (0x340, 0, 0x8000_0100)]
+        );
+    }
+
+    #[test]
+    fn test_captured_console_output() {
+        let mut emulator = Emulator::new().with_captured_console();
+        let mut program = vec![0x100000b7]; // lui x1, 0x10000 (UART TX)
+        for ch in "hello".bytes() {
+            program.push(((ch as u32) << 20) | (2 << 7) | 0x13); // addi x2, x0, ch
+            program.push(0x0020a023); // sw x2, 0(x1)
+        }
+        program.push(0x0000_0073); // ecall
+        emulator.load_program(&program).unwrap();
+
+        emulator.run(Some(100)).unwrap();
+        assert_eq!(emulator.captured_output(), "hello");
+    }
+
+    #[test]
+    fn test_tee_and_capture_in_either_order() {
+        let program = [
+            0x100000b7,  // lui x1, 0x10000 (UART TX)
+            0x02100113,  // addi x2, x0, '!'
+            0x0020a023,  // sw x2, 0(x1)
+            0x0000_0073, // ecall
+        ];
+        for (emulator, tee) in [
+            (Emulator::new().capture_output(true).tee_output(true), true),
+            (Emulator::new().tee_output(true).capture_output(true), true),
+            (Emulator::new().tee_output(true).tee_output(false), false),
+        ] {
+            let mut emulator = emulator;
+            assert_eq!(emulator.tee, tee);
+            assert_eq!(emulator.memory_map().len(), 1);
+            emulator.load_program(&program).unwrap();
+            emulator.run(Some(10)).unwrap();
+            assert_eq!(emulator.take_output(), "!");
+        }
+    }
+
+    #[test]
+    fn test_run_recording_frames() {
+        let mut emulator = Emulator::new();
+        let base = emulator.memory.base_address();
+        emulator
+            .load_program(&[
+                0x00500093, // addi x1, x0, 5
+                0x00108113, // addi x2, x1, 1
+                0x002081b3, // add x3, x1, x2
+                0x80000237, // lui x4, 0x80000
+                0x10322023, // sw x3, 256(x4)
+            ])
+            .unwrap();
+        let frames = emulator.run_recording(5).unwrap();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(
+            frames.iter().map(|frame| frame.pc).collect::<Vec<_>>(),
+            (1..=5).map(|i| base + i * 4).collect::<Vec<_>>()
+        );
+        assert_eq!(frames[0].registers[1], 5);
+        assert_eq!(frames[0].registers[2], 0);
+        assert_eq!(frames[1].registers[2], 6);
+        assert_eq!(frames[2].registers[3], 11);
+        assert_eq!(frames[3].registers[4], 0x8000_0000);
+        assert_eq!(frames[3].store, None);
+        assert_eq!(frames[4].store, Some((0x8000_0100, 4, 11)));
+    }
+
+    #[test]
+    fn test_boot_sets_only_boot_registers() {
+        let mut emulator = Emulator::new();
+        let entry = emulator.load_program(&[0x0000_0013]).unwrap();
+        for i in 1..NUM_REGISTERS {
+            emulator.cpu.write_register(i, 0xDEAD_0000 + i as u32);
+        }
+        emulator.cpu.pc = 0;
+
+        emulator.boot(entry + 0x100, 3, 0x87E0_0000, 0x8800_0000);
+        assert_eq!(emulator.cpu.pc, entry + 0x100);
+        assert_eq!(emulator.cpu.read_csr(CSR_MHARTID), 3);
+        let registers = emulator.cpu.registers_snapshot();
+        for (i, &value) in registers.iter().enumerate() {
+            let expected = match i {
+                2 => 0x8800_0000,  // sp
+                10 => 3,           // a0 = hartid
+                11 => 0x87E0_0000, // a1 = dtb
+                _ => 0,
+            };
+            assert_eq!(value, expected, "x{i}");
+        }
+    }
+
+    #[test]
+    fn test_patch_instruction_breakpoint() {
+        let mut emulator = Emulator::new();
+        let base = emulator.memory.base_address();
+
+        let program = [
+            (1 << 20) | (1 << 7) | 0x13,             // addi x1, x0, 1
+            (1 << 20) | (1 << 15) | (1 << 7) | 0x13, // addi x1, x1, 1
+            (1 << 20) | (1 << 15) | (1 << 7) | 0x13, // addi x1, x1, 1
+            0x0000_0073,                             // ecall
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            emulator
+                .memory
+                .write_word(base + i as u32 * 4, word)
+                .unwrap();
+        }
+        emulator.cpu.set_icache_enabled(true);
+        emulator.cpu.breakpoint_mode = true;
+
+        // Warm the decode cache with the original program
+        emulator.cpu.pc = base;
+        emulator.run(Some(10)).unwrap();
+        assert_eq!(emulator.cpu.read_register(1), 3);
+
+        // Patch the second ADDI into an EBREAK and run up to it
+        let original = emulator.patch_instruction(base + 4, EBREAK).unwrap();
+        assert_eq!(original, program[1]);
+        emulator.cpu.pc = base;
+        let executed = emulator.run(Some(10)).unwrap();
+        assert_eq!(executed, 1);
+        assert_eq!(emulator.cpu.pc, base + 4);
+        assert_eq!(emulator.cpu.read_register(1), 1);
+
+        // Restore the original instruction and continue past the breakpoint
+        let patched = emulator.patch_instruction(base + 4, original).unwrap();
+        assert_eq!(patched, EBREAK);
+        emulator.run(Some(10)).unwrap();
+        assert_eq!(emulator.cpu.read_register(1), 3);
+        assert_eq!(emulator.cpu.pc, base + 12);
+    }
+}
@@ -0,0 +1,372 @@
+/// Flattened device tree (FDT/DTB) generation and loading
+use crate::{
+    cpu::{Cpu, CSR_MHARTID},
+    memory::Memory,
+    Result,
+};
+
+/// FDT header magic number
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+/// FDT structure version emitted by the writer
+const FDT_VERSION: u32 = 17;
+/// Oldest FDT version the emitted blob is compatible with
+const FDT_LAST_COMP_VERSION: u32 = 16;
+
+/// Structure block tokens
+pub const FDT_BEGIN_NODE: u32 = 0x1;
+pub const FDT_END_NODE: u32 = 0x2;
+pub const FDT_PROP: u32 = 0x3;
+pub const FDT_END: u32 = 0x9;
+
+/// Default UART (console) base address described by the generated DTB
+pub const DEFAULT_UART_BASE: u32 = 0x1000_0000;
+/// Default CLINT base address described by the generated DTB
+pub const DEFAULT_CLINT_BASE: u32 = 0x0200_0000;
+
+/// Minimal writer for flattened device tree blobs
+pub struct FdtWriter {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    depth: usize,
+}
+
+impl FdtWriter {
+    /// Create a new, empty device tree writer
+    pub fn new() -> Self {
+        Self {
+            structure: Vec::new(),
+            strings: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    /// Open a node; the root node uses an empty name
+    pub fn begin_node(&mut self, name: &str) {
+        self.push_u32(FDT_BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.align_structure();
+        self.depth += 1;
+    }
+
+    /// Close the most recently opened node
+    pub fn end_node(&mut self) {
+        assert!(self.depth > 0, "end_node without matching begin_node");
+        self.push_u32(FDT_END_NODE);
+        self.depth -= 1;
+    }
+
+    /// Add a property with a raw byte value
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        let name_offset = self.string_offset(name);
+        self.push_u32(FDT_PROP);
+        self.push_u32(value.len() as u32);
+        self.push_u32(name_offset);
+        self.structure.extend_from_slice(value);
+        self.align_structure();
+    }
+
+    /// Add an empty (boolean) property
+    pub fn property_empty(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    /// Add a NUL-terminated string property
+    pub fn property_string(&mut self, name: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.property(name, &bytes);
+    }
+
+    /// Add a single 32-bit cell property
+    pub fn property_u32(&mut self, name: &str, value: u32) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    /// Add a property made of several 32-bit cells
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) {
+        let bytes: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+        self.property(name, &bytes);
+    }
+
+    /// Finish the tree and produce the blob
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.depth, 0, "unterminated node in device tree");
+        self.push_u32(FDT_END);
+
+        const HEADER_SIZE: usize = 40;
+        // Memory reservation map: a single terminating (0, 0) entry
+        const RSVMAP_SIZE: usize = 16;
+
+        let off_mem_rsvmap = HEADER_SIZE;
+        let off_dt_struct = off_mem_rsvmap + RSVMAP_SIZE;
+        let off_dt_strings = off_dt_struct + self.structure.len();
+        let total_size = off_dt_strings + self.strings.len();
+
+        let mut blob = Vec::with_capacity(total_size);
+        for field in [
+            FDT_MAGIC,
+            total_size as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            off_mem_rsvmap as u32,
+            FDT_VERSION,
+            FDT_LAST_COMP_VERSION,
+            0, // boot_cpuid_phys
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ] {
+            blob.extend_from_slice(&field.to_be_bytes());
+        }
+        blob.extend_from_slice(&[0; RSVMAP_SIZE]);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.structure.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn align_structure(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+
+    fn string_offset(&mut self, name: &str) -> u32 {
+        // Reuse an existing entry so each property name is stored once
+        let mut offset = 0;
+        for entry in self.strings.split(|&b| b == 0) {
+            if entry == name.as_bytes() && offset < self.strings.len() {
+                return offset as u32;
+            }
+            offset += entry.len() + 1;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset
+    }
+}
+
+impl Default for FdtWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generate a minimal device tree describing nekov's memory, UART and CLINT
+pub fn generate_dtb(
+    memory_base: u32,
+    memory_size: u32,
+    uart_base: u32,
+    clint_base: u32,
+) -> Vec<u8> {
+    let mut fdt = FdtWriter::new();
+
+    fdt.begin_node("");
+    fdt.property_u32("#address-cells", 1);
+    fdt.property_u32("#size-cells", 1);
+    fdt.property_string("compatible", "wipeseals,nekov");
+    fdt.property_string("model", "nekov");
+
+    fdt.begin_node("chosen");
+    fdt.property_string("stdout-path", &format!("/soc/uart@{uart_base:x}"));
+    fdt.end_node();
+
+    fdt.begin_node("cpus");
+    fdt.property_u32("#address-cells", 1);
+    fdt.property_u32("#size-cells", 0);
+    fdt.property_u32("timebase-frequency", 10_000_000);
+    fdt.begin_node("cpu@0");
+    fdt.property_string("device_type", "cpu");
+    fdt.property_u32("reg", 0);
+    fdt.property_string("status", "okay");
+    fdt.property_string("compatible", "riscv");
+    fdt.property_string("riscv,isa", "rv32ima");
+    fdt.property_string("mmu-type", "riscv,none");
+    fdt.begin_node("interrupt-controller");
+    fdt.property_u32("#interrupt-cells", 1);
+    fdt.property_empty("interrupt-controller");
+    fdt.property_string("compatible", "riscv,cpu-intc");
+    fdt.property_u32("phandle", 1);
+    fdt.end_node();
+    fdt.end_node();
+    fdt.end_node();
+
+    fdt.begin_node(&format!("memory@{memory_base:x}"));
+    fdt.property_string("device_type", "memory");
+    fdt.property_cells("reg", &[memory_base, memory_size]);
+    fdt.end_node();
+
+    fdt.begin_node("soc");
+    fdt.property_u32("#address-cells", 1);
+    fdt.property_u32("#size-cells", 1);
+    fdt.property_string("compatible", "simple-bus");
+    fdt.property_empty("ranges");
+
+    fdt.begin_node(&format!("uart@{uart_base:x}"));
+    fdt.property_string("compatible", "ns16550a");
+    fdt.property_cells("reg", &[uart_base, 0x1000]);
+    fdt.property_u32("clock-frequency", 3_686_400);
+    fdt.end_node();
+
+    fdt.begin_node(&format!("clint@{clint_base:x}"));
+    fdt.property_string("compatible", "riscv,clint0");
+    fdt.property_cells("reg", &[clint_base, 0x10000]);
+    // Machine software (3) and machine timer (7) interrupts of hart 0
+    fdt.property_cells("interrupts-extended", &[1, 3, 1, 7]);
+    fdt.end_node();
+
+    fdt.end_node();
+    fdt.end_node();
+
+    fdt.finish()
+}
+
+/// Generate the default device tree for the given memory configuration
+pub fn generate_default_dtb(memory: &Memory) -> Vec<u8> {
+    generate_dtb(
+        memory.base_address(),
+        memory.size(),
+        DEFAULT_UART_BASE,
+        DEFAULT_CLINT_BASE,
+    )
+}
+
+/// Default load address for a DTB: page-aligned, just below the top of RAM
+pub fn default_dtb_address(memory: &Memory, dtb_len: usize) -> u32 {
+    let top = memory.base_address().wrapping_add(memory.size());
+    top.wrapping_sub(dtb_len as u32) & !0xFFF
+}
+
+/// Load a DTB into memory and set up the boot registers (a0=mhartid, a1=dtb address)
+///
+/// When no address is given the blob is placed with [`default_dtb_address`].
+pub fn load_dtb(
+    cpu: &mut Cpu,
+    memory: &mut Memory,
+    dtb: &[u8],
+    address: Option<u32>,
+) -> Result<u32> {
+    let address = address.unwrap_or_else(|| default_dtb_address(memory, dtb.len()));
+    memory.load_data(address, dtb)?;
+
+    let hartid = cpu.read_csr(CSR_MHARTID);
+    cpu.write_register(10, hartid); // a0
+    cpu.write_register(11, address); // a1
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Parse a DTB into a map of "node/path:property" -> value
+    fn parse_fdt(blob: &[u8]) -> HashMap<String, Vec<u8>> {
+        let be32 = |off: usize| u32::from_be_bytes(blob[off..off + 4].try_into().unwrap());
+
+        assert_eq!(be32(0), FDT_MAGIC);
+        assert_eq!(be32(4) as usize, blob.len());
+        let off_struct = be32(8) as usize;
+        let off_strings = be32(12) as usize;
+        assert_eq!(
+            be32(16) % 8,
+            0,
+            "memory reservation map must be 8-byte aligned"
+        );
+        assert_eq!(be32(20), 17);
+        assert_eq!(be32(36) as usize, off_strings - off_struct);
+
+        let mut props = HashMap::new();
+        let mut path: Vec<String> = Vec::new();
+        let mut pos = off_struct;
+        loop {
+            let token = be32(pos);
+            pos += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let end = pos + blob[pos..].iter().position(|&b| b == 0).unwrap();
+                    path.push(String::from_utf8(blob[pos..end].to_vec()).unwrap());
+                    pos = (end + 1 + 3) & !3;
+                }
+                FDT_END_NODE => {
+                    path.pop().expect("unbalanced END_NODE");
+                }
+                FDT_PROP => {
+                    let len = be32(pos) as usize;
+                    let name_off = off_strings + be32(pos + 4) as usize;
+                    pos += 8;
+                    let name_end =
+                        name_off + blob[name_off..].iter().position(|&b| b == 0).unwrap();
+                    let name = String::from_utf8(blob[name_off..name_end].to_vec()).unwrap();
+                    props.insert(
+                        format!("{}:{name}", path.join("/")),
+                        blob[pos..pos + len].to_vec(),
+                    );
+                    pos = (pos + len + 3) & !3;
+                }
+                FDT_END => break,
+                other => panic!("unexpected FDT token 0x{other:x}"),
+            }
+        }
+        assert!(path.is_empty(), "unterminated node");
+        props
+    }
+
+    fn cells(value: &[u8]) -> Vec<u32> {
+        value
+            .chunks(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_generated_dtb_is_valid_fdt() {
+        let memory = Memory::new();
+        let blob = generate_default_dtb(&memory);
+        let props = parse_fdt(&blob);
+
+        assert_eq!(props["/memory@80000000:device_type"], b"memory\0".to_vec());
+        assert_eq!(
+            cells(&props["/memory@80000000:reg"]),
+            vec![0x8000_0000, memory.size()]
+        );
+        assert_eq!(
+            cells(&props["/soc/uart@10000000:reg"]),
+            vec![DEFAULT_UART_BASE, 0x1000]
+        );
+        assert_eq!(
+            cells(&props["/soc/clint@2000000:reg"]),
+            vec![DEFAULT_CLINT_BASE, 0x10000]
+        );
+    }
+
+    #[test]
+    fn test_load_dtb_sets_boot_registers() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let blob = generate_default_dtb(&memory);
+
+        let addr = load_dtb(&mut cpu, &mut memory, &blob, None).unwrap();
+        assert_eq!(cpu.read_register(10), 0); // mhartid
+        assert_eq!(cpu.read_register(11), addr);
+        assert_eq!(memory.read_byte(addr).unwrap(), 0xd0);
+        assert_eq!(memory.read_byte(addr + 3).unwrap(), 0xed);
+
+        let addr = load_dtb(&mut cpu, &mut memory, &blob, Some(0x8010_0000)).unwrap();
+        assert_eq!(addr, 0x8010_0000);
+        assert_eq!(cpu.read_register(11), 0x8010_0000);
+    }
+
+    #[test]
+    fn test_default_dtb_address_below_top_of_ram() {
+        let memory = Memory::new();
+        let addr = default_dtb_address(&memory, 0x300);
+        assert_eq!(addr & 0xFFF, 0);
+        assert!(addr + 0x300 <= memory.base_address() + memory.size());
+        assert!(addr >= memory.base_address());
+    }
+}
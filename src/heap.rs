@@ -0,0 +1,245 @@
+//! Guest heap managed through the `brk` syscall
+//!
+//! The heap starts at the end of the loaded image (rounded up to a page) and
+//! may grow up to a configured size, but never into the stack reserved at the
+//! top of RAM. Requests beyond that fail with `-ENOMEM`.
+//!
+//! With redzones enabled, every extension is followed by `REDZONE_SIZE`
+//! poisoned bytes so guest overflows off the end of it are reported.
+
+use crate::{
+    bus::Bus, cpu::Cpu, memory::Memory, reg::Reg, syscall::SyscallAction, syscall::SyscallHandler,
+    Result,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// `brk` syscall number (Linux RISC-V ABI)
+pub const SYS_BRK: u32 = 214;
+/// `exit` syscall number
+const SYS_EXIT: u32 = 93;
+
+const ENOMEM: u32 = 12;
+const ENOSYS: u32 = 38;
+
+/// Alignment of the initial program break
+const HEAP_ALIGN: u32 = 4096;
+
+/// Bytes kept free for the stack at the top of RAM
+pub const DEFAULT_STACK_SIZE: u32 = 1024 * 1024;
+
+/// Heap size used when none is configured (e.g. by `--heap-poison`)
+pub const DEFAULT_HEAP_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Poisoned bytes placed after each heap extension in redzone mode
+pub const REDZONE_SIZE: u32 = 16;
+
+/// Snapshot of the heap for diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct HeapStats {
+    /// Initial program break
+    pub start: u32,
+    /// Current program break
+    pub brk: u32,
+    /// Highest break the heap may reach
+    pub limit: u32,
+    /// Highest break reached so far
+    pub peak: u32,
+    /// Number of requests refused with `-ENOMEM`
+    pub failed_requests: u32,
+}
+
+/// Program break bookkeeping
+#[derive(Debug, Clone)]
+pub struct Heap {
+    start: u32,
+    brk: u32,
+    limit: u32,
+    peak: u32,
+    failed_requests: u32,
+    /// Redzone size after each extension (0 = redzones off)
+    redzone: u32,
+}
+
+impl Heap {
+    /// A heap after an image ending at `image_end`, at most `max_size` bytes and below `stack_floor`
+    pub fn new(image_end: u32, max_size: u32, stack_floor: u32) -> Self {
+        let start = image_end.next_multiple_of(HEAP_ALIGN);
+        let limit = start.saturating_add(max_size).min(stack_floor).max(start);
+        Self {
+            start,
+            brk: start,
+            limit,
+            peak: start,
+            failed_requests: 0,
+            redzone: 0,
+        }
+    }
+
+    /// A heap after an image ending at `image_end`, keeping `DEFAULT_STACK_SIZE` free at the top of `memory`
+    pub fn for_memory(image_end: u32, max_size: u32, memory: &Memory) -> Self {
+        let ram_end = memory.base_address().wrapping_add(memory.size());
+        Self::new(
+            image_end,
+            max_size,
+            ram_end.wrapping_sub(DEFAULT_STACK_SIZE),
+        )
+    }
+
+    /// Builder: follow every extension with a poisoned redzone
+    ///
+    /// The break returned to the guest then lies `REDZONE_SIZE` bytes past the
+    /// requested one, which libc `sbrk` accepts. Call `install` to poison the
+    /// redzone at the heap start.
+    pub fn with_redzones(mut self) -> Self {
+        self.redzone = REDZONE_SIZE;
+        self.reset();
+        self
+    }
+
+    /// Poison the initial redzone (no-op without redzones)
+    pub fn install(&self, bus: &mut dyn Bus) {
+        bus.poison(self.start, self.redzone);
+    }
+
+    /// Move the break to `address`, returning the new break
+    ///
+    /// Addresses below the heap start (including 0) only query the current
+    /// break. Addresses above the limit fail with `ENOMEM`.
+    pub fn brk(&mut self, address: u32) -> std::result::Result<u32, u32> {
+        if address < self.start + self.redzone {
+            return Ok(self.brk);
+        }
+        let new_brk = address.saturating_add(self.redzone);
+        if new_brk > self.limit {
+            self.failed_requests += 1;
+            return Err(ENOMEM);
+        }
+        self.brk = new_brk;
+        self.peak = self.peak.max(new_brk);
+        Ok(new_brk)
+    }
+
+    /// Update the poison shadow after the break moved from `old` to `new`
+    ///
+    /// Growing makes `[old, new - redzone)` an allocation followed by a
+    /// redzone; shrinking poisons everything from the new redzone up.
+    fn update_redzones(&self, bus: &mut dyn Bus, old: u32, new: u32) {
+        let redzone_start = new - self.redzone;
+        if new > old {
+            bus.add_allocation(old, redzone_start - old);
+            bus.poison(redzone_start, self.redzone);
+        } else {
+            bus.poison(redzone_start, old - redzone_start);
+        }
+    }
+
+    /// Return the break to the heap start, as after loading
+    pub fn reset(&mut self) {
+        *self = Self {
+            brk: self.start + self.redzone,
+            peak: self.start + self.redzone,
+            failed_requests: 0,
+            ..*self
+        };
+    }
+
+    /// Current heap state
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            start: self.start,
+            brk: self.brk,
+            limit: self.limit,
+            peak: self.peak,
+            failed_requests: self.failed_requests,
+        }
+    }
+}
+
+/// Syscall handler serving `brk` from a shared heap and `exit`
+///
+/// Other syscalls return `-ENOSYS`.
+pub struct HeapSyscalls {
+    heap: Rc<RefCell<Heap>>,
+}
+
+impl HeapSyscalls {
+    pub fn new(heap: Rc<RefCell<Heap>>) -> Self {
+        Self { heap }
+    }
+}
+
+impl SyscallHandler for HeapSyscalls {
+    fn handle(&mut self, cpu: &mut Cpu, bus: &mut dyn Bus) -> Result<SyscallAction> {
+        let result = match cpu.reg(Reg::A7) {
+            SYS_EXIT => return Ok(SyscallAction::Exit),
+            SYS_BRK => {
+                let mut heap = self.heap.borrow_mut();
+                let old = heap.brk;
+                match heap.brk(cpu.reg(Reg::A0)) {
+                    Ok(brk) => {
+                        if heap.redzone > 0 && brk != old {
+                            heap.update_redzones(bus, old, brk);
+                        }
+                        brk
+                    }
+                    Err(errno) => errno.wrapping_neg(),
+                }
+            }
+            _ => ENOSYS.wrapping_neg(),
+        };
+        cpu.set_reg(Reg::A0, result);
+        Ok(SyscallAction::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EmulatorError;
+
+    #[test]
+    fn test_brk_grows_until_limit() {
+        let mut heap = Heap::new(0x8000_0123, 0x2000, 0x9000_0000);
+        assert_eq!(heap.brk(0), Ok(0x8000_1000));
+        assert_eq!(heap.brk(0x8000_2000), Ok(0x8000_2000));
+        assert_eq!(heap.brk(0x8000_3001), Err(ENOMEM));
+        assert_eq!(heap.brk(0x8000_1800), Ok(0x8000_1800));
+
+        // The stack floor wins over a larger size
+        let capped = Heap::new(0x8000_0000, 0x10_0000, 0x8000_4000);
+        assert_eq!(capped.stats().limit, 0x8000_4000);
+
+        let stats = heap.stats();
+        assert_eq!(stats.peak, 0x8000_2000);
+        assert_eq!(stats.failed_requests, 1);
+        heap.reset();
+        assert_eq!(heap.stats().brk, 0x8000_1000);
+    }
+
+    #[test]
+    fn test_redzones_follow_each_extension() {
+        let mut memory = Memory::new();
+        let mut heap = Heap::new(0x8000_0000, 0x1000, 0x9000_0000).with_redzones();
+        heap.install(&mut memory);
+        let start = heap.brk(0).unwrap();
+        assert_eq!(start, 0x8000_0010);
+        assert!(memory.read_byte(0x8000_000F).is_err());
+
+        let brk = heap.brk(start + 8).unwrap();
+        assert_eq!(brk, start + 8 + REDZONE_SIZE);
+        heap.update_redzones(&mut memory, start, brk);
+        memory.write_byte(start + 7, 1).unwrap();
+        let Err(EmulatorError::PoisonedAccess(access)) = memory.write_byte(start + 8, 1) else {
+            panic!("overflow not reported");
+        };
+        assert_eq!(access.address, start + 8);
+        assert_eq!(access.allocation, Some((start, 8)));
+
+        // Shrinking poisons the released bytes
+        let shrunk = heap.brk(start + 4).unwrap();
+        heap.update_redzones(&mut memory, brk, shrunk);
+        assert!(memory.read_byte(start + 5).is_err());
+        assert!(memory.read_byte(start + 3).is_ok());
+    }
+}
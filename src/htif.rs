@@ -0,0 +1,194 @@
+//! Host-target interface (HTIF) console over the `tohost` / `fromhost` words
+//!
+//! The guest stores a 64-bit command `device << 56 | command << 48 | payload`
+//! to `tohost`. Two console requests are understood:
+//!
+//! - device 1, command 1: write the byte in the low 8 bits of the payload
+//! - device 0, command 0 with an even payload: syscall block at `payload`
+//!   (`[number, arg0, arg1, arg2, ...]` as 64-bit words); only
+//!   `write(fd 1|2, buf, len)` is served
+//!
+//! Served commands clear `tohost` and are acknowledged in `fromhost`. Anything
+//! else (including the exit request `payload & 1`) is left in `tohost`.
+
+use crate::{
+    memory::Memory,
+    peripheral::{write_console, ConsoleBuffer},
+    Result,
+};
+
+/// Console device number
+const DEVICE_CONSOLE: u64 = 1;
+/// Console "write byte" command
+const COMMAND_PUTCHAR: u64 = 1;
+/// Syscall device number (proxy kernel interface)
+const DEVICE_SYSCALL: u64 = 0;
+/// `write` syscall number
+const SYS_WRITE: u64 = 64;
+
+/// HTIF endpoint serving console output requests
+#[derive(Debug, Clone)]
+pub struct Htif {
+    tohost: u32,
+    fromhost: Option<u32>,
+    /// When set, output goes to this buffer instead of stdout / the web console
+    capture: Option<ConsoleBuffer>,
+    /// Also print captured output
+    tee: bool,
+}
+
+impl Htif {
+    /// Serve requests stored to `tohost`, acknowledging them in `fromhost` if present
+    pub fn new(tohost: u32, fromhost: Option<u32>) -> Self {
+        Self {
+            tohost,
+            fromhost,
+            capture: None,
+            tee: false,
+        }
+    }
+
+    /// Create an endpoint that appends console output to `buffer`
+    pub fn with_capture(tohost: u32, fromhost: Option<u32>, buffer: ConsoleBuffer) -> Self {
+        Self {
+            capture: Some(buffer),
+            ..Self::new(tohost, fromhost)
+        }
+    }
+
+    /// Builder: print captured output to stdout / the web console as well
+    pub fn with_tee(mut self, tee: bool) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    /// Address of the `tohost` word
+    pub fn tohost(&self) -> u32 {
+        self.tohost
+    }
+
+    /// Whether a store of `size` bytes at `address` completes a command
+    ///
+    /// RV32 guests store the low half of `tohost` first, so the command is
+    /// taken when the high half is written.
+    pub(crate) fn is_command_store(&self, address: u32, size: u32) -> bool {
+        let high = self.tohost.wrapping_add(4);
+        address < high.wrapping_add(4) && address.wrapping_add(size) > high
+    }
+
+    /// Serve the command currently in `tohost`, returning whether it was handled
+    pub fn handle(&mut self, memory: &mut Memory) -> Result<bool> {
+        let command = read_u64(memory, self.tohost)?;
+        let device = command >> 56;
+        let code = (command >> 48) & 0xFF;
+        let payload = command & 0xFFFF_FFFF_FFFF;
+        let response = match (device, code) {
+            (DEVICE_CONSOLE, COMMAND_PUTCHAR) => {
+                let byte = payload as u8;
+                write_console(self.capture.as_ref(), self.tee, &[byte]);
+                0x100 | u64::from(byte)
+            }
+            (DEVICE_SYSCALL, 0) if command != 0 && payload & 1 == 0 => {
+                if !self.syscall(memory, payload as u32)? {
+                    return Ok(false);
+                }
+                1
+            }
+            _ => return Ok(false),
+        };
+        write_u64(memory, self.tohost, 0)?;
+        if let Some(fromhost) = self.fromhost {
+            write_u64(memory, fromhost, device << 56 | code << 48 | response)?;
+        }
+        Ok(true)
+    }
+
+    /// Run the syscall block at `block`, storing the return value in its first word
+    fn syscall(&mut self, memory: &mut Memory, block: u32) -> Result<bool> {
+        let number = read_u64(memory, block)?;
+        let fd = read_u64(memory, block + 8)?;
+        if number != SYS_WRITE || !(fd == 1 || fd == 2) {
+            return Ok(false);
+        }
+        let buffer = read_u64(memory, block + 16)? as u32;
+        let len = read_u64(memory, block + 24)? as u32;
+        let bytes = (0..len)
+            .map(|i| memory.read_byte(buffer.wrapping_add(i)))
+            .collect::<Result<Vec<u8>>>()?;
+        write_console(self.capture.as_ref(), self.tee, &bytes);
+        write_u64(memory, block, u64::from(len))?;
+        Ok(true)
+    }
+}
+
+/// Read a little-endian 64-bit value as two words
+fn read_u64(memory: &Memory, address: u32) -> Result<u64> {
+    let low = memory.read_u32_le(address)?;
+    let high = memory.read_u32_le(address.wrapping_add(4))?;
+    Ok(u64::from(high) << 32 | u64::from(low))
+}
+
+/// Write a little-endian 64-bit value as two words
+fn write_u64(memory: &mut Memory, address: u32, value: u64) -> Result<()> {
+    memory.write_u32_le(address, value as u32)?;
+    memory.write_u32_le(address.wrapping_add(4), (value >> 32) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpu::Cpu, reg::Reg};
+
+    /// Store a1:a0 to the 64-bit word at t0, low half first
+    fn store_tohost(cpu: &mut Cpu, memory: &mut Memory, tohost: u32, command: u64) {
+        let base = memory.base_address();
+        memory.write_word(base, 0x00a2a023).unwrap(); // sw a0, 0(t0)
+        memory.write_word(base + 4, 0x00b2a223).unwrap(); // sw a1, 4(t0)
+        cpu.pc = base;
+        cpu.set_reg(Reg::T0, tohost);
+        cpu.set_reg(Reg::A0, command as u32);
+        cpu.set_reg(Reg::A1, (command >> 32) as u32);
+        assert_eq!(cpu.run(memory, Some(2)).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_htif_console_requests() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let (tohost, fromhost) = (base + 0x1000, base + 0x1040);
+        let output = ConsoleBuffer::default();
+        cpu.set_htif(Htif::with_capture(tohost, Some(fromhost), output.clone()));
+
+        // Console putchar
+        store_tohost(
+            &mut cpu,
+            &mut memory,
+            tohost,
+            1 << 56 | 1 << 48 | u64::from(b'A'),
+        );
+        assert_eq!(output.borrow().as_slice(), b"A");
+        assert_eq!(read_u64(&memory, tohost).unwrap(), 0);
+        assert_eq!(
+            read_u64(&memory, fromhost).unwrap(),
+            1 << 56 | 1 << 48 | 0x141
+        );
+
+        // write(1, "hi\n", 3) through a syscall block
+        let block = base + 0x2000;
+        let text = base + 0x3000;
+        memory.load_data(text, b"hi\n").unwrap();
+        for (i, word) in [SYS_WRITE, 1, u64::from(text), 3].into_iter().enumerate() {
+            write_u64(&mut memory, block + i as u32 * 8, word).unwrap();
+        }
+        store_tohost(&mut cpu, &mut memory, tohost, u64::from(block));
+        assert_eq!(output.borrow().as_slice(), b"Ahi\n");
+        assert_eq!(read_u64(&memory, block).unwrap(), 3);
+        assert_eq!(read_u64(&memory, tohost).unwrap(), 0);
+        assert_eq!(read_u64(&memory, fromhost).unwrap(), 1);
+
+        // An exit request is not a console command and stays in tohost
+        store_tohost(&mut cpu, &mut memory, tohost, 1);
+        assert_eq!(read_u64(&memory, tohost).unwrap(), 1);
+    }
+}
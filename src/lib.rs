@@ -1,20 +1,89 @@
+pub mod addr_expr;
+pub mod asm;
+pub mod bus;
 pub mod cpu;
+pub mod crash_report;
+pub mod disasm;
 pub mod elf_loader;
+pub mod emulator;
+pub mod fdt;
+pub mod heap;
+pub mod htif;
+pub mod machine;
 pub mod memory;
+pub mod memory_map;
 pub mod peripheral;
+pub mod reg;
+pub mod riscv_tests;
+pub mod state;
+pub mod syscall;
+pub mod throttle;
+pub mod trace_compare;
+pub mod watch;
 
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
-use std::path::Path;
+// Test-only; the integration tests include the same file from tests/common
+#[cfg(test)]
+mod elf_builder;
+
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum EmulatorError {
+    /// Input file (ELF, device tree, trace, ...) could not be read
     FileNotFound,
+    /// File is not an ELF the loader can parse
     InvalidElfFormat,
+    /// Instruction word this hart does not implement
     UnsupportedInstruction,
-    MemoryAccessError,
-    EcallTermination, // Normal termination via ECALL
+    /// Guest access refused: the address, the access kind and why
+    MemoryAccessError {
+        addr: u32,
+        kind: memory::AccessKind,
+        reason: memory::AccessFailReason,
+    },
+    /// Normal termination via ECALL
+    EcallTermination,
+    /// EBREAK hit while in breakpoint mode
+    Breakpoint,
+    /// `unimp` (unreachable code marker) reached at the given PC
+    Unimp(u32),
+    /// ELF relocation type the loader cannot apply
+    UnsupportedRelocation(u32),
+    /// Saved machine state is corrupt or from another version
+    InvalidState,
+    /// WFI executed with no interrupt pending
+    WaitForInterrupt,
+    /// Guest touched a heap redzone byte
+    PoisonedAccess(memory::PoisonedAccess),
+    /// Jump or branch into memory that was never written
+    WildJump(cpu::WildJump),
+    /// Called guest function stopped without returning
+    NoReturn(Option<ExitReason>),
+    /// Guest call given more arguments than fit in a0..a7
+    TooManyArguments(usize),
+    /// sp-relative store below the stack region
+    StackOverflow(cpu::StackOverflow),
+    /// Modified code fetched without FENCE.I (`detect_smc`)
+    UnfencedCode(cpu::CodeModification),
+    /// Read of a never-written byte under `UninitPolicy::Trap`
+    UninitializedRead(u32),
+    /// Machine description nekov cannot build
+    InvalidMachine(String),
+    /// Symbol missing from the loaded ELF (or no ELF loaded)
+    UnknownSymbol(String),
+    /// Access to a reserved MMIO window with no device (`UnmappedPolicy::Fault`)
+    BusError(u32),
+    /// ELF entry point outside every loaded segment
+    EntryNotLoaded(elf_loader::EntryNotLoaded),
+    /// ELF segment written over another segment or a peripheral
+    LoadOverlap(Box<elf_loader::LoadOverlap>),
+    /// `--break`/`--watch-mem` expression naming no address
+    AddressExpression(addr_expr::ResolveError),
+    /// Trap taken with this many traps already nested (`CpuConfig::max_trap_depth`)
+    TrapLoop(u32),
 }
 
 impl std::fmt::Display for EmulatorError {
@@ -23,8 +92,49 @@ impl std::fmt::Display for EmulatorError {
             EmulatorError::FileNotFound => write!(f, "ELF file not found"),
             EmulatorError::InvalidElfFormat => write!(f, "Invalid ELF format"),
             EmulatorError::UnsupportedInstruction => write!(f, "Unsupported instruction"),
-            EmulatorError::MemoryAccessError => write!(f, "Memory access error"),
+            EmulatorError::MemoryAccessError { addr, kind, reason } => {
+                write!(f, "Memory access error: {kind} at 0x{addr:08x} {reason}")
+            }
             EmulatorError::EcallTermination => write!(f, "Normal termination via ECALL"),
+            EmulatorError::Breakpoint => write!(f, "Breakpoint (EBREAK)"),
+            EmulatorError::UnsupportedRelocation(r_type) => {
+                write!(f, "Unsupported ELF relocation type {r_type}")
+            }
+            EmulatorError::Unimp(pc) => {
+                write!(f, "reached unimp / unreachable code at pc 0x{pc:08x}")
+            }
+            EmulatorError::InvalidState => write!(f, "Invalid or incompatible saved state"),
+            EmulatorError::WaitForInterrupt => write!(f, "Waiting for interrupt (WFI)"),
+            EmulatorError::PoisonedAccess(access) => write!(f, "{access}"),
+            EmulatorError::WildJump(jump) => write!(f, "{jump}"),
+            EmulatorError::NoReturn(Some(reason)) => {
+                write!(f, "function stopped before returning: {reason}")
+            }
+            EmulatorError::TooManyArguments(count) => {
+                write!(
+                    f,
+                    "{count} arguments given, at most 8 are passed in registers"
+                )
+            }
+            EmulatorError::StackOverflow(overflow) => write!(f, "{overflow}"),
+            EmulatorError::UnfencedCode(modification) => write!(f, "{modification}"),
+            EmulatorError::UninitializedRead(address) => {
+                write!(f, "uninitialized read at 0x{address:08x}")
+            }
+            EmulatorError::InvalidMachine(reason) => {
+                write!(f, "invalid machine description: {reason}")
+            }
+            EmulatorError::UnknownSymbol(name) => write!(f, "symbol `{name}` not found"),
+            EmulatorError::BusError(address) => {
+                write!(f, "bus error at 0x{address:08x} (no device mapped)")
+            }
+            EmulatorError::EntryNotLoaded(entry) => write!(f, "{entry}"),
+            EmulatorError::LoadOverlap(overlap) => write!(f, "{overlap}"),
+            EmulatorError::AddressExpression(error) => write!(f, "{error}"),
+            EmulatorError::TrapLoop(depth) => {
+                write!(f, "trap loop: {depth} nested traps without MRET")
+            }
+            EmulatorError::NoReturn(None) => write!(f, "function stopped before returning"),
         }
     }
 }
@@ -33,6 +143,305 @@ impl std::error::Error for EmulatorError {}
 
 pub type Result<T> = std::result::Result<T, EmulatorError>;
 
+/// Every mnemonic the decoder executes with the default `CpuConfig`
+///
+/// Built from `cpu::EXTENSIONS`; see `Cpu::instructions` for a CPU with
+/// extensions disabled.
+pub fn supported_instructions() -> Vec<&'static str> {
+    cpu::Cpu::new().instructions()
+}
+
+/// What this build of nekov supports, as returned by `capabilities`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Capabilities {
+    /// Register width in bits
+    pub xlen: u32,
+    /// ISA string, e.g. `rv32ima_zicsr_zifencei_zihintpause`
+    pub isa: String,
+    /// Enabled extensions, in canonical order
+    pub extensions: Vec<&'static str>,
+    /// Reset value of misa
+    pub misa: u32,
+    /// Mnemonics the decoder executes with this configuration
+    pub instructions: Vec<&'static str>,
+    /// Names of the memory-mapped peripheral types
+    pub peripherals: Vec<&'static str>,
+    /// Program image formats that can be loaded
+    pub loader_formats: Vec<&'static str>,
+    /// Per-instruction trace formats
+    pub trace_formats: Vec<&'static str>,
+    /// Default RAM size in bytes
+    pub max_memory: u32,
+}
+
+impl Capabilities {
+    /// Capabilities of `cpu`, which may have extensions disabled by its `CpuConfig`
+    pub fn of(cpu: &cpu::Cpu) -> Self {
+        let extensions = cpu.extensions();
+        let letters: String = extensions
+            .iter()
+            .filter(|name| name.len() == 1)
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+        let mut isa = format!("rv32{letters}");
+        for name in extensions.iter().filter(|name| name.len() > 1) {
+            isa.push('_');
+            isa.push_str(&name.to_ascii_lowercase());
+        }
+        Self {
+            xlen: 32,
+            isa,
+            extensions,
+            misa: cpu.misa(),
+            instructions: cpu.instructions(),
+            peripherals: peripheral::PERIPHERAL_TYPES.to_vec(),
+            // ELF through `ElfLoader`, flat images through `WasmEmulator::load_binary`
+            loader_formats: vec!["elf", "raw"],
+            trace_formats: cpu::TraceFormat::ALL.map(cpu::TraceFormat::name).to_vec(),
+            max_memory: memory::DEFAULT_MEMORY_SIZE,
+        }
+    }
+
+    /// Render as a pretty-printed JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("capabilities serialize")
+    }
+}
+
+/// Capabilities of a CPU with the default configuration
+pub fn capabilities() -> Capabilities {
+    Capabilities::of(&cpu::Cpu::new())
+}
+
+/// Why a run loop stopped without an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    /// Guest terminated via ECALL; carries the exit code from a0
+    EcallExit(u32),
+    /// The configured instruction limit was reached
+    InstructionLimit,
+    /// An unsupported instruction stopped execution
+    UnsupportedInstruction,
+    /// EBREAK was hit in breakpoint mode
+    Breakpoint,
+    /// The `unimp` pseudo-instruction was reached at the given PC
+    Unimp(u32),
+    /// Execution diverged from the reference trace at the given instruction index
+    TraceDivergence(u32),
+    /// A watch condition fired after the instruction at `pc`
+    WatchHit { watch: usize, pc: u32, value: u32 },
+    /// A `run_until_*` helper reached its stop condition with the PC at the given address
+    TargetReached(u32),
+    /// The run took longer than the wall-clock budget set with `Cpu::set_time_budget`
+    /// (`RunOptions::max_time`, `--max-time`)
+    TimeBudgetExceeded,
+    /// WFI was executed with no interrupt pending; the PC is past the WFI
+    Waiting,
+    /// The progress callback cancelled the run
+    Cancelled,
+    /// More than `CpuConfig::max_trap_depth` traps were taken without an MRET;
+    /// `Cpu::trap_chain` holds the mcause/mepc of each
+    TrapLoop { depth: u32 },
+}
+
+impl ExitReason {
+    /// Short machine-readable name of the reason
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExitReason::EcallExit(_) => "ecall_exit",
+            ExitReason::InstructionLimit => "instruction_limit",
+            ExitReason::UnsupportedInstruction => "unsupported_instruction",
+            ExitReason::Breakpoint => "breakpoint",
+            ExitReason::Unimp(_) => "unimp",
+            ExitReason::TraceDivergence(_) => "trace_divergence",
+            ExitReason::WatchHit { .. } => "watch_hit",
+            ExitReason::TargetReached(_) => "target_reached",
+            ExitReason::TimeBudgetExceeded => "time_budget_exceeded",
+            ExitReason::Waiting => "waiting",
+            ExitReason::Cancelled => "cancelled",
+            ExitReason::TrapLoop { .. } => "trap_loop",
+        }
+    }
+
+    /// Guest exit code, if the guest terminated itself
+    pub fn exit_code(&self) -> Option<u32> {
+        match self {
+            ExitReason::EcallExit(code) => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitReason::EcallExit(code) => write!(f, "ECALL exit (code {code})"),
+            ExitReason::InstructionLimit => write!(f, "Instruction limit reached"),
+            ExitReason::UnsupportedInstruction => write!(f, "Unsupported instruction"),
+            ExitReason::Breakpoint => write!(f, "Breakpoint"),
+            ExitReason::Unimp(pc) => write!(f, "Reached unimp / unreachable code at pc 0x{pc:08x}"),
+            ExitReason::TraceDivergence(index) => {
+                write!(f, "Diverged from reference trace at instruction #{index}")
+            }
+            ExitReason::WatchHit { watch, pc, value } => write!(
+                f,
+                "Watch #{watch} hit at pc 0x{pc:08x} (value 0x{value:08x})"
+            ),
+            ExitReason::TargetReached(pc) => write!(f, "Reached target pc 0x{pc:08x}"),
+            ExitReason::TimeBudgetExceeded => write!(f, "Time budget exceeded"),
+            ExitReason::Waiting => write!(f, "Waiting for interrupt"),
+            ExitReason::Cancelled => write!(f, "Cancelled"),
+            ExitReason::TrapLoop { depth } => {
+                write!(f, "Trap loop ({depth} nested traps without MRET)")
+            }
+        }
+    }
+}
+
+/// Where the device tree blob handed to the guest in a1 comes from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum DtbSource {
+    /// Do not provide a device tree
+    #[default]
+    None,
+    /// Load a DTB file from disk
+    File(PathBuf),
+    /// Generate a minimal DTB describing nekov's memory, UART and CLINT
+    Generated,
+}
+
+/// Options controlling a single emulator run
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Maximum number of instructions to execute
+    pub instruction_limit: Option<usize>,
+    /// Verbosity level (0-3)
+    pub verbosity: u8,
+    /// Device tree to place in memory before starting execution
+    pub dtb: DtbSource,
+    /// Load address for the device tree (defaults to just below the top of RAM)
+    pub dtb_address: Option<u32>,
+    /// Suppress all human-readable output (used for machine-readable reports)
+    pub quiet: bool,
+    /// Emit a per-instruction trace to stdout in the given format
+    pub trace_format: Option<cpu::TraceFormat>,
+    /// Write-protect executable ELF segments so stray stores into code fail
+    pub protect_text: bool,
+    /// Report stores into executed code and fetches of it without FENCE.I
+    pub detect_smc: Option<cpu::SmcAction>,
+    /// Reference trace to compare execution against
+    pub compare: Option<CompareOptions>,
+    /// Register and memory watches that stop the run when they fire
+    pub watches: Vec<watch::WatchSpec>,
+    /// Stop the run when the PC reaches any of these addresses
+    pub breakpoints: Vec<addr_expr::AddrExpr>,
+    /// Stop with `ExitReason::TimeBudgetExceeded` once the run has taken this long
+    pub max_time: Option<std::time::Duration>,
+    /// Retired instructions between clock checks for `max_time` (default `cpu::TIME_CHECK_INTERVAL`)
+    pub time_check_interval: Option<u32>,
+    /// RAM base address (defaults to `memory::DEFAULT_BASE_ADDRESS`)
+    pub memory_base: Option<u32>,
+    /// Start at this address instead of the ELF entry point
+    pub entry: Option<u32>,
+    /// Load ELF segments that overlap each other or a peripheral instead of failing
+    pub allow_overlap: bool,
+    /// Print the memory map of the loaded program before running
+    pub print_map: bool,
+    /// Serve `brk` from a heap with poisoned redzones after every extension
+    pub heap_poison: bool,
+    /// Print a status line to stderr every this many retired instructions (ignored when quiet)
+    pub progress_interval: Option<u32>,
+    /// Attach a console UART at `fdt::DEFAULT_UART_BASE` with this register layout
+    pub uart: Option<peripheral::UartLayout>,
+    /// Write a crash report bundle to this directory if the run stops on a fatal error
+    pub crash_report: Option<PathBuf>,
+    /// Attach a CLINT (timer and software interrupts) at `fdt::DEFAULT_CLINT_BASE`
+    pub clint: bool,
+    /// Run with `Cpu::run_fast` when no peripherals are attached (no tracing or watches)
+    pub fast: bool,
+    /// Highlight changed values in the `-vv` step output with ANSI colors
+    pub color: bool,
+    /// What guest reads of never-written memory return (or whether they fail)
+    pub uninit_policy: memory::UninitPolicy,
+    /// Fast-forward interrupt-bound polling loops by at most this many cycles
+    /// (needs a peripheral such as the CLINT to provide the interrupt)
+    pub idle_skip: Option<u64>,
+}
+
+/// Reference trace comparison settings
+#[derive(Debug, Clone, Default)]
+pub struct CompareOptions {
+    /// Reference trace file
+    pub path: PathBuf,
+    /// Format of the reference trace
+    pub format: trace_compare::ReferenceFormat,
+    /// Rules for ignoring differences nekov doesn't model
+    pub skip: Vec<trace_compare::SkipRule>,
+}
+
+/// Result of a completed emulator run
+#[derive(Debug)]
+pub struct RunReport {
+    pub cpu: cpu::Cpu,
+    pub memory: memory::Memory,
+    /// ELF entry point the run started from
+    pub entry_point: u32,
+    /// Number of instructions executed
+    pub instructions_executed: u32,
+    /// Why execution stopped
+    pub exit_reason: Option<ExitReason>,
+    /// Machine the run was configured with, if it came from `RunOptions`
+    pub machine: Option<machine::MachineDescription>,
+    /// Wall-clock time spent in the run loop, excluding loading and setup
+    pub elapsed: std::time::Duration,
+}
+
+impl RunReport {
+    /// Guest exit code, if the guest terminated itself
+    pub fn guest_exit_code(&self) -> Option<u32> {
+        self.exit_reason.and_then(|reason| reason.exit_code())
+    }
+
+    /// Render the report as a JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("run report serializes")
+    }
+}
+
+/// The JSON form of a `RunReport`
+#[derive(serde::Serialize)]
+struct RunReportJson<'a> {
+    entry_point: String,
+    instructions_executed: u32,
+    final_pc: String,
+    exit_reason: Option<&'static str>,
+    guest_exit_code: Option<u32>,
+    registers: [u32; cpu::NUM_REGISTERS],
+    capabilities: Capabilities,
+    machine: &'a Option<machine::MachineDescription>,
+    memory: memory::MemoryStats,
+}
+
+impl serde::Serialize for RunReport {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        RunReportJson {
+            entry_point: format!("0x{:08x}", self.entry_point),
+            instructions_executed: self.instructions_executed,
+            final_pc: format!("0x{:08x}", self.cpu.pc),
+            exit_reason: self.exit_reason.map(|reason| reason.name()),
+            guest_exit_code: self.guest_exit_code(),
+            registers: std::array::from_fn(|i| self.cpu.read_register(i)),
+            capabilities: Capabilities::of(&self.cpu),
+            machine: &self.machine,
+            memory: self.memory.stats(),
+        }
+        .serialize(serializer)
+    }
+}
+
 /// Main entry point for running the emulator
 pub fn run_emulator(binary_path: &Path) -> Result<(cpu::Cpu, memory::Memory)> {
     run_emulator_with_limit(binary_path, Some(1000))
@@ -52,33 +461,271 @@ pub fn run_emulator_with_limit_and_verbosity(
     instruction_limit: Option<usize>,
     verbosity: u8,
 ) -> Result<(cpu::Cpu, memory::Memory)> {
+    let options = RunOptions {
+        instruction_limit,
+        verbosity,
+        ..RunOptions::default()
+    };
+    let report = run_emulator_with_options(binary_path, &options)?;
+    Ok((report.cpu, report.memory))
+}
+
+/// The peripherals `options` attach, at their default addresses
+pub(crate) fn attach_peripherals(options: &RunOptions) -> peripheral::PeripheralManager {
+    let mut peripherals = peripheral::PeripheralManager::new();
+    if let Some(layout) = options.uart {
+        peripherals.add_peripheral(Box::new(
+            peripheral::ConsolePeriph::new(fdt::DEFAULT_UART_BASE).with_layout(layout),
+        ));
+    }
+    if options.clint {
+        peripherals.add_peripheral(Box::new(peripheral::Clint::new(fdt::DEFAULT_CLINT_BASE)));
+    }
+    peripherals
+}
+
+/// Text of a caught panic payload
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Number of uninitialized-read ranges listed at the end of a run
+const UNINIT_REPORT_LIMIT: usize = 10;
+
+/// Run emulator with the full set of run options
+pub fn run_emulator_with_options(binary_path: &Path, options: &RunOptions) -> Result<RunReport> {
+    let instruction_limit = options.instruction_limit;
+    // Quiet runs behave like verbosity 0 without the final state dump
+    let verbosity = if options.quiet { 0 } else { options.verbosity };
+
     // Check if file exists
     if !binary_path.exists() {
         return Err(EmulatorError::FileNotFound);
     }
 
-    // Initialize CPU and memory
+    // Initialize CPU, memory and peripherals
     let mut cpu = cpu::Cpu::new();
-    let mut memory = memory::Memory::new();
+    let mut peripherals = attach_peripherals(options);
+    let mut memory =
+        memory::Memory::new_with_base(options.memory_base.unwrap_or(memory::DEFAULT_BASE_ADDRESS));
 
     // Load ELF binary into memory
-    let entry_point = elf_loader::ElfLoader::load_elf(binary_path, &mut memory)?;
+    let loader_verbosity = if options.quiet { 0 } else { verbosity.max(1) };
+    let load_options = elf_loader::LoadOptions {
+        verbosity: loader_verbosity,
+        protect_text: options.protect_text,
+        entry: options.entry,
+        reserved: peripherals.memory_map(),
+        allow_overlap: options.allow_overlap,
+        ..elf_loader::LoadOptions::default()
+    };
+    let elf = elf_loader::ElfLoader::load_elf_file(binary_path, &mut memory, &load_options)?;
+    let entry_point = elf.entry;
 
-    // Set CPU program counter to entry point
+    // Start (and reset) at the entry point
+    cpu.set_reset_vector(entry_point);
     cpu.pc = entry_point;
+    memory.set_uninit_policy(options.uninit_policy);
     if verbosity >= 1 {
         println!("Entry point: 0x{entry_point:08x}");
     }
 
+    // Place the device tree and set a0=mhartid, a1=dtb address
+    let dtb = match &options.dtb {
+        DtbSource::None => None,
+        DtbSource::File(path) => {
+            Some(std::fs::read(path).map_err(|_| EmulatorError::FileNotFound)?)
+        }
+        DtbSource::Generated => Some(fdt::generate_default_dtb(&memory)),
+    };
+    if let Some(dtb) = dtb {
+        let dtb_address = fdt::load_dtb(&mut cpu, &mut memory, &dtb, options.dtb_address)?;
+        if verbosity >= 1 {
+            println!("Device tree: 0x{dtb_address:08x} ({} bytes)", dtb.len());
+        }
+    }
+
+    if let Some(format) = options.trace_format {
+        cpu.set_trace_sink(format, Box::new(std::io::BufWriter::new(std::io::stdout())));
+    }
+    cpu.detect_smc(options.detect_smc);
+    cpu.set_color(options.color);
+    cpu.set_idle_skip(options.idle_skip);
+
+    if let Some(compare) = &options.compare {
+        let text =
+            std::fs::read_to_string(&compare.path).map_err(|_| EmulatorError::FileNotFound)?;
+        cpu.set_trace_comparator(trace_compare::TraceComparator::from_text(
+            compare.format,
+            &text,
+            compare.skip.clone(),
+        ));
+    }
+
+    // Serve HTIF console output for binaries that define `tohost`
+    if let Some(tohost) = elf.symbol("tohost") {
+        cpu.set_htif(htif::Htif::new(tohost, elf.symbol("fromhost")));
+    }
+
+    // Symbolic watch and breakpoint addresses resolve against the ELF's symbols
+    for spec in &options.watches {
+        cpu.add_watch(
+            spec.to_watch(&elf.symbols)
+                .map_err(EmulatorError::AddressExpression)?,
+        );
+    }
+    for breakpoint in &options.breakpoints {
+        let address = breakpoint
+            .resolve(&elf.symbols)
+            .map_err(EmulatorError::AddressExpression)?;
+        cpu.add_watch(watch::Watch::Pc(watch::WatchPredicate::Equals(address)));
+    }
+
+    if let Some(max_time) = options.max_time {
+        cpu.set_time_budget_with_interval(
+            max_time,
+            options
+                .time_check_interval
+                .unwrap_or(cpu::TIME_CHECK_INTERVAL),
+        );
+    }
+
+    if options.heap_poison {
+        let heap =
+            heap::Heap::for_memory(elf.image_end, heap::DEFAULT_HEAP_SIZE, &memory).with_redzones();
+        heap.install(&mut memory);
+        cpu.set_ecall_behavior(syscall::EcallBehavior::Handler(Box::new(
+            heap::HeapSyscalls::new(std::rc::Rc::new(std::cell::RefCell::new(heap))),
+        )));
+    }
+
+    if options.print_map && !options.quiet {
+        let mut entries = elf.sections.clone();
+        entries.extend(peripherals.memory_map());
+        println!("Memory map:");
+        print!("{}", memory_map::format_table(&entries));
+    }
+
+    // Keep a single status line updated on stderr
+    let progress_shown = std::rc::Rc::new(std::cell::Cell::new(false));
+    if let Some(interval) = options.progress_interval.filter(|_| !options.quiet) {
+        let shown = progress_shown.clone();
+        cpu.set_progress_callback(
+            interval,
+            Box::new(move |progress| {
+                eprint!(
+                    "\r{} instructions retired ({:.1} MIPS)",
+                    progress.retired,
+                    progress.mips()
+                );
+                shown.set(true);
+                cpu::ProgressAction::Continue
+            }),
+        );
+    }
+
     // Run emulation with instruction limit for safety
+    let machine = machine::MachineDescription::of(options);
     if verbosity >= 1 {
+        print!("Machine:\n{machine}");
         println!("Starting emulation...");
     }
     let limit = instruction_limit.map(|l| l as u32);
-    let executed_instructions = cpu.run_with_verbosity(&mut memory, limit, verbosity)?;
+    if options.crash_report.is_some() {
+        cpu.record_pc_history(true);
+    }
+    // Panics are caught only to write the crash report, then resumed
+    let start = std::time::Instant::now();
+    let run = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // The fast path does not check the clock
+        if peripherals.is_empty() && options.fast && options.max_time.is_none() {
+            cpu.run_fast(&mut memory, limit)
+        } else if peripherals.is_empty() {
+            cpu.run_with_verbosity(&mut memory, limit, verbosity)
+        } else {
+            cpu.run_with_peripherals_and_verbosity(&mut memory, &mut peripherals, limit, verbosity)
+        }
+    }));
+    let elapsed = start.elapsed();
+    if progress_shown.get() {
+        eprintln!();
+    }
+    if let Some(mut sink) = cpu.take_trace_sink() {
+        let _ = std::io::Write::flush(&mut sink);
+    }
+    let fatal = match &run {
+        Ok(Ok(_)) if cpu.exit_reason == Some(ExitReason::UnsupportedInstruction) => {
+            Some(cpu.describe_fault(&memory, &EmulatorError::UnsupportedInstruction))
+        }
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(cpu.describe_fault(&memory, e)),
+        Err(payload) => Some(format!("panic: {}", panic_message(payload.as_ref()))),
+    };
+    if let (Some(dir), Some(error)) = (&options.crash_report, fatal) {
+        let sections = elf.sections;
+        let exit_reason = cpu.exit_reason;
+        let instructions_executed = match &run {
+            Ok(Ok(executed)) => *executed,
+            _ => cpu.instret() as u32,
+        };
+        let report = RunReport {
+            cpu,
+            memory,
+            entry_point,
+            instructions_executed,
+            exit_reason,
+            machine: Some(machine.clone()),
+            elapsed,
+        };
+        match crash_report::write_crash_report(dir, &report, &error, &sections) {
+            Ok(()) => eprintln!("Crash report written to {}", dir.display()),
+            Err(e) => eprintln!("Failed to write crash report to {}: {e}", dir.display()),
+        }
+        (cpu, memory) = (report.cpu, report.memory);
+    }
+    let executed_instructions = match run {
+        Ok(result) => result?,
+        Err(payload) => std::panic::resume_unwind(payload),
+    };
+    if let Some(divergence) = cpu.trace_divergence() {
+        if !options.quiet {
+            println!("{divergence}");
+        }
+    }
+    if let Some(ExitReason::WatchHit { watch, pc, value }) = cpu.exit_reason {
+        if !options.quiet {
+            if let Some(watch) = cpu.watch(watch) {
+                println!("Watch hit: {watch} at pc 0x{pc:08x} (value 0x{value:08x})");
+            }
+        }
+    }
+    if let (Some(ExitReason::TimeBudgetExceeded), Some(max_time)) =
+        (cpu.exit_reason, options.max_time)
+    {
+        if !options.quiet {
+            println!(
+                "Wall-clock limit of {:.3}s reached after {executed_instructions} instructions at pc 0x{:08x}",
+                max_time.as_secs_f64(),
+                cpu.pc
+            );
+        }
+    }
     if verbosity >= 1 {
         println!("Emulation completed. Executed {executed_instructions} instructions.");
     }
+    let uninit_reads = memory.uninit_report();
+    if !options.quiet && !uninit_reads.is_empty() {
+        println!("Reads of uninitialized memory (top offenders):");
+        for range in uninit_reads.iter().take(UNINIT_REPORT_LIMIT) {
+            println!("  {range}");
+        }
+    }
 
     // Print final CPU state if verbose
     if verbosity >= 2 {
@@ -99,7 +746,7 @@ pub fn run_emulator_with_limit_and_verbosity(
                 cpu.read_register(i + 24)
             );
         }
-    } else if verbosity == 0 {
+    } else if verbosity == 0 && !options.quiet {
         // Keep the old behavior for non-verbose mode
         println!("Entry point: 0x{entry_point:08x}");
         println!("Starting emulation...");
@@ -121,7 +768,16 @@ pub fn run_emulator_with_limit_and_verbosity(
         }
     }
 
-    Ok((cpu, memory))
+    let exit_reason = cpu.exit_reason;
+    Ok(RunReport {
+        cpu,
+        memory,
+        entry_point,
+        instructions_executed: executed_instructions,
+        exit_reason,
+        machine: Some(machine),
+        elapsed,
+    })
 }
 
 #[cfg(test)]
@@ -129,6 +785,97 @@ mod tests {
     use super::*;
     use std::path::PathBuf;
 
+    #[test]
+    fn test_supported_instructions() {
+        let supported = supported_instructions();
+        for mnemonic in [
+            "addi",
+            "mul",
+            "lw",
+            "amoadd.w",
+            "csrrw",
+            "fence.i",
+            "mret",
+            "wfi",
+            "sfence.vma",
+        ] {
+            assert!(supported.contains(&mnemonic), "{mnemonic}");
+        }
+        for mnemonic in ["fadd.s", "flw", "sret", "c.addi"] {
+            assert!(!supported.contains(&mnemonic), "{mnemonic}");
+        }
+    }
+
+    #[test]
+    fn test_capabilities_follow_cpu_config() {
+        let caps = capabilities();
+        assert!(caps.extensions.contains(&"M") && caps.extensions.contains(&"A"));
+        assert_eq!(caps.isa, "rv32ima_zicsr_zifencei_zihintpause");
+        assert_eq!(caps.misa, 0x4000_1101);
+        assert_eq!(caps.xlen, 32);
+        assert_eq!(
+            caps.peripherals,
+            ["console", "text-display", "debug-print", "clint"]
+        );
+        assert_eq!(caps.instructions, supported_instructions());
+        assert!(caps.trace_formats.contains(&"jsonl"));
+
+        let mut cpu = cpu::Cpu::with_config(cpu::CpuConfig {
+            disabled_extensions: cpu::MISA_M,
+            ..cpu::CpuConfig::default()
+        });
+        let caps = Capabilities::of(&cpu);
+        assert!(!caps.extensions.contains(&"M"));
+        assert!(!caps.instructions.contains(&"mul"));
+        assert!(caps.instructions.contains(&"amoadd.w"));
+        assert_eq!(caps.isa, "rv32ia_zicsr_zifencei_zihintpause");
+        assert_eq!(cpu.read_csr(cpu::CSR_MISA), 0x4000_0101);
+
+        // The decoder rejects what misa no longer advertises
+        let mut memory = memory::Memory::new();
+        cpu.pc = memory.base_address();
+        memory.write_word(cpu.pc, 0x0220_81B3).unwrap(); // mul x3, x1, x2
+        assert!(matches!(
+            cpu.step(&mut memory),
+            Err(EmulatorError::UnsupportedInstruction)
+        ));
+    }
+
+    #[test]
+    fn test_guest_exit_code_in_report() {
+        let mut cpu = cpu::Cpu::new();
+        let mut memory = memory::Memory::new();
+        let base = memory.base_address();
+        cpu.pc = base;
+
+        memory.write_word(base, 0x02a00513).unwrap(); // addi a0, x0, 42
+        memory.write_word(base + 4, 0x05d00893).unwrap(); // addi a7, x0, 93
+        memory.write_word(base + 8, 0x00000073).unwrap(); // ecall
+        let executed = cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(cpu.exit_reason, Some(ExitReason::EcallExit(42)));
+        assert_eq!(cpu.exit_code(), Some(42));
+
+        let report = RunReport {
+            exit_reason: cpu.exit_reason,
+            cpu,
+            memory,
+            entry_point: base,
+            instructions_executed: executed,
+            machine: None,
+            elapsed: std::time::Duration::ZERO,
+        };
+        assert_eq!(report.guest_exit_code(), Some(42));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["exit_reason"], "ecall_exit");
+        assert_eq!(json["guest_exit_code"], 42);
+        assert_eq!(
+            json["capabilities"]["isa"],
+            "rv32ima_zicsr_zifencei_zihintpause"
+        );
+        assert_eq!(json["machine"], serde_json::Value::Null);
+        assert_eq!(json["registers"][10], 42);
+    }
+
     #[test]
     fn test_run_emulator_file_not_found() {
         let non_existent_path = PathBuf::from("non_existent_file.elf");
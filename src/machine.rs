@@ -0,0 +1,310 @@
+//! Description of the machine a run is configured with
+//!
+//! A `MachineDescription` records everything about the emulated machine that
+//! a run's options decide: ISA, RAM, the peripheral map, the uninitialized
+//! memory policy and the ECALL environment. It is printed at `-v`, embedded
+//! in `--json` reports and crash bundles, and can be parsed back (from JSON,
+//! or from a TOML machine config given with `--machine`) and applied to
+//! `RunOptions` to reproduce the same machine.
+
+use crate::{memory::UninitPolicy, peripheral::UartLayout, EmulatorError, Result, RunOptions};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A RAM region
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub base: u32,
+    pub size: u32,
+}
+
+/// A memory-mapped peripheral
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeripheralDescription {
+    pub name: String,
+    pub base: u32,
+    pub size: u32,
+    /// Register layout of the console UART
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<UartLayout>,
+}
+
+/// How ECALL is serviced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvMode {
+    /// ECALL stops the run with the exit code in a0 (riscv-tests convention)
+    Tests,
+    /// `brk` is served from a heap with poisoned redzones
+    PoisonedHeap,
+}
+
+/// The emulated machine, as configured by a set of `RunOptions`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MachineDescription {
+    /// ISA string, e.g. `rv32ima_zicsr_zifencei_zihintpause`
+    pub isa: String,
+    pub memory: Vec<MemoryRegion>,
+    pub peripherals: Vec<PeripheralDescription>,
+    pub uninit_policy: UninitPolicy,
+    pub env: EnvMode,
+}
+
+impl MachineDescription {
+    /// Describe the machine `options` configure
+    pub fn of(options: &RunOptions) -> Self {
+        let memory = crate::memory::Memory::new_with_base(
+            options
+                .memory_base
+                .unwrap_or(crate::memory::DEFAULT_BASE_ADDRESS),
+        );
+        let peripherals = crate::attach_peripherals(options)
+            .memory_map()
+            .into_iter()
+            .map(|entry| PeripheralDescription {
+                model: (entry.name == "console").then(|| options.uart.unwrap_or_default()),
+                name: entry.name,
+                base: entry.start,
+                size: entry.size,
+            })
+            .collect();
+        Self {
+            isa: crate::capabilities().isa,
+            memory: vec![MemoryRegion {
+                name: "ram".to_string(),
+                base: memory.base_address(),
+                size: memory.size(),
+            }],
+            peripherals,
+            uninit_policy: options.uninit_policy,
+            env: if options.heap_poison {
+                EnvMode::PoisonedHeap
+            } else {
+                EnvMode::Tests
+            },
+        }
+    }
+
+    /// Configure `options` to build this machine, leaving the non-machine options alone
+    ///
+    /// Fails if the description asks for something nekov cannot build, such
+    /// as another ISA, RAM size or a peripheral at a non-default address.
+    pub fn configure(&self, options: &mut RunOptions) -> Result<()> {
+        let invalid = |what: String| Err(EmulatorError::InvalidMachine(what));
+        let isa = crate::capabilities().isa;
+        if self.isa != isa {
+            return invalid(format!("unsupported isa {} (expected {isa})", self.isa));
+        }
+        let [ram] = self.memory.as_slice() else {
+            return invalid("expected exactly one memory region".to_string());
+        };
+        if ram.size != crate::memory::DEFAULT_MEMORY_SIZE {
+            return invalid(format!("unsupported RAM size {} bytes", ram.size));
+        }
+
+        let mut configured = RunOptions {
+            memory_base: Some(ram.base),
+            uart: None,
+            clint: false,
+            uninit_policy: self.uninit_policy,
+            heap_poison: self.env == EnvMode::PoisonedHeap,
+            ..options.clone()
+        };
+        for peripheral in &self.peripherals {
+            let (expected_base, duplicate) = match peripheral.name.as_str() {
+                "console" => {
+                    let duplicate = configured.uart.is_some();
+                    configured.uart = Some(peripheral.model.unwrap_or_default());
+                    (crate::fdt::DEFAULT_UART_BASE, duplicate)
+                }
+                "clint" => {
+                    let duplicate = configured.clint;
+                    configured.clint = true;
+                    (crate::fdt::DEFAULT_CLINT_BASE, duplicate)
+                }
+                name => return invalid(format!("unknown peripheral {name}")),
+            };
+            if duplicate || peripheral.base != expected_base {
+                return invalid(format!(
+                    "unsupported {} at 0x{:08x}",
+                    peripheral.name, peripheral.base
+                ));
+            }
+        }
+        *options = configured;
+        Ok(())
+    }
+
+    /// Render as a pretty-printed JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("machine description serializes")
+    }
+
+    /// Parse a description written by `to_json`
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| EmulatorError::InvalidMachine(e.to_string()))
+    }
+
+    /// Render as a TOML machine config
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("machine description serializes")
+    }
+
+    /// Parse a TOML machine config, such as one written by `to_toml`
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|e| EmulatorError::InvalidMachine(e.to_string()))
+    }
+}
+
+impl fmt::Display for MachineDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "isa:            {}", self.isa)?;
+        for region in &self.memory {
+            writeln!(
+                f,
+                "memory:         {} 0x{:08x}-0x{:08x} ({} bytes)",
+                region.name,
+                region.base,
+                region.base.wrapping_add(region.size),
+                region.size
+            )?;
+        }
+        if self.peripherals.is_empty() {
+            writeln!(f, "peripherals:    none")?;
+        }
+        for peripheral in &self.peripherals {
+            write!(
+                f,
+                "peripheral:     {} 0x{:08x}-0x{:08x}",
+                peripheral.name,
+                peripheral.base,
+                peripheral.base.wrapping_add(peripheral.size)
+            )?;
+            match peripheral.model {
+                Some(UartLayout::Simple) => writeln!(f, " (simple)")?,
+                Some(UartLayout::Ns16550Compatible) => writeln!(f, " (ns16550)")?,
+                None => writeln!(f)?,
+            }
+        }
+        let uninit = match self.uninit_policy {
+            UninitPolicy::ReturnFF => "ff",
+            UninitPolicy::ReturnZero => "zero",
+            UninitPolicy::Trap => "trap",
+        };
+        writeln!(f, "uninit policy:  {uninit}")?;
+        let env = match self.env {
+            EnvMode::Tests => "tests (ECALL exits with a0)",
+            EnvMode::PoisonedHeap => "poisoned heap (brk with redzones)",
+        };
+        writeln!(f, "env:            {env}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom_options() -> RunOptions {
+        RunOptions {
+            memory_base: Some(0x4000_0000),
+            uart: Some(UartLayout::Ns16550Compatible),
+            clint: true,
+            uninit_policy: UninitPolicy::Trap,
+            heap_poison: true,
+            ..RunOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_describe_custom_machine() {
+        let machine = MachineDescription::of(&custom_options());
+        let json: serde_json::Value = serde_json::from_str(&machine.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "isa": "rv32ima_zicsr_zifencei_zihintpause",
+                "memory": [{ "name": "ram", "base": 0x4000_0000u32, "size": 128 << 20 }],
+                "peripherals": [
+                    { "name": "console", "base": 0x1000_0000u32, "size": 0x1000, "model": "ns16550" },
+                    { "name": "clint", "base": 0x0200_0000u32, "size": 0x1_0000 },
+                ],
+                "uninit_policy": "trap",
+                "env": "poisoned_heap",
+            })
+        );
+        assert_eq!(
+            machine.to_string(),
+            "isa:            rv32ima_zicsr_zifencei_zihintpause\n\
+             memory:         ram 0x40000000-0x48000000 (134217728 bytes)\n\
+             peripheral:     console 0x10000000-0x10001000 (ns16550)\n\
+             peripheral:     clint 0x02000000-0x02010000\n\
+             uninit policy:  trap\n\
+             env:            poisoned heap (brk with redzones)\n"
+        );
+    }
+
+    #[test]
+    fn test_machine_description_round_trips() {
+        for options in [RunOptions::default(), custom_options()] {
+            let machine = MachineDescription::of(&options);
+            let parsed = MachineDescription::from_json(&machine.to_json()).unwrap();
+            assert_eq!(parsed, machine);
+
+            // Rebuilding from the description keeps unrelated options
+            let mut rebuilt = RunOptions {
+                verbosity: 2,
+                ..RunOptions::default()
+            };
+            parsed.configure(&mut rebuilt).unwrap();
+            assert_eq!(rebuilt.verbosity, 2);
+            assert_eq!(MachineDescription::of(&rebuilt), machine);
+        }
+
+        let mut machine = MachineDescription::of(&custom_options());
+        machine.peripherals[1].base = 0x0300_0000;
+        assert!(matches!(
+            machine.configure(&mut RunOptions::default()),
+            Err(EmulatorError::InvalidMachine(message)) if message == "unsupported clint at 0x03000000"
+        ));
+        assert!(MachineDescription::from_json("{\"isa\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_machine_config_toml_round_trips() {
+        let machine = MachineDescription::of(&custom_options());
+        let config = "\
+            isa = \"rv32ima_zicsr_zifencei_zihintpause\"\n\
+            uninit_policy = \"trap\"\n\
+            env = \"poisoned_heap\"\n\
+            \n\
+            [[memory]]\n\
+            name = \"ram\"\n\
+            base = 1073741824\n\
+            size = 134217728\n\
+            \n\
+            [[peripherals]]\n\
+            name = \"console\"\n\
+            base = 268435456\n\
+            size = 4096\n\
+            model = \"ns16550\"\n\
+            \n\
+            [[peripherals]]\n\
+            name = \"clint\"\n\
+            base = 33554432\n\
+            size = 65536\n";
+        assert_eq!(machine.to_toml(), config);
+
+        // Loading the config and dumping the machine it builds is lossless
+        let loaded = MachineDescription::from_toml(config).unwrap();
+        assert_eq!(loaded, machine);
+        let mut options = RunOptions::default();
+        loaded.configure(&mut options).unwrap();
+        assert_eq!(MachineDescription::of(&options).to_toml(), config);
+
+        assert!(matches!(
+            MachineDescription::from_toml("isa = 1"),
+            Err(EmulatorError::InvalidMachine(_))
+        ));
+    }
+}
@@ -1,11 +1,246 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
+use nekov::{
+    addr_expr::AddrExpr,
+    cpu::{SmcAction, TraceFormat},
+    memory::UninitPolicy,
+    peripheral::UartLayout,
+    riscv_tests::{
+        check_riscv_test_result, default_cache_path, load_budgets, run_batch, BatchOptions,
+        TestResult,
+    },
+    trace_compare::{ReferenceFormat, SkipRule},
+    watch::WatchSpec,
+    CompareOptions, DtbSource, ExitReason, RunOptions,
+};
+use std::io::IsTerminal;
 use std::path::PathBuf;
 
+/// Parse an address given in hex (0x-prefixed) or decimal
+fn parse_address(s: &str) -> Result<u32, String> {
+    match AddrExpr::parse(s) {
+        Ok(AddrExpr::Literal(address)) => Ok(address),
+        _ => Err(format!("invalid address '{s}'")),
+    }
+}
+
+/// Parse a positive number of seconds such as `30` or `0.5`
+fn parse_seconds(s: &str) -> Result<std::time::Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|&seconds| seconds > 0.0)
+        .and_then(|seconds| std::time::Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("invalid number of seconds '{s}'"))
+}
+
+/// Reads of never-written memory selected by `--uninit`
+fn uninit_policy(matches: &ArgMatches) -> UninitPolicy {
+    match matches.get_one::<String>("uninit").map(String::as_str) {
+        Some("zero") => UninitPolicy::ReturnZero,
+        Some("trap") => UninitPolicy::Trap,
+        _ => UninitPolicy::ReturnFF,
+    }
+}
+
+/// The `--uninit` argument shared by runs and `nekov test`
+fn uninit_arg() -> Arg {
+    Arg::new("uninit")
+        .long("uninit")
+        .help("Reads of never-written memory: return 0xff, return 0, or stop with an error (trap)")
+        .value_name("POLICY")
+        .value_parser(["ff", "zero", "trap"])
+        .default_value("ff")
+}
+
+/// `nekov test <dir>`: run every riscv-tests binary in a directory and exit nonzero on failures
+fn run_test_command(matches: &ArgMatches) -> ! {
+    let dir = matches.get_one::<PathBuf>("dir").unwrap();
+    let json_output = matches.get_flag("json");
+    let budgets = matches.get_one::<PathBuf>("budgets").map(|path| {
+        load_budgets(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read budgets file {}: {e}", path.display());
+            std::process::exit(1);
+        })
+    });
+    let options = BatchOptions {
+        filter: matches.get_one::<String>("filter").cloned(),
+        jobs: matches.get_one::<usize>("jobs").copied().unwrap_or(1),
+        instruction_limit: matches.get_one::<usize>("limit").copied(),
+        budgets,
+        budget_tolerance: matches
+            .get_one::<f64>("budget-tolerance")
+            .copied()
+            .unwrap_or(0.0),
+        uninit_policy: uninit_policy(matches),
+        cache: (!matches.get_flag("no-cache")).then(|| default_cache_path(dir)),
+        rerun_failed: matches.get_flag("rerun-failed"),
+    };
+
+    if !json_output {
+        println!("🐈 Nekov RISC-V Test Runner");
+        println!("===========================");
+        println!("Running tests from: {}", dir.display());
+        println!();
+    }
+
+    let summary = match run_batch(dir, &options) {
+        Ok(summary) => summary,
+        Err(e) => {
+            if json_output {
+                println!(
+                    "{}",
+                    serde_json::json!({ "error": format!("Failed to read tests directory: {e}") })
+                );
+            } else {
+                eprintln!("Failed to read tests directory: {e}");
+            }
+            std::process::exit(1);
+        }
+    };
+    if let Some(path) = matches.get_one::<PathBuf>("write-budgets") {
+        let budgets = serde_json::to_string_pretty(&summary.budgets()).expect("budgets serialize");
+        if let Err(e) = std::fs::write(path, budgets + "\n") {
+            eprintln!("Failed to write budgets file {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+    if json_output {
+        println!("{}", summary.to_json());
+    } else {
+        summary.print_table();
+        if summary.cached > 0 {
+            println!("({} passing results reused from the cache)", summary.cached);
+        }
+    }
+    std::process::exit(if summary.all_passed() { 0 } else { 1 });
+}
+
+/// `nekov info`: print the version and, with `--capabilities`, what this build supports
+fn run_info_command(matches: &ArgMatches) -> ! {
+    let capabilities = nekov::capabilities();
+    if matches.get_flag("json") {
+        println!("{}", capabilities.to_json());
+    } else {
+        println!("nekov {}", env!("CARGO_PKG_VERSION"));
+        print!(
+            "\nDefault machine:\n{}",
+            nekov::machine::MachineDescription::of(&nekov::RunOptions::default())
+        );
+        if matches.get_flag("capabilities") {
+            println!("isa:            {}", capabilities.isa);
+            println!("extensions:     {}", capabilities.extensions.join(", "));
+            println!("instructions:   {}", capabilities.instructions.len());
+            println!("misa:           0x{:08x}", capabilities.misa);
+            println!("xlen:           {}", capabilities.xlen);
+            println!("max memory:     {} bytes", capabilities.max_memory);
+            println!("peripherals:    {}", capabilities.peripherals.join(", "));
+            println!("loader formats: {}", capabilities.loader_formats.join(", "));
+            println!("trace formats:  {}", capabilities.trace_formats.join(", "));
+        }
+    }
+    std::process::exit(0);
+}
+
 fn main() {
     let matches = Command::new("nekov")
         .version("0.1.0")
         .author("wipeseals")
         .about("A RISC-V emulator in Rust, probably written by a cat. 🐈")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("test")
+                .about("Run every riscv-tests binary (extensionless file) in a directory")
+                .arg(
+                    Arg::new("dir")
+                        .help("Directory containing the test binaries")
+                        .required(true)
+                        .value_name("DIR")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the results as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .long("filter")
+                        .help("Only run tests whose name matches the glob (* and ?)")
+                        .value_name("GLOB"),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .long("jobs")
+                        .short('j')
+                        .help("Number of tests to run in parallel")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .short('l')
+                        .help("Maximum number of instructions per test")
+                        .value_name("NUM")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("budgets")
+                        .long("budgets")
+                        .help(
+                            "JSON file of per-test instruction budgets; tests over budget are SLOW",
+                        )
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("budget-tolerance")
+                        .long("budget-tolerance")
+                        .help("Percentage a test may exceed its budget by")
+                        .value_name("PERCENT")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("0")
+                        .requires("budgets"),
+                )
+                .arg(
+                    Arg::new("write-budgets")
+                        .long("write-budgets")
+                        .help("Record the instruction counts of passing tests as a budgets file")
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf)),
+                )
+                .arg(
+                    Arg::new("rerun-failed")
+                        .long("rerun-failed")
+                        .help("Only run tests that failed or changed since the cached run")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("no-cache")
+                        .long("no-cache")
+                        .help("Neither read nor write the results cache (<DIR>.nekov-results.json)")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("rerun-failed"),
+                )
+                .arg(uninit_arg()),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Show version information")
+                .arg(
+                    Arg::new("capabilities")
+                        .long("capabilities")
+                        .help("List supported extensions, peripherals, formats and limits")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the capabilities as JSON")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
         .arg(
             Arg::new("binary")
                 .help("ELF binary file to emulate")
@@ -21,12 +256,232 @@ fn main() {
                 .value_name("NUM")
                 .value_parser(clap::value_parser!(usize)),
         )
+        .arg(
+            Arg::new("max-time")
+                .long("max-time")
+                .help("Stop the run after this many seconds of wall-clock time")
+                .value_name("SECONDS")
+                .value_parser(parse_seconds),
+        )
+        .arg(
+            Arg::new("time-check-interval")
+                .long("time-check-interval")
+                .help("Retired instructions between clock checks for --max-time (default: 4096)")
+                .value_name("NUM")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .requires("max-time"),
+        )
         .arg(
             Arg::new("riscv-tests")
                 .long("riscv-tests")
                 .help("Enable riscv-tests pass/fail detection")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dtb")
+                .long("dtb")
+                .help("Device tree blob to load; a0/a1 are set to mhartid/DTB address")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .conflicts_with("generate-dtb"),
+        )
+        .arg(
+            Arg::new("generate-dtb")
+                .long("generate-dtb")
+                .help("Generate a minimal device tree describing memory, UART and CLINT")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dtb-addr")
+                .long("dtb-addr")
+                .help("Load address for the device tree (default: just below the top of RAM)")
+                .value_name("ADDR")
+                .value_parser(parse_address),
+        )
+        .arg(
+            Arg::new("mem-base")
+                .long("mem-base")
+                .help("RAM base address (default: 0x80000000)")
+                .value_name("ADDR")
+                .value_parser(parse_address),
+        )
+        .arg(
+            Arg::new("entry")
+                .long("entry")
+                .help("Start at ADDR instead of the ELF entry point")
+                .value_name("ADDR")
+                .value_parser(parse_address),
+        )
+        .arg(
+            Arg::new("allow-overlap")
+                .long("allow-overlap")
+                .help("Load ELF segments that overlap each other or a peripheral instead of failing")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("heap-poison")
+                .long("heap-poison")
+                .help(
+                    "Serve brk with redzones after each heap extension; touching one stops the run",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("uart-model")
+                .long("uart-model")
+                .help("Attach a console UART at 0x10000000: word TX register or byte-wide 16550")
+                .value_name("MODEL")
+                .value_parser(["simple", "ns16550"]),
+        )
+        .arg(
+            Arg::new("map")
+                .long("map")
+                .help("Print the loaded sections and peripherals as a memory map before running")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("progress-interval")
+                .long("progress-interval")
+                .help("Update a status line on stderr every N retired instructions (0 = off; default 10000000 when stderr is a terminal)")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32)),
+        )
+        .arg(
+            Arg::new("machine")
+                .long("machine")
+                .help("Build the machine described by a TOML machine config (overrides the machine options)")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("clint")
+                .long("clint")
+                .help("Attach a CLINT timer; WFI sleeps until its next interrupt")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(uninit_arg())
+        .arg(
+            Arg::new("idle-skip")
+                .long("idle-skip")
+                .help("Fast-forward polling loops to the next timer interrupt, at most MAX cycles at once")
+                .value_name("MAX")
+                .value_parser(clap::value_parser!(u64))
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("1000000")
+                .requires("clint"),
+        )
+        .arg(
+            Arg::new("crash-report")
+                .long("crash-report")
+                .help("On a fatal error, write a crash report bundle into this directory")
+                .value_name("DIR")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print a machine-readable JSON run report instead of human-readable output")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("count-only")
+                .long("count-only")
+                .help("Run on the fast path and print only the instruction count and MIPS")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "json",
+                    "trace-format",
+                    "compare-trace",
+                    "watch-reg",
+                    "watch-mem",
+                    "break",
+                ]),
+        )
+        .arg(
+            Arg::new("trace-format")
+                .long("trace-format")
+                .help("Emit a per-instruction trace in the given format")
+                .value_name("FORMAT")
+                .value_parser(clap::builder::PossibleValuesParser::new(
+                    TraceFormat::ALL.map(|format| match format {
+                        TraceFormat::Jsonl => clap::builder::PossibleValue::new("jsonl").alias("json"),
+                        _ => clap::builder::PossibleValue::new(format.name()),
+                    }),
+                )),
+        )
+        .arg(
+            Arg::new("compare-trace")
+                .long("compare-trace")
+                .help("Stop at the first divergence from a reference trace")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("compare-format")
+                .long("compare-format")
+                .alias("format")
+                .help("Format of the reference trace")
+                .value_name("FORMAT")
+                .value_parser(["jsonl", "spike"])
+                .default_value("jsonl")
+                .requires("compare-trace"),
+        )
+        .arg(
+            Arg::new("compare-skip")
+                .long("compare-skip")
+                .help("Ignore register differences: 'csr' or a PC address (repeatable)")
+                .value_name("RULE")
+                .value_parser(clap::value_parser!(SkipRule))
+                .action(clap::ArgAction::Append)
+                .requires("compare-trace"),
+        )
+        .arg(
+            Arg::new("watch-reg")
+                .long("watch-reg")
+                .help("Stop when a register reaches a value (REG=VALUE) or changes (REG)")
+                .value_name("REG[=VALUE]")
+                .value_parser(WatchSpec::parse_register)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("watch-mem")
+                .long("watch-mem")
+                .help("Stop when a memory word reaches a value (ADDR=VALUE) or changes (ADDR); ADDR may be SYMBOL[+OFFSET]")
+                .value_name("ADDR[=VALUE]")
+                .value_parser(WatchSpec::parse_memory)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("break")
+                .long("break")
+                .help("Stop when the PC reaches ADDR (hex, decimal, SYMBOL or SYMBOL+OFFSET)")
+                .value_name("ADDR")
+                .value_parser(AddrExpr::parse)
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("detect-smc")
+                .long("detect-smc")
+                .help("Log stores into executed code; fetching it without FENCE.I warns or stops")
+                .value_name("ACTION")
+                .value_parser(["warn", "error"])
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("warn"),
+        )
+        .arg(
+            Arg::new("protect-text")
+                .long("protect-text")
+                .help("Write-protect executable segments; stores into code stop the run")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-color")
+                .long("no-color")
+                .help("Never color the verbose output (also disabled by NO_COLOR or a non-TTY stdout)")
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -36,46 +491,182 @@ fn main() {
         )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("test", test_matches)) => run_test_command(test_matches),
+        Some(("info", info_matches)) => run_info_command(info_matches),
+        _ => {}
+    }
+
     let binary_path = matches.get_one::<PathBuf>("binary").unwrap();
     let instruction_limit = matches.get_one::<usize>("limit").copied();
     let riscv_tests_mode = matches.get_flag("riscv-tests");
     let verbosity = matches.get_count("verbose");
+    let json_output = matches.get_flag("json");
+    let count_only = matches.get_flag("count-only");
+    // Everything but the final JSON report or count summary is suppressed
+    let quiet = json_output || count_only;
+    let dtb = if let Some(path) = matches.get_one::<PathBuf>("dtb") {
+        DtbSource::File(path.clone())
+    } else if matches.get_flag("generate-dtb") {
+        DtbSource::Generated
+    } else {
+        DtbSource::None
+    };
+    let mut options = RunOptions {
+        instruction_limit,
+        verbosity,
+        dtb,
+        dtb_address: matches.get_one::<u32>("dtb-addr").copied(),
+        quiet,
+        trace_format: matches
+            .get_one::<String>("trace-format")
+            .and_then(|name| TraceFormat::from_name(name)),
+        protect_text: matches.get_flag("protect-text"),
+        detect_smc: matches
+            .get_one::<String>("detect-smc")
+            .map(|action| match action.as_str() {
+                "error" => SmcAction::Fault,
+                _ => SmcAction::Warn,
+            }),
+        compare: matches
+            .get_one::<PathBuf>("compare-trace")
+            .map(|path| CompareOptions {
+                path: path.clone(),
+                format: match matches
+                    .get_one::<String>("compare-format")
+                    .map(String::as_str)
+                {
+                    Some("spike") => ReferenceFormat::Spike,
+                    _ => ReferenceFormat::Jsonl,
+                },
+                skip: matches
+                    .get_many::<SkipRule>("compare-skip")
+                    .map(|rules| rules.copied().collect())
+                    .unwrap_or_default(),
+            }),
+        watches: ["watch-reg", "watch-mem"]
+            .into_iter()
+            .filter_map(|id| matches.get_many::<WatchSpec>(id))
+            .flatten()
+            .cloned()
+            .collect(),
+        breakpoints: matches
+            .get_many::<AddrExpr>("break")
+            .map(|breakpoints| breakpoints.cloned().collect())
+            .unwrap_or_default(),
+        max_time: matches.get_one::<std::time::Duration>("max-time").copied(),
+        time_check_interval: matches.get_one::<u32>("time-check-interval").copied(),
+        memory_base: matches.get_one::<u32>("mem-base").copied(),
+        entry: matches.get_one::<u32>("entry").copied(),
+        allow_overlap: matches.get_flag("allow-overlap"),
+        print_map: matches.get_flag("map"),
+        heap_poison: matches.get_flag("heap-poison"),
+        // The status line is for humans; logs and pipes only get it when asked for
+        progress_interval: matches
+            .get_one::<u32>("progress-interval")
+            .copied()
+            .or_else(|| std::io::stderr().is_terminal().then_some(10_000_000))
+            .filter(|&interval| interval > 0),
+        uart: matches
+            .get_one::<String>("uart-model")
+            .map(|model| match model.as_str() {
+                "ns16550" => UartLayout::Ns16550Compatible,
+                _ => UartLayout::Simple,
+            }),
+        crash_report: matches.get_one::<PathBuf>("crash-report").cloned(),
+        clint: matches.get_flag("clint"),
+        fast: count_only,
+        idle_skip: matches.get_one::<u64>("idle-skip").copied(),
+        uninit_policy: uninit_policy(&matches),
+        color: !matches.get_flag("no-color")
+            && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            && std::io::stdout().is_terminal(),
+    };
+    if let Some(path) = matches.get_one::<PathBuf>("machine") {
+        let configured = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                nekov::machine::MachineDescription::from_toml(&text)
+                    .and_then(|machine| machine.configure(&mut options))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = configured {
+            eprintln!("Failed to load machine config {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
 
-    println!("Nekov RISC-V Emulator");
-    println!("Loading ELF binary: {}", binary_path.display());
+    if !quiet {
+        println!("Nekov RISC-V Emulator");
+        println!("Loading ELF binary: {}", binary_path.display());
 
-    if let Some(limit) = instruction_limit {
-        println!("Instruction limit: {limit}");
-    }
+        if let Some(limit) = instruction_limit {
+            println!("Instruction limit: {limit}");
+        }
 
-    if riscv_tests_mode {
-        println!("RISC-V tests mode enabled");
-    }
+        if riscv_tests_mode {
+            println!("RISC-V tests mode enabled");
+        }
 
-    if verbosity > 0 {
-        println!("Verbose output level: {verbosity}");
+        if verbosity > 0 {
+            println!("Verbose output level: {verbosity}");
+        }
     }
 
-    match nekov::run_emulator_with_limit_and_verbosity(binary_path, instruction_limit, verbosity) {
-        Ok((cpu, _memory)) => {
+    match nekov::run_emulator_with_options(binary_path, &options) {
+        Ok(report) => {
+            if json_output {
+                println!("{}", report.to_json());
+            }
+            if count_only {
+                let seconds = report.elapsed.as_secs_f64();
+                println!(
+                    "executed {} instructions in {:.3} ms ({:.2} MIPS)",
+                    report.instructions_executed,
+                    seconds * 1e3,
+                    report.instructions_executed as f64 / seconds / 1e6
+                );
+            }
+            if let Some(ExitReason::TraceDivergence(_)) = report.exit_reason {
+                std::process::exit(1);
+            }
             if riscv_tests_mode {
                 // Check for riscv-tests pass/fail patterns
-                let test_result = check_riscv_test_result(&cpu, verbosity);
+                let verbosity = if quiet { 0 } else { verbosity };
+                let test_result = check_riscv_test_result(&report.cpu, verbosity);
                 match test_result {
                     TestResult::Pass => {
-                        println!("RISC-V test PASSED");
+                        if !quiet {
+                            println!("RISC-V test PASSED");
+                        }
                         std::process::exit(0);
                     }
                     TestResult::Fail(code) => {
-                        println!("RISC-V test FAILED (code: 0x{code:x})");
+                        if !quiet {
+                            println!("RISC-V test FAILED (test #{}, code: 0x{code:x})", code >> 1);
+                        }
+                        std::process::exit(1);
+                    }
+                    TestResult::Error(message) => {
+                        if !quiet {
+                            println!("RISC-V test FAILED: {message}");
+                        }
                         std::process::exit(1);
                     }
-                    TestResult::Unknown => {
-                        println!("RISC-V test result: UNKNOWN");
+                    TestResult::Unknown | TestResult::Slow { .. } => {
+                        if !quiet {
+                            println!("RISC-V test result: UNKNOWN");
+                        }
                         std::process::exit(2);
                     }
                 }
-            } else {
+            } else if let Some(code) = report.guest_exit_code().filter(|&code| code != 0) {
+                if !quiet {
+                    println!("Guest exited with code {code}");
+                }
+                // Statuses wrap modulo 256; keep a failing guest from looking successful
+                std::process::exit(code.clamp(1, 255) as i32);
+            } else if !quiet {
                 println!("Emulation completed successfully");
             }
         }
@@ -85,64 +676,3 @@ fn main() {
         }
     }
 }
-
-#[derive(Debug, PartialEq)]
-enum TestResult {
-    Pass,
-    Fail(u32),
-    Unknown,
-}
-
-/// Check RISC-V test result based on register state
-/// Based on RVTEST_PASS/RVTEST_FAIL macros:
-/// - PASS: TESTNUM=1 (gp=1), a7=93, a0=0, ecall
-/// - FAIL: TESTNUM!=1 (gp!=1), a7=93, a0=TESTNUM, ecall
-fn check_riscv_test_result(cpu: &nekov::cpu::Cpu, verbosity: u8) -> TestResult {
-    // Check if we ended with an ecall (system call)
-    // Register assignments for RISC-V:
-    // gp = x3 (TESTNUM)
-    // a0 = x10 (first argument)
-    // a7 = x17 (system call number)
-
-    let testnum = cpu.read_register(3); // gp register
-    let a0 = cpu.read_register(10); // a0 register
-    let a7 = cpu.read_register(17); // a7 register
-
-    if verbosity >= 1 {
-        println!("=== RISC-V Test Result Analysis ===");
-        println!("Register state at termination:");
-        println!("  gp (x3)  = 0x{testnum:08x} (TESTNUM)");
-        println!("  a0 (x10) = 0x{a0:08x} (exit code)");
-        println!("  a7 (x17) = 0x{a7:08x} (syscall number)");
-        println!();
-        println!("Test result determination:");
-    }
-
-    // Check if this looks like a test termination (a7=93 is exit syscall)
-    if a7 == 93 {
-        if verbosity >= 1 {
-            println!("  ✓ System call number is 93 (exit syscall)");
-        }
-        if testnum == 1 && a0 == 0 {
-            if verbosity >= 1 {
-                println!("  ✓ TESTNUM=1 and exit code=0 → PASS");
-            }
-            TestResult::Pass
-        } else if testnum != 1 {
-            if verbosity >= 1 {
-                println!("  ✗ TESTNUM={testnum} (≠1) and exit code={a0} → FAIL");
-            }
-            TestResult::Fail(a0)
-        } else {
-            if verbosity >= 1 {
-                println!("  ? TESTNUM=1 but exit code={a0} (≠0) → UNKNOWN");
-            }
-            TestResult::Unknown
-        }
-    } else {
-        if verbosity >= 1 {
-            println!("  ? System call number is {a7} (≠93) → UNKNOWN");
-        }
-        TestResult::Unknown
-    }
-}
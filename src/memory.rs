@@ -1,97 +1,841 @@
 /// Memory management for the RISC-V emulator
 use crate::EmulatorError;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Default RAM base address (typical RISC-V layout)
+pub const DEFAULT_BASE_ADDRESS: u32 = 0x8000_0000;
+
+/// Default RAM size advertised to the guest (128 MiB)
+pub const DEFAULT_MEMORY_SIZE: u32 = 128 * 1024 * 1024;
+
+/// Granularity of the page accounting behind `Memory::stats` and `Memory::set_page_limit`
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Host memory used by the guest image, as reported by `Memory::stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MemoryStats {
+    /// Distinct `PAGE_SIZE` pages holding at least one written byte
+    pub pages_allocated: usize,
+    /// Bytes written or loaded by the guest or host
+    pub bytes_written: usize,
+    /// Host bytes taken by the stored word cells
+    pub resident_bytes: usize,
+}
+
+/// Kind of guest memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Instruction fetch
+    Fetch,
+    /// Data load
+    Load,
+    /// Data store
+    Store,
+}
+
+/// Why a memory access was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessFailReason {
+    /// Outside RAM while `Memory::set_bounds_check` is on
+    OutOfRange,
+    /// Store into a write-protected range
+    Permission,
+    /// Store that would allocate a page beyond `Memory::set_page_limit`
+    LimitExceeded,
+}
+
+impl std::fmt::Display for AccessKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AccessKind::Fetch => "fetch",
+            AccessKind::Load => "load",
+            AccessKind::Store => "store",
+        })
+    }
+}
+
+impl std::fmt::Display for AccessFailReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AccessFailReason::OutOfRange => "outside RAM",
+            AccessFailReason::Permission => "write-protected",
+            AccessFailReason::LimitExceeded => "guest memory limit exceeded",
+        })
+    }
+}
+
+/// Four bytes at a word-aligned address and which of them have been written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct WordCell {
+    bytes: [u8; 4],
+    /// Bit `i` is set once byte `i` has been written
+    written: u8,
+}
+
+impl WordCell {
+    const ALL_WRITTEN: u8 = 0b1111;
+
+    fn byte(&self, index: usize) -> Option<u8> {
+        (self.written & (1 << index) != 0).then_some(self.bytes[index])
+    }
+}
+
+/// Value returned by reads of bytes that were never written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UninitPolicy {
+    /// Read as 0xFF (erased flash)
+    #[default]
+    #[serde(rename = "ff")]
+    ReturnFF,
+    /// Read as 0x00
+    #[serde(rename = "zero")]
+    ReturnZero,
+    /// Fail the read with `EmulatorError::UninitializedRead`, which guest loads
+    /// take as a load access fault (side-effect-free inspection such as
+    /// `peek_word` still sees 0xFF)
+    #[serde(rename = "trap")]
+    Trap,
+}
+
+impl UninitPolicy {
+    fn fill(self) -> u8 {
+        match self {
+            UninitPolicy::ReturnFF | UninitPolicy::Trap => 0xFF,
+            UninitPolicy::ReturnZero => 0x00,
+        }
+    }
+}
+
+/// Uninitialized bytes read by the same guest instruction, from `Memory::uninit_report`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UninitRead {
+    /// First byte of the contiguous range
+    pub start: u32,
+    /// Number of bytes in the range
+    pub len: u32,
+    /// PC of the instruction that first read the range (`None` for host accesses)
+    pub pc: Option<u32>,
+    /// Number of uninitialized byte reads in the range
+    pub count: u32,
+}
+
+impl std::fmt::Display for UninitRead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let end = self.start.wrapping_add(self.len - 1);
+        write!(f, "0x{:08x}..=0x{end:08x} ", self.start)?;
+        match self.pc {
+            Some(pc) => write!(f, "first read at pc 0x{pc:08x}")?,
+            None => write!(f, "first read by the host")?,
+        }
+        write!(f, " ({} reads)", self.count)
+    }
+}
+
+/// A guest access that touched a poisoned heap byte (see `Memory::poison`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoisonedAccess {
+    /// First poisoned byte the access touched
+    pub address: u32,
+    /// Access size in bytes
+    pub size: u32,
+    pub write: bool,
+    /// PC of the accessing instruction (`None` for host accesses)
+    pub pc: Option<u32>,
+    /// Nearest heap allocation as `(start, len)`, if any were recorded
+    pub allocation: Option<(u32, u32)>,
+}
+
+impl std::fmt::Display for PoisonedAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.write { "write" } else { "read" };
+        write!(
+            f,
+            "heap redzone {kind} of {} byte(s) at 0x{:08x}",
+            self.size, self.address
+        )?;
+        if let Some(pc) = self.pc {
+            write!(f, " by pc 0x{pc:08x}")?;
+        }
+        if let Some((start, len)) = self.allocation {
+            let end = start.wrapping_add(len);
+            let place = if self.address >= end {
+                format!("{} bytes after", self.address - end)
+            } else if self.address < start {
+                format!("{} bytes before", start - self.address)
+            } else {
+                "inside".to_string()
+            };
+            write!(
+                f,
+                ", {place} allocation 0x{start:08x}..0x{end:08x} ({len} bytes)"
+            )?;
+        }
+        Ok(())
+    }
+}
 
 /// Memory implementation using dictionary-based storage
+///
+/// Written bytes are grouped into aligned words so that aligned word
+/// accesses take a single lookup.
 #[derive(Debug, Clone)]
 pub struct Memory {
-    /// Memory data - only stores written bytes
-    data: HashMap<u32, u8>,
+    /// Memory data keyed by word-aligned address - only stores written words
+    data: HashMap<u32, WordCell>,
     /// Base address
     base_address: u32,
+    /// RAM size advertised to the guest (storage itself is sparse)
+    size: u32,
+    /// Write-protected address ranges as half-open `[start, end)` intervals
+    protected: Vec<(u64, u64)>,
+    /// What reads of unwritten bytes return
+    uninit_policy: UninitPolicy,
+    /// PC of the instruction currently executing, for attributing reads
+    access_pc: Cell<Option<u32>>,
+    /// Unwritten byte addresses that were read: (first reader's PC, read count)
+    uninit_reads: RefCell<BTreeMap<u32, (Option<u32>, u32)>>,
+    /// Shadow map of poisoned (redzone) bytes, keyed like `data`; bit `i` marks byte `i`
+    poisoned: HashMap<u32, u8>,
+    /// Heap allocations as start -> length, for describing poisoned accesses
+    allocations: BTreeMap<u32, u32>,
+    /// Most pages writes may allocate; `None` is unlimited
+    page_limit: Option<usize>,
+    /// Allocated page numbers, tracked only while a page limit is set
+    pages: HashSet<u32>,
+    /// Whether accesses outside `[base_address, base_address + size)` fail
+    bounds_check: bool,
 }
 
 impl Memory {
-    /// Create a new memory instance
+    /// Create a new memory instance at `DEFAULT_BASE_ADDRESS`
     pub fn new() -> Self {
+        Self::new_with_base(DEFAULT_BASE_ADDRESS)
+    }
+
+    /// Create a new memory instance whose RAM starts at `base`
+    pub fn new_with_base(base: u32) -> Self {
         Self {
             data: HashMap::new(),
-            base_address: 0x8000_0000, // Typical RISC-V RAM base address
+            base_address: base,
+            size: DEFAULT_MEMORY_SIZE,
+            protected: Vec::new(),
+            uninit_policy: UninitPolicy::default(),
+            access_pc: Cell::new(None),
+            uninit_reads: RefCell::new(BTreeMap::new()),
+            poisoned: HashMap::new(),
+            allocations: BTreeMap::new(),
+            page_limit: None,
+            pages: HashSet::new(),
+            bounds_check: false,
         }
     }
 
-    /// Create a new memory instance (kept for API compatibility)
-    pub fn with_size(_size: usize) -> Self {
-        Self::new()
+    /// Create a new memory instance advertising the given RAM size
+    ///
+    /// Sizes beyond the 32-bit address space saturate at `u32::MAX` bytes.
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            size: u32::try_from(size).unwrap_or(u32::MAX),
+            ..Self::new()
+        }
     }
 
     /// Read a byte from memory
     pub fn read_byte(&self, address: u32) -> Result<u8, EmulatorError> {
-        match self.data.get(&address) {
-            Some(&value) => Ok(value),
+        self.check_access(address, 1, false)?;
+        match self.stored_byte(address) {
+            Some(value) => Ok(value),
+            None if self.uninit_policy == UninitPolicy::Trap => {
+                self.record_uninit_read(address);
+                Err(EmulatorError::UninitializedRead(address))
+            }
             None => {
-                eprintln!("Warning: Reading from uninitialized memory address 0x{address:08x}, returning 0xFF");
-                Ok(0xFF)
+                let fill = self.uninit_policy.fill();
+                eprintln!("Warning: Reading from uninitialized memory address 0x{address:08x}, returning 0x{fill:02X}");
+                self.record_uninit_read(address);
+                Ok(fill)
             }
         }
     }
 
+    /// Choose what reads of unwritten bytes return
+    pub fn set_uninit_policy(&mut self, policy: UninitPolicy) {
+        self.uninit_policy = policy;
+    }
+
+    /// What reads of unwritten bytes return
+    pub fn uninit_policy(&self) -> UninitPolicy {
+        self.uninit_policy
+    }
+
+    /// Attribute subsequent reads to the instruction at `pc` (`None` for host accesses)
+    pub fn set_access_pc(&self, pc: Option<u32>) {
+        self.access_pc.set(pc);
+    }
+
+    fn record_uninit_read(&self, address: u32) {
+        let pc = self.access_pc.get();
+        let mut reads = self.uninit_reads.borrow_mut();
+        reads.entry(address).or_insert((pc, 0)).1 += 1;
+    }
+
+    /// Ranges of unwritten memory that were read, most frequently read first
+    ///
+    /// Contiguous bytes first read by the same instruction form one range.
+    pub fn uninit_report(&self) -> Vec<UninitRead> {
+        let mut ranges: Vec<UninitRead> = Vec::new();
+        for (&address, &(pc, count)) in self.uninit_reads.borrow().iter() {
+            match ranges.last_mut() {
+                Some(range) if range.pc == pc && range.start.wrapping_add(range.len) == address => {
+                    range.len += 1;
+                    range.count += count;
+                }
+                _ => ranges.push(UninitRead {
+                    start: address,
+                    len: 1,
+                    pc,
+                    count,
+                }),
+            }
+        }
+        ranges.sort_by(|a, b| b.count.cmp(&a.count).then(a.start.cmp(&b.start)));
+        ranges
+    }
+
     /// Write a byte to memory
     pub fn write_byte(&mut self, address: u32, value: u8) -> Result<(), EmulatorError> {
-        self.data.insert(address, value);
+        self.check_access(address, 1, true)?;
+        if self.is_write_protected(address) {
+            return Err(protection_fault(address));
+        }
+        self.claim_pages(address, 1)?;
+        let cell = self.data.entry(address & !3).or_default();
+        let index = (address & 3) as usize;
+        cell.bytes[index] = value;
+        cell.written |= 1 << index;
         Ok(())
     }
 
-    /// Read a 16-bit halfword from memory (little-endian, supports misaligned access)
+    /// The byte at `address`, if it has been written
+    fn stored_byte(&self, address: u32) -> Option<u8> {
+        self.data
+            .get(&(address & !3))
+            .and_then(|cell| cell.byte((address & 3) as usize))
+    }
+
+    /// Whether the byte at `address` was ever written or loaded
+    pub fn is_written(&self, address: u32) -> bool {
+        self.stored_byte(address).is_some()
+    }
+
+    /// Single-lookup read of the word at `address`, if it is aligned and fully written
+    ///
+    /// This is the fast path behind `read_u32_le`, `peek_word` and so
+    /// instruction fetch; everything else takes the byte-assembled path. It
+    /// has no side effects and does not check poisoning.
+    pub fn read_word_aligned(&self, address: u32) -> Option<u32> {
+        if !address.is_multiple_of(4) {
+            return None;
+        }
+        match self.data.get(&address) {
+            Some(cell) if cell.written == WordCell::ALL_WRITTEN => {
+                Some(u32::from_le_bytes(cell.bytes))
+            }
+            _ => None,
+        }
+    }
+
+    /// Read a 16-bit halfword from memory; same as `read_u16_le`
     pub fn read_halfword(&self, address: u32) -> Result<u16, EmulatorError> {
+        self.read_u16_le(address)
+    }
+
+    /// Read a 32-bit word from memory; same as `read_u32_le`
+    pub fn read_word(&self, address: u32) -> Result<u32, EmulatorError> {
+        self.read_u32_le(address)
+    }
+
+    /// Write a 16-bit halfword to memory; same as `write_u16_le`
+    pub fn write_halfword(&mut self, address: u32, value: u16) -> Result<(), EmulatorError> {
+        self.write_u16_le(address, value)
+    }
+
+    /// Write a 32-bit word to memory; same as `write_u32_le`
+    pub fn write_word(&mut self, address: u32, value: u32) -> Result<(), EmulatorError> {
+        self.write_u32_le(address, value)
+    }
+
+    /// Read a little-endian 16-bit value (supports misaligned access)
+    pub fn read_u16_le(&self, address: u32) -> Result<u16, EmulatorError> {
+        self.check_access(address, 2, false)?;
         let byte0 = self.read_byte(address)?;
-        let byte1 = self.read_byte(address + 1)?;
+        let byte1 = self.read_byte(address.wrapping_add(1))?;
 
         let value = u16::from_le_bytes([byte0, byte1]);
         Ok(value)
     }
 
-    /// Read a 32-bit word from memory (little-endian, supports misaligned access)
-    pub fn read_word(&self, address: u32) -> Result<u32, EmulatorError> {
+    /// Read a little-endian 32-bit value (supports misaligned access)
+    pub fn read_u32_le(&self, address: u32) -> Result<u32, EmulatorError> {
+        self.check_access(address, 4, false)?;
+        if let Some(value) = self.read_word_aligned(address) {
+            return Ok(value);
+        }
         let byte0 = self.read_byte(address)?;
-        let byte1 = self.read_byte(address + 1)?;
-        let byte2 = self.read_byte(address + 2)?;
-        let byte3 = self.read_byte(address + 3)?;
+        let byte1 = self.read_byte(address.wrapping_add(1))?;
+        let byte2 = self.read_byte(address.wrapping_add(2))?;
+        let byte3 = self.read_byte(address.wrapping_add(3))?;
 
         let value = u32::from_le_bytes([byte0, byte1, byte2, byte3]);
         Ok(value)
     }
 
-    /// Write a 16-bit halfword to memory (little-endian, supports misaligned access)
-    pub fn write_halfword(&mut self, address: u32, value: u16) -> Result<(), EmulatorError> {
+    /// Read a little-endian signed 32-bit value
+    pub fn read_i32_le(&self, address: u32) -> Result<i32, EmulatorError> {
+        Ok(self.read_u32_le(address)? as i32)
+    }
+
+    /// Read a big-endian (network order) 16-bit value, e.g. from a guest packet buffer
+    pub fn read_u16_be(&self, address: u32) -> Result<u16, EmulatorError> {
+        Ok(self.read_u16_le(address)?.swap_bytes())
+    }
+
+    /// Read a big-endian (network order) 32-bit value, e.g. from a guest packet buffer
+    pub fn read_u32_be(&self, address: u32) -> Result<u32, EmulatorError> {
+        Ok(self.read_u32_le(address)?.swap_bytes())
+    }
+
+    /// Read a word without side effects; uninitialized bytes follow the policy without a warning
+    pub fn peek_word(&self, address: u32) -> u32 {
+        if let Some(value) = self.read_word_aligned(address) {
+            return value;
+        }
+        let bytes: [u8; 4] = std::array::from_fn(|i| {
+            self.stored_byte(address.wrapping_add(i as u32))
+                .unwrap_or(self.uninit_policy.fill())
+        });
+        u32::from_le_bytes(bytes)
+    }
+
+    /// Standard CRC-32 (IEEE 802.3, as used by zlib) of `len` bytes at `start`
+    ///
+    /// Like `peek_word`, this has no side effects: unwritten bytes contribute the
+    /// uninitialized fill value without a warning.
+    pub fn crc32(&self, start: u32, len: usize) -> u32 {
+        let fill = self.uninit_policy.fill();
+        let mut crc = !0u32;
+        for i in 0..len {
+            let byte = self
+                .stored_byte(start.wrapping_add(i as u32))
+                .unwrap_or(fill);
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        !crc
+    }
+
+    /// Write a little-endian 16-bit value (supports misaligned access)
+    pub fn write_u16_le(&mut self, address: u32, value: u16) -> Result<(), EmulatorError> {
+        self.check_access(address, 2, true)?;
+        if self.is_range_write_protected(address, 2) {
+            return Err(protection_fault(address));
+        }
+        self.claim_pages(address, 2)?;
         let bytes = value.to_le_bytes();
         self.write_byte(address, bytes[0])?;
-        self.write_byte(address + 1, bytes[1])?;
+        self.write_byte(address.wrapping_add(1), bytes[1])?;
         Ok(())
     }
 
-    /// Write a 32-bit word to memory (little-endian, supports misaligned access)
-    pub fn write_word(&mut self, address: u32, value: u32) -> Result<(), EmulatorError> {
+    /// Write a little-endian 32-bit value (supports misaligned access)
+    pub fn write_u32_le(&mut self, address: u32, value: u32) -> Result<(), EmulatorError> {
+        self.check_access(address, 4, true)?;
+        if self.is_range_write_protected(address, 4) {
+            return Err(protection_fault(address));
+        }
+        self.claim_pages(address, 4)?;
+        // Fast path: aligned, so the word fills exactly one cell
+        if address.is_multiple_of(4) {
+            self.data.insert(
+                address,
+                WordCell {
+                    bytes: value.to_le_bytes(),
+                    written: WordCell::ALL_WRITTEN,
+                },
+            );
+            return Ok(());
+        }
         let bytes = value.to_le_bytes();
         self.write_byte(address, bytes[0])?;
-        self.write_byte(address + 1, bytes[1])?;
-        self.write_byte(address + 2, bytes[2])?;
-        self.write_byte(address + 3, bytes[3])?;
+        self.write_byte(address.wrapping_add(1), bytes[1])?;
+        self.write_byte(address.wrapping_add(2), bytes[2])?;
+        self.write_byte(address.wrapping_add(3), bytes[3])?;
         Ok(())
     }
 
     /// Load data into memory at specified address
+    ///
+    /// Checked like `fill_bytes`: the data is written entirely or not at all.
     pub fn load_data(&mut self, address: u32, data: &[u8]) -> Result<(), EmulatorError> {
+        let len = data.len() as u32;
+        self.check_access(address, len, true)?;
+        if self.is_range_write_protected(address, len) {
+            return Err(protection_fault(address));
+        }
+        self.claim_pages(address, len)?;
         for (i, &byte) in data.iter().enumerate() {
-            self.write_byte(address + i as u32, byte)?;
+            self.write_byte(address.wrapping_add(i as u32), byte)?;
+        }
+        Ok(())
+    }
+
+    /// Write `word` to `count` consecutive words starting at `start`
+    ///
+    /// Poisoning and write protection are checked once for the whole block,
+    /// which fails without writing anything. A block longer than the 32-bit
+    /// address space is refused as out of range.
+    pub fn fill_words(&mut self, start: u32, word: u32, count: usize) -> Result<(), EmulatorError> {
+        let len = u32::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(4))
+            .ok_or_else(|| block_out_of_range(start))?;
+        self.check_access(start, len, true)?;
+        if self.is_range_write_protected(start, len) {
+            return Err(protection_fault(start));
+        }
+        self.claim_pages(start, len)?;
+        if !start.is_multiple_of(4) {
+            self.fill_bytes_unchecked(start, &word.to_le_bytes(), len);
+            return Ok(());
+        }
+        let cell = WordCell {
+            bytes: word.to_le_bytes(),
+            written: WordCell::ALL_WRITTEN,
+        };
+        self.data.reserve(count);
+        for offset in (0..len).step_by(4) {
+            self.data.insert(start.wrapping_add(offset), cell);
+        }
+        Ok(())
+    }
+
+    /// Write `value` to `len` consecutive bytes starting at `start`
+    ///
+    /// Checked like `fill_words`: the block is written entirely or not at all.
+    pub fn fill_bytes(&mut self, start: u32, value: u8, len: usize) -> Result<(), EmulatorError> {
+        let len = u32::try_from(len).map_err(|_| block_out_of_range(start))?;
+        self.check_access(start, len, true)?;
+        if self.is_range_write_protected(start, len) {
+            return Err(protection_fault(start));
+        }
+        self.claim_pages(start, len)?;
+        self.fill_bytes_unchecked(start, &[value], len);
+        Ok(())
+    }
+
+    /// Write `len` bytes at `start` repeating `pattern`, without poison or protection checks
+    fn fill_bytes_unchecked(&mut self, start: u32, pattern: &[u8], len: u32) {
+        for (offset, &byte) in (0..len).zip(pattern.iter().cycle()) {
+            let address = start.wrapping_add(offset);
+            let cell = self.data.entry(address & !3).or_default();
+            cell.bytes[(address & 3) as usize] = byte;
+            cell.written |= 1 << (address & 3);
+        }
+    }
+
+    /// Initialized bytes as runs of contiguous `(address, bytes)` in address order
+    pub fn contents(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut cells: Vec<(&u32, &WordCell)> = self.data.iter().collect();
+        cells.sort_unstable_by_key(|&(&address, _)| address);
+        let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (&word_address, cell) in cells {
+            for index in 0..4 {
+                let Some(byte) = cell.byte(index) else {
+                    continue;
+                };
+                let address = word_address + index as u32;
+                match runs.last_mut() {
+                    Some((start, bytes)) if start.wrapping_add(bytes.len() as u32) == address => {
+                        bytes.push(byte)
+                    }
+                    _ => runs.push((address, vec![byte])),
+                }
+            }
+        }
+        runs
+    }
+
+    /// Replace all contents with `runs`, ignoring write protection
+    pub fn restore_contents(&mut self, runs: &[(u32, Vec<u8>)]) {
+        self.data.clear();
+        for (start, bytes) in runs {
+            for (i, &byte) in bytes.iter().enumerate() {
+                let address = start.wrapping_add(i as u32);
+                let cell = self.data.entry(address & !3).or_default();
+                cell.bytes[(address & 3) as usize] = byte;
+                cell.written |= 1 << (address & 3);
+            }
+        }
+        self.recount_pages();
+    }
+
+    /// Limit writes to `limit` allocated pages (`None` removes the limit)
+    ///
+    /// A write that would allocate a page beyond the limit fails with a
+    /// `MemoryAccessError` for `AccessFailReason::LimitExceeded` and writes
+    /// nothing. Pages already allocated
+    /// count towards the limit but are never freed by it.
+    pub fn set_page_limit(&mut self, limit: Option<usize>) {
+        self.page_limit = limit;
+        self.recount_pages();
+    }
+
+    /// The page limit set by `set_page_limit`
+    pub fn page_limit(&self) -> Option<usize> {
+        self.page_limit
+    }
+
+    /// Current page, byte and host memory usage
+    pub fn stats(&self) -> MemoryStats {
+        let pages: HashSet<u32> = self
+            .data
+            .keys()
+            .map(|&address| address / PAGE_SIZE)
+            .collect();
+        MemoryStats {
+            pages_allocated: pages.len(),
+            bytes_written: self
+                .data
+                .values()
+                .map(|cell| cell.written.count_ones() as usize)
+                .sum(),
+            resident_bytes: self.data.len() * std::mem::size_of::<(u32, WordCell)>(),
+        }
+    }
+
+    /// Rebuild the tracked page set from the stored cells
+    fn recount_pages(&mut self) {
+        self.pages.clear();
+        if self.page_limit.is_some() {
+            self.pages = self
+                .data
+                .keys()
+                .map(|&address| address / PAGE_SIZE)
+                .collect();
+        }
+    }
+
+    /// Account for the pages of a `len`-byte write at `address` against the page limit
+    fn claim_pages(&mut self, address: u32, len: u32) -> Result<(), EmulatorError> {
+        let Some(limit) = self.page_limit else {
+            return Ok(());
+        };
+        if len == 0 {
+            return Ok(());
         }
+        // Computed in 64 bits so a write wrapping past 0xffffffff spans a
+        // few pages at each end of the address space, not all of them
+        let first = u64::from(address) / u64::from(PAGE_SIZE);
+        let last = (u64::from(address) + u64::from(len) - 1) / u64::from(PAGE_SIZE);
+        let pages = (1u64 << 32) / u64::from(PAGE_SIZE);
+        let new: Vec<u32> = (first..=last)
+            .map(|page| (page % pages) as u32)
+            .filter(|page| !self.pages.contains(page))
+            .collect();
+        if self.pages.len() + new.len() > limit {
+            return Err(EmulatorError::MemoryAccessError {
+                addr: address,
+                kind: AccessKind::Store,
+                reason: AccessFailReason::LimitExceeded,
+            });
+        }
+        self.pages.extend(new);
         Ok(())
     }
 
+    /// Poison `len` bytes at `address`: guest accesses to them fail with `PoisonedAccess`
+    ///
+    /// Recorded allocations overlapping the range are cut back to end before it.
+    pub fn poison(&mut self, address: u32, len: u32) {
+        for offset in 0..len {
+            let byte = address.wrapping_add(offset);
+            *self.poisoned.entry(byte & !3).or_default() |= 1 << (byte & 3);
+        }
+        let end = address.wrapping_add(len);
+        let overlapping: Vec<u32> = self
+            .allocations
+            .range(..end)
+            .filter(|&(&start, &size)| start.wrapping_add(size) > address)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in overlapping {
+            if start < address {
+                self.allocations.insert(start, address - start);
+            } else {
+                self.allocations.remove(&start);
+            }
+        }
+    }
+
+    /// Unpoison `len` bytes at `address` and record them as a heap allocation
+    pub fn add_allocation(&mut self, address: u32, len: u32) {
+        for offset in 0..len {
+            let byte = address.wrapping_add(offset);
+            if let Some(mask) = self.poisoned.get_mut(&(byte & !3)) {
+                *mask &= !(1 << (byte & 3));
+                if *mask == 0 {
+                    self.poisoned.remove(&(byte & !3));
+                }
+            }
+        }
+        if len > 0 {
+            self.allocations.insert(address, len);
+        }
+    }
+
+    /// Drop all poisoned bytes and recorded allocations
+    pub fn clear_poison(&mut self) {
+        self.poisoned.clear();
+        self.allocations.clear();
+    }
+
+    /// Make accesses outside RAM fail with `AccessFailReason::OutOfRange`
+    ///
+    /// Off by default: storage is sparse, so any address can hold data.
+    pub fn set_bounds_check(&mut self, enabled: bool) {
+        self.bounds_check = enabled;
+    }
+
+    /// Fail if the access leaves RAM (with bounds checking on) or touches a poisoned byte
+    fn check_access(&self, address: u32, size: u32, write: bool) -> Result<(), EmulatorError> {
+        if self.bounds_check {
+            let offset = address.wrapping_sub(self.base_address) as u64;
+            if offset + size as u64 > self.size as u64 {
+                return Err(EmulatorError::MemoryAccessError {
+                    addr: address,
+                    kind: if write {
+                        AccessKind::Store
+                    } else {
+                        AccessKind::Load
+                    },
+                    reason: AccessFailReason::OutOfRange,
+                });
+            }
+        }
+        if self.poisoned.is_empty() {
+            return Ok(());
+        }
+        let Some(hit) = (0..size).map(|i| address.wrapping_add(i)).find(|&byte| {
+            self.poisoned
+                .get(&(byte & !3))
+                .is_some_and(|mask| mask & (1 << (byte & 3)) != 0)
+        }) else {
+            return Ok(());
+        };
+        Err(EmulatorError::PoisonedAccess(PoisonedAccess {
+            address: hit,
+            size,
+            write,
+            pc: self.access_pc.get(),
+            allocation: self.nearest_allocation(hit),
+        }))
+    }
+
+    /// The recorded allocation closest to `address`
+    fn nearest_allocation(&self, address: u32) -> Option<(u32, u32)> {
+        let before = self.allocations.range(..=address).next_back();
+        let after = self.allocations.range(address..).next();
+        let distance = |(&start, &len): (&u32, &u32)| {
+            if address < start {
+                start - address
+            } else {
+                address.saturating_sub(start.wrapping_add(len))
+            }
+        };
+        [before, after]
+            .into_iter()
+            .flatten()
+            .min_by_key(|&entry| distance(entry))
+            .map(|(&start, &len)| (start, len))
+    }
+
+    /// Protect or unprotect `len` bytes starting at `start` against writes
+    ///
+    /// Writes into a protected range fail with `MemoryAccessError` (`Permission`).
+    pub fn write_protect_range(&mut self, start: u32, len: u32, protected: bool) {
+        let start = start as u64;
+        let end = start + len as u64;
+        if start == end {
+            return;
+        }
+        // Carve the range out of every existing interval, then re-add it if protecting
+        let mut ranges = Vec::with_capacity(self.protected.len() + 1);
+        for &(lo, hi) in &self.protected {
+            if hi <= start || lo >= end {
+                ranges.push((lo, hi));
+                continue;
+            }
+            if lo < start {
+                ranges.push((lo, start));
+            }
+            if hi > end {
+                ranges.push((end, hi));
+            }
+        }
+        if protected {
+            ranges.push((start, end));
+        }
+        self.protected = ranges;
+    }
+
+    /// Check whether a byte address lies in a write-protected range
+    pub fn is_write_protected(&self, address: u32) -> bool {
+        let address = address as u64;
+        self.protected
+            .iter()
+            .any(|&(lo, hi)| lo <= address && address < hi)
+    }
+
+    /// Check whether any byte of `[address, address + len)` is write-protected
+    fn is_range_write_protected(&self, address: u32, len: u32) -> bool {
+        let start = address as u64;
+        let end = start + len as u64;
+        self.protected
+            .iter()
+            .any(|&(lo, hi)| lo < end && start < hi)
+    }
+
     /// Get the base address of memory
     pub fn base_address(&self) -> u32 {
         self.base_address
     }
+
+    /// Get the RAM size advertised to the guest
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+/// Error for a block store that cannot fit in the address space
+fn block_out_of_range(addr: u32) -> EmulatorError {
+    EmulatorError::MemoryAccessError {
+        addr,
+        kind: AccessKind::Store,
+        reason: AccessFailReason::OutOfRange,
+    }
+}
+
+/// Error for a store refused by write protection
+fn protection_fault(addr: u32) -> EmulatorError {
+    EmulatorError::MemoryAccessError {
+        addr,
+        kind: AccessKind::Store,
+        reason: AccessFailReason::Permission,
+    }
 }
 
 impl Default for Memory {
@@ -112,6 +856,30 @@ mod tests {
         assert!(memory.data.is_empty());
     }
 
+    #[test]
+    fn test_with_size_saturates() {
+        assert_eq!(Memory::with_size(0x1000).size(), 0x1000);
+        assert_eq!(Memory::with_size(u32::MAX as usize).size(), u32::MAX);
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(Memory::with_size(1 << 32).size(), u32::MAX);
+    }
+
+    #[test]
+    fn test_memory_new_with_base() {
+        let mut memory = Memory::new_with_base(0x2000_0000);
+        assert_eq!(memory.base_address(), 0x2000_0000);
+        memory.write_word(0x2000_0000, 0x12345678).unwrap();
+        memory.write_byte(0x2000_0004, 0xAB).unwrap();
+        assert_eq!(memory.read_word(0x2000_0000).unwrap(), 0x12345678);
+        assert_eq!(memory.read_byte(0x2000_0004).unwrap(), 0xAB);
+
+        let mut zero_based = Memory::new_with_base(0);
+        zero_based.write_word(0, 0xCAFEF00D).unwrap();
+        assert_eq!(zero_based.read_word(0).unwrap(), 0xCAFEF00D);
+
+        assert_eq!(Memory::new().base_address(), DEFAULT_BASE_ADDRESS);
+    }
+
     #[test]
     fn test_memory_byte_access() {
         let mut memory = Memory::new();
@@ -174,6 +942,49 @@ mod tests {
         assert_eq!(memory.read_byte(base + 3).unwrap(), 0x04);
     }
 
+    #[test]
+    fn test_fill_words_and_bytes() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let addi = 0x0010_8093; // addi x1, x1, 1
+        memory.fill_words(base, addi, 100).unwrap();
+        for i in [0, 1, 37, 50, 99] {
+            assert_eq!(memory.read_word(base + i * 4).unwrap(), addi);
+        }
+        assert!(!memory.is_written(base + 400));
+
+        // Unaligned fills keep the little-endian byte order of the word
+        memory.fill_words(base + 0x1002, 0x1122_3344, 2).unwrap();
+        assert_eq!(memory.read_word(base + 0x1004).unwrap(), 0x3344_1122);
+        assert!(!memory.is_written(base + 0x1001));
+
+        memory.fill_bytes(base + 0x2001, 0xAA, 6).unwrap();
+        assert_eq!(memory.read_word(base + 0x2004).unwrap(), 0xFFAA_AAAA);
+        assert!(!memory.is_written(base + 0x2000));
+        assert!(!memory.is_written(base + 0x2007));
+
+        // A protected word anywhere in the block rejects the whole fill
+        memory.write_protect_range(base + 0x3010, 4, true);
+        assert!(memory.fill_words(base + 0x3000, 0, 8).is_err());
+        assert!(!memory.is_written(base + 0x3000));
+        assert!(memory.fill_bytes(base + 0x3013, 0, 1).is_err());
+
+        // Lengths that do not fit in 32 bits are refused, not truncated
+        let too_long = EmulatorError::MemoryAccessError {
+            addr: base,
+            kind: AccessKind::Store,
+            reason: AccessFailReason::OutOfRange,
+        }
+        .to_string();
+        let error = memory.fill_words(base, 0x13, 0x4000_0001).unwrap_err();
+        assert_eq!(error.to_string(), too_long);
+        if let Ok(len) = usize::try_from(1u64 << 32) {
+            let error = memory.fill_bytes(base, 0, len).unwrap_err();
+            assert_eq!(error.to_string(), too_long);
+        }
+        assert!(!memory.is_written(base + 0x5000));
+    }
+
     #[test]
     fn test_memory_uninitialized_read() {
         let memory = Memory::new();
@@ -185,6 +996,78 @@ mod tests {
         assert_eq!(memory.read_byte(0x1000).unwrap(), 0xFF); // Any address should work now
     }
 
+    #[test]
+    fn test_crc32_of_region() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.load_data(base, b"123456789").unwrap();
+        // The CRC-32 check value
+        assert_eq!(memory.crc32(base, 9), 0xCBF4_3926);
+        assert_eq!(memory.crc32(base, 0), 0);
+
+        memory.write_byte(base + 4, b'x').unwrap();
+        assert_ne!(memory.crc32(base, 9), 0xCBF4_3926);
+
+        // Gaps read as the uninitialized fill value
+        memory.set_uninit_policy(UninitPolicy::ReturnZero);
+        assert_eq!(memory.crc32(base + 0x100, 4), 0x2144_DF1C);
+        assert!(memory.uninit_report().is_empty());
+    }
+
+    #[test]
+    fn test_uninit_policy_and_report() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.set_uninit_policy(UninitPolicy::ReturnZero);
+        memory.write_byte(base + 1, 0xAB).unwrap();
+        assert_eq!(memory.read_word(base).unwrap(), 0x0000AB00);
+        assert_eq!(memory.peek_word(base), 0x0000AB00);
+        memory.read_byte(base).unwrap();
+
+        // Host reads (no PC) of bytes 0, 2 and 3; byte 0 twice
+        let report = memory.uninit_report();
+        assert_eq!(
+            report,
+            vec![
+                UninitRead {
+                    start: base,
+                    len: 1,
+                    pc: None,
+                    count: 2
+                },
+                UninitRead {
+                    start: base + 2,
+                    len: 2,
+                    pc: None,
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(
+            report[1].to_string(),
+            "0x80000002..=0x80000003 first read by the host (2 reads)"
+        );
+    }
+
+    #[test]
+    fn test_uninit_trap_policy_fails_reads() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.set_uninit_policy(UninitPolicy::Trap);
+        memory.write_byte(base, 0x12).unwrap();
+        assert_eq!(memory.read_byte(base).unwrap(), 0x12);
+        // The first unwritten byte of a partially written word is reported
+        assert!(matches!(
+            memory.read_word(base),
+            Err(EmulatorError::UninitializedRead(address)) if address == base + 1
+        ));
+        assert_eq!(memory.peek_word(base), 0xFFFF_FF12);
+        assert_eq!(
+            EmulatorError::UninitializedRead(base + 1).to_string(),
+            "uninitialized read at 0x80000001"
+        );
+    }
+
     #[test]
     fn test_memory_read_uninitialized_word() {
         let memory = Memory::new();
@@ -194,6 +1077,170 @@ mod tests {
         assert_eq!(memory.read_word(base).unwrap(), 0xFFFFFFFF);
     }
 
+    #[test]
+    fn test_write_protect_range() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.write_word(base + 0x10, 0x1234_5678).unwrap();
+
+        memory.write_protect_range(base + 0x10, 0x10, true);
+        assert!(matches!(
+            memory.write_word(base + 0x10, 0xDEAD_BEEF),
+            Err(EmulatorError::MemoryAccessError {
+                kind: AccessKind::Store,
+                reason: AccessFailReason::Permission,
+                ..
+            })
+        ));
+        // A store straddling the start of the range is rejected too
+        assert!(memory.write_halfword(base + 0x0F, 0xAAAA).is_err());
+        assert_eq!(memory.read_word(base + 0x10).unwrap(), 0x1234_5678);
+        // Neighbouring memory stays writable
+        memory.write_word(base + 0x20, 0xCAFE_F00D).unwrap();
+
+        // Unprotecting part of the range leaves the rest protected
+        memory.write_protect_range(base + 0x10, 4, false);
+        memory.write_word(base + 0x10, 0xDEAD_BEEF).unwrap();
+        assert_eq!(memory.read_word(base + 0x10).unwrap(), 0xDEAD_BEEF);
+        assert!(memory.is_write_protected(base + 0x14));
+
+        memory.write_protect_range(base + 0x10, 0x10, false);
+        assert!(!memory.is_write_protected(base + 0x14));
+    }
+
+    #[test]
+    fn test_aligned_word_fast_path_matches_byte_path() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let count = 64 * 1024;
+        for i in 0..count {
+            memory
+                .write_word(base + i * 4, i.wrapping_mul(0x9E37_79B9))
+                .unwrap();
+        }
+        let mut sum = 0u32;
+        for i in 0..count {
+            let value = memory.read_word(base + i * 4).unwrap();
+            assert_eq!(value, i.wrapping_mul(0x9E37_79B9));
+            sum = sum.wrapping_add(value);
+        }
+        let expected =
+            (0..count).fold(0u32, |acc, i| acc.wrapping_add(i.wrapping_mul(0x9E37_79B9)));
+        assert_eq!(sum, expected);
+        assert_eq!(memory.data.len(), count as usize);
+
+        // Straddling two words and partially written words take the byte path
+        memory.write_word(base + 2, 0xAABB_CCDD).unwrap();
+        assert_eq!(memory.read_word(base + 2).unwrap(), 0xAABB_CCDD);
+        assert_eq!(memory.read_halfword(base).unwrap(), 0);
+        let top = base + count * 4;
+        memory.write_byte(top + 1, 0x12).unwrap();
+        assert_eq!(memory.read_word(top).unwrap(), 0xFFFF_12FF);
+        assert_eq!(memory.peek_word(top), 0xFFFF_12FF);
+
+        // The aligned store still honours write protection
+        memory.write_protect_range(base + 7, 1, true);
+        assert!(memory.write_word(base + 4, 0).is_err());
+        assert_eq!(memory.contents()[0].0, base);
+    }
+
+    #[test]
+    fn test_read_word_aligned_boundaries() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let last = base + memory.size() - 4;
+
+        // Aligned at the start and at the end of RAM
+        memory.write_word(base, 0x1111_2222).unwrap();
+        memory.write_word(last, 0x3333_4444).unwrap();
+        assert_eq!(memory.read_word_aligned(base), Some(0x1111_2222));
+        assert_eq!(memory.read_word_aligned(last), Some(0x3333_4444));
+
+        // Misaligned, crossing from one word into the next: byte path only
+        memory.write_word(base + 6, 0xAABB_CCDD).unwrap();
+        assert_eq!(memory.read_word_aligned(base + 6), None);
+        assert_eq!(memory.read_word(base + 6).unwrap(), 0xAABB_CCDD);
+        assert_eq!(memory.read_word_aligned(base + 8), None); // half written
+        assert_eq!(memory.read_word(base + 8).unwrap(), 0xFFFF_AABB);
+
+        // Byte writes into a word are visible to the next aligned read
+        memory.write_byte(last + 3, 0x55).unwrap();
+        assert_eq!(memory.read_word_aligned(last), Some(0x5533_4444));
+        memory.write_halfword(base + 4, 0x6666).unwrap();
+        assert_eq!(memory.read_word_aligned(base + 4), Some(0xCCDD_6666));
+        assert_eq!(memory.peek_word(base + 4), 0xCCDD_6666);
+    }
+
+    #[test]
+    fn test_page_limit_and_stats() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        memory.set_page_limit(Some(3));
+
+        // Sparse writes: one page each, however far apart
+        memory.write_word(base, 0x1234_5678).unwrap();
+        memory.write_byte(base + 0x10_0000, 0xAB).unwrap();
+        memory.write_halfword(base + 0x10_0002, 0xCDEF).unwrap();
+        // A misaligned word straddling into a third page
+        memory
+            .write_word(base + PAGE_SIZE - 2, 0xDEAD_BEEF)
+            .unwrap();
+        assert_eq!(
+            memory.stats(),
+            MemoryStats {
+                pages_allocated: 3,
+                bytes_written: 11,
+                resident_bytes: 4 * std::mem::size_of::<(u32, WordCell)>(),
+            }
+        );
+
+        // Allocated pages can still be written
+        memory.fill_bytes(base + 8, 0x5A, 64).unwrap();
+        memory.write_word(base + 0x10_0004, 7).unwrap();
+
+        // One page too many fails without writing anything
+        assert!(matches!(
+            memory.write_byte(base + 2 * PAGE_SIZE, 1),
+            Err(EmulatorError::MemoryAccessError {
+                addr,
+                kind: AccessKind::Store,
+                reason: AccessFailReason::LimitExceeded,
+            }) if addr == base + 2 * PAGE_SIZE
+        ));
+        assert!(memory.fill_words(base + PAGE_SIZE + 4, 0, 1024).is_err());
+        assert!(!memory.is_written(base + 2 * PAGE_SIZE - 4));
+        assert_eq!(memory.stats().pages_allocated, 3);
+        assert_eq!(
+            memory
+                .write_byte(base + 2 * PAGE_SIZE, 1)
+                .unwrap_err()
+                .to_string(),
+            "Memory access error: store at 0x80002000 guest memory limit exceeded"
+        );
+
+        memory.set_page_limit(None);
+        memory.write_byte(base + 2 * PAGE_SIZE, 1).unwrap();
+        assert_eq!(memory.stats().pages_allocated, 4);
+
+        // Refused stores claim no pages
+        let mut memory = Memory::new();
+        memory.set_page_limit(Some(1));
+        memory.write_protect_range(base + PAGE_SIZE, 8, true);
+        assert!(memory.write_word(base + PAGE_SIZE, 1).is_err());
+        assert!(memory.write_halfword(base + PAGE_SIZE + 2, 1).is_err());
+        assert!(memory.load_data(base + PAGE_SIZE, &[1; 8]).is_err());
+        assert_eq!(memory.stats().pages_allocated, 0);
+        memory.write_word(base, 1).unwrap();
+
+        // A store wrapping past the top of the address space spans two pages
+        let mut memory = Memory::new();
+        memory.set_page_limit(Some(2));
+        memory.write_word(0xFFFF_FFFE, 0x4433_2211).unwrap();
+        assert_eq!(memory.stats().pages_allocated, 2);
+        assert_eq!(memory.read_byte(0x0000_0001).unwrap(), 0x44);
+        assert!(memory.write_byte(PAGE_SIZE, 1).is_err());
+    }
+
     #[test]
     fn test_little_endian_encoding() {
         let mut memory = Memory::new();
@@ -206,4 +1253,49 @@ mod tests {
         assert_eq!(memory.read_byte(base + 2).unwrap(), 0x34);
         assert_eq!(memory.read_byte(base + 3).unwrap(), 0x12); // MSB
     }
+
+    #[test]
+    fn test_typed_accessors_byte_layout() {
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let bytes = |memory: &Memory, address: u32, len: u32| -> Vec<u8> {
+            (0..len)
+                .map(|i| memory.read_byte(address + i).unwrap())
+                .collect()
+        };
+
+        // Writers, aligned and misaligned
+        memory.write_u32_le(base, 0x1122_3344).unwrap();
+        assert_eq!(bytes(&memory, base, 4), [0x44, 0x33, 0x22, 0x11]);
+        memory.write_u32_le(base + 5, 0xA1B2_C3D4).unwrap();
+        assert_eq!(bytes(&memory, base + 5, 4), [0xD4, 0xC3, 0xB2, 0xA1]);
+        memory.write_u16_le(base + 11, 0xBEEF).unwrap();
+        assert_eq!(bytes(&memory, base + 11, 2), [0xEF, 0xBE]);
+
+        // Readers over a fixed byte pattern
+        memory
+            .load_data(base + 16, &[0x01, 0x02, 0x03, 0x84, 0x05])
+            .unwrap();
+        assert_eq!(memory.read_u16_le(base + 16).unwrap(), 0x0201);
+        assert_eq!(memory.read_u16_be(base + 16).unwrap(), 0x0102);
+        assert_eq!(memory.read_u32_le(base + 16).unwrap(), 0x8403_0201);
+        assert_eq!(memory.read_u32_be(base + 16).unwrap(), 0x0102_0384);
+        assert_eq!(memory.read_u32_le(base + 17).unwrap(), 0x0584_0302);
+        assert_eq!(memory.read_u32_be(base + 17).unwrap(), 0x0203_8405);
+        assert_eq!(
+            memory.read_i32_le(base + 16).unwrap(),
+            0x8403_0201u32 as i32
+        );
+        assert!(memory.read_i32_le(base + 16).unwrap() < 0);
+
+        // The historical names are the little-endian accessors
+        assert_eq!(
+            memory.read_word(base + 17).unwrap(),
+            memory.read_u32_le(base + 17).unwrap()
+        );
+        assert_eq!(
+            memory.read_halfword(base + 16).unwrap(),
+            memory.read_u16_le(base + 16).unwrap()
+        );
+    }
 }
@@ -0,0 +1,67 @@
+//! Summary of what is mapped where: loaded ELF sections and peripherals
+
+use std::fmt;
+
+/// What a memory map entry describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MapKind {
+    /// An allocated section of the loaded ELF
+    Section,
+    /// Bytes a PT_LOAD segment of the loaded ELF writes
+    Segment,
+    /// A memory-mapped peripheral
+    Peripheral,
+}
+
+/// One mapped address range
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MapEntry {
+    pub kind: MapKind,
+    /// Section or peripheral name
+    pub name: String,
+    pub start: u32,
+    pub size: u32,
+    /// Access permissions as `rwx` with `-` for missing ones
+    pub perms: String,
+}
+
+impl MapEntry {
+    /// End of the range (exclusive)
+    pub fn end(&self) -> u32 {
+        self.start.wrapping_add(self.size)
+    }
+}
+
+impl fmt::Display for MapEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            MapKind::Section => "section",
+            MapKind::Segment => "segment",
+            MapKind::Peripheral => "peripheral",
+        };
+        write!(
+            f,
+            "0x{:08x}-0x{:08x} {:>10} {} {kind:<10} {}",
+            self.start,
+            self.end(),
+            self.size,
+            self.perms,
+            self.name
+        )
+    }
+}
+
+/// Render entries as a table sorted by start address
+pub fn format_table(entries: &[MapEntry]) -> String {
+    let mut sorted: Vec<&MapEntry> = entries.iter().collect();
+    sorted.sort_by_key(|entry| entry.start);
+    let mut table = format!(
+        "{:<21} {:>10} {:<4} {:<10} {}\n",
+        "range", "size", "perm", "kind", "name"
+    );
+    for entry in sorted {
+        table.push_str(&format!("{entry}\n"));
+    }
+    table
+}
@@ -1,5 +1,9 @@
 /// Peripheral abstraction for hardware interfacing
-use crate::Result;
+use crate::{
+    memory_map::{MapEntry, MapKind},
+    EmulatorError, Result,
+};
+use std::collections::VecDeque;
 
 /// Trait for peripheral devices that can be attached to the CPU
 pub trait Peripheral {
@@ -9,6 +13,16 @@ pub trait Peripheral {
     /// Write to the peripheral at the given address offset
     fn write(&mut self, offset: u32, value: u32) -> Result<()>;
 
+    /// Read a byte-wide register (unsupported unless the device has byte registers)
+    fn read_byte(&mut self, _offset: u32) -> Result<u8> {
+        Err(EmulatorError::UnsupportedInstruction)
+    }
+
+    /// Write a byte-wide register (unsupported unless the device has byte registers)
+    fn write_byte(&mut self, _offset: u32, _value: u8) -> Result<()> {
+        Err(EmulatorError::UnsupportedInstruction)
+    }
+
     /// Get the base address of this peripheral
     fn base_address(&self) -> u32;
 
@@ -20,43 +34,221 @@ pub trait Peripheral {
         let base = self.base_address();
         address >= base && address < base + self.size()
     }
+
+    /// Interrupt lines this peripheral currently raises, as `mip` bits (e.g. `MIP_MTIP`)
+    fn pending_interrupts(&self) -> u32 {
+        0
+    }
+
+    /// Advance time-based state by `cycles` (called after every retired instruction)
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Ticks until this peripheral raises interrupt lines on its own, and those lines
+    ///
+    /// Lets an idle hart (WFI) skip ahead to the next interrupt instead of
+    /// stepping through the wait. `None` if nothing is scheduled.
+    fn next_interrupt(&self) -> Option<(u64, u32)> {
+        None
+    }
+
+    /// Short name shown in the memory map
+    fn name(&self) -> &str {
+        "peripheral"
+    }
 }
 
+/// Shared buffer receiving bytes written by a capturing console
+pub type ConsoleBuffer = std::rc::Rc<std::cell::RefCell<Vec<u8>>>;
+
+/// Send console output to `capture`, or to stdout / the web console without one
+///
+/// With `tee` captured output also goes to stdout / the web console.
+pub(crate) fn write_console(capture: Option<&ConsoleBuffer>, tee: bool, bytes: &[u8]) {
+    if let Some(buffer) = capture {
+        buffer.borrow_mut().extend_from_slice(bytes);
+        if !tee {
+            return;
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::console::log_1(&String::from_utf8_lossy(bytes).into_owned().into());
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::io::{self, Write};
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(bytes);
+        let _ = stdout.flush();
+    }
+}
+
+/// Register map of the console UART
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum UartLayout {
+    /// Word writes to offset 0 transmit a character; reads return 0
+    #[default]
+    #[serde(rename = "simple")]
+    Simple,
+    /// Byte-wide NS16550 registers as on QEMU's virt machine (THR/RBR, IER, IIR, LCR, LSR, ...)
+    #[serde(rename = "ns16550")]
+    Ns16550Compatible,
+}
+
+// NS16550 register offsets
+const UART_RBR_THR: u32 = 0;
+const UART_IER: u32 = 1;
+const UART_IIR_FCR: u32 = 2;
+const UART_LCR: u32 = 3;
+const UART_MCR: u32 = 4;
+const UART_LSR: u32 = 5;
+const UART_SCR: u32 = 7;
+
+/// LCR divisor latch access bit: offsets 0 and 1 address the baud divisor
+const LCR_DLAB: u8 = 0x80;
+/// LSR data ready
+const LSR_DR: u8 = 0x01;
+/// LSR transmit holding register empty and transmitter empty
+const LSR_THRE_TEMT: u8 = 0x60;
+/// IIR with no interrupt pending
+const IIR_NO_INTERRUPT: u8 = 0x01;
+
 /// Console peripheral for standard I/O
 pub struct ConsolePeriph {
     base_addr: u32,
+    /// When set, output goes to this buffer instead of stdout / the web console
+    capture: Option<ConsoleBuffer>,
+    /// Also print captured output
+    tee: bool,
+    layout: UartLayout,
+    /// Bytes waiting in RBR (16550 layout)
+    input: VecDeque<u8>,
+    /// IER, LCR, MCR and SCR as last written (16550 layout)
+    ier: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
 }
 
 impl ConsolePeriph {
+    /// `Peripheral::name` of every instance
+    pub const NAME: &'static str = "console";
+
     pub fn new(base_addr: u32) -> Self {
-        Self { base_addr }
+        Self {
+            base_addr,
+            capture: None,
+            tee: false,
+            layout: UartLayout::Simple,
+            input: VecDeque::new(),
+            ier: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+        }
+    }
+
+    /// Create a console that appends its output to `buffer`
+    pub fn with_capture(base_addr: u32, buffer: ConsoleBuffer) -> Self {
+        Self {
+            capture: Some(buffer),
+            ..Self::new(base_addr)
+        }
+    }
+
+    /// Builder: print captured output to stdout / the web console as well
+    pub fn with_tee(mut self, tee: bool) -> Self {
+        self.tee = tee;
+        self
+    }
+
+    /// Builder: select the register map
+    pub fn with_layout(mut self, layout: UartLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Register map in use
+    pub fn layout(&self) -> UartLayout {
+        self.layout
+    }
+
+    /// Queue bytes for the guest to read from RBR (16550 layout)
+    pub fn queue_input(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    fn read_16550(&mut self, offset: u32) -> u8 {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            UART_RBR_THR if !dlab => self.input.pop_front().unwrap_or(0),
+            UART_IER if !dlab => self.ier,
+            UART_IIR_FCR => IIR_NO_INTERRUPT,
+            UART_LCR => self.lcr,
+            UART_MCR => self.mcr,
+            UART_LSR => {
+                let ready = if self.input.is_empty() { 0 } else { LSR_DR };
+                LSR_THRE_TEMT | ready
+            }
+            UART_SCR => self.scr,
+            // Divisor latch, MSR and unused offsets
+            _ => 0,
+        }
+    }
+
+    fn write_16550(&mut self, offset: u32, value: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            UART_RBR_THR if !dlab => write_console(self.capture.as_ref(), self.tee, &[value]),
+            UART_IER if !dlab => self.ier = value & 0x0F,
+            UART_LCR => self.lcr = value,
+            UART_MCR => self.mcr = value,
+            UART_SCR => self.scr = value,
+            // Divisor latch and FCR have no effect on an emulated line
+            _ => {}
+        }
     }
 }
 
 impl Peripheral for ConsolePeriph {
-    fn read(&mut self, _offset: u32) -> Result<u32> {
-        // Console is write-only for now
-        Ok(0)
+    fn read(&mut self, offset: u32) -> Result<u32> {
+        match self.layout {
+            // Console is write-only for now
+            UartLayout::Simple => Ok(0),
+            UartLayout::Ns16550Compatible => Ok(self.read_16550(offset) as u32),
+        }
     }
 
     fn write(&mut self, offset: u32, value: u32) -> Result<()> {
-        match offset {
-            0 => {
+        match (self.layout, offset) {
+            (UartLayout::Simple, 0) => {
                 // TX register - output character
                 let ch = (value & 0xFF) as u8;
-                #[cfg(target_arch = "wasm32")]
-                {
-                    web_sys::console::log_1(&format!("{}", ch as char).into());
-                }
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    print!("{}", ch as char);
-                    use std::io::{self, Write};
-                    io::stdout().flush().unwrap();
-                }
+                write_console(self.capture.as_ref(), self.tee, &[ch]);
+                Ok(())
+            }
+            (UartLayout::Simple, _) => Ok(()),
+            (UartLayout::Ns16550Compatible, _) => {
+                self.write_16550(offset, value as u8);
+                Ok(())
+            }
+        }
+    }
+
+    fn read_byte(&mut self, offset: u32) -> Result<u8> {
+        match self.layout {
+            UartLayout::Simple => Err(EmulatorError::UnsupportedInstruction),
+            UartLayout::Ns16550Compatible => Ok(self.read_16550(offset)),
+        }
+    }
+
+    fn write_byte(&mut self, offset: u32, value: u8) -> Result<()> {
+        match self.layout {
+            UartLayout::Simple => Err(EmulatorError::UnsupportedInstruction),
+            UartLayout::Ns16550Compatible => {
+                self.write_16550(offset, value);
                 Ok(())
             }
-            _ => Ok(()),
         }
     }
 
@@ -67,17 +259,396 @@ impl Peripheral for ConsolePeriph {
     fn size(&self) -> u32 {
         0x1000 // 4KB address space
     }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
 }
 
+/// Character cells and dirty flag shared by the clones of a `TextDisplay`
+#[derive(Debug)]
+struct TextCells {
+    cells: Vec<u8>,
+    dirty: bool,
+}
+
+/// Memory-mapped text screen: `width`×`height` character cells, row-major from the base address
+///
+/// Cells are byte and word writable and start as spaces. Clones share the
+/// cells, so the host keeps one clone to `render` while another is attached
+/// to the `PeripheralManager`.
+#[derive(Debug, Clone)]
+pub struct TextDisplay {
+    base_addr: u32,
+    width: u32,
+    height: u32,
+    cells: std::rc::Rc<std::cell::RefCell<TextCells>>,
+}
+
+impl TextDisplay {
+    /// `Peripheral::name` of every instance
+    pub const NAME: &'static str = "text-display";
+
+    /// Most cells a display may have
+    pub const MAX_CELLS: u32 = 1 << 20;
+
+    /// A blank display; fails with `InvalidMachine` beyond `MAX_CELLS` cells
+    pub fn new(base_addr: u32, width: u32, height: u32) -> Result<Self> {
+        let cells = width
+            .checked_mul(height)
+            .filter(|&cells| cells <= Self::MAX_CELLS)
+            .ok_or_else(|| {
+                EmulatorError::InvalidMachine(format!(
+                    "text display of {width}x{height} cells exceeds {} cells",
+                    Self::MAX_CELLS
+                ))
+            })?;
+        Ok(Self {
+            base_addr,
+            width,
+            height,
+            cells: std::rc::Rc::new(std::cell::RefCell::new(TextCells {
+                cells: vec![b' '; cells as usize],
+                dirty: true,
+            })),
+        })
+    }
+
+    /// Reset every cell to a space
+    pub fn clear(&self) {
+        let mut cells = self.cells.borrow_mut();
+        cells.cells.fill(b' ');
+        cells.dirty = true;
+    }
+
+    /// Width in characters
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Height in characters
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether a cell was written since the last `render`
+    pub fn is_dirty(&self) -> bool {
+        self.cells.borrow().dirty
+    }
+
+    /// The grid as text, one line per row; non-printable cells render as spaces
+    ///
+    /// Clears the dirty flag.
+    pub fn render(&self) -> String {
+        let mut cells = self.cells.borrow_mut();
+        cells.dirty = false;
+        let mut text = String::with_capacity(((self.width + 1) * self.height) as usize);
+        for row in cells.cells.chunks(self.width.max(1) as usize) {
+            text.extend(
+                row.iter()
+                    .map(|&c| if c.is_ascii_graphic() { c as char } else { ' ' }),
+            );
+            text.push('\n');
+        }
+        text
+    }
+
+    fn cell(&self, offset: u32) -> u8 {
+        self.cells
+            .borrow()
+            .cells
+            .get(offset as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Write a cell; offsets past the last cell are ignored
+    fn set_cell(&self, offset: u32, value: u8) {
+        let mut cells = self.cells.borrow_mut();
+        if let Some(cell) = cells.cells.get_mut(offset as usize) {
+            *cell = value;
+            cells.dirty = true;
+        }
+    }
+}
+
+impl Peripheral for TextDisplay {
+    fn read(&mut self, offset: u32) -> Result<u32> {
+        Ok(u32::from_le_bytes(std::array::from_fn(|i| {
+            self.cell(offset + i as u32)
+        })))
+    }
+
+    fn write(&mut self, offset: u32, value: u32) -> Result<()> {
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.set_cell(offset + i as u32, byte);
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self, offset: u32) -> Result<u8> {
+        Ok(self.cell(offset))
+    }
+
+    fn write_byte(&mut self, offset: u32, value: u8) -> Result<()> {
+        self.set_cell(offset, value);
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        self.base_addr
+    }
+
+    fn size(&self) -> u32 {
+        // Whole words, so word accesses to the last cells stay inside the device
+        (self.width * self.height).next_multiple_of(4)
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+}
+
+/// Debug-print register offsets
+const DEBUG_PRINT_DEC: u32 = 0;
+const DEBUG_PRINT_HEX: u32 = 4;
+
+/// "Magic" printf-debugging registers for bring-up before a UART driver exists
+///
+/// A word stored at offset 0 is printed as a signed decimal, one stored at
+/// offset 4 as `0x`-prefixed hex, each on its own line. Output goes to stderr
+/// (the web console on wasm) or to a capture buffer. Reads return 0.
+pub struct DebugPrintPeriph {
+    base_addr: u32,
+    /// When set, lines go to this buffer instead of stderr / the web console
+    capture: Option<ConsoleBuffer>,
+}
+
+impl DebugPrintPeriph {
+    /// `Peripheral::name` of every instance
+    pub const NAME: &'static str = "debug-print";
+
+    pub fn new(base_addr: u32) -> Self {
+        Self {
+            base_addr,
+            capture: None,
+        }
+    }
+
+    /// Create a debug-print device that appends its lines to `buffer`
+    pub fn with_capture(base_addr: u32, buffer: ConsoleBuffer) -> Self {
+        Self {
+            capture: Some(buffer),
+            ..Self::new(base_addr)
+        }
+    }
+
+    fn print(&self, line: String) {
+        if let Some(buffer) = &self.capture {
+            buffer.borrow_mut().extend_from_slice(line.as_bytes());
+            return;
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            web_sys::console::log_1(&line.trim_end().into());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            eprint!("{line}");
+        }
+    }
+}
+
+impl Peripheral for DebugPrintPeriph {
+    fn read(&mut self, _offset: u32) -> Result<u32> {
+        Ok(0)
+    }
+
+    fn write(&mut self, offset: u32, value: u32) -> Result<()> {
+        match offset {
+            DEBUG_PRINT_DEC => self.print(format!("{}\n", value as i32)),
+            DEBUG_PRINT_HEX => self.print(format!("0x{value:x}\n")),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        self.base_addr
+    }
+
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+}
+
+// CLINT register offsets (SiFive layout, as on QEMU's virt machine)
+const CLINT_MSIP: u32 = 0x0000;
+const CLINT_MTIMECMP: u32 = 0x4000;
+const CLINT_MTIME: u32 = 0xBFF8;
+
+/// Core-local interruptor for one hart: `msip`, `mtimecmp` and `mtime`
+///
+/// `mtime` advances by one per tick (retired instruction). MTIP is raised
+/// while `mtime >= mtimecmp` and MSIP while bit 0 of `msip` is set.
+#[derive(Debug, Clone)]
+pub struct Clint {
+    base_addr: u32,
+    msip: bool,
+    mtimecmp: u64,
+    mtime: u64,
+}
+
+impl Clint {
+    /// `Peripheral::name` of every instance
+    pub const NAME: &'static str = "clint";
+
+    pub fn new(base_addr: u32) -> Self {
+        Self {
+            base_addr,
+            msip: false,
+            mtimecmp: u64::MAX,
+            mtime: 0,
+        }
+    }
+
+    /// Current value of `mtime`
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+}
+
+/// Replace the low (`high == false`) or high word of `value`
+fn set_half(value: u64, high: bool, word: u32) -> u64 {
+    if high {
+        (value & 0xFFFF_FFFF) | u64::from(word) << 32
+    } else {
+        (value & !0xFFFF_FFFF) | u64::from(word)
+    }
+}
+
+impl Peripheral for Clint {
+    fn read(&mut self, offset: u32) -> Result<u32> {
+        Ok(match offset {
+            CLINT_MSIP => self.msip as u32,
+            CLINT_MTIMECMP => self.mtimecmp as u32,
+            o if o == CLINT_MTIMECMP + 4 => (self.mtimecmp >> 32) as u32,
+            CLINT_MTIME => self.mtime as u32,
+            o if o == CLINT_MTIME + 4 => (self.mtime >> 32) as u32,
+            _ => 0,
+        })
+    }
+
+    fn write(&mut self, offset: u32, value: u32) -> Result<()> {
+        match offset {
+            CLINT_MSIP => self.msip = value & 1 != 0,
+            CLINT_MTIMECMP => self.mtimecmp = set_half(self.mtimecmp, false, value),
+            o if o == CLINT_MTIMECMP + 4 => self.mtimecmp = set_half(self.mtimecmp, true, value),
+            CLINT_MTIME => self.mtime = set_half(self.mtime, false, value),
+            o if o == CLINT_MTIME + 4 => self.mtime = set_half(self.mtime, true, value),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        self.base_addr
+    }
+
+    fn size(&self) -> u32 {
+        0x10000
+    }
+
+    fn pending_interrupts(&self) -> u32 {
+        let mut lines = 0;
+        if self.msip {
+            lines |= crate::cpu::MIP_MSIP;
+        }
+        if self.mtime >= self.mtimecmp {
+            lines |= crate::cpu::MIP_MTIP;
+        }
+        lines
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.mtime = self.mtime.wrapping_add(cycles);
+    }
+
+    fn next_interrupt(&self) -> Option<(u64, u32)> {
+        (self.mtime < self.mtimecmp).then(|| (self.mtimecmp - self.mtime, crate::cpu::MIP_MTIP))
+    }
+
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+}
+
+/// What accesses to a reserved MMIO window with no device there do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// Reads return 0 and writes are dropped
+    #[default]
+    Ignore,
+    /// The access fails with `EmulatorError::BusError`
+    Fault,
+}
+
+/// `Peripheral::name` of every peripheral type this crate provides
+///
+/// `nekov::capabilities` lists these; a new peripheral type belongs here.
+pub const PERIPHERAL_TYPES: [&str; 4] = [
+    ConsolePeriph::NAME,
+    TextDisplay::NAME,
+    DebugPrintPeriph::NAME,
+    Clint::NAME,
+];
+
 /// Peripheral manager to handle multiple peripherals
 pub struct PeripheralManager {
     peripherals: Vec<Box<dyn Peripheral>>,
+    /// MMIO windows `(base, size)` that never fall through to RAM
+    reserved: Vec<(u32, u32)>,
+    unmapped_policy: UnmappedPolicy,
 }
 
 impl PeripheralManager {
     pub fn new() -> Self {
         Self {
             peripherals: Vec::new(),
+            reserved: Vec::new(),
+            unmapped_policy: UnmappedPolicy::Ignore,
+        }
+    }
+
+    /// Treat `size` bytes at `base` as device space even where no peripheral is attached
+    ///
+    /// Accesses there go to the peripherals instead of RAM; those no device
+    /// claims follow the `set_unmapped_policy` policy.
+    pub fn reserve_window(&mut self, base: u32, size: u32) {
+        self.reserved.push((base, size));
+    }
+
+    /// Choose what accesses to a reserved window with no device there do
+    pub fn set_unmapped_policy(&mut self, policy: UnmappedPolicy) {
+        self.unmapped_policy = policy;
+    }
+
+    fn in_reserved_window(&self, address: u32) -> bool {
+        self.reserved
+            .iter()
+            .any(|&(base, size)| address.wrapping_sub(base) < size)
+    }
+
+    /// Outcome of an access no peripheral claimed
+    fn unmapped(&self, address: u32) -> Result<()> {
+        if self.unmapped_policy == UnmappedPolicy::Fault && self.in_reserved_window(address) {
+            Err(EmulatorError::BusError(address))
+        } else {
+            Ok(())
         }
     }
 
@@ -85,6 +656,16 @@ impl PeripheralManager {
         self.peripherals.push(peripheral);
     }
 
+    /// Number of attached peripherals, i.e. the index the next one gets
+    pub(crate) fn len(&self) -> usize {
+        self.peripherals.len()
+    }
+
+    /// Swap the peripheral added `index`-th for `peripheral`
+    pub(crate) fn replace_peripheral(&mut self, index: usize, peripheral: Box<dyn Peripheral>) {
+        self.peripherals[index] = peripheral;
+    }
+
     pub fn read(&mut self, address: u32) -> Result<u32> {
         for peripheral in &mut self.peripherals {
             if peripheral.contains_address(address) {
@@ -93,7 +674,7 @@ impl PeripheralManager {
             }
         }
         // If no peripheral handles this address, return 0
-        Ok(0)
+        self.unmapped(address).map(|()| 0)
     }
 
     pub fn write(&mut self, address: u32, value: u32) -> Result<()> {
@@ -104,11 +685,78 @@ impl PeripheralManager {
             }
         }
         // If no peripheral handles this address, ignore the write
-        Ok(())
+        self.unmapped(address)
     }
 
+    /// Byte read from the peripheral at `address`; fails if it has no byte registers
+    pub fn read_byte(&mut self, address: u32) -> Result<u8> {
+        for peripheral in &mut self.peripherals {
+            if peripheral.contains_address(address) {
+                let offset = address - peripheral.base_address();
+                return peripheral.read_byte(offset);
+            }
+        }
+        self.unmapped(address).map(|()| 0)
+    }
+
+    /// Byte write to the peripheral at `address`; fails if it has no byte registers
+    pub fn write_byte(&mut self, address: u32, value: u8) -> Result<()> {
+        for peripheral in &mut self.peripherals {
+            if peripheral.contains_address(address) {
+                let offset = address - peripheral.base_address();
+                return peripheral.write_byte(offset, value);
+            }
+        }
+        self.unmapped(address)
+    }
+
+    /// Whether no peripheral is attached and no window reserved
+    pub fn is_empty(&self) -> bool {
+        self.peripherals.is_empty() && self.reserved.is_empty()
+    }
+
+    /// Whether `address` belongs to a peripheral or a reserved window
     pub fn is_peripheral_address(&self, address: u32) -> bool {
         self.peripherals.iter().any(|p| p.contains_address(address))
+            || self.in_reserved_window(address)
+    }
+
+    /// Memory map entries for the attached peripherals, in the order they were added
+    pub fn memory_map(&self) -> Vec<MapEntry> {
+        self.peripherals
+            .iter()
+            .map(|p| MapEntry {
+                kind: MapKind::Peripheral,
+                name: p.name().to_string(),
+                start: p.base_address(),
+                size: p.size(),
+                perms: "rw-".to_string(),
+            })
+            .collect()
+    }
+
+    /// Advance every peripheral by `cycles`
+    pub fn tick_all(&mut self, cycles: u64) {
+        for peripheral in &mut self.peripherals {
+            peripheral.tick(cycles);
+        }
+    }
+
+    /// Ticks until the soonest scheduled interrupt on one of the `lines` (`mip` bits)
+    pub fn cycles_until_interrupt(&self, lines: u32) -> Option<u64> {
+        self.peripherals
+            .iter()
+            .filter_map(|p| p.next_interrupt())
+            .filter(|&(_, raised)| raised & lines != 0)
+            .map(|(cycles, _)| cycles)
+            .min()
+    }
+
+    /// Interrupt lines raised by any peripheral, OR-ed together as `mip` bits
+    pub fn pending_interrupts(&self) -> u32 {
+        self.peripherals
+            .iter()
+            .fold(0, |lines, p| lines | p.pending_interrupts())
     }
 }
 
@@ -121,6 +769,7 @@ impl Default for PeripheralManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{cpu::Cpu, memory::Memory, reg::Reg};
 
     #[test]
     fn test_console_peripheral() {
@@ -142,6 +791,107 @@ mod tests {
         assert!(console.write(0, b'i' as u32).is_ok());
     }
 
+    #[test]
+    fn test_ns16550_registers() {
+        let buffer = ConsoleBuffer::default();
+        let mut uart = ConsolePeriph::with_capture(0x10000000, buffer.clone())
+            .with_layout(UartLayout::Ns16550Compatible);
+        assert_eq!(uart.read_byte(UART_LSR).unwrap(), LSR_THRE_TEMT);
+        assert_eq!(uart.read_byte(UART_IIR_FCR).unwrap(), IIR_NO_INTERRUPT);
+
+        // Divisor latch writes during init are not transmitted
+        uart.write_byte(UART_LCR, LCR_DLAB | 0x03).unwrap();
+        uart.write_byte(UART_RBR_THR, 0x01).unwrap();
+        uart.write_byte(UART_LCR, 0x03).unwrap();
+        uart.write_byte(UART_IER, 0xFF).unwrap();
+        assert_eq!(uart.read_byte(UART_IER).unwrap(), 0x0F);
+        uart.write_byte(UART_RBR_THR, b'o').unwrap();
+        uart.write(UART_RBR_THR, b'k' as u32).unwrap();
+        assert_eq!(*buffer.borrow(), b"ok");
+
+        uart.queue_input(b"x");
+        assert_eq!(uart.read_byte(UART_LSR).unwrap() & LSR_DR, LSR_DR);
+        assert_eq!(uart.read_byte(UART_RBR_THR).unwrap(), b'x');
+        assert_eq!(uart.read_byte(UART_LSR).unwrap() & LSR_DR, 0);
+
+        // The simple layout keeps rejecting byte accesses
+        assert!(ConsolePeriph::new(0x10000000).read_byte(UART_LSR).is_err());
+    }
+
+    #[test]
+    fn test_clint_timer() {
+        let mut clint = Clint::new(0x0200_0000);
+        assert_eq!(
+            clint.next_interrupt(),
+            Some((u64::MAX, crate::cpu::MIP_MTIP))
+        );
+        clint.write(0x4000, 10).unwrap();
+        clint.write(0x4004, 0).unwrap();
+        clint.tick(4);
+        assert_eq!(clint.read(0xBFF8).unwrap(), 4);
+        assert_eq!(clint.next_interrupt(), Some((6, crate::cpu::MIP_MTIP)));
+        assert_eq!(clint.pending_interrupts(), 0);
+        clint.tick(6);
+        assert_eq!(clint.pending_interrupts(), crate::cpu::MIP_MTIP);
+        assert_eq!(clint.next_interrupt(), None);
+
+        clint.write(0x0, 1).unwrap();
+        assert_eq!(
+            clint.pending_interrupts(),
+            crate::cpu::MIP_MTIP | crate::cpu::MIP_MSIP
+        );
+        clint.write(0xBFFC, 1).unwrap();
+        assert_eq!(clint.mtime(), 1 << 32 | 10);
+    }
+
+    #[test]
+    fn test_debug_print_registers() {
+        let buffer = ConsoleBuffer::default();
+        let mut manager = PeripheralManager::new();
+        manager.add_peripheral(Box::new(DebugPrintPeriph::with_capture(
+            0x1000_1000,
+            buffer.clone(),
+        )));
+        manager.write(0x1000_1000, 42).unwrap();
+        manager.write(0x1000_1004, 0xCAFE).unwrap();
+        manager.write(0x1000_1000, -7i32 as u32).unwrap();
+        assert_eq!(manager.read(0x1000_1004).unwrap(), 0);
+        assert_eq!(
+            String::from_utf8(buffer.borrow().clone()).unwrap(),
+            "42\n0xcafe\n-7\n"
+        );
+        assert!(!manager.is_peripheral_address(0x1000_1008));
+    }
+
+    #[test]
+    fn test_text_display_cells() {
+        let display = TextDisplay::new(0x2000_0000, 3, 2).unwrap();
+        let mut attached = display.clone();
+        assert_eq!(attached.size(), 8);
+        assert_eq!(display.render(), "   \n   \n");
+        assert!(!display.is_dirty());
+
+        attached.write(0, u32::from_le_bytes(*b"abcd")).unwrap();
+        attached.write_byte(5, b'!').unwrap();
+        attached.write_byte(4, 0).unwrap();
+        // Writes past the last cell are dropped
+        attached.write_byte(7, b'x').unwrap();
+        assert!(display.is_dirty());
+        assert_eq!(
+            attached.read(4).unwrap(),
+            u32::from_le_bytes([0, b'!', 0, 0])
+        );
+        assert_eq!(display.render(), "abc\nd !\n");
+        assert!(!display.is_dirty());
+        display.clear();
+        assert_eq!(display.render(), "   \n   \n");
+
+        // Oversized screens are refused instead of overflowing
+        assert!(TextDisplay::new(0x2000_0000, 0x1_0000, 0x1_0000).is_err());
+        assert!(TextDisplay::new(0x2000_0000, 1025, 1024).is_err());
+        assert!(TextDisplay::new(0x2000_0000, 1024, 1024).is_ok());
+    }
+
     #[test]
     fn test_peripheral_manager() {
         let mut manager = PeripheralManager::new();
@@ -163,4 +913,39 @@ mod tests {
         assert_eq!(manager.read(0x20000000).unwrap(), 0);
         assert!(manager.write(0x20000000, 0x12345678).is_ok());
     }
+
+    #[test]
+    fn test_unmapped_policy_in_reserved_window() {
+        let mut manager = PeripheralManager::new();
+        manager.add_peripheral(Box::new(ConsolePeriph::new(0x1000_0000)));
+        manager.reserve_window(0x1000_0000, 0x10_0000);
+        let mut memory = Memory::new();
+        let mut cpu = Cpu::new();
+        let base = memory.base_address();
+        memory.write_word(base, 0x0000_A503).unwrap(); // lw a0, 0(ra)
+        cpu.set_reg(Reg::Ra, 0x1000_2000);
+
+        // Ignored by default: reads as 0 instead of reaching RAM
+        assert!(manager.is_peripheral_address(0x1000_2000));
+        assert!(!manager.is_peripheral_address(0x1010_0000));
+        cpu.set_reg(Reg::A0, 7);
+        cpu.pc = base;
+        cpu.step_with_peripherals(&mut memory, &mut manager)
+            .unwrap();
+        assert_eq!(cpu.reg(Reg::A0), 0);
+
+        manager.set_unmapped_policy(UnmappedPolicy::Fault);
+        cpu.pc = base;
+        let error = cpu
+            .step_with_peripherals(&mut memory, &mut manager)
+            .unwrap_err();
+        assert!(matches!(error, EmulatorError::BusError(0x1000_2000)));
+        assert_eq!(
+            error.to_string(),
+            "bus error at 0x10002000 (no device mapped)"
+        );
+        // The attached console still answers inside the window
+        assert!(manager.write(0x1000_0000, u32::from(b'x')).is_ok());
+        assert!(manager.write_byte(0x1000_2000, 0).is_err());
+    }
 }
@@ -0,0 +1,169 @@
+//! Integer register names (numeric xN and ABI mnemonics)
+
+/// An integer register, named by its ABI mnemonic
+///
+/// Parse from either form with `"x10".parse()` or `"a0".parse()`; `fp` is accepted for s0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Reg {
+    Zero,
+    Ra,
+    Sp,
+    Gp,
+    Tp,
+    T0,
+    T1,
+    T2,
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+/// ABI names indexed by register number
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+impl Reg {
+    /// All registers in index order
+    pub const ALL: [Reg; 32] = [
+        Reg::Zero,
+        Reg::Ra,
+        Reg::Sp,
+        Reg::Gp,
+        Reg::Tp,
+        Reg::T0,
+        Reg::T1,
+        Reg::T2,
+        Reg::S0,
+        Reg::S1,
+        Reg::A0,
+        Reg::A1,
+        Reg::A2,
+        Reg::A3,
+        Reg::A4,
+        Reg::A5,
+        Reg::A6,
+        Reg::A7,
+        Reg::S2,
+        Reg::S3,
+        Reg::S4,
+        Reg::S5,
+        Reg::S6,
+        Reg::S7,
+        Reg::S8,
+        Reg::S9,
+        Reg::S10,
+        Reg::S11,
+        Reg::T3,
+        Reg::T4,
+        Reg::T5,
+        Reg::T6,
+    ];
+
+    /// Register with the given number (x0-x31)
+    pub fn from_index(index: usize) -> Option<Reg> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// Register number (0-31)
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// ABI mnemonic, e.g. `a0`
+    pub fn abi_name(self) -> &'static str {
+        ABI_NAMES[self.index()]
+    }
+}
+
+impl std::fmt::Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.abi_name())
+    }
+}
+
+impl serde::Serialize for Reg {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.abi_name())
+    }
+}
+
+impl std::str::FromStr for Reg {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        let index = match name.strip_prefix('x').and_then(|n| n.parse::<usize>().ok()) {
+            Some(index) => Some(index),
+            None if name == "fp" => Some(8),
+            None => ABI_NAMES.iter().position(|&abi| abi == name),
+        };
+        index
+            .and_then(Reg::from_index)
+            .ok_or_else(|| format!("unknown register '{s}'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_index_mapping() {
+        for (index, reg) in Reg::ALL.iter().enumerate() {
+            assert_eq!(reg.index(), index);
+            assert_eq!(Reg::from_index(index), Some(*reg));
+            assert_eq!(format!("x{index}").parse::<Reg>(), Ok(*reg));
+            assert_eq!(reg.abi_name().parse::<Reg>(), Ok(*reg));
+        }
+        assert_eq!(Reg::from_index(32), None);
+
+        let expected = [
+            (Reg::Zero, 0),
+            (Reg::Ra, 1),
+            (Reg::Sp, 2),
+            (Reg::Gp, 3),
+            (Reg::Tp, 4),
+            (Reg::T0, 5),
+            (Reg::T2, 7),
+            (Reg::S0, 8),
+            (Reg::S1, 9),
+            (Reg::A0, 10),
+            (Reg::A7, 17),
+            (Reg::S2, 18),
+            (Reg::S11, 27),
+            (Reg::T3, 28),
+            (Reg::T6, 31),
+        ];
+        for (reg, index) in expected {
+            assert_eq!(reg.index(), index, "{reg}");
+        }
+        assert_eq!("fp".parse::<Reg>(), Ok(Reg::S0));
+        assert_eq!("A0".parse::<Reg>(), Ok(Reg::A0));
+        assert!("x32".parse::<Reg>().is_err());
+        assert!("a8".parse::<Reg>().is_err());
+    }
+}
@@ -0,0 +1,578 @@
+//! riscv-tests pass/fail evaluation and batch runs over a directory of test binaries
+
+use crate::{
+    cpu::{Cpu, CAUSE_LOAD_ACCESS_FAULT, CSR_MCAUSE, CSR_MTVAL},
+    memory::UninitPolicy,
+    reg::Reg,
+    run_emulator_with_options, RunOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Outcome of a riscv-tests binary
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestResult {
+    Pass,
+    /// Failed with the given exit code (`TESTNUM << 1 | 1`)
+    Fail(u32),
+    Unknown,
+    /// The emulator stopped with an error
+    Error(String),
+    /// Passed, but retired more instructions than its budget allows
+    Slow {
+        instructions: u32,
+        budget: u32,
+    },
+}
+
+impl TestResult {
+    /// Whether the test passed
+    pub fn passed(&self) -> bool {
+        *self == TestResult::Pass
+    }
+
+    /// Status column: PASS, SLOW or FAIL
+    pub fn status(&self) -> &'static str {
+        match self {
+            TestResult::Pass => "PASS",
+            TestResult::Slow { .. } => "SLOW",
+            _ => "FAIL",
+        }
+    }
+
+    /// Details shown next to a failing test (empty for a pass)
+    pub fn message(&self) -> String {
+        match self {
+            TestResult::Pass => String::new(),
+            TestResult::Fail(code) => format!("test #{} failed (code: 0x{code:x})", code >> 1),
+            TestResult::Unknown => "result unknown".to_string(),
+            TestResult::Error(error) => format!("Error: {error}"),
+            TestResult::Slow {
+                instructions,
+                budget,
+            } => format!("retired {instructions} instructions (budget: {budget})"),
+        }
+    }
+}
+
+/// Check RISC-V test result based on register state and the guest exit code
+/// Based on RVTEST_PASS/RVTEST_FAIL macros:
+/// - PASS: TESTNUM=1 (gp=1), a7=93, a0=0, ecall
+/// - FAIL: TESTNUM!=1 (gp!=1), a7=93, a0=(TESTNUM<<1)|1, ecall
+pub fn check_riscv_test_result(cpu: &Cpu, verbosity: u8) -> TestResult {
+    // Only `UninitPolicy::Trap` raises load access faults; whatever the
+    // guest's handler reported next, the test read undefined memory
+    if cpu.read_csr(CSR_MCAUSE) == CAUSE_LOAD_ACCESS_FAULT {
+        let address = cpu.read_csr(CSR_MTVAL);
+        if verbosity >= 1 {
+            println!("=== RISC-V Test Result Analysis ===");
+            println!("  ✗ Load access fault on never-written memory at 0x{address:08x} → FAIL");
+        }
+        return TestResult::Error(format!("uninitialized read at 0x{address:08x}"));
+    }
+    // The exit code travels through ExitReason::EcallExit; without it the test never finished
+    let Some(a0) = cpu.exit_code() else {
+        if verbosity >= 1 {
+            println!("=== RISC-V Test Result Analysis ===");
+            println!("  ? Program did not terminate via ECALL → UNKNOWN");
+        }
+        return TestResult::Unknown;
+    };
+
+    let testnum = cpu.reg(Reg::Gp); // TESTNUM
+    let a7 = cpu.reg(Reg::A7); // system call number
+
+    if verbosity >= 1 {
+        println!("=== RISC-V Test Result Analysis ===");
+        println!("Register state at termination:");
+        println!("  gp (x3)  = 0x{testnum:08x} (TESTNUM)");
+        println!("  a0 (x10) = 0x{a0:08x} (exit code)");
+        println!("  a7 (x17) = 0x{a7:08x} (syscall number)");
+        println!();
+        println!("Test result determination:");
+    }
+
+    // Check if this looks like a test termination (a7=93 is exit syscall)
+    if a7 == 93 {
+        if verbosity >= 1 {
+            println!("  ✓ System call number is 93 (exit syscall)");
+        }
+        if testnum == 1 && a0 == 0 {
+            if verbosity >= 1 {
+                println!("  ✓ TESTNUM=1 and exit code=0 → PASS");
+            }
+            TestResult::Pass
+        } else if testnum != 1 {
+            if verbosity >= 1 {
+                println!("  ✗ TESTNUM={testnum} (≠1) and exit code={a0} → FAIL");
+            }
+            TestResult::Fail(a0)
+        } else {
+            if verbosity >= 1 {
+                println!("  ? TESTNUM=1 but exit code={a0} (≠0) → UNKNOWN");
+            }
+            TestResult::Unknown
+        }
+    } else {
+        if verbosity >= 1 {
+            println!("  ? System call number is {a7} (≠93) → UNKNOWN");
+        }
+        TestResult::Unknown
+    }
+}
+
+/// Options for `run_batch`
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Only run tests whose file name matches this glob (`*` and `?`)
+    pub filter: Option<String>,
+    /// Number of tests run in parallel
+    pub jobs: usize,
+    /// Maximum number of instructions per test
+    pub instruction_limit: Option<usize>,
+    /// Maximum retired instructions per test name; passing tests over budget are `Slow`
+    pub budgets: Option<Budgets>,
+    /// Percentage a test may exceed its budget by before it is `Slow`
+    pub budget_tolerance: f64,
+    /// What reads of never-written memory do; `Trap` turns them into test errors
+    pub uninit_policy: UninitPolicy,
+    /// Results cache file read and updated by the run (see `default_cache_path`)
+    pub cache: Option<PathBuf>,
+    /// Only run tests that are not cached as passing with an unchanged binary
+    pub rerun_failed: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            filter: None,
+            jobs: 1,
+            instruction_limit: None,
+            budgets: None,
+            budget_tolerance: 0.0,
+            uninit_policy: UninitPolicy::default(),
+            cache: None,
+            rerun_failed: false,
+        }
+    }
+}
+
+/// Instruction budgets: test name → maximum retired instructions
+pub type Budgets = BTreeMap<String, u32>;
+
+/// Read a budgets file (a JSON object mapping test names to instruction counts)
+pub fn load_budgets(path: &Path) -> std::io::Result<Budgets> {
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(std::io::Error::other)
+}
+
+/// A test result remembered by the results cache
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResult {
+    /// `binary_hash` of the test binary that produced the result
+    pub hash: u64,
+    pub result: TestResult,
+    /// Instructions retired, if the test ran to completion
+    pub instructions: Option<u32>,
+}
+
+/// Results of earlier batch runs keyed by test name, for `BatchOptions::rerun_failed`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultsCache {
+    pub tests: BTreeMap<String, CachedResult>,
+}
+
+impl ResultsCache {
+    /// Read a cache file; a missing or unreadable file is an empty cache
+    pub fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the cache as JSON
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("cache serializes");
+        std::fs::write(path, json + "\n")
+    }
+
+    /// The cached result for `name` if it passed with a binary hashing to `hash`
+    pub fn reusable(&self, name: &str, hash: u64) -> Option<&CachedResult> {
+        self.tests
+            .get(name)
+            .filter(|cached| cached.hash == hash && cached.result.passed())
+    }
+
+    /// Remember the latest result of `name`
+    pub fn record(&mut self, name: &str, hash: u64, result: TestResult, instructions: Option<u32>) {
+        self.tests.insert(
+            name.to_string(),
+            CachedResult {
+                hash,
+                result,
+                instructions,
+            },
+        );
+    }
+}
+
+/// Default results cache for the tests in `dir`: `<dir>.nekov-results.json` beside it
+pub fn default_cache_path(dir: &Path) -> PathBuf {
+    let name = dir
+        .file_name()
+        .map_or_else(|| "tests".into(), |name| name.to_string_lossy());
+    dir.with_file_name(format!("{name}.nekov-results.json"))
+}
+
+/// FNV-1a hash of a test binary, used to notice rebuilt tests
+pub fn binary_hash(path: &Path) -> std::io::Result<u64> {
+    Ok(std::fs::read(path)?
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+        }))
+}
+
+/// Results of a batch run, sorted by test name
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    pub results: Vec<(String, TestResult)>,
+    /// Instructions retired by each test that ran to completion
+    pub instructions: BTreeMap<String, u32>,
+    /// How many of the results were taken from the cache instead of running the test
+    pub cached: usize,
+}
+
+impl BatchSummary {
+    /// Number of tests run
+    pub fn total(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Number of passing tests
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|(_, r)| r.passed()).count()
+    }
+
+    /// Whether every test passed
+    pub fn all_passed(&self) -> bool {
+        self.passed() == self.total()
+    }
+
+    /// Mark passing tests that retired more than `budget * (1 + tolerance / 100)` instructions as `Slow`
+    ///
+    /// Tests without a budget or an instruction count are left alone.
+    pub fn apply_budgets(&mut self, budgets: &Budgets, tolerance: f64) {
+        for (name, result) in &mut self.results {
+            let (Some(&budget), Some(&instructions)) =
+                (budgets.get(name), self.instructions.get(name))
+            else {
+                continue;
+            };
+            let allowed = f64::from(budget) * (1.0 + tolerance / 100.0);
+            if result.passed() && f64::from(instructions) > allowed {
+                *result = TestResult::Slow {
+                    instructions,
+                    budget,
+                };
+            }
+        }
+    }
+
+    /// Instruction counts of the passing tests, for bootstrapping a budgets file
+    pub fn budgets(&self) -> Budgets {
+        self.results
+            .iter()
+            .filter(|(_, result)| result.passed())
+            .filter_map(|(name, _)| Some((name.clone(), *self.instructions.get(name)?)))
+            .collect()
+    }
+
+    fn pass_rate(&self) -> f64 {
+        if self.total() > 0 {
+            self.passed() as f64 / self.total() as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Render the summary as a JSON object
+    pub fn to_json(&self) -> String {
+        let results: Vec<serde_json::Value> = self
+            .results
+            .iter()
+            .map(|(name, result)| {
+                serde_json::json!({
+                    "test": name,
+                    "status": result.status(),
+                    "message": result.message(),
+                    "instructions": self.instructions.get(name),
+                })
+            })
+            .collect();
+        let summary = serde_json::json!({
+            "total_tests": self.total(),
+            "passed_tests": self.passed(),
+            "failed_tests": self.total() - self.passed(),
+            "pass_rate": (self.pass_rate() * 100.0).round() / 100.0,
+            "cached_tests": self.cached,
+            "results": results,
+        });
+        serde_json::to_string_pretty(&summary).expect("summary serializes")
+    }
+
+    /// Print the PASS/FAIL table and summary
+    pub fn print_table(&self) {
+        println!("Test Results:");
+        println!("=============");
+        for (name, result) in &self.results {
+            let color = match result {
+                TestResult::Pass => "\x1b[32m",
+                TestResult::Slow { .. } => "\x1b[33m",
+                _ => "\x1b[31m",
+            };
+            print!("{color}{:4}\x1b[0m {name}", result.status());
+            if !result.passed() {
+                print!(" - {}", result.message());
+            }
+            println!();
+        }
+
+        println!();
+        println!(
+            "Summary: {}/{} tests passed ({:.1}% pass rate)",
+            self.passed(),
+            self.total(),
+            self.pass_rate()
+        );
+        if self.all_passed() {
+            println!("🎉 All tests passed!");
+        } else {
+            println!(
+                "❌ {}/{} tests failed",
+                self.total() - self.passed(),
+                self.total()
+            );
+            println!("\nFailed tests:");
+            for (name, result) in &self.results {
+                if !result.passed() {
+                    println!("  - {name}");
+                }
+            }
+        }
+    }
+}
+
+/// Match `name` against a glob supporting `*` and `?`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it was tried at
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Test binaries in `dir`: extensionless files matching the filter, sorted by name
+fn collect_tests(dir: &Path, filter: Option<&str>) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut tests = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        // Skip files that are not test binaries (no extension typically)
+        if name.contains('.') || filter.is_some_and(|glob| !glob_match(glob, &name)) {
+            continue;
+        }
+        tests.push((name, path));
+    }
+    tests.sort();
+    Ok(tests)
+}
+
+/// Run a single riscv-tests binary in-process
+pub fn run_test(path: &Path, instruction_limit: Option<usize>) -> TestResult {
+    run_test_counted(path, instruction_limit, UninitPolicy::default()).0
+}
+
+/// Run a single riscv-tests binary, also returning the instructions it retired
+fn run_test_counted(
+    path: &Path,
+    instruction_limit: Option<usize>,
+    uninit_policy: UninitPolicy,
+) -> (TestResult, Option<u32>) {
+    let options = RunOptions {
+        instruction_limit,
+        quiet: true,
+        uninit_policy,
+        ..RunOptions::default()
+    };
+    match run_emulator_with_options(path, &options) {
+        Ok(report) => (
+            check_riscv_test_result(&report.cpu, 0),
+            Some(report.instructions_executed),
+        ),
+        Err(e) => (TestResult::Error(e.to_string()), None),
+    }
+}
+
+/// Run every test binary in `dir`, using up to `options.jobs` threads
+///
+/// With `options.cache`, results are merged into that file afterwards; with
+/// `rerun_failed` as well, tests cached as passing with an unchanged binary
+/// are not run again and their cached results are reported instead.
+pub fn run_batch(dir: &Path, options: &BatchOptions) -> std::io::Result<BatchSummary> {
+    let tests = collect_tests(dir, options.filter.as_deref())?;
+    let mut cache = options
+        .cache
+        .as_deref()
+        .map(ResultsCache::load)
+        .unwrap_or_default();
+    let hashes: Vec<u64> = tests
+        .iter()
+        .map(|(_, path)| match options.cache {
+            Some(_) => binary_hash(path).unwrap_or(0),
+            None => 0,
+        })
+        .collect();
+    let mut results: Vec<Option<(TestResult, Option<u32>)>> = tests
+        .iter()
+        .zip(&hashes)
+        .map(|((name, _), &hash)| {
+            let cached = cache
+                .reusable(name, hash)
+                .filter(|_| options.rerun_failed)?;
+            Some((cached.result.clone(), cached.instructions))
+        })
+        .collect();
+    let cached = results.iter().filter(|result| result.is_some()).count();
+    let pending: Vec<usize> = (0..tests.len()).filter(|&i| results[i].is_none()).collect();
+
+    let ran = Mutex::new(Vec::with_capacity(pending.len()));
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.clamp(1, pending.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(&index) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let (_, path) = &tests[index];
+                    let outcome =
+                        run_test_counted(path, options.instruction_limit, options.uninit_policy);
+                    ran.lock().unwrap().push((index, outcome));
+                }
+            });
+        }
+    });
+    for (index, outcome) in ran.into_inner().unwrap() {
+        results[index] = Some(outcome);
+    }
+
+    let mut summary = BatchSummary {
+        cached,
+        ..BatchSummary::default()
+    };
+    for (((name, _), hash), result) in tests.iter().zip(hashes).zip(results) {
+        let (result, count) = result.expect("every test ran or was cached");
+        cache.record(name, hash, result.clone(), count);
+        if let Some(count) = count {
+            summary.instructions.insert(name.clone(), count);
+        }
+        summary.results.push((name.clone(), result));
+    }
+    if let Some(budgets) = &options.budgets {
+        summary.apply_budgets(budgets, options.budget_tolerance);
+    }
+    if let Some(path) = &options.cache {
+        cache.save(path)?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "rv32ui-p-add"));
+        assert!(glob_match("rv32ui-p-*", "rv32ui-p-add"));
+        assert!(glob_match("*-add", "rv32ui-p-add"));
+        assert!(glob_match("rv32u?-p-*d*", "rv32um-p-div"));
+        assert!(!glob_match("rv32um-*", "rv32ui-p-add"));
+        assert!(!glob_match("add", "rv32ui-p-add"));
+        assert!(glob_match("*a*b*", "xaxxb"));
+        assert!(!glob_match("*a*b", "xaxxbc"));
+    }
+
+    #[test]
+    fn test_apply_budgets() {
+        let mut summary = BatchSummary {
+            results: vec![
+                ("fast".to_string(), TestResult::Pass),
+                ("slow".to_string(), TestResult::Pass),
+                ("tolerated".to_string(), TestResult::Pass),
+                ("failing".to_string(), TestResult::Fail(3)),
+                ("unbudgeted".to_string(), TestResult::Pass),
+            ],
+            instructions: [
+                ("fast", 90),
+                ("slow", 120),
+                ("tolerated", 104),
+                ("failing", 500),
+                ("unbudgeted", 1000),
+            ]
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect(),
+            cached: 0,
+        };
+        let budgets: Budgets =
+            serde_json::from_str(r#"{"fast": 100, "slow": 100, "tolerated": 100, "failing": 100}"#)
+                .unwrap();
+        summary.apply_budgets(&budgets, 5.0);
+
+        let statuses: Vec<&str> = summary.results.iter().map(|(_, r)| r.status()).collect();
+        assert_eq!(statuses, ["PASS", "SLOW", "PASS", "FAIL", "PASS"]);
+        assert_eq!(
+            summary.results[1].1,
+            TestResult::Slow {
+                instructions: 120,
+                budget: 100
+            }
+        );
+        assert_eq!(summary.passed(), 3);
+        assert!(!summary.all_passed());
+        assert!(summary.to_json().contains("\"SLOW\""));
+
+        // Only passing tests are recorded as budgets
+        let recorded = summary.budgets();
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded["tolerated"], 104);
+        assert!(!recorded.contains_key("slow") && !recorded.contains_key("failing"));
+    }
+}
@@ -0,0 +1,231 @@
+//! Saving and restoring the architectural state of a machine
+//!
+//! The state is a compact little-endian binary blob: a magic and version
+//! header, the PC, reset vector, registers, CSRs, the 64-bit counters and the
+//! initialized memory runs. Host-side hooks and peripherals are not part of it.
+
+use crate::{cpu::Cpu, memory::Memory, EmulatorError, Result};
+
+/// Version of the saved state layout; bumped whenever it changes
+pub const STATE_VERSION: u32 = 2;
+
+/// Leading bytes of every saved state
+const MAGIC: &[u8; 4] = b"NKVS";
+
+/// Serialize the CPU and memory state
+pub fn save_state(cpu: &Cpu, memory: &Memory) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    put_u32(&mut out, STATE_VERSION);
+    put_u32(&mut out, cpu.pc);
+    put_u32(&mut out, cpu.reset_vector());
+    out.push(cpu.breakpoint_mode as u8);
+    for &value in &cpu.registers {
+        put_u32(&mut out, value);
+    }
+
+    let mut csrs: Vec<(u16, u32)> = cpu.csrs.iter().map(|(&csr, &value)| (csr, value)).collect();
+    csrs.sort_unstable();
+    put_u32(&mut out, csrs.len() as u32);
+    for (csr, value) in csrs {
+        out.extend_from_slice(&csr.to_le_bytes());
+        put_u32(&mut out, value);
+    }
+    out.extend_from_slice(&cpu.cycles().to_le_bytes());
+    out.extend_from_slice(&cpu.instret().to_le_bytes());
+
+    let runs = memory.contents();
+    put_u32(&mut out, runs.len() as u32);
+    for (address, bytes) in runs {
+        put_u32(&mut out, address);
+        put_u32(&mut out, bytes.len() as u32);
+        out.extend_from_slice(&bytes);
+    }
+    out
+}
+
+/// Restore a state produced by `save_state`, replacing registers, CSRs and memory contents
+///
+/// Fails with `InvalidState` (leaving the machine untouched) if the data is
+/// truncated or was saved by a different `STATE_VERSION`.
+pub fn load_state(cpu: &mut Cpu, memory: &mut Memory, data: &[u8]) -> Result<()> {
+    let mut reader = Reader { data };
+    if reader.take(4)? != MAGIC || reader.u32()? != STATE_VERSION {
+        return Err(EmulatorError::InvalidState);
+    }
+    let pc = reader.u32()?;
+    let reset_vector = reader.u32()?;
+    let breakpoint_mode = reader.take(1)?[0] != 0;
+    let mut registers = [0; crate::cpu::NUM_REGISTERS];
+    for value in &mut registers {
+        *value = reader.u32()?;
+    }
+    let mut csrs = std::collections::HashMap::new();
+    for _ in 0..reader.u32()? {
+        let csr = u16::from_le_bytes(reader.take(2)?.try_into().expect("two bytes"));
+        csrs.insert(csr, reader.u32()?);
+    }
+    let cycle = reader.u64()?;
+    let instret = reader.u64()?;
+    let mut runs = Vec::new();
+    for _ in 0..reader.u32()? {
+        let address = reader.u32()?;
+        let len = reader.u32()? as usize;
+        runs.push((address, reader.take(len)?.to_vec()));
+    }
+    if !reader.data.is_empty() {
+        return Err(EmulatorError::InvalidState);
+    }
+
+    cpu.pc = pc;
+    cpu.set_reset_vector(reset_vector);
+    cpu.breakpoint_mode = breakpoint_mode;
+    cpu.registers = registers;
+    cpu.csrs = csrs;
+    cpu.set_counters(cycle, instret);
+    cpu.exit_reason = None;
+    cpu.flush_icache();
+    memory.restore_contents(&runs);
+    Ok(())
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Cursor over a saved state
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(EmulatorError::InvalidState);
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("four bytes"),
+        ))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(
+            self.take(8)?.try_into().expect("eight bytes"),
+        ))
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard (padded) base64 encoding
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64, ignoring whitespace
+pub fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    if !digits.len().is_multiple_of(4) {
+        return Err(EmulatorError::InvalidState);
+    }
+    let mut out = Vec::with_capacity(digits.len() / 4 * 3);
+    for chunk in digits.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&byte| byte == b'=').count();
+        if padding > 2 {
+            return Err(EmulatorError::InvalidState);
+        }
+        let mut bits = 0u32;
+        for &digit in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET
+                .iter()
+                .position(|&symbol| symbol == digit)
+                .ok_or(EmulatorError::InvalidState)?;
+            bits = bits << 6 | value as u32;
+        }
+        bits <<= 6 * padding;
+        out.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reg::Reg;
+
+    #[test]
+    fn test_base64_round_trip() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foobar"), "Zm9vYmFy");
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 200) as u8).collect();
+            assert_eq!(decode_base64(&encode_base64(&data)).unwrap(), data);
+        }
+        assert!(decode_base64("Zm9").is_err());
+        assert!(decode_base64("Zm9*").is_err());
+    }
+
+    #[test]
+    fn test_state_round_trip_continues_execution() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, word) in [
+            0x00100513u32, // addi a0, zero, 1
+            0x00150513,    // addi a0, a0, 1
+            0x00150513,    // addi a0, a0, 1
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base;
+        cpu.run(&mut memory, Some(2)).unwrap();
+        let saved = encode_base64(&save_state(&cpu, &memory));
+
+        let mut restored_cpu = Cpu::new();
+        let mut restored_memory = Memory::new();
+        restored_memory.write_word(0x1000, 0xdead_beef).unwrap();
+        let data = decode_base64(&saved).unwrap();
+        load_state(&mut restored_cpu, &mut restored_memory, &data).unwrap();
+        assert_eq!(restored_cpu.pc, base + 8);
+        assert_eq!(restored_memory.contents(), memory.contents());
+
+        restored_cpu.run(&mut restored_memory, Some(1)).unwrap();
+        assert_eq!(restored_cpu.reg(Reg::A0), 3);
+
+        let mut stale = data.clone();
+        stale[4] = 0xFF;
+        assert!(matches!(
+            load_state(&mut restored_cpu, &mut restored_memory, &stale),
+            Err(EmulatorError::InvalidState)
+        ));
+        assert!(load_state(&mut restored_cpu, &mut restored_memory, &data[..20]).is_err());
+    }
+}
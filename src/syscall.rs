@@ -0,0 +1,39 @@
+/// ECALL dispatch: how the CPU reacts to environment calls
+use crate::{bus::Bus, cpu::Cpu, Result};
+
+/// What the run loop should do after a syscall handler returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallAction {
+    /// Resume at the instruction after the ECALL
+    Continue,
+    /// Stop the run as an ECALL exit; the exit code is taken from a0
+    Exit,
+}
+
+/// Host implementation of guest environment calls
+pub trait SyscallHandler {
+    /// Handle an ECALL at `cpu.pc`; arguments and results are in the guest registers
+    fn handle(&mut self, cpu: &mut Cpu, bus: &mut dyn Bus) -> Result<SyscallAction>;
+}
+
+/// How the CPU reacts to ECALL
+#[derive(Default)]
+pub enum EcallBehavior {
+    /// Stop the run with the exit code in a0 (riscv-tests convention)
+    #[default]
+    TerminateTests,
+    /// Take an environment-call exception through mtvec
+    Trap,
+    /// Pass the call to a host handler
+    Handler(Box<dyn SyscallHandler>),
+}
+
+impl std::fmt::Debug for EcallBehavior {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EcallBehavior::TerminateTests => write!(f, "TerminateTests"),
+            EcallBehavior::Trap => write!(f, "Trap"),
+            EcallBehavior::Handler(_) => write!(f, "Handler(..)"),
+        }
+    }
+}
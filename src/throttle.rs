@@ -0,0 +1,162 @@
+//! Run-speed throttling for animated front ends
+//!
+//! A `Throttle` never sleeps: it only bounds how many instructions a call may
+//! retire, based on the time elapsed since the previous call. With
+//! `TimeSource::Virtual` every call is credited one frame instead of the wall
+//! clock, so the sequence of budgets is the same on every run.
+
+/// Longest gap credited between two calls, so a paused tab does not resume with a burst
+const MAX_ELAPSED_MS: f64 = 1000.0;
+
+/// Time credited to the first call after a (re)start: one 60 Hz frame
+const FIRST_CALL_MS: f64 = 1000.0 / 60.0;
+
+/// Where a `Throttle` takes the time between calls from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeSource {
+    /// The host's wall clock
+    #[default]
+    RealTime,
+    /// Every call is one 60 Hz frame after the previous one (reproducible)
+    Virtual,
+}
+
+/// Per-call instruction budget at a target instructions-per-second rate
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    /// Target rate; 0 means unlimited
+    instructions_per_second: u32,
+    /// Clock the elapsed time is measured with
+    source: TimeSource,
+    /// Timestamp (ms) of the previous call
+    last_ms: Option<f64>,
+    /// Fractional instructions carried over between calls
+    carry: f64,
+}
+
+impl Throttle {
+    /// A throttle running at `instructions_per_second` (0 = unlimited)
+    pub fn new(instructions_per_second: u32) -> Self {
+        Self {
+            instructions_per_second,
+            ..Self::default()
+        }
+    }
+
+    /// Target rate in instructions per second (0 = unlimited)
+    pub fn speed(&self) -> u32 {
+        self.instructions_per_second
+    }
+
+    /// Builder: measure time with `source`
+    pub fn with_time_source(mut self, source: TimeSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Clock the elapsed time is measured with
+    pub fn time_source(&self) -> TimeSource {
+        self.source
+    }
+
+    /// Change the clock and restart the time base
+    pub fn set_time_source(&mut self, source: TimeSource) {
+        *self = Self::new(self.instructions_per_second).with_time_source(source);
+    }
+
+    /// Change the target rate and restart the time base
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        *self = Self::new(instructions_per_second).with_time_source(self.source);
+    }
+
+    /// Budget for a call made now, capped at `max_instructions`
+    pub fn budget(&mut self, max_instructions: u32) -> u32 {
+        let now = match self.source {
+            TimeSource::RealTime => now_ms(),
+            TimeSource::Virtual => self.last_ms.map_or(0.0, |last| last + FIRST_CALL_MS),
+        };
+        self.budget_at(now, max_instructions)
+    }
+
+    /// Budget for a call made at `now_ms` milliseconds, capped at `max_instructions`
+    pub fn budget_at(&mut self, now_ms: f64, max_instructions: u32) -> u32 {
+        if self.instructions_per_second == 0 {
+            return max_instructions;
+        }
+        let elapsed = match self.last_ms {
+            Some(last) => (now_ms - last).clamp(0.0, MAX_ELAPSED_MS),
+            None => FIRST_CALL_MS,
+        };
+        self.last_ms = Some(now_ms);
+        let owed = self.carry + elapsed * f64::from(self.instructions_per_second) / 1000.0;
+        let budget = (owed.floor() as u32).min(max_instructions);
+        // Only keep the fraction: instructions cut by `max_instructions` are not owed later
+        self.carry = owed.fract();
+        budget
+    }
+}
+
+/// Current time in milliseconds
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+/// Current time in milliseconds since the first call
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn now_ms() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+        * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_follows_elapsed_time() {
+        let mut unlimited = Throttle::new(0);
+        assert_eq!(unlimited.budget_at(0.0, 5000), 5000);
+
+        let mut throttle = Throttle::new(1000);
+        // First call is credited one 60 Hz frame
+        assert_eq!(throttle.budget_at(100.0, u32::MAX), 16);
+        assert_eq!(throttle.budget_at(110.0, u32::MAX), 10);
+        // Fractions carry over: 2/3 from the first frame plus 2.5 + 2.5 instructions
+        assert_eq!(throttle.budget_at(112.5, u32::MAX), 3);
+        assert_eq!(throttle.budget_at(115.0, u32::MAX), 2);
+        // Capped by the caller's maximum and by MAX_ELAPSED_MS
+        assert_eq!(throttle.budget_at(165.0, 20), 20);
+        assert_eq!(throttle.budget_at(60_000.0, u32::MAX), 1000);
+        // Time going backwards grants nothing
+        assert_eq!(throttle.budget_at(59_000.0, u32::MAX), 0);
+
+        throttle.set_speed(120);
+        assert_eq!(throttle.speed(), 120);
+        assert_eq!(throttle.budget_at(0.0, u32::MAX), 2);
+    }
+
+    #[test]
+    fn test_virtual_time_gives_the_same_budgets_every_run() {
+        let budgets = || {
+            let mut throttle = Throttle::new(1000).with_time_source(TimeSource::Virtual);
+            (0..6)
+                .map(|_| throttle.budget(u32::MAX))
+                .collect::<Vec<_>>()
+        };
+        // One frame (16.67 instructions) per call, fractions carried over
+        assert_eq!(budgets(), [16, 17, 17, 16, 17, 17]);
+        assert_eq!(budgets(), budgets());
+
+        let mut throttle = Throttle::new(1000).with_time_source(TimeSource::Virtual);
+        throttle.set_speed(60);
+        assert_eq!(throttle.time_source(), TimeSource::Virtual);
+        assert_eq!(throttle.budget(u32::MAX), 1);
+        throttle.set_time_source(TimeSource::RealTime);
+        assert_eq!(throttle.speed(), 60);
+    }
+}
@@ -0,0 +1,367 @@
+//! Lock-step comparison against a reference execution trace
+//!
+//! A `TraceComparator` holds the retired-instruction records of a reference
+//! run (Spike commit log or nekov `jsonl` trace) and checks every instruction
+//! nekov retires against the next record, stopping at the first divergence.
+
+use std::collections::VecDeque;
+
+/// Number of recently retired PCs kept for divergence reports
+const HISTORY_LEN: usize = 16;
+
+/// Format of a reference trace file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferenceFormat {
+    /// nekov `--trace-format jsonl` output
+    #[default]
+    Jsonl,
+    /// Spike `--log-commits` output
+    Spike,
+}
+
+/// Rule for ignoring destination-register differences nekov doesn't model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipRule {
+    /// Ignore written values of CSR instructions (counters, side effects)
+    Csr,
+    /// Ignore the written value of the instruction at this PC
+    Pc(u32),
+}
+
+impl std::str::FromStr for SkipRule {
+    type Err = String;
+
+    /// Parse `csr` or a PC address (hex with 0x prefix, or decimal)
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "csr" {
+            return Ok(SkipRule::Csr);
+        }
+        crate::addr_expr::parse_number(s)
+            .and_then(|pc| u32::try_from(pc).ok())
+            .map(SkipRule::Pc)
+            .ok_or_else(|| format!("invalid skip rule '{s}' (expected 'csr' or a PC address)"))
+    }
+}
+
+/// One retired instruction: its PC and the destination register write, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u32,
+    /// Destination register and written value (x0 writes are not recorded)
+    pub write: Option<(usize, u32)>,
+}
+
+impl std::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "pc=0x{:08x}", self.pc)?;
+        match self.write {
+            Some((rd, value)) => write!(f, " x{rd}=0x{value:08x}"),
+            None => write!(f, " (no register write)"),
+        }
+    }
+}
+
+/// First point where nekov and the reference disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// 1-based index of the retired instruction
+    pub index: u32,
+    /// Reference record, or `None` if the reference trace ended early
+    pub expected: Option<TraceRecord>,
+    /// What nekov actually retired
+    pub actual: TraceRecord,
+    /// PCs retired before the divergent instruction, oldest first
+    pub history: Vec<u32>,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Trace divergence at instruction #{}", self.index)?;
+        match &self.expected {
+            Some(expected) => writeln!(f, "  reference: {expected}")?,
+            None => writeln!(f, "  reference: <end of trace>")?,
+        }
+        writeln!(f, "  nekov:     {}", self.actual)?;
+        write!(f, "  recent PCs:")?;
+        for pc in &self.history {
+            write!(f, " 0x{pc:08x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a hex value with an optional 0x prefix (64-bit Spike values are truncated)
+fn parse_hex(s: &str) -> Option<u32> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .map(|value| value as u32)
+}
+
+/// Parse a `jsonl` trace line; lines that are not instruction records yield `None`
+fn parse_jsonl(line: &str) -> Option<TraceRecord> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let pc = parse_hex(value.get("pc")?.as_str()?)?;
+    let write = match value.get("rd") {
+        Some(rd) => {
+            let x = rd.get("x")?.as_u64()? as usize;
+            let new = parse_hex(rd.get("new")?.as_str()?)?;
+            (x != 0).then_some((x, new))
+        }
+        None => None,
+    };
+    Some(TraceRecord { pc, write })
+}
+
+/// Parse a Spike commit-log line such as
+/// `core   0: 3 0x80000000 (0x00a00093) x1  0x0000000a`
+///
+/// Exception lines and `-l` disassembly lines are not commit records, and
+/// CSR and memory annotations are not modelled; all of them are ignored.
+fn parse_spike(line: &str) -> Option<TraceRecord> {
+    let rest = line.trim_start().strip_prefix("core")?;
+    let (_, rest) = rest.split_once(':')?;
+    let mut tokens = rest.split_whitespace();
+    // Commit records carry the privilege level before the PC; `-l` disassembly lines don't
+    tokens.next()?.parse::<u8>().ok()?;
+    let pc = parse_hex(tokens.next().filter(|t| t.starts_with("0x"))?)?;
+    // The instruction word in parentheses marks this as a commit record
+    let insn = tokens.next()?;
+    if !(insn.starts_with("(0x") && insn.ends_with(')')) {
+        return None;
+    }
+    let mut write = None;
+    while let Some(token) = tokens.next() {
+        if let Some(rd) = token
+            .strip_prefix('x')
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            if let Some(value) = tokens.next().and_then(parse_hex) {
+                if rd != 0 && rd < 32 {
+                    write = Some((rd, value));
+                }
+            }
+            break;
+        }
+    }
+    Some(TraceRecord { pc, write })
+}
+
+/// Parse a reference trace into its instruction records
+pub fn parse_reference(format: ReferenceFormat, text: &str) -> Vec<TraceRecord> {
+    let parse = match format {
+        ReferenceFormat::Jsonl => parse_jsonl,
+        ReferenceFormat::Spike => parse_spike,
+    };
+    text.lines().filter_map(parse).collect()
+}
+
+/// Checks retired instructions against a reference trace
+#[derive(Debug, Clone)]
+pub struct TraceComparator {
+    reference: Vec<TraceRecord>,
+    skip: Vec<SkipRule>,
+    next: usize,
+    history: VecDeque<u32>,
+    divergence: Option<Divergence>,
+}
+
+impl TraceComparator {
+    /// Create a comparator over already-parsed reference records
+    pub fn new(reference: Vec<TraceRecord>, skip: Vec<SkipRule>) -> Self {
+        Self {
+            reference,
+            skip,
+            next: 0,
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            divergence: None,
+        }
+    }
+
+    /// Create a comparator from the text of a reference trace
+    pub fn from_text(format: ReferenceFormat, text: &str, skip: Vec<SkipRule>) -> Self {
+        Self::new(parse_reference(format, text), skip)
+    }
+
+    /// Compare the next retired instruction; returns false on divergence
+    pub fn check(&mut self, instruction: u32, actual: TraceRecord) -> bool {
+        if self.divergence.is_some() {
+            return false;
+        }
+        let index = self.next as u32 + 1;
+        let expected = self.reference.get(self.next).copied();
+        self.next += 1;
+
+        let matches = expected.is_some_and(|expected| {
+            expected.pc == actual.pc
+                && (expected.write == actual.write || self.skips(instruction, actual.pc))
+        });
+        if !matches {
+            self.divergence = Some(Divergence {
+                index,
+                expected,
+                actual,
+                history: self.history.iter().copied().collect(),
+            });
+            return false;
+        }
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(actual.pc);
+        true
+    }
+
+    /// Whether a skip rule suppresses register comparison for this instruction
+    fn skips(&self, instruction: u32, pc: u32) -> bool {
+        let is_csr = instruction & 0x7F == 0x73 && (instruction >> 12) & 0x7 != 0;
+        self.skip.iter().any(|rule| match rule {
+            SkipRule::Csr => is_csr,
+            SkipRule::Pc(skip_pc) => *skip_pc == pc,
+        })
+    }
+
+    /// The first divergence found, if any
+    pub fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Number of instructions compared so far
+    pub fn compared(&self) -> usize {
+        self.next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Cpu, SharedSink, TraceFormat};
+    use crate::memory::Memory;
+    use crate::ExitReason;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn load_counting_program() -> (Cpu, Memory) {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        let program = [
+            0x00100093, // addi x1, x0, 1
+            0x00108093, // addi x1, x1, 1
+            0x00108093, // addi x1, x1, 1
+            0x00108093, // addi x1, x1, 1
+            0x00008133, // add x2, x1, x0
+        ];
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base;
+        (cpu, memory)
+    }
+
+    #[test]
+    fn test_doctored_reference_diverges_at_index() {
+        // Record a reference jsonl trace of the program
+        let (mut cpu, mut memory) = load_counting_program();
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        cpu.set_trace_sink(TraceFormat::Jsonl, Box::new(SharedSink(buffer.clone())));
+        cpu.run(&mut memory, Some(5)).unwrap();
+        let reference = String::from_utf8(buffer.borrow().clone()).unwrap();
+
+        // An unmodified reference matches the whole run
+        let (mut cpu, mut memory) = load_counting_program();
+        cpu.set_trace_comparator(TraceComparator::from_text(
+            ReferenceFormat::Jsonl,
+            &reference,
+            vec![],
+        ));
+        assert_eq!(cpu.run(&mut memory, Some(5)).unwrap(), 5);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::InstructionLimit));
+        assert!(cpu.trace_divergence().is_none());
+
+        // Doctor the value written by the third instruction
+        let doctored = reference.replacen("\"new\":\"0x00000003\"", "\"new\":\"0x00000004\"", 1);
+        assert_ne!(doctored, reference);
+        let (mut cpu, mut memory) = load_counting_program();
+        let base = memory.base_address();
+        cpu.set_trace_comparator(TraceComparator::from_text(
+            ReferenceFormat::Jsonl,
+            &doctored,
+            vec![],
+        ));
+        assert_eq!(cpu.run(&mut memory, Some(5)).unwrap(), 3);
+        assert_eq!(cpu.exit_reason, Some(ExitReason::TraceDivergence(3)));
+
+        let divergence = cpu.trace_divergence().unwrap();
+        assert_eq!(divergence.index, 3);
+        assert_eq!(
+            divergence.expected,
+            Some(TraceRecord {
+                pc: base + 8,
+                write: Some((1, 4))
+            })
+        );
+        assert_eq!(
+            divergence.actual,
+            TraceRecord {
+                pc: base + 8,
+                write: Some((1, 3))
+            }
+        );
+        assert_eq!(divergence.history, vec![base, base + 4]);
+    }
+
+    #[test]
+    fn test_parse_spike_commit_log() {
+        let log = "\
+core   0: 0x80000000 (0x00a00093) addi    ra, zero, 10
+core   0: 3 0x80000000 (0x00a00093) x1  0x0000000a
+core   0: exception trap_illegal_instruction, epc 0x80000008
+core   0: 3 0x80000004 (0x00112223) mem 0x80000104 0x0000000a
+core   0: 3 0x80000008 (0x30002573) x10 0x0000000000001800 c768_mstatus 0x00001800
+";
+        let records = parse_reference(ReferenceFormat::Spike, log);
+        assert_eq!(
+            records,
+            vec![
+                TraceRecord {
+                    pc: 0x8000_0000,
+                    write: Some((1, 10))
+                },
+                TraceRecord {
+                    pc: 0x8000_0004,
+                    write: None
+                },
+                TraceRecord {
+                    pc: 0x8000_0008,
+                    write: Some((10, 0x1800))
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_rules() {
+        assert_eq!("csr".parse::<SkipRule>(), Ok(SkipRule::Csr));
+        assert_eq!(
+            "0x80000010".parse::<SkipRule>(),
+            Ok(SkipRule::Pc(0x8000_0010))
+        );
+        assert!("bogus".parse::<SkipRule>().is_err());
+
+        let reference = vec![TraceRecord {
+            pc: 0x8000_0000,
+            write: Some((10, 1)),
+        }];
+        let actual = TraceRecord {
+            pc: 0x8000_0000,
+            write: Some((10, 2)),
+        };
+        let csrr_mcycle = 0xB000_2573;
+        let mut comparator = TraceComparator::new(reference.clone(), vec![SkipRule::Csr]);
+        assert!(comparator.check(csrr_mcycle, actual));
+        let mut comparator = TraceComparator::new(reference, vec![]);
+        assert!(!comparator.check(csrr_mcycle, actual));
+    }
+}
@@ -1,20 +1,58 @@
-/// WASM bindings for the RISC-V emulator
+//! WASM bindings for the RISC-V emulator
+//!
+//! Runs are reproducible: the emulated machine has no wall-clock or entropy
+//! input (`time` and the counters advance per instruction), and `run_for`
+//! paces itself with `TimeSource::Virtual`, crediting each call one 60 Hz
+//! frame. The same binary, speed and sequence of calls and console input
+//! therefore retire the same instructions and end in the same state.
+//! `use_real_time(true)` switches the pacing to the browser clock.
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 #[cfg(target_arch = "wasm32")]
 use crate::{
-    cpu::Cpu,
+    cpu::{Cpu, ProgressAction},
+    elf_loader::{ElfLoader, LoadOptions},
     memory::Memory,
-    peripheral::{ConsolePeriph, PeripheralManager},
+    peripheral::{ConsolePeriph, PeripheralManager, TextDisplay},
+    state,
+    throttle::{Throttle, TimeSource},
+    EmulatorError, ExitReason,
 };
 
+/// Peripherals of a fresh emulator: the console at the standard UART base
+#[cfg(target_arch = "wasm32")]
+fn default_peripherals() -> PeripheralManager {
+    let mut peripherals = PeripheralManager::new();
+    peripherals.add_peripheral(Box::new(ConsolePeriph::new(0x10000000)));
+    peripherals
+}
+
+#[cfg(target_arch = "wasm32")]
+use serde::Serialize;
+
+/// Structured result of `WasmEmulator::run_for`
+#[cfg(target_arch = "wasm32")]
+#[derive(Serialize)]
+struct RunForResult {
+    executed: u32,
+    pc: u32,
+    exit_reason: Option<&'static str>,
+    exit_code: Option<u32>,
+}
+
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub struct WasmEmulator {
     cpu: Cpu,
     memory: Memory,
     peripherals: PeripheralManager,
+    throttle: Throttle,
+    /// Memory contents right after `load_binary`/`load_elf`, restored by `restart`
+    loaded_image: Vec<(u32, Vec<u8>)>,
+    /// Host-side handle of the display added by `attach_text_display`
+    text_display: Option<TextDisplay>,
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -25,18 +63,13 @@ impl WasmEmulator {
         // Initialize console for panic output
         console_error_panic_hook::set_once();
 
-        let cpu = Cpu::new();
-        let memory = Memory::new();
-        let mut peripherals = PeripheralManager::new();
-
-        // Add console peripheral at address 0x10000000 (standard UART base)
-        let console = ConsolePeriph::new(0x10000000);
-        peripherals.add_peripheral(Box::new(console));
-
         WasmEmulator {
-            cpu,
-            memory,
-            peripherals,
+            cpu: Cpu::new(),
+            memory: Memory::new(),
+            peripherals: default_peripherals(),
+            throttle: Throttle::default().with_time_source(TimeSource::Virtual),
+            loaded_image: Vec::new(),
+            text_display: None,
         }
     }
 
@@ -55,12 +88,33 @@ impl WasmEmulator {
                 .map_err(|e| JsValue::from_str(&format!("Memory error: {}", e)))?;
         }
 
-        // Set PC to load address
+        // Start (and reset) at the load address
+        self.cpu.set_reset_vector(load_address);
         self.cpu.pc = load_address;
+        self.loaded_image = self.memory.contents();
 
         Ok(load_address)
     }
 
+    /// Load an ELF image and point the CPU (and its reset vector) at its entry point
+    #[wasm_bindgen]
+    pub fn load_elf(&mut self, data: &[u8]) -> Result<u32, JsValue> {
+        let entry_point = ElfLoader::load_elf_data(data, &mut self.memory, &LoadOptions::default())
+            .map_err(|e| JsValue::from_str(&format!("ELF error: {}", e)))?;
+        self.cpu.set_reset_vector(entry_point);
+        self.cpu.pc = entry_point;
+        self.loaded_image = self.memory.contents();
+        Ok(entry_point)
+    }
+
+    /// riscv-tests verdict of the finished program: "PASS" or "FAIL"
+    #[wasm_bindgen]
+    pub fn riscv_test_status(&self) -> String {
+        crate::riscv_tests::check_riscv_test_result(&self.cpu, 0)
+            .status()
+            .to_string()
+    }
+
     #[wasm_bindgen]
     pub fn step(&mut self) -> Result<bool, JsValue> {
         match self
@@ -68,19 +122,167 @@ impl WasmEmulator {
             .step_with_peripherals(&mut self.memory, &mut self.peripherals)
         {
             Ok(()) => Ok(true),
-            Err(crate::EmulatorError::EcallTermination) => Ok(false), // Normal termination
+            Err(EmulatorError::EcallTermination) => {
+                // Normal termination
+                self.cpu.exit_reason = Some(self.cpu.ecall_exit_reason());
+                Ok(false)
+            }
+            Err(EmulatorError::Unimp(pc)) => {
+                self.cpu.exit_reason = Some(ExitReason::Unimp(pc));
+                Ok(false)
+            }
+            Err(EmulatorError::WaitForInterrupt) => {
+                self.cpu.exit_reason = Some(ExitReason::Waiting);
+                Ok(false)
+            }
+            Err(EmulatorError::TrapLoop(depth)) => {
+                self.cpu.exit_reason = Some(ExitReason::TrapLoop { depth });
+                Ok(false)
+            }
             Err(e) => Err(JsValue::from_str(&format!("CPU error: {}", e))),
         }
     }
 
+    /// Execute one instruction and return `{ pc, word, asm, write, next_pc, exit_reason }`
+    ///
+    /// `write` is `{ reg, old, new }` for the register the instruction changed, or null.
+    #[wasm_bindgen]
+    pub fn step_info(&mut self) -> Result<JsValue, JsValue> {
+        let info = self
+            .cpu
+            .step_info(&mut self.memory, Some(&mut self.peripherals))
+            .map_err(|e| JsValue::from_str(&format!("CPU error: {}", e)))?;
+        serde_wasm_bindgen::to_value(&info).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Supported extensions, peripherals, formats and limits (see `nekov::Capabilities`)
+    #[wasm_bindgen]
+    pub fn capabilities(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&crate::Capabilities::of(&self.cpu))
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Guest memory usage as `{ pages_allocated, bytes_written, resident_bytes }`
+    #[wasm_bindgen]
+    pub fn get_memory_stats(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.memory.stats())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Cap guest memory at `pages` 4 KiB pages (`undefined` removes the cap)
+    ///
+    /// The cap survives `reset`; writes past it stop the run with "guest memory limit exceeded".
+    #[wasm_bindgen]
+    pub fn set_memory_page_limit(&mut self, pages: Option<u32>) {
+        self.memory
+            .set_page_limit(pages.map(|pages| pages as usize));
+    }
+
+    /// Limit `run_for` to `instructions_per_second` (0 = unlimited)
+    ///
+    /// Each `run_for` call then retires only the instructions owed for the time
+    /// since the previous call, so calling it from `requestAnimationFrame` animates
+    /// at a steady rate.
+    #[wasm_bindgen]
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.throttle.set_speed(instructions_per_second);
+    }
+
+    /// Pace `run_for` by the browser clock instead of one frame per call
+    ///
+    /// Real time follows the page's actual frame rate but makes how many
+    /// instructions each call retires depend on timing, so runs stop being
+    /// reproducible.
+    #[wasm_bindgen]
+    pub fn use_real_time(&mut self, enabled: bool) {
+        self.throttle.set_time_source(if enabled {
+            TimeSource::RealTime
+        } else {
+            TimeSource::Virtual
+        });
+    }
+
+    /// Run up to `max_instructions` and return `{ executed, pc, exit_reason, exit_code }`
+    ///
+    /// After the guest stops with the `waiting` reason (WFI), calls execute
+    /// nothing until an enabled interrupt becomes pending.
+    #[wasm_bindgen]
+    pub fn run_for(&mut self, max_instructions: u32) -> Result<JsValue, JsValue> {
+        let budget = self.throttle.budget(max_instructions);
+        let executed = if self.is_waiting() {
+            0
+        } else {
+            self.cpu
+                .run_with_peripherals(&mut self.memory, &mut self.peripherals, Some(budget))
+                .map_err(|e| JsValue::from_str(&format!("CPU error: {}", e)))?
+        };
+        let result = RunForResult {
+            executed,
+            pc: self.cpu.pc,
+            exit_reason: self.cpu.exit_reason.map(|reason| reason.name()),
+            exit_code: self.cpu.exit_code(),
+        };
+        serde_wasm_bindgen::to_value(&result).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Stop each `run`/`run_for` call after `ms` milliseconds of JS time (`undefined` removes the bound)
+    ///
+    /// A call stopped this way reports the `time_budget_exceeded` exit reason.
+    /// `NaN`, `Infinity` and durations too long to represent also remove the bound.
+    #[wasm_bindgen]
+    pub fn set_max_run_time(&mut self, ms: Option<f64>) {
+        let budget = ms
+            .filter(|ms| ms.is_finite())
+            .and_then(|ms| std::time::Duration::try_from_secs_f64(ms.max(0.0) / 1000.0).ok());
+        match budget {
+            Some(budget) => self.cpu.set_time_budget(budget),
+            None => self.cpu.clear_time_budget(),
+        }
+    }
+
+    /// Whether the guest is idle in WFI with nothing to wake it
+    fn is_waiting(&self) -> bool {
+        self.cpu
+            .waiting_for_interrupt(self.peripherals.pending_interrupts())
+    }
+
+    /// Call `callback(retired, pc, elapsedMs)` every `interval` instructions during `run`/`run_for`
+    ///
+    /// Returning `false` from the callback cancels the run.
+    #[wasm_bindgen]
+    pub fn set_progress_callback(&mut self, interval: u32, callback: js_sys::Function) {
+        self.cpu.set_progress_callback(
+            interval,
+            Box::new(move |progress| {
+                let result = callback.call3(
+                    &JsValue::NULL,
+                    &JsValue::from(progress.retired),
+                    &JsValue::from(progress.pc),
+                    &JsValue::from(progress.elapsed.as_secs_f64() * 1000.0),
+                );
+                match result {
+                    Ok(value) if value == JsValue::FALSE => ProgressAction::Cancel,
+                    _ => ProgressAction::Continue,
+                }
+            }),
+        );
+    }
+
+    /// Guest exit code, once the program has terminated via ECALL
+    #[wasm_bindgen]
+    pub fn get_exit_code(&self) -> Option<u32> {
+        self.cpu.exit_code()
+    }
+
     #[wasm_bindgen]
     pub fn run(&mut self, max_instructions: Option<u32>) -> Result<u32, JsValue> {
+        if self.is_waiting() {
+            return Ok(0);
+        }
         self.cpu
             .run_with_peripherals(&mut self.memory, &mut self.peripherals, max_instructions)
             .map_err(|e| match e {
-                crate::EmulatorError::EcallTermination => {
-                    JsValue::from_str("Program terminated normally")
-                }
+                EmulatorError::EcallTermination => JsValue::from_str("Program terminated normally"),
                 _ => JsValue::from_str(&format!("CPU error: {}", e)),
             })
     }
@@ -99,6 +301,12 @@ impl WasmEmulator {
         }
     }
 
+    /// All 32 integer registers in index order
+    #[wasm_bindgen]
+    pub fn get_registers(&self) -> Vec<u32> {
+        self.cpu.registers_snapshot().to_vec()
+    }
+
     #[wasm_bindgen]
     pub fn set_register(&mut self, reg: usize, value: u32) {
         if reg < 32 {
@@ -108,28 +316,114 @@ impl WasmEmulator {
 
     #[wasm_bindgen]
     pub fn reset(&mut self) {
-        self.cpu = Cpu::new();
+        self.cpu = Cpu::new().with_reset_vector(self.cpu.reset_vector());
+        let page_limit = self.memory.page_limit();
         self.memory = Memory::new();
-        self.peripherals = PeripheralManager::new();
+        self.memory.set_page_limit(page_limit);
+        self.rebuild_peripherals();
+        self.throttle.set_speed(self.throttle.speed());
+        self.loaded_image.clear();
+    }
+
+    /// Run the loaded program again: reset the CPU and restore memory to its loaded contents
+    #[wasm_bindgen]
+    pub fn restart(&mut self) {
+        self.cpu.reset();
+        self.memory.restore_contents(&self.loaded_image);
+        self.throttle.set_speed(self.throttle.speed());
+    }
+
+    /// Serialize registers, CSRs and memory (see `state_version`)
+    #[wasm_bindgen]
+    pub fn save_state(&self) -> Vec<u8> {
+        state::save_state(&self.cpu, &self.memory)
+    }
+
+    /// Restore a state from `save_state`; peripherals are rebuilt from scratch
+    #[wasm_bindgen]
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), JsValue> {
+        state::load_state(&mut self.cpu, &mut self.memory, data)
+            .map_err(|e| JsValue::from_str(&format!("State error: {}", e)))?;
+        self.rebuild_peripherals();
+        Ok(())
+    }
+
+    /// `save_state` as a base64 string, e.g. for localStorage
+    #[wasm_bindgen]
+    pub fn export_state_base64(&self) -> String {
+        state::encode_base64(&self.save_state())
+    }
+
+    /// Restore a state from `export_state_base64`
+    #[wasm_bindgen]
+    pub fn import_state_base64(&mut self, text: &str) -> Result<(), JsValue> {
+        let data = state::decode_base64(text)
+            .map_err(|e| JsValue::from_str(&format!("State error: {}", e)))?;
+        self.load_state(&data)
+    }
+
+    /// Layout version of saved states; states from other versions are rejected
+    #[wasm_bindgen]
+    pub fn state_version() -> u32 {
+        state::STATE_VERSION
+    }
+
+    /// Attach a `width`×`height` text display at `base_address`, replacing any previous one
+    ///
+    /// Fails for displays of more than `TextDisplay::MAX_CELLS` cells.
+    #[wasm_bindgen]
+    pub fn attach_text_display(
+        &mut self,
+        base_address: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), JsValue> {
+        let display = TextDisplay::new(base_address, width, height)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.text_display = Some(display);
+        self.rebuild_peripherals();
+        Ok(())
+    }
+
+    /// The text display rendered as lines, or null without one; poll it once per frame
+    #[wasm_bindgen]
+    pub fn get_text_display(&self) -> Option<String> {
+        self.text_display.as_ref().map(TextDisplay::render)
+    }
 
-        // Re-add console peripheral
-        let console = ConsolePeriph::new(0x10000000);
-        self.peripherals.add_peripheral(Box::new(console));
+    /// Whether the text display changed since the last `get_text_display`
+    #[wasm_bindgen]
+    pub fn text_display_dirty(&self) -> bool {
+        self.text_display
+            .as_ref()
+            .is_some_and(TextDisplay::is_dirty)
     }
 
     #[wasm_bindgen]
     pub fn read_memory(&self, address: u32) -> u32 {
-        self.memory.read_word(address).unwrap_or(0)
+        self.memory.read_u32_le(address).unwrap_or(0)
     }
 
     #[wasm_bindgen]
     pub fn write_memory(&mut self, address: u32, value: u32) -> Result<(), JsValue> {
         self.memory
-            .write_word(address, value)
+            .write_u32_le(address, value)
             .map_err(|e| JsValue::from_str(&format!("Memory error: {}", e)))
     }
 }
 
+#[cfg(target_arch = "wasm32")]
+impl WasmEmulator {
+    /// Fresh default peripherals plus a cleared text display, if one is attached
+    fn rebuild_peripherals(&mut self) {
+        self.peripherals = default_peripherals();
+        if let Some(display) = &self.text_display {
+            display.clear();
+            self.peripherals.add_peripheral(Box::new(display.clone()));
+        }
+    }
+}
+
 // WASM utility functions
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
@@ -142,3 +436,104 @@ extern "C" {
 macro_rules! console_log {
     ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
 }
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    /// addi a0, x0, 7; addi a7, x0, 93; ecall
+    const EXIT_7: [u8; 12] = [
+        0x13, 0x05, 0x70, 0x00, 0x93, 0x08, 0xd0, 0x05, 0x73, 0x00, 0x00, 0x00,
+    ];
+
+    #[wasm_bindgen_test]
+    fn test_exit_code_propagates_through_run_for() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        assert_eq!(emulator.get_exit_code(), None);
+
+        let result = emulator.run_for(100).unwrap();
+        let exit_code = js_sys::Reflect::get(&result, &JsValue::from_str("exit_code")).unwrap();
+        assert_eq!(exit_code.as_f64(), Some(7.0));
+        assert_eq!(emulator.get_exit_code(), Some(7));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_unrepresentable_max_run_time_removes_the_bound() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        for ms in [f64::INFINITY, f64::NAN, 1e300] {
+            emulator.set_max_run_time(Some(ms));
+        }
+        emulator.run_for(100).unwrap();
+        assert_eq!(emulator.get_exit_code(), Some(7));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_state_round_trips_through_base64() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        assert!(emulator.step().unwrap());
+        let saved = emulator.export_state_base64();
+
+        let mut restored = WasmEmulator::new();
+        restored.import_state_base64(&saved).unwrap();
+        assert_eq!(restored.get_pc(), 0x80000004);
+        assert_eq!(restored.get_register(10), 7);
+        while restored.step().unwrap() {}
+        assert_eq!(restored.get_exit_code(), Some(7));
+        assert!(restored.import_state_base64("not base64!").is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn test_restart_runs_the_program_again() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        while emulator.step().unwrap() {}
+        emulator.restart();
+        assert_eq!(emulator.get_pc(), 0x80000000);
+        assert_eq!(emulator.get_exit_code(), None);
+        while emulator.step().unwrap() {}
+        assert_eq!(emulator.get_exit_code(), Some(7));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_step_info_reports_mnemonic_and_register_write() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        let info = emulator.step_info().unwrap();
+        let get = |object: &JsValue, key: &str| js_sys::Reflect::get(object, &key.into()).unwrap();
+        assert_eq!(get(&info, "asm").as_string().unwrap(), "addi x10,x0,7");
+        assert_eq!(get(&info, "next_pc").as_f64(), Some(0x80000004u32 as f64));
+        let write = get(&info, "write");
+        assert_eq!(get(&write, "reg").as_string().unwrap(), "a0");
+        assert_eq!(get(&write, "new").as_f64(), Some(7.0));
+    }
+
+    #[wasm_bindgen_test]
+    fn test_run_for_stops_advancing_while_waiting() {
+        // loop: wfi; j loop
+        let idle = [0x73, 0x00, 0x50, 0x10, 0x6f, 0xf0, 0xdf, 0xff];
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&idle).unwrap();
+        let get = |object: &JsValue, key: &str| js_sys::Reflect::get(object, &key.into()).unwrap();
+
+        let result = emulator.run_for(1000).unwrap();
+        assert_eq!(get(&result, "exit_reason").as_string().unwrap(), "waiting");
+        assert_eq!(get(&result, "executed").as_f64(), Some(1.0));
+        assert_eq!(emulator.get_pc(), 0x80000004);
+
+        let result = emulator.run_for(1000).unwrap();
+        assert_eq!(get(&result, "executed").as_f64(), Some(0.0));
+        assert_eq!(emulator.get_pc(), 0x80000004);
+    }
+
+    #[wasm_bindgen_test]
+    fn test_exit_code_propagates_through_step() {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_binary(&EXIT_7).unwrap();
+        while emulator.step().unwrap() {}
+        assert_eq!(emulator.get_exit_code(), Some(7));
+    }
+}
@@ -0,0 +1,285 @@
+//! Watch conditions that stop a run when a register or memory word reaches a value
+
+use crate::{
+    addr_expr::{parse_number, AddrExpr, ResolveError},
+    cpu::Cpu,
+    memory::Memory,
+    reg::Reg,
+};
+
+/// Condition evaluated against a watched value after every step
+pub enum WatchPredicate {
+    /// Fires when the value becomes equal to the given one
+    Equals(u32),
+    /// Fires whenever the value changes
+    Changed,
+    /// Fires when the closure returns true for the current value
+    Custom(Box<dyn FnMut(u32) -> bool>),
+}
+
+impl WatchPredicate {
+    /// Evaluate the predicate for a transition from `old` to `new`
+    fn fires(&mut self, old: u32, new: u32) -> bool {
+        match self {
+            WatchPredicate::Equals(value) => new == *value && old != *value,
+            WatchPredicate::Changed => new != old,
+            WatchPredicate::Custom(predicate) => predicate(new),
+        }
+    }
+}
+
+impl std::fmt::Debug for WatchPredicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchPredicate::Equals(value) => write!(f, "Equals(0x{value:08x})"),
+            WatchPredicate::Changed => write!(f, "Changed"),
+            WatchPredicate::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// A watched register or memory word together with its predicate
+#[derive(Debug)]
+pub enum Watch {
+    /// Watch an integer register
+    Register(Reg, WatchPredicate),
+    /// Watch the 32-bit word at an address
+    MemoryWord(u32, WatchPredicate),
+    /// Watch the PC; `Equals` makes a breakpoint
+    Pc(WatchPredicate),
+}
+
+impl Watch {
+    /// Current value of the watched location
+    pub(crate) fn value(&self, cpu: &Cpu, memory: &Memory) -> u32 {
+        match self {
+            Watch::Register(reg, _) => cpu.reg(*reg),
+            Watch::MemoryWord(address, _) => memory.peek_word(*address),
+            Watch::Pc(_) => cpu.pc,
+        }
+    }
+
+    /// Evaluate the predicate for a transition from `old` to `new`
+    pub(crate) fn fires(&mut self, old: u32, new: u32) -> bool {
+        match self {
+            Watch::Register(_, predicate)
+            | Watch::MemoryWord(_, predicate)
+            | Watch::Pc(predicate) => predicate.fires(old, new),
+        }
+    }
+}
+
+impl std::fmt::Display for Watch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (target, predicate) = match self {
+            Watch::Register(reg, predicate) => (reg.to_string(), predicate),
+            Watch::MemoryWord(address, predicate) => (format!("mem[0x{address:08x}]"), predicate),
+            Watch::Pc(predicate) => ("pc".to_string(), predicate),
+        };
+        match predicate {
+            WatchPredicate::Equals(value) => write!(f, "{target} == 0x{value:08x}"),
+            WatchPredicate::Changed => write!(f, "{target} changed"),
+            WatchPredicate::Custom(_) => write!(f, "{target} matches predicate"),
+        }
+    }
+}
+
+/// Simple watch parsed from the command line: `a0=0xdeadbeef`, `a0`, `0x80001000[=VALUE]`, `buf+4`
+///
+/// Without a value the watch fires on any change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchSpec {
+    /// Watched register, or `None` for a memory word
+    pub register: Option<Reg>,
+    /// Watched address when `register` is `None`
+    pub address: AddrExpr,
+    /// Value to wait for; `None` means any change
+    pub equals: Option<u32>,
+}
+
+/// Parse a hex (0x-prefixed) or decimal 32-bit value
+fn parse_value(s: &str) -> std::result::Result<u32, String> {
+    let s = s.trim();
+    parse_number(s)
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or_else(|| format!("invalid number '{s}'"))
+}
+
+/// Split `target[=value]` into its parts
+fn split_spec(s: &str) -> std::result::Result<(&str, Option<u32>), String> {
+    match s.split_once('=') {
+        Some((target, value)) => Ok((target, Some(parse_value(value)?))),
+        None => Ok((s, None)),
+    }
+}
+
+impl WatchSpec {
+    /// Parse a register watch such as `a0=0xdeadbeef` or `x5`
+    pub fn parse_register(s: &str) -> std::result::Result<Self, String> {
+        let (target, equals) = split_spec(s)?;
+        Ok(Self {
+            register: Some(target.parse()?),
+            address: AddrExpr::Literal(0),
+            equals,
+        })
+    }
+
+    /// Parse a memory watch such as `0x80001000`, `0x80001000=1` or `counter+4=0`
+    pub fn parse_memory(s: &str) -> std::result::Result<Self, String> {
+        let (target, equals) = split_spec(s)?;
+        Ok(Self {
+            register: None,
+            address: AddrExpr::parse(target)?,
+            equals,
+        })
+    }
+
+    /// Build the corresponding watch, resolving a symbolic address against `symbols`
+    pub fn to_watch(&self, symbols: &[(u32, String)]) -> std::result::Result<Watch, ResolveError> {
+        let predicate = match self.equals {
+            Some(value) => WatchPredicate::Equals(value),
+            None => WatchPredicate::Changed,
+        };
+        match self.register {
+            Some(reg) => Ok(Watch::Register(reg, predicate)),
+            None => Ok(Watch::MemoryWord(self.address.resolve(symbols)?, predicate)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExitReason;
+
+    fn load(program: &[u32]) -> (Cpu, Memory) {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let base = memory.base_address();
+        for (i, &word) in program.iter().enumerate() {
+            memory.write_word(base + i as u32 * 4, word).unwrap();
+        }
+        cpu.pc = base;
+        (cpu, memory)
+    }
+
+    #[test]
+    fn test_register_equals_watch_fires_at_setting_instruction() {
+        let (mut cpu, mut memory) = load(&[
+            0x00100513, // addi a0, zero, 1
+            0x00150513, // addi a0, a0, 1
+            0x00150513, // addi a0, a0, 1  <- a0 becomes 3
+            0x00150513, // addi a0, a0, 1
+        ]);
+        let base = memory.base_address();
+        cpu.add_watch(Watch::Register(Reg::A0, WatchPredicate::Equals(3)));
+
+        let executed = cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(executed, 3);
+        assert_eq!(
+            cpu.exit_reason,
+            Some(ExitReason::WatchHit {
+                watch: 0,
+                pc: base + 8,
+                value: 3
+            })
+        );
+        assert_eq!(cpu.watch(0).unwrap().to_string(), "a0 == 0x00000003");
+        assert_eq!(cpu.pc, base + 12);
+    }
+
+    #[test]
+    fn test_memory_changed_and_custom_watches() {
+        let (mut cpu, mut memory) = load(&[
+            0x00100093, // addi x1, zero, 1
+            0x00200113, // addi x2, zero, 2
+            0x1020a023, // sw x2, 256(x1)
+            0x00000013, // nop
+        ]);
+        let base = memory.base_address();
+        memory.write_word(0x101, 0).unwrap();
+        cpu.add_watch(
+            WatchSpec::parse_memory("0x101")
+                .unwrap()
+                .to_watch(&[])
+                .unwrap(),
+        );
+        cpu.run(&mut memory, Some(10)).unwrap();
+        assert_eq!(
+            cpu.exit_reason,
+            Some(ExitReason::WatchHit {
+                watch: 0,
+                pc: base + 8,
+                value: 2
+            })
+        );
+
+        let (mut cpu, mut memory) = load(&[
+            0x00100093, // addi x1, zero, 1
+            0x00200113, // addi x2, zero, 2
+        ]);
+        cpu.add_watch(Watch::Register(
+            Reg::Sp,
+            WatchPredicate::Custom(Box::new(|value| value % 2 == 0 && value != 0)),
+        ));
+        assert_eq!(cpu.run(&mut memory, Some(10)).unwrap(), 2);
+        assert!(matches!(
+            cpu.exit_reason,
+            Some(ExitReason::WatchHit { watch: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_watch_specs() {
+        assert_eq!(
+            WatchSpec::parse_register("a0=0xdeadbeef").unwrap(),
+            WatchSpec {
+                register: Some(Reg::A0),
+                address: AddrExpr::Literal(0),
+                equals: Some(0xdead_beef)
+            }
+        );
+        assert_eq!(WatchSpec::parse_memory("0x80001000").unwrap().equals, None);
+        assert!(WatchSpec::parse_register("q9=1").is_err());
+        assert!(WatchSpec::parse_memory("0x8000=zz").is_err());
+
+        // Symbolic addresses resolve when the watch is built
+        let spec = WatchSpec::parse_memory("counter+4=0").unwrap();
+        let symbols = [(0x8000_1000, "counter".to_string())];
+        assert_eq!(
+            spec.to_watch(&symbols).unwrap().to_string(),
+            "mem[0x80001004] == 0x00000000"
+        );
+        assert!(matches!(
+            spec.to_watch(&[]),
+            Err(ResolveError::UnknownSymbol { name, .. }) if name == "counter"
+        ));
+    }
+
+    #[test]
+    fn test_pc_watch_stops_on_arrival() {
+        let (mut cpu, mut memory) = load(&[
+            0x00100513, // addi a0, zero, 1
+            0x0080006f, // j +8
+            0x00150513, // addi a0, a0, 1  (skipped)
+            0x00150513, // addi a0, a0, 1  <- breakpoint
+        ]);
+        let base = memory.base_address();
+        cpu.add_watch(Watch::Pc(WatchPredicate::Equals(base + 12)));
+        assert_eq!(cpu.run(&mut memory, Some(10)).unwrap(), 2);
+        assert_eq!(
+            cpu.exit_reason,
+            Some(ExitReason::WatchHit {
+                watch: 0,
+                pc: base + 4,
+                value: base + 12
+            })
+        );
+        assert_eq!(cpu.pc, base + 12);
+        assert_eq!(cpu.reg(Reg::A0), 1);
+        assert_eq!(
+            cpu.watch(0).unwrap().to_string(),
+            format!("pc == 0x{:08x}", base + 12)
+        );
+    }
+}
@@ -0,0 +1,240 @@
+/// Integration test for `nekov test <dir>` batch runs
+use nekov::{
+    cpu::{CAUSE_LOAD_ACCESS_FAULT, CSR_MCAUSE, CSR_MTVAL},
+    memory::UninitPolicy,
+    riscv_tests::{default_cache_path, run_batch, BatchOptions, TestResult},
+    run_emulator_with_options, RunOptions,
+};
+use std::process::Command;
+
+mod common;
+use common::{build_elf, riscv_test_program};
+
+/// A directory with one passing and one failing test plus a non-test file
+fn fixture_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let base = 0x8000_0000;
+    std::fs::write(
+        dir.path().join("rv32ui-p-pass"),
+        build_elf(base, &riscv_test_program(1, true)),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("rv32ui-p-fail"),
+        build_elf(base, &riscv_test_program(3, false)),
+    )
+    .unwrap();
+    std::fs::write(dir.path().join("README.txt"), "not a test").unwrap();
+    dir
+}
+
+#[test]
+fn test_run_batch_in_process() {
+    let dir = fixture_dir();
+    let options = BatchOptions {
+        jobs: 2,
+        ..BatchOptions::default()
+    };
+    let summary = run_batch(dir.path(), &options).unwrap();
+    assert_eq!(summary.total(), 2);
+    assert_eq!(summary.passed(), 1);
+    assert_eq!(
+        summary.results,
+        vec![
+            ("rv32ui-p-fail".to_string(), TestResult::Fail(7)),
+            ("rv32ui-p-pass".to_string(), TestResult::Pass),
+        ]
+    );
+
+    let options = BatchOptions {
+        filter: Some("*-pass".to_string()),
+        ..BatchOptions::default()
+    };
+    let summary = run_batch(dir.path(), &options).unwrap();
+    assert_eq!(summary.total(), 1);
+    assert!(summary.all_passed());
+}
+
+#[test]
+fn test_nekov_test_subcommand() {
+    let dir = fixture_dir();
+    let output = Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .args(["test", "--no-cache"])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{stdout}");
+    assert!(stdout.contains("Summary: 1/2 tests passed (50.0% pass rate)"));
+    assert!(stdout.contains("test #3 failed"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .args([
+            "test",
+            "--no-cache",
+            "--json",
+            "--jobs",
+            "2",
+            "--filter",
+            "*-pass",
+        ])
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["total_tests"], 1);
+    assert_eq!(json["passed_tests"], 1);
+    assert_eq!(json["results"][0]["test"], "rv32ui-p-pass");
+}
+
+#[test]
+fn test_nekov_test_instruction_budgets() {
+    let dir = fixture_dir();
+    let budgets = tempfile::NamedTempFile::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .args([
+            "test",
+            "--no-cache",
+            "--filter",
+            "*-pass",
+            "--write-budgets",
+        ])
+        .arg(budgets.path())
+        .arg(dir.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let recorded: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(&std::fs::read(budgets.path()).unwrap()).unwrap();
+    let count = recorded["rv32ui-p-pass"].as_u64().unwrap();
+    assert!(count > 0);
+
+    // The recorded counts are within budget
+    let run_with_budget = |budget: u64, tolerance: &str| {
+        std::fs::write(
+            budgets.path(),
+            serde_json::json!({ "rv32ui-p-pass": budget }).to_string(),
+        )
+        .unwrap();
+        Command::new(env!("CARGO_BIN_EXE_nekov"))
+            .args([
+                "test",
+                "--no-cache",
+                "--json",
+                "--filter",
+                "*-pass",
+                "--budgets",
+            ])
+            .arg(budgets.path())
+            .args(["--budget-tolerance", tolerance])
+            .arg(dir.path())
+            .output()
+            .unwrap()
+    };
+    assert_eq!(run_with_budget(count, "0").status.code(), Some(0));
+
+    let output = run_with_budget(count - 1, "0");
+    assert_eq!(output.status.code(), Some(1));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["results"][0]["status"], "SLOW");
+    assert_eq!(json["results"][0]["instructions"], count);
+
+    // A generous tolerance lets the same run pass
+    assert_eq!(run_with_budget(count - 1, "50").status.code(), Some(0));
+}
+
+#[test]
+fn test_uninit_trap_policy_fails_tests_reading_unwritten_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rv32ui-p-uninit");
+    // Loads from an address nothing was loaded to, then passes; the trap handler fails test 2
+    let mut program = vec![
+        0x00000297, // auipc t0, 0
+        0x02428293, // addi t0, t0, 36      (handler)
+        0x30529073, // csrw mtvec, t0
+        0x800102B7, // lui t0, 0x80010
+        0x0042A503, // lw a0, 4(t0)
+    ];
+    program.extend(riscv_test_program(1, true));
+    program.extend(riscv_test_program(2, false));
+    std::fs::write(&path, build_elf(0x8000_0000, &program)).unwrap();
+
+    // The load faults recoverably into the guest's handler
+    let options = RunOptions {
+        quiet: true,
+        uninit_policy: UninitPolicy::Trap,
+        ..RunOptions::default()
+    };
+    let report = run_emulator_with_options(&path, &options).unwrap();
+    assert_eq!(report.cpu.read_csr(CSR_MCAUSE), CAUSE_LOAD_ACCESS_FAULT);
+    assert_eq!(report.cpu.read_csr(CSR_MTVAL), 0x8001_0004);
+    assert_eq!(report.guest_exit_code(), Some(5));
+
+    // The default 0xff fill hides the bug; the trap policy fails the test
+    let summary = run_batch(dir.path(), &BatchOptions::default()).unwrap();
+    assert!(summary.all_passed());
+    let options = BatchOptions {
+        uninit_policy: UninitPolicy::Trap,
+        ..BatchOptions::default()
+    };
+    let summary = run_batch(dir.path(), &options).unwrap();
+    assert_eq!(
+        summary.results,
+        vec![(
+            "rv32ui-p-uninit".to_string(),
+            TestResult::Error("uninitialized read at 0x80010004".to_string())
+        )]
+    );
+    assert_eq!(summary.results[0].1.status(), "FAIL");
+}
+
+#[test]
+fn test_rerun_failed_reuses_cached_passes() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = root.path().join("isa");
+    std::fs::create_dir(&dir).unwrap();
+    let write = |name: &str, pass: bool| {
+        std::fs::write(
+            dir.join(name),
+            build_elf(
+                0x8000_0000,
+                &riscv_test_program(if pass { 1 } else { 3 }, pass),
+            ),
+        )
+        .unwrap()
+    };
+    write("rv32ui-p-a", true);
+    write("rv32ui-p-b", false);
+    let cache = default_cache_path(&dir);
+    assert_eq!(cache, root.path().join("isa.nekov-results.json"));
+    let options = BatchOptions {
+        cache: Some(cache.clone()),
+        rerun_failed: true,
+        ..BatchOptions::default()
+    };
+    let run = || {
+        let summary = run_batch(&dir, &options).unwrap();
+        (summary.total(), summary.passed(), summary.cached)
+    };
+
+    // Nothing is cached yet, so everything runs
+    assert_eq!(run(), (2, 1, 0));
+    assert!(cache.exists());
+    // Fixing the failing test reruns only it; the pass comes from the cache
+    write("rv32ui-p-b", true);
+    assert_eq!(run(), (2, 2, 1));
+    assert_eq!(run(), (2, 2, 2));
+    // A rebuilt binary is rerun even though it passed before
+    write("rv32ui-p-a", false);
+    assert_eq!(run(), (2, 1, 1));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .args(["test", "--rerun-failed"])
+        .arg(&dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Summary: 1/2 tests passed"), "{stdout}");
+    assert!(stdout.contains("(1 passing results reused from the cache)"));
+}
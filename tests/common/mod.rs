@@ -0,0 +1,55 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)] // each test binary uses a different subset
+
+#[path = "../../src/elf_builder.rs"]
+mod elf_builder;
+
+use elf_builder::{ElfBuilder, Segment};
+use nekov::{cpu::Cpu, reg::Reg};
+use object::elf::{PF_R, PF_X};
+
+/// Assert several registers at once, reporting every mismatch rather than the first
+#[track_caller]
+pub fn assert_registers(cpu: &Cpu, expected: &[(Reg, u32)]) {
+    let mismatches: Vec<String> = expected
+        .iter()
+        .filter(|&&(reg, value)| cpu.reg(reg) != value)
+        .map(|&(reg, value)| {
+            let actual = cpu.reg(reg);
+            format!(
+                "  x{:<2} ({reg:>4}): expected 0x{value:08x} ({value}), got 0x{actual:08x} ({actual})",
+                reg.index()
+            )
+        })
+        .collect();
+    if !mismatches.is_empty() {
+        panic!(
+            "{} of {} registers differ:\n{}",
+            mismatches.len(),
+            expected.len(),
+            mismatches.join("\n")
+        );
+    }
+}
+
+/// Build a minimal RV32 executable: one PT_LOAD segment holding `program`,
+/// entered at its first word, which sits where it would if the segment also
+/// covered the ELF and program headers at `base`
+pub fn build_elf(base: u32, program: &[u32]) -> Vec<u8> {
+    const HEADERS: u32 = 52 + 32;
+    let code: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+    ElfBuilder::new(base + HEADERS)
+        .with_segment(Segment::new(base + HEADERS, &code, PF_R | PF_X))
+        .build()
+}
+
+/// riscv-tests style exit: gp = TESTNUM, a7 = 93, a0 = 0 on pass or `TESTNUM << 1 | 1`
+pub fn riscv_test_program(testnum: u32, pass: bool) -> Vec<u32> {
+    let a0 = if pass { 0 } else { testnum << 1 | 1 };
+    vec![
+        testnum << 20 | 3 << 7 | 0x13, // addi gp, zero, TESTNUM
+        93 << 20 | 17 << 7 | 0x13,     // addi a7, zero, 93
+        a0 << 20 | 10 << 7 | 0x13,     // addi a0, zero, code
+        0x00000073,                    // ecall
+    ]
+}
@@ -0,0 +1,271 @@
+/// Architecturally tricky RV32IM cases
+///
+/// Expected values are worked out from the ISA specification (sign
+/// extension, shift amount masking, M-extension overflow and division by
+/// zero rules), not taken from the emulator.
+use nekov::{
+    asm::{encode_branch, Branch},
+    cpu::Cpu,
+    memory::Memory,
+    reg::Reg,
+};
+
+/// R-type instruction `x3 = x1 op x2`
+fn r_type(funct7: u32, funct3: u32) -> u32 {
+    funct7 << 25 | 2 << 20 | 1 << 15 | funct3 << 12 | 3 << 7 | 0x33
+}
+
+/// OP-IMM instruction `x3 = x1 op imm`
+fn i_type(imm: i32, funct3: u32) -> u32 {
+    ((imm as u32) & 0xFFF) << 20 | 1 << 15 | funct3 << 12 | 3 << 7 | 0x13
+}
+
+/// S-type store `op x2, imm(x1)`
+fn s_type(imm: i32, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7F) << 25 | 2 << 20 | 1 << 15 | funct3 << 12 | (imm & 0x1F) << 7 | 0x23
+}
+
+/// Execute `instruction` at the RAM base with x1 = `rs1`, x2 = `rs2`
+fn step(instruction: u32, rs1: u32, rs2: u32) -> (Cpu, Memory) {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    cpu.pc = memory.base_address();
+    memory.write_word(cpu.pc, instruction).unwrap();
+    cpu.write_register(1, rs1);
+    cpu.write_register(2, rs2);
+    cpu.step(&mut memory).unwrap();
+    (cpu, memory)
+}
+
+/// Execute `instruction` with x1 = `rs1`, x2 = `rs2` and return x3
+fn execute(instruction: u32, rs1: u32, rs2: u32) -> u32 {
+    step(instruction, rs1, rs2).0.read_register(3)
+}
+
+/// RAM base, where `step` places the instruction
+const BASE: u32 = 0x8000_0000;
+
+const MIN: u32 = 0x8000_0000;
+const MAX: u32 = 0x7FFF_FFFF;
+const NEG1: u32 = 0xFFFF_FFFF;
+
+#[test]
+fn test_rv32im_edge_cases() {
+    let (add, sub, sll, slt, sltu, sra, srl) = (
+        r_type(0, 0),
+        r_type(0x20, 0),
+        r_type(0, 1),
+        r_type(0, 2),
+        r_type(0, 3),
+        r_type(0x20, 5),
+        r_type(0, 5),
+    );
+    let (mul, mulh, mulhsu, mulhu) = (r_type(1, 0), r_type(1, 1), r_type(1, 2), r_type(1, 3));
+    let (div, divu, rem, remu) = (r_type(1, 4), r_type(1, 5), r_type(1, 6), r_type(1, 7));
+    let cases = [
+        // Wrapping add/sub
+        ("add MAX + 1", add, MAX, 1, MIN),
+        ("sub MIN - 1", sub, MIN, 1, MAX),
+        ("sub 0 - 1", sub, 0, 1, NEG1),
+        // Register shifts only use rs2[4:0]
+        ("sll by 33", sll, 1, 33, 2),
+        ("srl MIN by 31", srl, MIN, 31, 1),
+        ("sra MIN by 31", sra, MIN, 31, NEG1),
+        ("sra MIN by 32", sra, MIN, 32, MIN),
+        // Signed vs unsigned compares
+        ("slt -1 < 0", slt, NEG1, 0, 1),
+        ("slt MAX < MIN", slt, MAX, MIN, 0),
+        ("sltu 0 < 0xffffffff", sltu, 0, NEG1, 1),
+        ("sltu 0xffffffff < 0", sltu, NEG1, 0, 0),
+        // OP-IMM immediates are sign-extended before use
+        ("addi 0 + -1", i_type(-1, 0), 0, 0, NEG1),
+        ("slti -2 < -1", i_type(-1, 2), 0xFFFF_FFFE, 0, 1),
+        ("slti -1 < -1", i_type(-1, 2), NEG1, 0, 0),
+        ("sltiu 5 < (imm -1 = 0xffffffff)", i_type(-1, 3), 5, 0, 1),
+        ("sltiu 0xffffffff < 0xffffffff", i_type(-1, 3), NEG1, 0, 0),
+        ("xori -1 is not", i_type(-1, 4), 0x0F0F_0F0F, 0, 0xF0F0_F0F0),
+        ("andi -2048", i_type(-2048, 7), NEG1, 0, 0xFFFF_F800),
+        ("slli 1 by 31", i_type(31, 1), 1, 0, MIN),
+        ("srli MIN by 31", i_type(31, 5), MIN, 0, 1),
+        ("srai MIN by 31", i_type(0x400 | 31, 5), MIN, 0, NEG1),
+        // Multiplication: low word is sign-agnostic, high words differ per signedness
+        ("mul MIN * -1", mul, MIN, NEG1, MIN),
+        ("mulh -1 * -1", mulh, NEG1, NEG1, 0),
+        ("mulh MIN * MIN", mulh, MIN, MIN, 0x4000_0000),
+        ("mulhsu -1 * 0xffffffff", mulhsu, NEG1, NEG1, NEG1),
+        ("mulhsu MIN * 0xffffffff", mulhsu, MIN, NEG1, MIN),
+        ("mulhsu MAX * 0xffffffff", mulhsu, MAX, NEG1, 0x7FFF_FFFE),
+        (
+            "mulhsu MIN * MIN (rs2 unsigned)",
+            mulhsu,
+            MIN,
+            MIN,
+            0xC000_0000,
+        ),
+        ("mulhsu -2 * 3", mulhsu, 0xFFFF_FFFE, 3, NEG1),
+        ("mulhsu 1 * 0xffffffff", mulhsu, 1, NEG1, 0),
+        (
+            "mulhu 0xffffffff * 0xffffffff",
+            mulhu,
+            NEG1,
+            NEG1,
+            0xFFFF_FFFE,
+        ),
+        // Division truncates toward zero; overflow and division by zero do not trap
+        ("div -7 / 2", div, (-7i32) as u32, 2, (-3i32) as u32),
+        ("div MIN / -1 overflows", div, MIN, NEG1, MIN),
+        ("div 5 / 0", div, 5, 0, NEG1),
+        ("divu 0xffffffff / 2", divu, NEG1, 2, MAX),
+        ("divu 5 / 0", divu, 5, 0, NEG1),
+        ("rem -7 % 2", rem, (-7i32) as u32, 2, NEG1),
+        ("rem MIN % -1 overflows", rem, MIN, NEG1, 0),
+        ("rem 5 % 0", rem, 5, 0, 5),
+        ("remu 0xffffffff % 0", remu, NEG1, 0, NEG1),
+    ];
+
+    let mismatches: Vec<String> = cases
+        .iter()
+        .filter_map(|&(name, instruction, rs1, rs2, expected)| {
+            let actual = execute(instruction, rs1, rs2);
+            (actual != expected)
+                .then(|| format!("{name}: expected 0x{expected:08x}, got 0x{actual:08x}"))
+        })
+        .collect();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+/// Multi-byte accesses wrap modulo 2^32; they used to overflow (and panic in
+/// debug builds) when computing the addresses of the following bytes
+#[test]
+fn test_loads_wrap_at_top_of_address_space() {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    memory.write_byte(0xFFFF_FFFF, 0x01).unwrap();
+    memory.write_byte(0x0000_0000, 0x80).unwrap();
+    memory.write_byte(0x0000_0001, 0x7F).unwrap();
+    let base = memory.base_address();
+    let program = [
+        0x00009183, // lh x3, 0(x1)    halfword at 0xffffffff continues at address 0
+        0x0000d203, // lhu x4, 0(x1)
+        0x00108283, // lb x5, 1(x1)    0xffffffff + 1 wraps to address 0
+        0x0000a303, // lw x6, 0(x1)
+    ];
+    for (i, &word) in program.iter().enumerate() {
+        memory.write_word(base + i as u32 * 4, word).unwrap();
+    }
+    memory.write_byte(0x0000_0002, 0x00).unwrap();
+    cpu.pc = base;
+    cpu.write_register(1, NEG1);
+    cpu.run(&mut memory, Some(program.len() as u32)).unwrap();
+
+    assert_eq!(cpu.read_register(3), 0xFFFF_8001);
+    assert_eq!(cpu.read_register(4), 0x0000_8001);
+    assert_eq!(cpu.read_register(5), 0xFFFF_FF80);
+    assert_eq!(cpu.read_register(6), 0x007F_8001);
+}
+
+#[test]
+fn test_branches_compare_signed_and_unsigned() {
+    let cases = [
+        ("blt -1 < 1", Branch::Blt, NEG1, 1, true),
+        ("bltu 0xffffffff < 1", Branch::Bltu, NEG1, 1, false),
+        ("blt MIN < MAX", Branch::Blt, MIN, MAX, true),
+        ("bltu MIN < MAX", Branch::Bltu, MIN, MAX, false),
+        ("bge MIN >= MAX", Branch::Bge, MIN, MAX, false),
+        ("bgeu MIN >= MAX", Branch::Bgeu, MIN, MAX, true),
+        ("bge -1 >= -1", Branch::Bge, NEG1, NEG1, true),
+        ("bgeu 0 >= 0xffffffff", Branch::Bgeu, 0, NEG1, false),
+        ("beq -1 == 0xffffffff", Branch::Beq, NEG1, NEG1, true),
+        ("bne MIN != MAX", Branch::Bne, MIN, MAX, true),
+    ];
+    let mismatches: Vec<String> = cases
+        .iter()
+        .filter_map(|&(name, op, rs1, rs2, taken)| {
+            let branch = encode_branch(op, Reg::Ra, Reg::Sp, -16).unwrap();
+            let pc = step(branch, rs1, rs2).0.pc;
+            let expected = if taken { BASE - 16 } else { BASE + 4 };
+            (pc != expected)
+                .then(|| format!("{name}: expected pc 0x{expected:08x}, got 0x{pc:08x}"))
+        })
+        .collect();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn test_jalr_clears_the_target_lsb() {
+    // jalr rd, imm(x1)
+    let jalr = |rd: u32, imm: i32| ((imm as u32) & 0xFFF) << 20 | 1 << 15 | rd << 7 | 0x67;
+
+    // The sum is computed first, then bit 0 is cleared
+    let (cpu, _) = step(jalr(3, 0), BASE + 0x11, 0);
+    assert_eq!((cpu.pc, cpu.read_register(3)), (BASE + 0x10, BASE + 4));
+    let (cpu, _) = step(jalr(3, -1), BASE + 0x22, 0);
+    assert_eq!((cpu.pc, cpu.read_register(3)), (BASE + 0x20, BASE + 4));
+    let (cpu, _) = step(jalr(3, 1), BASE + 0x20, 0);
+    assert_eq!(cpu.pc, BASE + 0x20);
+
+    // With rd == rs1 the target uses the old register value
+    let (cpu, _) = step(jalr(1, 0), BASE + 0x31, 0);
+    assert_eq!((cpu.pc, cpu.read_register(1)), (BASE + 0x30, BASE + 4));
+}
+
+#[test]
+fn test_upper_immediates() {
+    let lui = |imm: u32| imm << 12 | 3 << 7 | 0x37;
+    let auipc = |imm: u32| imm << 12 | 3 << 7 | 0x17;
+    let cases = [
+        ("lui 0xfffff", lui(0xFFFFF), 0xFFFF_F000),
+        ("lui 0x80000", lui(0x80000), MIN),
+        ("lui 0x00001", lui(0x00001), 0x0000_1000),
+        ("auipc 0", auipc(0), BASE),
+        ("auipc 0x80000 wraps", auipc(0x80000), 0),
+        ("auipc 0xfffff is -4096", auipc(0xFFFFF), BASE - 0x1000),
+    ];
+    let mismatches: Vec<String> = cases
+        .iter()
+        .filter_map(|&(name, instruction, expected)| {
+            let actual = execute(instruction, 0, 0);
+            (actual != expected)
+                .then(|| format!("{name}: expected 0x{expected:08x}, got 0x{actual:08x}"))
+        })
+        .collect();
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
+
+#[test]
+fn test_stores_write_only_their_width() {
+    let (sb, sh, sw) = (0, 1, 2);
+    let value = 0x1234_5678;
+    let slot = BASE + 0x100;
+    let stored = |instruction: u32, address: u32| {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        memory.write_word(address & !3, NEG1).unwrap();
+        memory.write_word(BASE, instruction).unwrap();
+        cpu.pc = BASE;
+        cpu.write_register(1, slot);
+        cpu.write_register(2, value);
+        cpu.step(&mut memory).unwrap();
+        memory.read_word(address & !3).unwrap()
+    };
+
+    assert_eq!(stored(s_type(0, sb), slot), 0xFFFF_FF78);
+    assert_eq!(stored(s_type(3, sb), slot + 3), 0x78FF_FFFF);
+    assert_eq!(stored(s_type(0, sh), slot), 0xFFFF_5678);
+    assert_eq!(stored(s_type(2, sh), slot + 2), 0x5678_FFFF);
+    assert_eq!(stored(s_type(0, sw), slot), value);
+    // Store offsets are sign-extended 12-bit immediates
+    assert_eq!(stored(s_type(-4, sw), slot - 4), value);
+    assert_eq!(stored(s_type(-2048, sb), slot - 2048), 0xFFFF_FF78);
+}
+
+/// Loading data across the top of the address space wraps like guest accesses do
+#[test]
+fn test_load_data_wraps_at_top_of_address_space() {
+    let mut memory = Memory::new();
+    memory.load_data(0xFFFF_FFFE, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(memory.read_byte(0xFFFF_FFFF).unwrap(), 2);
+    assert_eq!(memory.read_byte(0x0000_0000).unwrap(), 3);
+    assert_eq!(memory.read_byte(0x0000_0001).unwrap(), 4);
+}
@@ -0,0 +1,406 @@
+/// Integration tests for the high-level `Emulator`
+mod common;
+
+use common::build_elf;
+use nekov::{
+    asm::encode_jal,
+    cpu::{Cpu, CpuConfig, JumpCheck, StackOverflow},
+    emulator::Emulator,
+    reg::Reg,
+    run_emulator_with_options, EmulatorError, ExitReason, RunOptions,
+};
+
+/// Increments a counter word stored after the code and exits with it (41 + 1)
+const COUNTER_PROGRAM: [u32; 8] = [
+    0x00000297, // auipc t0, 0
+    0x01C2A303, // lw t1, 28(t0)
+    0x00130313, // addi t1, t1, 1
+    0x0062AE23, // sw t1, 28(t0)
+    0x00030513, // addi a0, t1, 0
+    0x05D00893, // addi a7, zero, 93
+    0x00000073, // ecall
+    41,         // counter
+];
+
+#[test]
+fn test_load_program_runs_raw_words() {
+    let mut emulator = Emulator::new();
+    let entry = emulator
+        .load_program(&[
+            0x00700513, // addi a0, zero, 7
+            0x00550593, // addi a1, a0, 5
+            0x00000073, // ecall
+        ])
+        .unwrap();
+    assert_eq!(entry, emulator.memory.base_address());
+    emulator.run(Some(10)).unwrap();
+    assert_eq!(emulator.cpu.reg(Reg::A0), 7);
+    assert_eq!(emulator.cpu.reg(Reg::A1), 12);
+    assert_eq!(emulator.cpu.exit_reason, Some(ExitReason::EcallExit(7)));
+}
+
+#[test]
+fn test_restart_reruns_loaded_program() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counter");
+    std::fs::write(&path, build_elf(0x8000_0000, &COUNTER_PROGRAM)).unwrap();
+
+    let mut emulator = Emulator::new();
+    let entry = emulator.load_elf(&path).unwrap();
+    let first = emulator.run(Some(100)).unwrap();
+    assert_eq!(emulator.cpu.exit_code(), Some(42));
+
+    emulator.restart();
+    assert_eq!(emulator.cpu.pc, entry);
+    assert_eq!(emulator.cpu.exit_reason, None);
+    assert_eq!(emulator.cpu.instret(), 0);
+    // The counter written by the first run is back to its loaded value
+    let second = emulator.run(Some(100)).unwrap();
+    assert_eq!(second, first);
+    assert_eq!(emulator.cpu.exit_code(), Some(42));
+}
+
+/// Grows the heap one page at a time with `brk`, writing the page index into
+/// each new page, until the request fails; exits with the number of pages
+const SBRK_PROGRAM: [u32; 19] = [
+    0x0D600893, // addi a7, zero, 214
+    0x00000513, // addi a0, zero, 0
+    0x00000073, // ecall
+    0x00050413, // addi s0, a0, 0
+    0x00000493, // addi s1, zero, 0
+    0x000012B7, // loop: lui t0, 1
+    0x00540333, // add t1, s0, t0
+    0x00030513, // addi a0, t1, 0
+    0x0D600893, // addi a7, zero, 214
+    0x00000073, // ecall
+    0x00651A63, // bne a0, t1, done
+    0x00942023, // sw s1, 0(s0)
+    0x00050413, // addi s0, a0, 0
+    0x00148493, // addi s1, s1, 1
+    0xFDDFF06F, // j loop
+    0x00050593, // done: addi a1, a0, 0
+    0x00048513, // addi a0, s1, 0
+    0x05D00893, // addi a7, zero, 93
+    0x00000073, // ecall
+];
+
+#[test]
+fn test_brk_heap_grows_until_limit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("sbrk");
+    std::fs::write(&path, build_elf(0x8000_0000, &SBRK_PROGRAM)).unwrap();
+
+    let mut emulator = Emulator::new().with_heap(4 * 4096);
+    emulator.load_elf(&path).unwrap();
+    emulator.run(Some(1000)).unwrap();
+    assert_eq!(emulator.cpu.exit_code(), Some(4));
+    assert_eq!(emulator.cpu.reg(Reg::A1), (-12i32) as u32);
+
+    let stats = emulator.heap_stats().unwrap();
+    assert_eq!(stats.start, 0x8000_1000);
+    assert_eq!(stats.brk, stats.start + 4 * 4096);
+    assert_eq!(stats.failed_requests, 1);
+    for page in 0..4 {
+        let address = stats.start + page * 4096;
+        assert_eq!(emulator.memory.read_word(address).unwrap(), page);
+    }
+}
+
+/// Takes a 16-byte allocation with `brk`, writes its last byte, then one byte past it
+const OVERFLOW_PROGRAM: [u32; 10] = [
+    0x0D600893, // addi a7, zero, 214
+    0x00000513, // addi a0, zero, 0
+    0x00000073, // ecall
+    0x00050413, // addi s0, a0, 0
+    0x01040513, // addi a0, s0, 16
+    0x00000073, // ecall
+    0x000407A3, // sb zero, 15(s0)
+    0x00040823, // sb zero, 16(s0)
+    0x05D00893, // addi a7, zero, 93
+    0x00000073, // ecall
+];
+
+#[test]
+fn test_heap_poison_reports_overflow_address() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("overflow");
+    std::fs::write(&path, build_elf(0x8000_0000, &OVERFLOW_PROGRAM)).unwrap();
+
+    let mut emulator = Emulator::new().with_heap_poison();
+    let entry = emulator.load_elf(&path).unwrap();
+    let Err(EmulatorError::PoisonedAccess(access)) = emulator.run(Some(100)) else {
+        panic!("overflow not reported");
+    };
+    // The heap starts at 0x80001000 behind a 16-byte redzone
+    assert_eq!(access.address, 0x8000_1020);
+    assert_eq!(access.pc, Some(entry + 28));
+    assert!(access.write);
+    assert_eq!(access.allocation, Some((0x8000_1010, 16)));
+    assert_eq!(
+        access.to_string(),
+        "heap redzone write of 1 byte(s) at 0x80001020 by pc 0x80000070, \
+         0 bytes after allocation 0x80001010..0x80001020 (16 bytes)"
+    );
+}
+
+#[test]
+fn test_crash_report_bundle() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("illegal");
+    let program = [
+        0x02A00513, // addi a0, zero, 42
+        0x00150513, // addi a0, a0, 1
+        0x10200073, // sret (unsupported)
+    ];
+    std::fs::write(&path, build_elf(0x8000_0000, &program)).unwrap();
+    let bundle = dir.path().join("crash");
+    let options = RunOptions {
+        quiet: true,
+        crash_report: Some(bundle.clone()),
+        ..RunOptions::default()
+    };
+    let report = run_emulator_with_options(&path, &options).unwrap();
+    assert_eq!(report.exit_reason, Some(ExitReason::UnsupportedInstruction));
+
+    let read = |name: &str| std::fs::read_to_string(bundle.join(name)).unwrap();
+    let crash = read("crash.txt");
+    assert!(crash.starts_with(&format!("nekov {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(
+        crash.contains("illegal instruction `0x10200073`"),
+        "{crash}"
+    );
+    let json: serde_json::Value = serde_json::from_str(&read("report.json")).unwrap();
+    assert_eq!(json["exit_reason"], "unsupported_instruction");
+    let entry = report.entry_point;
+    assert_eq!(json["final_pc"], format!("0x{:08x}", entry + 8));
+    assert_eq!(json["machine"]["env"], "tests");
+    assert_eq!(json["memory"]["pages_allocated"], 1);
+    assert_eq!(
+        json["memory"]["bytes_written"],
+        report.memory.stats().bytes_written
+    );
+    assert!(read("machine.txt").starts_with("isa:            rv32ima"));
+    assert!(read("registers.txt").contains("x10/a0   0x0000002b"));
+    let history = read("history.txt");
+    assert_eq!(history.lines().count(), 2);
+    assert!(
+        history.starts_with(&format!("0x{entry:08x}: 02a00513  addi")),
+        "{history}"
+    );
+    let memory = read("memory.txt");
+    assert!(
+        memory.contains("13 05 a0 02 13 05 15 00 73 00 20 10"),
+        "{memory}"
+    );
+    // build_elf emits no section headers
+    assert_eq!(read("sections.txt"), "(no sections loaded)\n");
+
+    // Memory faults also dump the faulting data address
+    let path = dir.path().join("overflow");
+    std::fs::write(&path, build_elf(0x8000_0000, &OVERFLOW_PROGRAM)).unwrap();
+    let options = RunOptions {
+        heap_poison: true,
+        ..options
+    };
+    assert!(matches!(
+        run_emulator_with_options(&path, &options),
+        Err(EmulatorError::PoisonedAccess(_))
+    ));
+    assert!(read("crash.txt").contains("heap redzone write"));
+    assert!(read("memory.txt").contains("around data address 0x80001020:"));
+}
+
+#[test]
+fn test_call_function_returns_a0() {
+    let base = 0x8000_0000;
+    // The entry point exits; `add` follows it and keeps ra on the stack like compiled code
+    let program = [
+        0x00000073, // ecall
+        0xFF010113, // add: addi sp, sp, -16
+        0x00112623, // sw ra, 12(sp)
+        0x00B50533, // add a0, a0, a1
+        0x00C12083, // lw ra, 12(sp)
+        0x01010113, // addi sp, sp, 16
+        0x00008067, // ret
+    ];
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("add");
+    std::fs::write(&path, build_elf(base, &program)).unwrap();
+    let mut emulator = Emulator::new();
+    let entry = emulator.load_elf(&path).unwrap();
+
+    let add = entry + 4;
+    let sp = emulator.cpu.reg(Reg::Sp);
+    assert_eq!(emulator.call_function(add, &[40, 2]).unwrap(), 42);
+    // Like `call`, the hart is left as it was
+    assert_eq!(emulator.cpu.reg(Reg::Sp), sp);
+    assert_eq!(emulator.cpu.pc, entry);
+    assert_eq!(emulator.call_function(add, &[0xFFFF_FFFF, 2]).unwrap(), 1);
+
+    // The entry point exits instead of returning
+    assert!(matches!(
+        emulator.call_function(entry, &[]),
+        Err(EmulatorError::NoReturn(Some(ExitReason::EcallExit(_))))
+    ));
+    // Only a0..a7 carry arguments
+    assert!(matches!(
+        emulator.call_function(add, &[0; 9]),
+        Err(EmulatorError::TooManyArguments(9))
+    ));
+
+    // A function that never returns is abandoned
+    let spin = [0x0000006F]; // j .
+    std::fs::write(&path, build_elf(base, &spin)).unwrap();
+    let entry = emulator.load_elf(&path).unwrap();
+    assert!(matches!(
+        emulator.call_function(entry, &[]),
+        Err(EmulatorError::NoReturn(Some(ExitReason::InstructionLimit)))
+    ));
+    assert_eq!(emulator.cpu.instret(), 0);
+}
+
+#[test]
+fn test_call_returns_under_jump_check() {
+    let mut emulator = Emulator::new();
+    emulator.cpu = Cpu::with_config(CpuConfig {
+        jump_check: JumpCheck::Always,
+        ..CpuConfig::default()
+    });
+    let entry = emulator
+        .load_program(&[
+            0x00150513, // addi a0, a0, 1
+            0x00008067, // ret
+            0x00050067, // jr a0
+        ])
+        .unwrap();
+
+    // The return to the sentinel is where the call stops, not a wild jump
+    assert_eq!(emulator.call(entry, &[41]).unwrap(), 42);
+    // Other jumps into unwritten memory are still caught
+    assert!(matches!(
+        emulator.call(entry + 8, &[0x8000_1000]),
+        Err(EmulatorError::WildJump(jump)) if jump.target == 0x8000_1000
+    ));
+}
+
+#[test]
+fn test_stack_guard_stops_runaway_recursion() {
+    let base = 0x8000_0000;
+    // f() { f(); } with no base case
+    let program = [
+        0xFF010113,                       // f: addi sp, sp, -16
+        0x00112623,                       // sw ra, 12(sp)
+        encode_jal(Reg::Ra, -8).unwrap(), // jal ra, f
+    ];
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("recurse");
+    std::fs::write(&path, build_elf(base, &program)).unwrap();
+
+    // Left unchecked, the stack would grow down over the text within 256 frames
+    let top = base + 0x1000;
+    let guard = base + 0x800;
+    let mut emulator = Emulator::new().with_stack_region(top, top - guard);
+    let entry = emulator.load_elf(&path).unwrap();
+    emulator.cpu.set_reg(Reg::Sp, top);
+
+    let error = emulator.run(Some(10_000)).unwrap_err();
+    let expected = StackOverflow {
+        pc: entry + 4,
+        sp: guard - 16,
+        guard,
+        address: guard - 4,
+    };
+    assert!(
+        matches!(&error, EmulatorError::StackOverflow(overflow) if *overflow == expected),
+        "{error}"
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "stack overflow (sp=0x{:08x}, guard=0x{guard:08x}): store to 0x{:08x} at pc 0x{:08x}",
+            guard - 16,
+            guard - 4,
+            entry + 4
+        )
+    );
+    for (i, &word) in program.iter().enumerate() {
+        assert_eq!(emulator.memory.peek_word(entry + i as u32 * 4), word);
+    }
+}
+
+#[test]
+fn test_count_only_prints_a_single_summary_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counter");
+    std::fs::write(&path, build_elf(0x8000_0000, &COUNTER_PROGRAM)).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .arg("--count-only")
+        .arg(&path)
+        .output()
+        .unwrap();
+    // The guest exit code is still passed through
+    assert_eq!(output.status.code(), Some(42));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.strip_suffix('\n').unwrap();
+    assert!(!line.contains('\n'), "{stdout}");
+    let rest = line.strip_prefix("executed 7 instructions in ").unwrap();
+    let (ms, mips) = rest.split_once(" ms (").unwrap();
+    assert!(ms.parse::<f64>().unwrap() >= 0.0, "{line}");
+    assert!(mips.strip_suffix(" MIPS)").unwrap().parse::<f64>().is_ok());
+}
+
+#[test]
+fn test_guest_exit_codes_clamp_to_a_failing_status() {
+    let dir = tempfile::tempdir().unwrap();
+    for (code, status) in [(1, 1), (255, 255), (256, 255), (0x1000_0000, 255)] {
+        let path = dir.path().join(format!("exit_{code}"));
+        let program = [
+            0x00000537 | (code & 0xFFFF_F000), // lui a0, %hi(code)
+            0x00050513 | (code & 0xFFF) << 20, // addi a0, a0, %lo(code)
+            0x05D00893,                        // addi a7, zero, 93
+            0x00000073,                        // ecall
+        ];
+        std::fs::write(&path, build_elf(0x8000_0000, &program)).unwrap();
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_nekov"))
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(status), "guest exit code {code}");
+    }
+}
+
+#[test]
+fn test_vv_prints_register_and_store_deltas() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("delta");
+    let program = [
+        0x00700513, // addi a0, zero, 7
+        0x800012B7, // lui t0, 0x80001
+        0x00A2A023, // sw a0, 0(t0)
+        0x00A282A3, // sb a0, 5(t0)
+        0x05D00893, // addi a7, zero, 93
+        0x00000073, // ecall
+    ];
+    std::fs::write(&path, build_elf(0x8000_0000, &program)).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nekov"))
+        .args(["-vv", "--no-color"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let start = stdout.find("Cycle 1:").unwrap();
+    let end = stdout.find("Cycle 6:").unwrap();
+    assert_eq!(
+        &stdout[start..end],
+        "Cycle 1: PC=0x80000054\n\
+         \x20 x10(a0): 0x00000000 -> 0x00000007\n\
+         Cycle 2: PC=0x80000058\n\
+         \x20 x5(t0): 0x00000000 -> 0x80001000\n\
+         Cycle 3: PC=0x8000005c\n\
+         \x20 mem[0x80001000] <- 0x00000007\n\
+         Cycle 4: PC=0x80000060\n\
+         \x20 mem[0x80001005] <- 0x07\n\
+         Cycle 5: PC=0x80000064\n\
+         \x20 x17(a7): 0x00000000 -> 0x0000005d\n"
+    );
+}
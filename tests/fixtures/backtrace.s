@@ -0,0 +1,47 @@
+# Nested calls keeping a frame pointer chain (saved ra at fp-4, caller's fp
+# at fp-8) for `Emulator::backtrace`; stop at `inner_body` to walk it
+
+.include "common.inc"
+
+.macro PROLOGUE
+    addi sp, sp, -16
+    sw ra, 12(sp)
+    sw s0, 8(sp)
+    addi s0, sp, 16
+.endm
+
+.macro EPILOGUE
+    lw ra, 12(sp)
+    lw s0, 8(sp)
+    addi sp, sp, 16
+    ret
+.endm
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+    li s0, 0
+    call outer
+    li a0, 0
+    EXIT
+
+.globl outer
+outer:
+    PROLOGUE
+    call middle
+    EPILOGUE
+
+.globl middle
+middle:
+    PROLOGUE
+    call inner
+    EPILOGUE
+
+.globl inner
+inner:
+    PROLOGUE
+.globl inner_body
+inner_body:
+    nop
+    EPILOGUE
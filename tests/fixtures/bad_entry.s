@@ -0,0 +1,12 @@
+# Entry point outside the image, as left by a linker script that discards
+# the entry section. Run with --entry 0x80000000 to start at `main`.
+
+.include "common.inc"
+
+.globl _start
+.set _start, 0x90000000
+
+.section .text.entry
+main:
+    li a0, 7
+    EXIT
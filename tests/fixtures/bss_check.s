@@ -0,0 +1,75 @@
+# Check that .bss is zeroed and writable, then that .data holds its initial values
+# Exits with 0 on success, 1 if .bss was not zero, 2 if .data was wrong
+
+.include "common.inc"
+
+.equ BSS_WORDS, 256
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+
+    # Every .bss word must read as zero
+    la t0, buffer
+    li t1, BSS_WORDS
+1:
+    lw t2, 0(t0)
+    bnez t2, bss_dirty
+    addi t0, t0, 4
+    addi t1, t1, -1
+    bnez t1, 1b
+
+    # Fill .bss with its word index and sum it back: 0 + 1 + ... + 255 = 32640
+    la t0, buffer
+    li t1, 0
+2:
+    sw t1, 0(t0)
+    addi t0, t0, 4
+    addi t1, t1, 1
+    li t2, BSS_WORDS
+    bne t1, t2, 2b
+    la t0, buffer
+    li t1, BSS_WORDS
+    li a1, 0
+3:
+    lw t2, 0(t0)
+    add a1, a1, t2
+    addi t0, t0, 4
+    addi t1, t1, -1
+    bnez t1, 3b
+    li t2, 32640
+    bne a1, t2, bss_dirty
+
+    la t0, initialized
+    lw t1, 0(t0)
+    li t2, 0x12345678
+    bne t1, t2, data_wrong
+
+    PUTS ok_message
+    li a0, 0
+    EXIT
+
+bss_dirty:
+    li a0, 1
+    EXIT
+
+data_wrong:
+    li a0, 2
+    EXIT
+
+.include "puts.inc"
+
+.section .rodata
+ok_message:
+    .asciz "bss ok\n"
+
+.section .data
+.balign 4
+initialized:
+    .word 0x12345678
+
+.section .bss
+.balign 4
+buffer:
+    .space BSS_WORDS * 4
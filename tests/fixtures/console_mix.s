@@ -0,0 +1,48 @@
+# Interleave UART output with HTIF putchar and write() requests; the host
+# should see "uart1 htif-putc:! htif-write uart2\n" in exactly that order
+
+.include "common.inc"
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+    PUTS first
+
+    # HTIF console putchar: the command is taken when the high word is stored
+    la t0, tohost
+    li t1, '!'
+    sw t1, 0(t0)
+    li t1, 0x01010000
+    sw t1, 4(t0)
+
+    # HTIF write(1, message, 12) through a syscall block
+    la t1, block
+    sw t1, 0(t0)
+    sw zero, 4(t0)
+
+    PUTS last
+    li a0, 0
+    EXIT
+
+.include "puts.inc"
+
+.section .rodata
+first:
+    .asciz "uart1 htif-putc:"
+message:
+    .ascii " htif-write "
+last:
+    .asciz "uart2\n"
+
+.section .data
+.align 3
+.globl tohost
+tohost:
+    .word 0, 0
+.globl fromhost
+fromhost:
+    .word 0, 0
+# [number, fd, buffer, length] as 64-bit words
+block:
+    .word 64, 0, 1, 0, message, 0, 12, 0
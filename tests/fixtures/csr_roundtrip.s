@@ -0,0 +1,64 @@
+# Exercise CSR read/write/set/clear, MRET through mepc and the cycle counter
+# Exits with 0 on success or the number of the first failing check
+
+.include "common.inc"
+
+.macro CHECK reg, expected, number
+    li t6, \expected
+    li a0, \number
+    bne \reg, t6, fail
+.endm
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+
+    # 1: csrrw returns the old value and writes the new one
+    li t0, 0xcafe0000
+    csrw mscratch, t0
+    li t1, 0x1234
+    csrrw t2, mscratch, t1
+    CHECK t2, 0xcafe0000, 1
+    csrr t2, mscratch
+    CHECK t2, 0x1234, 2
+
+    # 3-4: csrrs/csrrc set and clear bits
+    li t1, 0xf0000
+    csrrs t2, mscratch, t1
+    CHECK t2, 0x1234, 3
+    csrrci t2, mscratch, 0x4
+    CHECK t2, 0xf1234, 4
+    csrr t2, mscratch
+    CHECK t2, 0xf1230, 5
+
+    # 6-7: MRET jumps to mepc and restores mstatus.MIE from MPIE,
+    # staying in M-mode through MPP
+    li t0, 0x1880
+    csrs mstatus, t0
+    la t0, after_mret
+    csrw mepc, t0
+    li s0, 0
+    mret
+    li s0, 1
+after_mret:
+    CHECK s0, 0, 6
+    csrr t2, mstatus
+    andi t2, t2, 0x8
+    CHECK t2, 0x8, 7
+
+    # 8: the cycle counter advances
+    rdcycle t2
+    li a0, 8
+    beqz t2, fail
+
+    PUTS ok_message
+    li a0, 0
+fail:
+    EXIT
+
+.include "puts.inc"
+
+.section .rodata
+ok_message:
+    .asciz "csr ok\n"
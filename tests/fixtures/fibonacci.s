@@ -0,0 +1,68 @@
+# Compute fib(20) recursively, print it in decimal and exit with fib(20) & 0xff
+
+.include "common.inc"
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+    li a0, 20
+    call fib
+    mv s0, a0
+    PUTS prefix
+    mv a0, s0
+    call print_decimal
+    PUTS newline
+    andi a0, s0, 0xff
+    EXIT
+
+# fib(a0) -> a0
+fib:
+    li t0, 2
+    blt a0, t0, 1f
+    addi sp, sp, -16
+    sw ra, 12(sp)
+    sw s0, 8(sp)
+    sw s1, 4(sp)
+    mv s0, a0
+    addi a0, s0, -1
+    call fib
+    mv s1, a0
+    addi a0, s0, -2
+    call fib
+    add a0, a0, s1
+    lw ra, 12(sp)
+    lw s0, 8(sp)
+    lw s1, 4(sp)
+    addi sp, sp, 16
+1:
+    ret
+
+# print_decimal(a0): write an unsigned number to the UART
+print_decimal:
+    addi sp, sp, -16
+    mv t0, sp
+    li t1, 10
+    li t3, UART_TX
+1:
+    remu t2, a0, t1
+    addi t2, t2, '0'
+    sb t2, 0(t0)
+    addi t0, t0, 1
+    divu a0, a0, t1
+    bnez a0, 1b
+2:
+    addi t0, t0, -1
+    lbu t2, 0(t0)
+    sw t2, 0(t3)
+    bne t0, sp, 2b
+    addi sp, sp, 16
+    ret
+
+.include "puts.inc"
+
+.section .rodata
+prefix:
+    .asciz "fib(20) = "
+newline:
+    .asciz "\n"
@@ -0,0 +1,35 @@
+# Leaf and recursive functions for calling into the guest from the host
+# (`Emulator::call`); running the program itself just exits with 0
+
+.include "common.inc"
+
+.section .text.entry
+.globl _start
+_start:
+    li a0, 0
+    EXIT
+
+# add(a0, a1) -> a0
+.globl add
+add:
+    add a0, a0, a1
+    ret
+
+# factorial(a0) -> a0, recursing on the stack
+.globl factorial
+factorial:
+    li t0, 2
+    bltu a0, t0, 1f
+    addi sp, sp, -16
+    sw ra, 12(sp)
+    sw a0, 8(sp)
+    addi a0, a0, -1
+    call factorial
+    lw t0, 8(sp)
+    mul a0, a0, t0
+    lw ra, 12(sp)
+    addi sp, sp, 16
+    ret
+1:
+    li a0, 1
+    ret
@@ -0,0 +1,17 @@
+# Print a greeting on the UART and exit with code 0
+
+.include "common.inc"
+
+.section .text.entry
+.globl _start
+_start:
+    la sp, _stack_top
+    PUTS message
+    li a0, 0
+    EXIT
+
+.include "puts.inc"
+
+.section .rodata
+message:
+    .asciz "Hello from nekov!\n"
@@ -0,0 +1,11 @@
+# Never terminates: for wall-clock limits such as --max-time
+
+.include "common.inc"
+
+.section .text.entry
+.globl _start
+_start:
+    li a0, 0
+spin:
+    addi a0, a0, 1
+    j spin
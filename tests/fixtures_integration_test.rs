@@ -0,0 +1,243 @@
+/// End-to-end tests running the prebuilt ELFs in `tests/fixtures`
+use nekov::{
+    addr_expr::{AddrExpr, ResolveError},
+    emulator::Emulator,
+    memory_map::{MapEntry, MapKind},
+    reg::Reg,
+    EmulatorError, ExitReason, RunOptions,
+};
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Load `name` from the fixtures directory, run it with a captured console and
+/// return (exit code, console output)
+fn run_fixture(name: &str) -> (Option<u32>, String) {
+    let mut emulator = Emulator::new().capture_output(true);
+    emulator.load_elf(&fixture(name)).unwrap();
+    emulator.run(Some(1_000_000)).unwrap();
+    (emulator.cpu.exit_code(), emulator.take_output())
+}
+
+#[test]
+fn test_hello_uart() {
+    assert_eq!(
+        run_fixture("hello_uart"),
+        (Some(0), "Hello from nekov!\n".to_string())
+    );
+}
+
+#[test]
+fn test_bss_is_zeroed() {
+    assert_eq!(run_fixture("bss_check"), (Some(0), "bss ok\n".to_string()));
+}
+
+#[test]
+fn test_recursive_fibonacci() {
+    // fib(20) = 6765; the exit code is its low byte
+    assert_eq!(
+        run_fixture("fibonacci"),
+        (Some(6765 & 0xff), "fib(20) = 6765\n".to_string())
+    );
+}
+
+#[test]
+fn test_csr_roundtrip() {
+    assert_eq!(
+        run_fixture("csr_roundtrip"),
+        (Some(0), "csr ok\n".to_string())
+    );
+}
+
+#[test]
+fn test_uart_and_htif_output_interleave() {
+    assert_eq!(
+        run_fixture("console_mix"),
+        (Some(0), "uart1 htif-putc:! htif-write uart2\n".to_string())
+    );
+
+    // Taking the output empties the buffer
+    let mut emulator = Emulator::new().capture_output(true);
+    emulator.load_elf(&fixture("console_mix")).unwrap();
+    emulator.run(Some(1_000)).unwrap();
+    assert_eq!(
+        emulator.output_bytes(),
+        b"uart1 htif-putc:! htif-write uart2\n"
+    );
+    assert_eq!(emulator.take_output().len(), 35);
+    assert!(emulator.output_bytes().is_empty());
+}
+
+#[test]
+fn test_call_guest_functions() {
+    let mut emulator = Emulator::new();
+    let entry = emulator.load_elf(&fixture("functions")).unwrap();
+    emulator.cpu.set_reg(Reg::S0, 0x5A5A);
+
+    assert_eq!(emulator.call("add", &[40, 2]).unwrap(), 42);
+    assert_eq!(emulator.call("factorial", &[5]).unwrap(), 120);
+    assert_eq!(emulator.call("factorial", &[10]).unwrap(), 3_628_800);
+    assert_eq!(emulator.call("factorial", &[0]).unwrap(), 1);
+
+    // The hart is left as it was before each call
+    assert_eq!(emulator.cpu.pc, entry);
+    assert_eq!(emulator.cpu.reg(Reg::S0), 0x5A5A);
+    assert_eq!(emulator.cpu.reg(Reg::Sp), 0);
+    assert_eq!(emulator.cpu.instret(), 0);
+
+    // Addresses work too, and the program itself still runs afterwards
+    assert_eq!(emulator.call(entry + 0xC, &[1, 2]).unwrap(), 3);
+    assert!(matches!(
+        emulator.call("missing", &[]),
+        Err(EmulatorError::UnknownSymbol(name)) if name == "missing"
+    ));
+    emulator.run(Some(100)).unwrap();
+    assert_eq!(emulator.cpu.exit_code(), Some(0));
+}
+
+#[test]
+fn test_memory_map_lists_sections_and_peripherals() {
+    let mut emulator = Emulator::new().with_captured_console();
+    emulator.load_elf(&fixture("bss_check")).unwrap();
+    let map = emulator.memory_map();
+    let entry = |name: &str| map.iter().find(|e| e.name == name).unwrap().clone();
+
+    let text = entry(".text");
+    assert_eq!(
+        (text.kind, text.start, text.perms.as_str()),
+        (MapKind::Section, 0x8000_0000, "r-x")
+    );
+    let bss = entry(".bss");
+    assert_eq!((bss.size, bss.perms.as_str()), (1024, "rw-"));
+    assert!(bss.start >= entry(".data").end());
+    assert_eq!(
+        entry("console"),
+        MapEntry {
+            kind: MapKind::Peripheral,
+            name: "console".to_string(),
+            start: 0x1000_0000,
+            size: 0x1000,
+            perms: "rw-".to_string(),
+        }
+    );
+}
+
+#[test]
+fn test_entry_point_outside_loaded_segments() {
+    let mut options = RunOptions {
+        quiet: true,
+        ..RunOptions::default()
+    };
+    let error = nekov::run_emulator_with_options(&fixture("bad_entry"), &options).unwrap_err();
+    let EmulatorError::EntryNotLoaded(bad) = &error else {
+        panic!("unexpected error: {error}");
+    };
+    assert_eq!(bad.entry, 0x9000_0000);
+    assert_eq!(bad.loaded, vec![(0x8000_0000, 0x8000_000c)]);
+    assert_eq!(
+        error.to_string(),
+        "entry point 0x90000000 not within any loaded segment; \
+         loaded ranges are 0x80000000-0x8000000c"
+    );
+
+    // --entry overrides the ELF header
+    options.entry = Some(0x8000_0000);
+    let report = nekov::run_emulator_with_options(&fixture("bad_entry"), &options).unwrap();
+    assert_eq!(report.entry_point, 0x8000_0000);
+    assert_eq!(report.guest_exit_code(), Some(7));
+}
+
+#[test]
+fn test_backtrace_follows_frame_pointers() {
+    let mut emulator = Emulator::new();
+    emulator.load_elf(&fixture("backtrace")).unwrap();
+    let inner_body = nekov::elf_loader::ElfLoader::find_symbol(&fixture("backtrace"), "inner_body")
+        .unwrap()
+        .unwrap();
+    emulator.run_until_pc(inner_body, Some(100)).unwrap();
+
+    let frames = emulator.backtrace();
+    assert_eq!(frames, vec![0x8000_0060, 0x8000_0038, 0x8000_0014]);
+    let symbolized: Vec<String> = frames.iter().map(|&ra| emulator.symbolize(ra)).collect();
+    assert_eq!(symbolized, ["middle+0x18", "outer+0x18", "_start+0x14"]);
+    assert_eq!(emulator.symbolize(inner_body), "inner_body");
+    assert_eq!(emulator.symbolize(0x100), "0x00000100");
+
+    // Without a frame pointer there is nothing to walk
+    emulator.restart();
+    assert!(emulator.backtrace().is_empty());
+}
+
+#[test]
+fn test_breakpoint_by_symbol() {
+    let path = fixture("backtrace");
+    let symbol = |name| {
+        nekov::elf_loader::ElfLoader::find_symbol(&path, name)
+            .unwrap()
+            .unwrap()
+    };
+    for (expression, address) in [
+        ("middle", symbol("middle")),
+        ("inner_body", symbol("inner_body")),
+        ("outer+0x8", symbol("outer") + 8),
+    ] {
+        let options = RunOptions {
+            quiet: true,
+            breakpoints: vec![AddrExpr::parse(expression).unwrap()],
+            ..RunOptions::default()
+        };
+        let report = nekov::run_emulator_with_options(&path, &options).unwrap();
+        assert!(
+            matches!(report.exit_reason, Some(ExitReason::WatchHit { value, .. }) if value == address),
+            "{expression}: {:?}",
+            report.exit_reason
+        );
+        assert_eq!(report.cpu.pc, address, "{expression}");
+    }
+
+    let options = RunOptions {
+        quiet: true,
+        breakpoints: vec![AddrExpr::parse("midle").unwrap()],
+        ..RunOptions::default()
+    };
+    let error = nekov::run_emulator_with_options(&path, &options).unwrap_err();
+    assert!(
+        matches!(&error, EmulatorError::AddressExpression(ResolveError::UnknownSymbol { near, .. }) if near == &["middle"]),
+        "{error}"
+    );
+    assert_eq!(
+        error.to_string(),
+        "symbol `midle` not found (did you mean middle?)"
+    );
+}
+
+#[test]
+fn test_max_time_stops_infinite_loop() {
+    let options = RunOptions {
+        quiet: true,
+        instruction_limit: None,
+        max_time: Some(std::time::Duration::from_millis(5)),
+        time_check_interval: Some(64),
+        ..RunOptions::default()
+    };
+    let report = nekov::run_emulator_with_options(&fixture("spin"), &options).unwrap();
+    assert_eq!(report.exit_reason, Some(ExitReason::TimeBudgetExceeded));
+    assert!(report.instructions_executed > 0);
+    assert_eq!(report.instructions_executed % 64, 0);
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["exit_reason"], "time_budget_exceeded");
+    assert_eq!(json["guest_exit_code"], serde_json::Value::Null);
+
+    // An instruction limit is reported differently
+    let options = RunOptions {
+        instruction_limit: Some(1000),
+        ..options
+    };
+    let report = nekov::run_emulator_with_options(&fixture("spin"), &options).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["exit_reason"], "instruction_limit");
+}
@@ -1,6 +1,9 @@
 /// Integration test for RV32IMA instruction implementation
 /// This test exercises all major instruction categories through manual instruction creation
-use nekov::{cpu::Cpu, memory::Memory};
+use nekov::{cpu::Cpu, memory::Memory, reg::Reg};
+
+mod common;
+use common::assert_registers;
 
 fn run_instructions(
     cpu: &mut Cpu,
@@ -189,13 +192,27 @@ fn test_comprehensive_rv32ima_sequence() {
     run_instructions(&mut cpu, &mut memory, &instructions).unwrap();
 
     // Verify all steps worked correctly
-    assert_eq!(cpu.read_register(1), 42); // Original value
-    assert_eq!(cpu.read_register(2), 1764); // 42²
-    assert_eq!(cpu.read_register(3), 1000); // Address offset
-    assert_eq!(cpu.read_register(4), 1000); // Copied address
-    assert_eq!(cpu.read_register(5), 1764); // Value loaded from memory
+    assert_registers(
+        &cpu,
+        &[
+            (Reg::Ra, 42),   // Original value
+            (Reg::Sp, 1764), // 42²
+            (Reg::Gp, 1000), // Address offset
+            (Reg::Tp, 1000), // Copied address
+            (Reg::T0, 1764), // Value loaded from memory
+        ],
+    );
 
     // Verify memory contains the correct value
     let stored_value = memory.read_word(1000).unwrap();
     assert_eq!(stored_value, 1764);
 }
+
+#[test]
+#[should_panic(expected = "2 of 3 registers differ")]
+fn test_assert_registers_reports_every_mismatch() {
+    let mut cpu = Cpu::new();
+    cpu.write_register(1, 1);
+    cpu.write_register(2, 5);
+    assert_registers(&cpu, &[(Reg::Ra, 1), (Reg::Sp, 2), (Reg::A0, 3)]);
+}
@@ -1,9 +1,19 @@
 /// Integration test for peripheral system
 use nekov::{
-    cpu::Cpu,
+    cpu::{
+        Cpu, ProgressAction, CAUSE_INTERRUPT, CSR_MCAUSE, CSR_MEPC, CSR_MIE, CSR_MIP, CSR_MSTATUS,
+        CSR_MTVEC, MIP_MTIP,
+    },
+    emulator::Emulator,
+    fdt::DEFAULT_CLINT_BASE,
     memory::Memory,
-    peripheral::{ConsolePeriph, PeripheralManager},
+    peripheral::{
+        Clint, ConsoleBuffer, ConsolePeriph, Peripheral, PeripheralManager, TextDisplay, UartLayout,
+    },
+    reg::Reg,
 };
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 
 #[test]
 fn test_peripheral_uart_output() {
@@ -100,3 +110,404 @@ fn test_peripheral_memory_separation() {
     // and not interfere with memory operations
     println!("Peripheral separation test completed successfully");
 }
+
+/// "print H" for nekov's word-wide TX register
+const SIMPLE_DRIVER: [u32; 4] = [
+    0x100002b7, // lui t0, 0x10000
+    0x04800313, // li t1, 'H'
+    0x0062a023, // sw t1, 0(t0)
+    0x00000073, // ecall
+];
+
+/// "print H" for a 16550: poll LSR.THRE, then a byte store to THR
+const NS16550_DRIVER: [u32; 7] = [
+    0x100002b7, // lui t0, 0x10000
+    0x0052c383, // poll: lbu t2, 5(t0)
+    0x0203f393, // andi t2, t2, 0x20
+    0xfe038ce3, // beqz t2, poll
+    0x04800313, // li t1, 'H'
+    0x00628023, // sb t1, 0(t0)
+    0x00000073, // ecall
+];
+
+/// Run `program` with a capturing console using `layout`, returning its output
+fn run_driver(program: &[u32], layout: UartLayout) -> String {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    let mut peripherals = PeripheralManager::new();
+    let buffer = ConsoleBuffer::default();
+    peripherals.add_peripheral(Box::new(
+        ConsolePeriph::with_capture(0x10000000, buffer.clone()).with_layout(layout),
+    ));
+    let base = memory.base_address();
+    for (i, &word) in program.iter().enumerate() {
+        memory.write_word(base + i as u32 * 4, word).unwrap();
+    }
+    cpu.pc = base;
+    let _ = cpu.run_with_peripherals(&mut memory, &mut peripherals, Some(100));
+    let output = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+    output
+}
+
+#[test]
+fn test_uart_layouts_match_their_drivers() {
+    assert_eq!(run_driver(&SIMPLE_DRIVER, UartLayout::Simple), "H");
+    assert_eq!(
+        run_driver(&NS16550_DRIVER, UartLayout::Ns16550Compatible),
+        "H"
+    );
+    // A 16550 driver gets no output from the simple layout
+    assert_eq!(run_driver(&NS16550_DRIVER, UartLayout::Simple), "");
+}
+
+/// Timer that raises MTIP while its shared flag is set
+struct TimerLine {
+    raised: Rc<Cell<bool>>,
+}
+
+impl Peripheral for TimerLine {
+    fn read(&mut self, _offset: u32) -> nekov::Result<u32> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u32, _value: u32) -> nekov::Result<()> {
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        0x0200_0000
+    }
+
+    fn size(&self) -> u32 {
+        0x10000
+    }
+
+    fn pending_interrupts(&self) -> u32 {
+        if self.raised.get() {
+            MIP_MTIP
+        } else {
+            0
+        }
+    }
+}
+
+#[test]
+fn test_peripheral_interrupt_reaches_mip_and_traps() {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    let mut peripherals = PeripheralManager::new();
+    let raised = Rc::new(Cell::new(false));
+    peripherals.add_peripheral(Box::new(TimerLine {
+        raised: raised.clone(),
+    }));
+
+    let base = memory.base_address();
+    memory.write_word(base, 0x00150513).unwrap(); // loop: addi a0, a0, 1
+    memory.write_word(base + 4, 0xffdff06f).unwrap(); // j loop
+    memory.write_word(base + 0x100, 0x02a00593).unwrap(); // handler: addi a1, zero, 42
+    cpu.pc = base;
+    cpu.write_csr(CSR_MTVEC, base + 0x100);
+    cpu.write_csr(CSR_MIE, MIP_MTIP);
+    cpu.write_csr(CSR_MSTATUS, 1 << 3); // MIE
+
+    cpu.run_with_peripherals(&mut memory, &mut peripherals, Some(3))
+        .unwrap();
+    assert_eq!(cpu.read_csr(CSR_MIP), 0);
+    assert_eq!(cpu.reg(Reg::A0), 2);
+    assert_eq!(cpu.pc, base + 4);
+
+    raised.set(true);
+    cpu.step_with_peripherals(&mut memory, &mut peripherals)
+        .unwrap();
+    assert_eq!(cpu.read_csr(CSR_MIP), MIP_MTIP);
+    assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_INTERRUPT | 7);
+    assert_eq!(cpu.read_csr(CSR_MEPC), base + 4);
+    assert_eq!(cpu.read_csr(CSR_MSTATUS) & (1 << 3), 0);
+    assert_eq!(cpu.reg(Reg::A1), 42);
+    assert_eq!(cpu.pc, base + 0x104);
+
+    // Dropping the line clears mip again
+    raised.set(false);
+    memory.write_word(base + 0x104, 0x00000013).unwrap(); // nop
+    cpu.step_with_peripherals(&mut memory, &mut peripherals)
+        .unwrap();
+    assert_eq!(cpu.read_csr(CSR_MIP), 0);
+}
+
+/// Timer whose time advances each time the hart samples its line; fires from the `fire_at`-th sample on
+struct PolledTimer {
+    samples: Cell<u32>,
+    fire_at: u32,
+}
+
+impl Peripheral for PolledTimer {
+    fn read(&mut self, _offset: u32) -> nekov::Result<u32> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u32, _value: u32) -> nekov::Result<()> {
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        0x0200_0000
+    }
+
+    fn size(&self) -> u32 {
+        0x10000
+    }
+
+    fn pending_interrupts(&self) -> u32 {
+        self.samples.set(self.samples.get() + 1);
+        if self.samples.get() >= self.fire_at {
+            MIP_MTIP
+        } else {
+            0
+        }
+    }
+}
+
+#[test]
+fn test_pause_lets_polling_loop_take_timer_interrupt_sooner() {
+    // Steps a `hint; j loop` polling loop until the timer interrupt is taken
+    let steps_until_interrupt = |hint: u32| {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut peripherals = PeripheralManager::new();
+        peripherals.add_peripheral(Box::new(PolledTimer {
+            samples: Cell::new(0),
+            fire_at: 5,
+        }));
+        let base = memory.base_address();
+        memory.write_word(base, hint).unwrap(); // loop: pause / nop
+        memory.write_word(base + 4, 0xffdff06f).unwrap(); // j loop
+        memory.write_word(base + 0x100, 0x00000013).unwrap(); // handler: nop
+        cpu.pc = base;
+        cpu.write_csr(CSR_MTVEC, base + 0x100);
+        cpu.write_csr(CSR_MIE, MIP_MTIP);
+        cpu.write_csr(CSR_MSTATUS, 1 << 3);
+        let mut steps = 0;
+        while cpu.read_csr(CSR_MCAUSE) == 0 {
+            cpu.step_with_peripherals(&mut memory, &mut peripherals)
+                .unwrap();
+            steps += 1;
+        }
+        assert_eq!(cpu.read_csr(CSR_MCAUSE), CAUSE_INTERRUPT | 7);
+        steps
+    };
+
+    // PAUSE samples the lines again right after it executes
+    assert_eq!(steps_until_interrupt(0x0100000f), 3); // pause
+    assert_eq!(steps_until_interrupt(0x00000013), 5); // nop
+}
+
+/// Counts the cycles it has been ticked
+struct CycleCounter {
+    cycles: Rc<Cell<u64>>,
+}
+
+impl Peripheral for CycleCounter {
+    fn read(&mut self, _offset: u32) -> nekov::Result<u32> {
+        Ok(self.cycles.get() as u32)
+    }
+
+    fn write(&mut self, _offset: u32, _value: u32) -> nekov::Result<()> {
+        Ok(())
+    }
+
+    fn base_address(&self) -> u32 {
+        0x0300_0000
+    }
+
+    fn size(&self) -> u32 {
+        0x1000
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles.set(self.cycles.get() + cycles);
+    }
+}
+
+#[test]
+fn test_peripherals_tick_once_per_instruction() {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    let mut peripherals = PeripheralManager::new();
+    let cycles = Rc::new(Cell::new(0));
+    peripherals.add_peripheral(Box::new(CycleCounter {
+        cycles: cycles.clone(),
+    }));
+    let base = memory.base_address();
+    memory.write_word(base, 0x00150513).unwrap(); // loop: addi a0, a0, 1
+    memory.write_word(base + 4, 0x030005b7).unwrap(); // lui a1, 0x3000
+    memory.write_word(base + 8, 0x0005a603).unwrap(); // lw a2, 0(a1)
+    memory.write_word(base + 12, 0xff5ff06f).unwrap(); // j loop
+    cpu.pc = base;
+
+    let executed = cpu
+        .run_with_peripherals(&mut memory, &mut peripherals, Some(50))
+        .unwrap();
+    assert_eq!(executed, 50);
+    assert_eq!(cycles.get(), 50);
+    // The last completed read saw the ticks of every instruction before it
+    assert_eq!(cpu.reg(Reg::A2), 46);
+}
+
+/// Fills an 8x4 text display at 0x20000000 with '.', then draws a glider with byte stores
+const GLIDER_PROGRAM: [u32; 17] = [
+    0x200002B7, // lui t0, 0x20000
+    0x2E2E3337, // lui t1, 0x2e2e3
+    0xE2E30313, // addi t1, t1, -466    t1 = "...."
+    0x0062A023, // sw t1, 0(t0)
+    0x0062A223, // sw t1, 4(t0)
+    0x0062A423, // sw t1, 8(t0)
+    0x0062A623, // sw t1, 12(t0)
+    0x0062A823, // sw t1, 16(t0)
+    0x0062AA23, // sw t1, 20(t0)
+    0x0062AC23, // sw t1, 24(t0)
+    0x0062AE23, // sw t1, 28(t0)
+    0x02300393, // li t2, '#'
+    0x007280A3, // sb t2, 1(t0)
+    0x00728523, // sb t2, 10(t0)
+    0x00728823, // sb t2, 16(t0)
+    0x007288A3, // sb t2, 17(t0)
+    0x00728923, // sb t2, 18(t0)
+];
+
+#[test]
+fn test_text_display_renders_glider() {
+    let mut cpu = Cpu::new();
+    let mut memory = Memory::new();
+    let mut peripherals = PeripheralManager::new();
+    let display = TextDisplay::new(0x2000_0000, 8, 4).unwrap();
+    peripherals.add_peripheral(Box::new(display.clone()));
+    let base = memory.base_address();
+    for (i, &word) in GLIDER_PROGRAM.iter().enumerate() {
+        memory.write_word(base + i as u32 * 4, word).unwrap();
+    }
+    cpu.pc = base;
+
+    cpu.run_with_peripherals(
+        &mut memory,
+        &mut peripherals,
+        Some(GLIDER_PROGRAM.len() as u32),
+    )
+    .unwrap();
+    assert!(display.is_dirty());
+    assert_eq!(
+        display.render(),
+        ".#......\n\
+         ..#.....\n\
+         ###.....\n\
+         ........\n"
+    );
+    assert!(!display.is_dirty());
+}
+
+#[test]
+fn test_wfi_sleeps_until_clint_timer_fires() {
+    let program = [
+        0x00000297, // auipc t0, 0
+        0x03028293, // addi t0, t0, 48      (handler)
+        0x30529073, // csrw mtvec, t0
+        0x02004337, // lui t1, 0x2004       (mtimecmp)
+        0x3E800393, // li t2, 1000
+        0x00732023, // sw t2, 0(t1)
+        0x00032223, // sw zero, 4(t1)
+        0x08000293, // li t0, 0x80          (MTIE)
+        0x30429073, // csrw mie, t0
+        0x30046073, // csrsi mstatus, 8     (MIE)
+        0x10500073, // wfi
+        0x0000006F, // j .
+        0x342025F3, // handler: csrr a1, mcause
+        0x34102673, // csrr a2, mepc
+        0xC01026F3, // rdtime a3
+        0x05D00893, // li a7, 93
+        0x00700513, // li a0, 7
+        0x00000073, // ecall
+    ];
+    let mut emulator = Emulator::new();
+    emulator.add_peripheral(Box::new(Clint::new(DEFAULT_CLINT_BASE)));
+    let base = emulator.load_program(&program).unwrap();
+    // The WFI is the 11th instruction; the wakeup still reaches the progress check
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let recorded = reports.clone();
+    emulator.cpu.set_progress_callback(
+        11,
+        Box::new(move |progress| {
+            recorded.borrow_mut().push(progress.retired);
+            ProgressAction::Continue
+        }),
+    );
+
+    // Far fewer instructions than the 1000 ticks the timer is set to
+    emulator.run(Some(100)).unwrap();
+    assert_eq!(emulator.cpu.exit_code(), Some(7));
+    assert_eq!(*reports.borrow(), [11]);
+    assert_eq!(emulator.cpu.reg(Reg::A1), CAUSE_INTERRUPT | 7);
+    assert_eq!(emulator.cpu.reg(Reg::A2), base + 44);
+    assert!(emulator.cpu.reg(Reg::A3) >= 1000);
+    assert!(emulator.cpu.instret() < 100);
+}
+
+#[test]
+fn test_idle_skip_fast_forwards_polling_loop_to_timer() {
+    let program = [
+        0x00000297, // auipc t0, 0
+        0x03428293, // addi t0, t0, 52      (handler)
+        0x30529073, // csrw mtvec, t0
+        0x02004337, // lui t1, 0x2004       (mtimecmp)
+        0x000193B7, // lui t2, 25           (102400)
+        0x00732023, // sw t2, 0(t1)
+        0x00032223, // sw zero, 4(t1)
+        0x08000293, // li t0, 0x80          (MTIE)
+        0x30429073, // csrw mie, t0
+        0x30046073, // csrsi mstatus, 8     (MIE)
+        0x0200CEB7, // lui t4, 0x200c
+        0xFF8EAE03, // loop: lw t3, -8(t4)  (poll mtime)
+        0xFE7E6EE3, // bltu t3, t2, loop
+        0x342025F3, // handler: csrr a1, mcause
+        0x34102673, // csrr a2, mepc
+        0xC01026F3, // rdtime a3
+        0x05D00893, // li a7, 93
+        0x00700513, // li a0, 7
+        0x00000073, // ecall
+    ];
+    let run = |emulator: Emulator| {
+        let mut emulator = emulator;
+        emulator.add_peripheral(Box::new(Clint::new(DEFAULT_CLINT_BASE)));
+        emulator.load_program(&program).unwrap();
+        let executed = emulator.run(Some(1_000_000)).unwrap();
+        assert_eq!(emulator.cpu.exit_code(), Some(7));
+        (emulator, executed)
+    };
+    let (full, full_executed) = run(Emulator::new());
+    // Skips jump past progress boundaries without losing the report
+    let reports = Rc::new(Cell::new(0));
+    let counter = reports.clone();
+    let mut emulator = Emulator::new().with_idle_skip(1_000_000);
+    emulator.cpu.set_progress_callback(
+        1000,
+        Box::new(move |_| {
+            counter.set(counter.get() + 1);
+            ProgressAction::Continue
+        }),
+    );
+    let (skipped, skipped_executed) = run(emulator);
+    assert!(reports.get() > 0);
+
+    // Post-wakeup state and counters match the fully emulated run, apart
+    // from t3 keeping the last mtime value actually read
+    let mut registers = skipped.cpu.registers_snapshot();
+    assert!(registers[Reg::T3 as usize] < 102_400);
+    registers[Reg::T3 as usize] = full.cpu.reg(Reg::T3);
+    assert_eq!(registers, full.cpu.registers_snapshot());
+    assert_eq!(skipped.cpu.instret(), full.cpu.instret());
+    assert_eq!(skipped.cpu.cycles(), full.cpu.cycles());
+    assert_eq!(skipped_executed, full_executed);
+    assert_eq!(skipped.cpu.reg(Reg::A1), CAUSE_INTERRUPT | 7);
+    assert!(skipped.cpu.reg(Reg::A3) >= 102_400);
+    // ... but almost none of the polling iterations were stepped
+    assert_eq!(full.cpu.idle_skipped(), 0);
+    assert!(u64::from(full_executed) - skipped.cpu.idle_skipped() < 100);
+}
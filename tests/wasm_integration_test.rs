@@ -0,0 +1,92 @@
+//! riscv-tests style runs through the `WasmEmulator` bindings
+//!
+//! Exercises the wasm glue (ELF and raw loaders, step/run loops, peripherals)
+//! that native tests never touch. Run with `wasm-pack test --node` (or
+//! `--headless --firefox`).
+#![cfg(target_arch = "wasm32")]
+
+mod common;
+
+use common::{build_elf, riscv_test_program};
+use nekov::wasm::WasmEmulator;
+use wasm_bindgen_test::*;
+
+/// Run a loaded program to completion, returning (riscv-tests verdict, exit code)
+fn run_to_completion(emulator: &mut WasmEmulator) -> (String, Option<u32>) {
+    let result = emulator.run_for(1_000_000).unwrap();
+    let exit_reason = js_sys::Reflect::get(&result, &"exit_reason".into()).unwrap();
+    assert_eq!(exit_reason.as_string().as_deref(), Some("ecall_exit"));
+    (emulator.riscv_test_status(), emulator.get_exit_code())
+}
+
+#[wasm_bindgen_test]
+fn test_riscv_test_verdicts_through_load_elf() {
+    for (testnum, pass, expected) in [(1, true, "PASS"), (3, false, "FAIL"), (1, false, "FAIL")] {
+        let elf = build_elf(0x8000_0000, &riscv_test_program(testnum, pass));
+        let mut emulator = WasmEmulator::new();
+        emulator.load_elf(&elf).unwrap();
+        let (status, _) = run_to_completion(&mut emulator);
+        assert_eq!(status, expected, "testnum {testnum}, pass {pass}");
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_riscv_test_verdict_through_load_binary() {
+    let words = riscv_test_program(1, true);
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut emulator = WasmEmulator::new();
+    emulator.load_binary(&bytes).unwrap();
+    assert_eq!(run_to_completion(&mut emulator).0, "PASS");
+}
+
+#[wasm_bindgen_test]
+fn test_fixtures_run_to_their_exit_codes() {
+    let fixtures: [(&str, &[u8], u32); 4] = [
+        ("hello_uart", include_bytes!("fixtures/hello_uart"), 0),
+        ("bss_check", include_bytes!("fixtures/bss_check"), 0),
+        (
+            "fibonacci",
+            include_bytes!("fixtures/fibonacci"),
+            6765 & 0xff,
+        ),
+        ("csr_roundtrip", include_bytes!("fixtures/csr_roundtrip"), 0),
+    ];
+    for (name, elf, exit_code) in fixtures {
+        let mut emulator = WasmEmulator::new();
+        emulator.load_elf(elf).unwrap();
+        assert_eq!(
+            run_to_completion(&mut emulator).1,
+            Some(exit_code),
+            "{name}"
+        );
+
+        // A restart replays the same run from the loaded image
+        emulator.restart();
+        assert_eq!(
+            run_to_completion(&mut emulator).1,
+            Some(exit_code),
+            "{name}"
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+fn test_throttled_runs_are_reproducible() {
+    let run = || {
+        let mut emulator = WasmEmulator::new();
+        emulator
+            .load_elf(include_bytes!("fixtures/fibonacci"))
+            .unwrap();
+        emulator.set_speed(100_000);
+        let mut executed = Vec::new();
+        for _ in 0..20 {
+            let result = emulator.run_for(u32::MAX).unwrap();
+            let count = js_sys::Reflect::get(&result, &"executed".into()).unwrap();
+            executed.push(count.as_f64().unwrap() as u32);
+        }
+        (executed, emulator.get_pc(), emulator.get_registers())
+    };
+    let first = run();
+    assert!(first.0.iter().all(|&count| count > 0));
+    assert_eq!(first, run());
+}